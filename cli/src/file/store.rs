@@ -11,6 +11,7 @@ use sp_ipld::{
   Ipld,
 };
 use std::{
+  convert::TryFrom,
   fs,
   path::{
     Path,
@@ -93,6 +94,30 @@ pub fn put(expr: Ipld) -> Cid {
   link
 }
 
+// The links associated with keys are kept apart from the content, in files
+// named by their keys holding their content ids. Links only save work, such
+// as checking definitions again, so a links directory that can't be created
+// leaves them unrecorded rather than stopping.
+fn links_directory() -> Option<PathBuf> {
+  let path = hashspace_directory().join("links");
+  fs::create_dir_all(&path).ok()?;
+  Some(path)
+}
+
+pub fn get_link(key: Cid) -> Option<Cid> {
+  let path = links_directory()?.join(Path::new(&key.to_string()));
+  let link = fs::read_to_string(path).ok()?;
+  Cid::try_from(link.trim()).ok()
+}
+
+pub fn put_link(key: Cid, link: Cid) {
+  if let Some(dir) = links_directory() {
+    let path = dir.join(Path::new(&key.to_string()));
+    // A link that can't be written is left unrecorded, like one that's lost
+    let _ = fs::write(path, link.to_string());
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct FileStore {}
 
@@ -104,4 +129,8 @@ impl Store for FileStore {
   fn get(&self, link: Cid) -> Option<Ipld> { get(link) }
 
   fn put(&self, expr: Ipld) -> Cid { put(expr) }
+
+  fn put_link(&self, key: Cid, link: Cid) { put_link(key, link) }
+
+  fn get_link(&self, key: Cid) -> Option<Cid> { get_link(key) }
 }
@@ -9,7 +9,19 @@ use yatima_cli::{
   ipfs,
   repl,
 };
-use yatima_core::name::Name;
+use yatima_core::{
+  check::{
+    elaborate_defs,
+    CheckOpts,
+  },
+  erase::erase_defs,
+  eval::{
+    self,
+    trace,
+    Fuel,
+  },
+  name::Name,
+};
 use yatima_utils::{
   file,
   store::Store,
@@ -26,6 +38,22 @@ enum Cli {
     path: PathBuf,
   },
   Check {
+    /// Give `Type` the type `Type`, disabling universe levels
+    #[structopt(long)]
+    type_in_type: bool,
+    /// Rewrite the quantities of binders to the tightest ones that check
+    #[structopt(long)]
+    suggest_uses: bool,
+    /// Check every definition, even the ones recorded to have checked before
+    #[structopt(long)]
+    no_cache: bool,
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+  },
+  Lint {
+    /// Give `Type` the type `Type`, disabling universe levels
+    #[structopt(long)]
+    type_in_type: bool,
     #[structopt(parse(from_os_str))]
     path: PathBuf,
   },
@@ -35,6 +63,26 @@ enum Cli {
     typ_: String,
   },
   Run {
+    /// Evaluate every argument, without erasing the ones of quantity `0`
+    #[structopt(long)]
+    no_erase: bool,
+    /// Stop after this many reduction steps, printing the term as far as it
+    /// got
+    #[structopt(long)]
+    max_steps: Option<u64>,
+    /// Print counts of the reductions, allocations and reference expansions
+    /// the evaluation did
+    #[structopt(long)]
+    stats: bool,
+    /// Write the graph after each reduction step to a numbered DOT file in
+    /// this directory, highlighting the redex and the nodes the steps
+    /// allocate and free
+    #[structopt(long, parse(from_os_str))]
+    trace_dot: Option<PathBuf>,
+    /// Write the graph after each reduction step to this file as a JSON
+    /// trace
+    #[structopt(long, parse(from_os_str))]
+    trace_json: Option<PathBuf>,
     #[structopt(parse(from_os_str))]
     path: PathBuf,
   },
@@ -52,25 +100,33 @@ async fn main() -> std::io::Result<()> {
     Cli::Show { input, typ_ } => {
       use yatima_core::parse;
       let store = Rc::new(FileStore::new());
-      let (_, cid) = parse::package::parse_link(parse::span::Span::new(&input))
-        .expect("valid cid");
-      let ipld = store.get(cid).expect(&format!("cannot find {}", cid));
+      let span = parse::span::Span::new(&input);
+      let cid = match parse::package::parse_link(span) {
+        Ok((_, cid)) => cid,
+        Err(_) => fail(format!("{} is not a valid content id", input)),
+      };
+      let ipld = store
+        .get(cid)
+        .unwrap_or_else(|| fail(format!("Cannot find {} in the store", cid)));
       match typ_.as_str() {
         "package" => {
           let pack = yatima_core::package::Package::from_ipld(&ipld)
-            .expect("package ipld");
+            .unwrap_or_else(|e| fail(format!("Not a package: {:?}", e)));
           println!("{:?}", pack);
         }
         "entry" => {
-          let entry =
-            yatima_core::package::Entry::from_ipld(&ipld).expect("entry ipld");
+          let entry = yatima_core::package::Entry::from_ipld(&ipld)
+            .unwrap_or_else(|e| fail(format!("Not an entry: {:?}", e)));
           println!("{:?}", entry);
-          let def = file::parse::entry_to_def(entry, store).expect("valid def");
+          let def = file::parse::entry_to_def(entry, store).unwrap_or_else(|e| {
+            fail(format!("Cannot read the definition of the entry: {}", e))
+          });
           println!("{}", def);
         }
         "anon" => {
-          let pack =
-            yatima_core::anon::Anon::from_ipld(&ipld).expect("anon ipld");
+          let pack = yatima_core::anon::Anon::from_ipld(&ipld).unwrap_or_else(
+            |e| fail(format!("Not an anonymous term: {:?}", e)),
+          );
           println!("{:?}", pack);
         }
         _ => {
@@ -83,11 +139,18 @@ async fn main() -> std::io::Result<()> {
       let root = std::env::current_dir()?;
       let store = Rc::new(FileStore::new());
       let env = file::parse::PackageEnv::new(root, path, store.clone());
-      let (cid, p, d) = file::parse::parse_file(env);
+      let (cid, p, d) = file::parse::parse_file(env.clone());
+      let diagnostics = env.diagnostics();
+      if !diagnostics.is_empty() {
+        file::print_diagnostics(&diagnostics, store.clone());
+        std::process::exit(1);
+      }
       store.put(p.to_ipld());
 
       let ipld_cid = if !no_ipfs {
-        ipfs::dag_put(p.to_ipld()).await.expect("Failed to put to ipfs.")
+        ipfs::dag_put(p.to_ipld())
+          .await
+          .unwrap_or_else(|e| fail(format!("Failed to put to ipfs: {}", e)))
       }
       else {
         "Not using ipfs".to_string()
@@ -96,31 +159,113 @@ async fn main() -> std::io::Result<()> {
       println!("{}", d);
       Ok(())
     }
-    Cli::Check { path } => {
+    Cli::Check { type_in_type, suggest_uses, no_cache, path } => {
       let store = Rc::new(FileStore {});
-      file::check_all(path, store)?;
+      let opts = CheckOpts { type_in_type };
+      if suggest_uses {
+        return file::suggest_uses(path, store, opts);
+      }
+      let (_, diagnostics) =
+        file::check_all(path, store.clone(), opts, !no_cache)?;
+      file::print_diagnostics(&diagnostics, store);
+      if !diagnostics.is_empty() {
+        std::process::exit(1);
+      }
+      Ok(())
+    }
+    Cli::Lint { type_in_type, path } => {
+      let store = Rc::new(FileStore {});
+      let opts = CheckOpts { type_in_type };
+      file::lint(path, store, opts)?;
       Ok(())
     }
-    Cli::Run { path } => {
+    Cli::Run { no_erase, max_steps, stats, trace_dot, trace_json, path } => {
       let root = std::env::current_dir()?;
       let store = Rc::new(FileStore {});
       let env = file::parse::PackageEnv::new(root, path.clone(), store.clone());
-      let (_, p, defs) = file::parse::parse_file(env);
+      let (_, p, defs) = file::parse::parse_file(env.clone());
+      let diagnostics = env.diagnostics();
+      if !diagnostics.is_empty() {
+        file::print_diagnostics(&diagnostics, store.clone());
+        std::process::exit(1);
+      }
       let _cid = store.put(p.to_ipld());
-      let _ipld_cid =
-        ipfs::dag_put(p.to_ipld()).await.expect("Failed to put to ipfs.");
-      let def = defs.get(&Name::from("main")).expect(&format!(
-        "No `main` expression in package {} from file {:?}",
-        p.name, path
-      ));
+      if let Err(err) = ipfs::dag_put(p.to_ipld()).await {
+        eprintln!("Warning: failed to put to ipfs: {}", err);
+      }
+      let main = Name::from("main");
+      if defs.get(&main).is_none() {
+        eprintln!(
+          "No `main` expression in package {} from file {:?}",
+          p.name, path
+        );
+        std::process::exit(1);
+      }
+      // The definitions are elaborated, or erased, which elaborates them too,
+      // so that the arguments inserted for implicit parameters are filled in
+      let roots = [main.clone()];
+      let prepared = if no_erase {
+        elaborate_defs(&defs, &roots, CheckOpts::default())
+      }
+      else {
+        erase_defs(&defs, &roots, CheckOpts::default())
+      };
+      let defs = match prepared {
+        Ok(prepared) => prepared,
+        Err((name, errs)) => {
+          eprintln!("Error: {} failed to check:", name);
+          for err in errs {
+            eprintln!("{}", err);
+          }
+          std::process::exit(1);
+        }
+      };
+      let defs = &defs;
+      let def = defs.get(&main).unwrap();
       let mut dag = yatima_core::dag::DAG::from_term(&def.to_owned().term);
-      dag.norm(&defs);
+      let mut fuel = max_steps.map_or_else(Fuel::unlimited, Fuel::new);
+      let tracing = trace_dot.is_some() || trace_json.is_some();
+      let ((frames, res), counts) = eval::stats::collect(|| {
+        if tracing {
+          trace::trace(&mut dag, &mut fuel, |dag, fuel| {
+            dag.norm_fueled(defs, fuel)
+          })
+        }
+        else {
+          (vec![], dag.norm_fueled(defs, &mut fuel))
+        }
+      });
+      if let Some(dir) = trace_dot {
+        std::fs::create_dir_all(&dir)?;
+        for (step, frame) in frames.iter().enumerate() {
+          let file = dir.join(format!("step-{:04}.dot", step));
+          std::fs::write(file, frame.to_dot())?;
+        }
+      }
+      if let Some(file) = trace_json {
+        std::fs::write(file, trace::to_json(&frames))?;
+      }
+      if let Err(err) = &res {
+        eprintln!("{}, stopped at:", err);
+      }
       println!("{}", dag);
+      if stats {
+        eprintln!("{}", counts);
+      }
+      if res.is_err() {
+        std::process::exit(1);
+      }
       Ok(())
     }
   }
 }
 
+// Prints an error and exits with a non-zero status
+fn fail(msg: String) -> ! {
+  eprintln!("Error: {}", msg);
+  std::process::exit(1)
+}
+
 // for valgrind testing
 // Cli::Test => {
 //  use im::HashMap;
@@ -24,7 +24,9 @@ use yatima_utils::{
   repl::{
     error::ReplError,
     run_repl,
+    step::Stepper,
     Repl,
+    ReplSettings,
   },
   store::Store,
 };
@@ -33,6 +35,8 @@ struct RustyLineRepl {
   rl: Editor<()>,
   defs: Arc<Mutex<Defs>>,
   store: Rc<FileStore>,
+  settings: Arc<Mutex<ReplSettings>>,
+  stepper: Arc<Mutex<Option<Stepper>>>,
 }
 
 impl RustyLineRepl {
@@ -42,7 +46,13 @@ impl RustyLineRepl {
     rl.bind_sequence(KeyEvent::alt('l'), Cmd::Insert(1, String::from("λ ")));
     rl.bind_sequence(KeyEvent::alt('a'), Cmd::Insert(1, String::from("∀ ")));
     let store = Rc::new(FileStore {});
-    RustyLineRepl { rl, defs: Arc::new(Mutex::new(Defs::new())), store }
+    RustyLineRepl {
+      rl,
+      defs: Arc::new(Mutex::new(Defs::new())),
+      store,
+      settings: Arc::new(Mutex::new(ReplSettings::default())),
+      stepper: Arc::new(Mutex::new(None)),
+    }
   }
 }
 
@@ -74,6 +84,10 @@ impl Repl for RustyLineRepl {
   fn get_defs(&self) -> Arc<Mutex<Defs>> { self.defs.clone() }
 
   fn get_store(&self) -> Rc<dyn Store> { self.store.clone() }
+
+  fn get_settings(&self) -> Arc<Mutex<ReplSettings>> { self.settings.clone() }
+
+  fn get_stepper(&self) -> Arc<Mutex<Option<Stepper>>> { self.stepper.clone() }
 }
 
 pub fn main() { run_repl(&mut RustyLineRepl::new()); }
@@ -0,0 +1,58 @@
+// Times the typechecking of a package modeled on the introit, with its
+// datatypes, records, pattern matches, implicit arguments and equality
+// proofs, which compare large types for equality. Run with
+// `cargo bench -p yatima_core --bench check`.
+
+use std::time::{
+  Duration,
+  Instant,
+};
+
+use yatima_core::{
+  check::{
+    check_def_all,
+    CheckOpts,
+  },
+  defs::Defs,
+  parse::{
+    package::parse_defs,
+    span::Span,
+    term::input_cid,
+  },
+};
+
+const INTROIT: &str = include_str!("introit.ya");
+
+// How many times the package is checked, to even out the timings
+const RUNS: u32 = 10;
+
+fn main() {
+  let start = Instant::now();
+  let (_, (defs, _)) =
+    parse_defs(input_cid(INTROIT), Defs::new())(Span::new(INTROIT)).unwrap();
+  let parsing = start.elapsed();
+  let names: Vec<String> = defs.names().iter().map(|n| n.to_string()).collect();
+  let mut times = vec![Duration::default(); names.len()];
+  for _ in 0..RUNS {
+    for (name, time) in names.iter().zip(times.iter_mut()) {
+      let start = Instant::now();
+      if let Err(errs) = check_def_all(&defs, name, CheckOpts::default()) {
+        panic!("{} failed to check: {}", name, errs[0]);
+      }
+      *time += start.elapsed();
+    }
+  }
+  let checking: Duration = times.iter().sum();
+  println!(
+    "{} defs: parsed in {:.3?}, checked in {:.3?} on average",
+    names.len(),
+    parsing,
+    checking / RUNS
+  );
+  let mut slowest: Vec<(&Duration, &String)> =
+    times.iter().zip(names.iter()).collect();
+  slowest.sort_by(|a, b| b.0.cmp(a.0));
+  for (time, name) in slowest.into_iter().take(5) {
+    println!("  {:>10.3?} {}", *time / RUNS, name);
+  }
+}
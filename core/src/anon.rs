@@ -6,6 +6,7 @@ use sp_ipld::{
 };
 
 use crate::{
+  eql::EqlOp,
   literal::{
     LitType,
     Literal,
@@ -19,7 +20,7 @@ use std::convert::TryInto;
 #[derive(PartialEq, Clone, Debug)]
 pub enum Anon {
   Var(u64),
-  Lam(Box<Anon>),
+  Lam(Option<(Uses, Box<Anon>)>, Box<Anon>),
   App(Box<(Anon, Anon)>),
   All(Uses, Box<(Anon, Anon)>),
   Slf(Box<Anon>),
@@ -27,16 +28,22 @@ pub enum Anon {
   Cse(Box<Anon>),
   Ref(Cid),
   Let(bool, Uses, Box<(Anon, Anon, Anon)>),
-  Typ,
+  Typ(u64),
   Ann(Box<(Anon, Anon)>),
   Lit(Literal),
   LTy(LitType),
   Opr(Op),
+  Eql(EqlOp),
   Rec,
+  Mut(u64),
+  Hol,
+  // An axiom, identified by the anonymous content id of its type
+  Axm(Cid),
 }
 
 /// var: [0, idx]
 /// lam: [1, <body>]
+/// typed lam: [1, <uses>, <domain>, <body>]
 /// app: [2, <fun>, <arg>]
 
 impl Anon {
@@ -45,7 +52,15 @@ impl Anon {
       Self::Var(idx) => {
         Ipld::List(vec![Ipld::Integer(0), Ipld::Integer(*idx as i128)])
       }
-      Self::Lam(bod) => Ipld::List(vec![Ipld::Integer(1), bod.to_ipld()]),
+      Self::Lam(None, bod) => {
+        Ipld::List(vec![Ipld::Integer(1), bod.to_ipld()])
+      }
+      Self::Lam(Some((uses, dom)), bod) => Ipld::List(vec![
+        Ipld::Integer(1),
+        uses.to_ipld(),
+        dom.to_ipld(),
+        bod.to_ipld(),
+      ]),
       Self::App(fun_arg) => {
         let (fun, arg) = (*fun_arg).as_ref();
         Ipld::List(vec![Ipld::Integer(2), fun.to_ipld(), arg.to_ipld()])
@@ -74,7 +89,12 @@ impl Anon {
           bod.to_ipld(),
         ])
       }
-      Self::Typ => Ipld::List(vec![Ipld::Integer(9)]),
+      // The lowest universe keeps the encoding from before universe levels, so
+      // that the content ids of existing definitions are unchanged
+      Self::Typ(0) => Ipld::List(vec![Ipld::Integer(9)]),
+      Self::Typ(lvl) => {
+        Ipld::List(vec![Ipld::Integer(9), Ipld::Integer(*lvl as i128)])
+      }
       Self::Ann(typ_exp) => {
         let (typ, exp) = (*typ_exp).as_ref();
         Ipld::List(vec![Ipld::Integer(10), typ.to_ipld(), exp.to_ipld()])
@@ -85,6 +105,12 @@ impl Anon {
       Self::LTy(lty) => Ipld::List(vec![Ipld::Integer(12), lty.to_ipld()]),
       Self::Opr(opr) => Ipld::List(vec![Ipld::Integer(13), opr.to_ipld()]),
       Self::Rec => Ipld::List(vec![Ipld::Integer(14)]),
+      Self::Hol => Ipld::List(vec![Ipld::Integer(15)]),
+      Self::Mut(idx) => {
+        Ipld::List(vec![Ipld::Integer(16), Ipld::Integer(*idx as i128)])
+      }
+      Self::Axm(typ) => Ipld::List(vec![Ipld::Integer(17), Ipld::Link(*typ)]),
+      Self::Eql(op) => Ipld::List(vec![Ipld::Integer(18), op.to_ipld()]),
     }
   }
 
@@ -99,7 +125,13 @@ impl Anon {
         }
         [Ipld::Integer(1), bod] => {
           let bod = Anon::from_ipld(bod)?;
-          Ok(Anon::Lam(Box::new(bod)))
+          Ok(Anon::Lam(None, Box::new(bod)))
+        }
+        [Ipld::Integer(1), uses, dom, bod] => {
+          let uses = Uses::from_ipld(uses)?;
+          let dom = Anon::from_ipld(dom)?;
+          let bod = Anon::from_ipld(bod)?;
+          Ok(Anon::Lam(Some((uses, Box::new(dom))), Box::new(bod)))
         }
         [Ipld::Integer(2), fun, arg] => {
           let fun = Anon::from_ipld(fun)?;
@@ -132,7 +164,11 @@ impl Anon {
           let bod = Anon::from_ipld(bod)?;
           Ok(Anon::Let(*rec, uses, Box::new((typ, exp, bod))))
         }
-        [Ipld::Integer(9)] => Ok(Anon::Typ),
+        [Ipld::Integer(9)] => Ok(Anon::Typ(0)),
+        [Ipld::Integer(9), Ipld::Integer(lvl)] if *lvl > 0 => {
+          let lvl: u64 = (*lvl).try_into().map_err(IpldError::U64)?;
+          Ok(Anon::Typ(lvl))
+        }
         [Ipld::Integer(10), typ, exp] => {
           let typ = Anon::from_ipld(typ)?;
           let exp = Anon::from_ipld(exp)?;
@@ -151,6 +187,16 @@ impl Anon {
           Ok(Self::Opr(opr))
         }
         [Ipld::Integer(14)] => Ok(Self::Rec),
+        [Ipld::Integer(15)] => Ok(Self::Hol),
+        [Ipld::Integer(16), Ipld::Integer(idx)] => {
+          let idx: u64 = (*idx).try_into().map_err(IpldError::U64)?;
+          Ok(Self::Mut(idx))
+        }
+        [Ipld::Integer(17), Ipld::Link(typ)] => Ok(Self::Axm(*typ)),
+        [Ipld::Integer(18), op] => {
+          let op = EqlOp::from_ipld(op)?;
+          Ok(Self::Eql(op))
+        }
         xs => Err(IpldError::Anon(Ipld::List(xs.to_owned()))),
       },
       xs => Err(IpldError::Anon(xs.to_owned())),
@@ -183,4 +229,14 @@ pub mod tests {
       _ => false,
     }
   }
+
+  #[test]
+  fn anon_ipld_out_of_range() {
+    for ipld in &[
+      Ipld::List(vec![Ipld::Integer(16), Ipld::Integer(-1)]),
+      Ipld::List(vec![Ipld::Integer(9), Ipld::Integer(1 << 64)]),
+    ] {
+      assert!(Anon::from_ipld(ipld).is_err());
+    }
+  }
 }
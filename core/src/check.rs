@@ -1,13 +1,46 @@
 pub mod ctx;
 pub mod error;
+pub mod hole;
+pub mod pattern;
+pub mod positivity;
+pub mod quantity;
+pub mod termination;
+pub mod unify;
 
 use ctx::*;
 use error::CheckError;
+use hole::Hole;
+use positivity::check_positivity;
+use termination::{
+  check_termination,
+  check_total_refs,
+};
+use unify::{
+  number_holes,
+  same_head,
+  spine,
+  Metas,
+};
 
 use crate::{
-  dag::*,
-  defs::Defs,
+  dag::{
+    hash::{
+      hash,
+      set_dep,
+      Digest,
+    },
+    *,
+  },
+  datatype::record_fields,
+  defs::{
+    Def,
+    Defs,
+  },
   dll::*,
+  eval::{
+    error::EvalError,
+    reduce_lam,
+  },
   literal::Literal,
   name::Name,
   position::Pos,
@@ -26,67 +59,169 @@ use cid::Cid;
 use core::ptr::NonNull;
 use std::collections::HashSet;
 
-pub fn hash(dag: DAGPtr, dep: u64) -> Cid {
-  let mut map = HashMap::new();
-  DAG::dag_ptr_to_term(&dag, &mut map, dep, true).embed().0.cid()
+/// Options of the typechecker
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CheckOpts {
+  /// Gives `Type` the type `Type` and identifies all universes, as Yatima
+  /// did before universe levels, which is inconsistent
+  pub type_in_type: bool,
+}
+
+// Whether a reduction only stopped where its term is stuck, such as at a
+// primitive operation applied to literals it's undefined on, rather than at a
+// reference to a definition missing from the ones checked against
+fn reduced(res: Result<(), EvalError>) -> bool {
+  !matches!(res, Err(EvalError::UndefinedReference(..)))
+}
+
+// Reduces both sides to weak head normal form, unless reducing one of them
+// without unfolding definitions already exposes a hole. The other side is then
+// left as written, so that holes are solved by definitions rather than by
+// their unfoldings. Opaque definitions are never unfolded. Returns whether
+// neither side refers to a missing definition, which makes it incomparable.
+fn whnf_pair(defs: &Defs, metas: &Metas, a: &mut DAG, b: &mut DAG) -> bool {
+  let mut found = true;
+  for dag in [&mut *a, &mut *b] {
+    let unfolds = matches!(spine(dag.head).0, DAGPtr::Ref(_));
+    if !unfolds && !metas.is_flex(dag.head) {
+      found &= reduced(dag.whnf_with(defs, false));
+    }
+  }
+  if !metas.is_flex(a.head) && !metas.is_flex(b.head) {
+    found &= reduced(a.whnf_with(defs, false));
+    found &= reduced(b.whnf_with(defs, false));
+  }
+  found
+}
+
+// A term left for `equal` to compare. Subterms are read from the nodes they
+// are children of only when they're compared, since reducing the terms
+// compared before them replaces and frees the nodes they share.
+#[derive(Clone, Copy)]
+enum Side {
+  Top(DAGPtr),
+  Child(ParentPtr),
 }
 
-pub fn equal(defs: &Defs, a: &mut DAG, b: &mut DAG, dep: u64) -> bool {
-  a.whnf(defs);
-  b.whnf(defs);
-  let mut triples = vec![(a.head, b.head, dep)];
-  let mut set: HashSet<(Cid, Cid)> = HashSet::new();
+impl Side {
+  fn node(self) -> DAGPtr {
+    match self {
+      Self::Top(node) => node,
+      Self::Child(parent) => get_child(parent).unwrap(),
+    }
+  }
+}
+
+pub fn equal(
+  defs: &Defs,
+  metas: &mut Metas,
+  a: &mut DAG,
+  b: &mut DAG,
+  dep: u64,
+) -> bool {
+  if a.head == b.head {
+    return true;
+  }
+  metas.instantiate(a);
+  metas.instantiate(b);
+  let open = metas.is_open(a.head) || metas.is_open(b.head);
+  let reduces = !(open && same_head(a.head, b.head).is_some());
+  if reduces && !whnf_pair(defs, metas, a, b) {
+    return false;
+  }
+  let mut triples = vec![(Side::Top(a.head), Side::Top(b.head), dep)];
+  let mut set: HashSet<(Digest, Digest)> = HashSet::new();
   while let Some((a, b, dep)) = triples.pop() {
+    let (a, b) = (a.node(), b.node());
+    // A node shared by both sides is equal to itself, and reducing it on one
+    // side would free it from under the other
+    if a == b {
+      continue;
+    }
     let mut a = DAG::new(a);
     let mut b = DAG::new(b);
-    a.whnf(defs);
-    b.whnf(defs);
+    metas.instantiate(&mut a);
+    metas.instantiate(&mut b);
+    if metas.is_open(a.head) || metas.is_open(b.head) {
+      if let Some(args) = same_head(a.head, b.head) {
+        triples.extend(
+          args.into_iter().map(|(a, b)| (Side::Child(a), Side::Child(b), dep)),
+        );
+        continue;
+      }
+    }
+    if !whnf_pair(defs, metas, &mut a, &mut b) {
+      return false;
+    }
+    if metas.is_flex(a.head) && metas.solve(a.head, b.head) {
+      continue;
+    }
+    if metas.is_flex(b.head) && metas.solve(b.head, a.head) {
+      continue;
+    }
+    // Unsolved holes are anonymous once hashed, so terms mentioning them are
+    // only compared structurally
+    let open = metas.is_open(a.head) || metas.is_open(b.head);
     let hash_a = hash(a.head, dep);
     let hash_b = hash(b.head, dep);
-    let eq = hash_a == hash_b
-      || set.contains(&(hash_a, hash_b))
-      || set.contains(&(hash_b, hash_a));
-    set.insert((hash_a, hash_b));
+    let eq = !open
+      && (hash_a == hash_b
+        || set.contains(&(hash_a, hash_b))
+        || set.contains(&(hash_b, hash_a)));
+    if !open {
+      set.insert((hash_a, hash_b));
+    }
     if !eq {
       match (a.head, b.head) {
         (DAGPtr::Lam(a_link), DAGPtr::Lam(b_link)) => unsafe {
-          let Lam { bod: a_bod, .. } = *a_link.as_ptr();
-          let Lam { bod: b_bod, .. } = *b_link.as_ptr();
+          // Variables bound while comparing are identified by their depth
+          set_dep(&mut (*a_link.as_ptr()).var, dep);
+          set_dep(&mut (*b_link.as_ptr()).var, dep);
+          let a_bod = Side::Child(ParentPtr::LamBod(a_link));
+          let b_bod = Side::Child(ParentPtr::LamBod(b_link));
           triples.push((a_bod, b_bod, dep + 1));
         },
         (DAGPtr::Slf(a_link), DAGPtr::Slf(b_link)) => unsafe {
-          let Slf { bod: a_bod, .. } = *a_link.as_ptr();
-          let Slf { bod: b_bod, .. } = *b_link.as_ptr();
+          set_dep(&mut (*a_link.as_ptr()).var, dep);
+          set_dep(&mut (*b_link.as_ptr()).var, dep);
+          let a_bod = Side::Child(ParentPtr::SlfBod(a_link));
+          let b_bod = Side::Child(ParentPtr::SlfBod(b_link));
           triples.push((a_bod, b_bod, dep + 1));
         },
-        (DAGPtr::Cse(a_link), DAGPtr::Cse(b_link)) => unsafe {
-          let Cse { bod: a_bod, .. } = *a_link.as_ptr();
-          let Cse { bod: b_bod, .. } = *b_link.as_ptr();
+        (DAGPtr::Cse(a_link), DAGPtr::Cse(b_link)) => {
+          let a_bod = Side::Child(ParentPtr::CseBod(a_link));
+          let b_bod = Side::Child(ParentPtr::CseBod(b_link));
           triples.push((a_bod, b_bod, dep));
-        },
-        (DAGPtr::Dat(a_link), DAGPtr::Dat(b_link)) => unsafe {
-          let Dat { bod: a_bod, .. } = *a_link.as_ptr();
-          let Dat { bod: b_bod, .. } = *b_link.as_ptr();
+        }
+        (DAGPtr::Dat(a_link), DAGPtr::Dat(b_link)) => {
+          let a_bod = Side::Child(ParentPtr::DatBod(a_link));
+          let b_bod = Side::Child(ParentPtr::DatBod(b_link));
           triples.push((a_bod, b_bod, dep));
-        },
+        }
         (DAGPtr::All(a_link), DAGPtr::All(b_link)) => unsafe {
-          let All { uses: a_uses, dom: a_dom, img: a_img, .. } =
-            *a_link.as_ptr();
-          let All { uses: b_uses, dom: b_dom, img: b_img, .. } =
-            *b_link.as_ptr();
-          let a_img = DAGPtr::Lam(a_img);
-          let b_img = DAGPtr::Lam(b_img);
-          if a_uses != b_uses {
+          if (*a_link.as_ptr()).uses != (*b_link.as_ptr()).uses {
             return false;
           }
+          let a_dom = Side::Child(ParentPtr::AllDom(a_link));
+          let b_dom = Side::Child(ParentPtr::AllDom(b_link));
+          let a_img = Side::Child(ParentPtr::AllImg(a_link));
+          let b_img = Side::Child(ParentPtr::AllImg(b_link));
           triples.push((a_dom, b_dom, dep));
-          triples.push((a_img, b_img, dep + 1));
+          triples.push((a_img, b_img, dep));
         },
-        (DAGPtr::App(a_link), DAGPtr::App(b_link)) => unsafe {
-          let App { fun: a_fun, arg: a_arg, .. } = *a_link.as_ptr();
-          let App { fun: b_fun, arg: b_arg, .. } = *b_link.as_ptr();
+        (DAGPtr::App(a_link), DAGPtr::App(b_link)) => {
+          let a_fun = Side::Child(ParentPtr::AppFun(a_link));
+          let b_fun = Side::Child(ParentPtr::AppFun(b_link));
+          let a_arg = Side::Child(ParentPtr::AppArg(a_link));
+          let b_arg = Side::Child(ParentPtr::AppArg(b_link));
           triples.push((a_fun, b_fun, dep));
           triples.push((a_arg, b_arg, dep));
+        }
+        (DAGPtr::Typ(_), DAGPtr::Typ(_)) if metas.opts.type_in_type => (),
+        (DAGPtr::Hol(a_link), DAGPtr::Hol(b_link)) => unsafe {
+          if (*a_link.as_ptr()).idx != (*b_link.as_ptr()).idx {
+            return false;
+          }
         },
         _ => return false,
       }
@@ -95,32 +230,114 @@ pub fn equal(defs: &Defs, a: &mut DAG, b: &mut DAG, dep: u64) -> bool {
   true
 }
 
+// Whether a term of type `b` may be used at type `a`, which is the case when
+// they are equal and, as universes are cumulative, when both are universes
+// and `a` contains `b`, or when both are foralls with the same quantity and
+// equal domains whose codomains are related in the same way. Universes in
+// any other position, such as the arguments of an application, must be equal.
+pub fn subsumes(
+  defs: &Defs,
+  metas: &mut Metas,
+  a: &mut DAG,
+  b: &mut DAG,
+  dep: u64,
+) -> bool {
+  metas.instantiate(a);
+  metas.instantiate(b);
+  if !whnf_pair(defs, metas, a, b) {
+    return false;
+  }
+  match (a.head, b.head) {
+    (DAGPtr::Typ(a_link), DAGPtr::Typ(b_link)) => {
+      let a_lvl = unsafe { (*a_link.as_ptr()).lvl };
+      let b_lvl = unsafe { (*b_link.as_ptr()).lvl };
+      metas.opts.type_in_type || b_lvl <= a_lvl
+    }
+    (DAGPtr::All(a_link), DAGPtr::All(b_link)) => unsafe {
+      let All { uses: a_uses, dom: a_dom, img: a_img, .. } = *a_link.as_ptr();
+      let All { uses: b_uses, dom: b_dom, img: b_img, .. } = *b_link.as_ptr();
+      if a_uses != b_uses
+        || !equal(defs, metas, &mut DAG::new(a_dom), &mut DAG::new(b_dom), dep)
+      {
+        return false;
+      }
+      set_dep(&mut (*a_img.as_ptr()).var, dep);
+      set_dep(&mut (*b_img.as_ptr()).var, dep);
+      let mut a_bod = DAG::new((*a_img.as_ptr()).bod);
+      let mut b_bod = DAG::new((*b_img.as_ptr()).bod);
+      subsumes(defs, metas, &mut a_bod, &mut b_bod, dep + 1)
+    },
+    _ => equal(defs, metas, a, b, dep),
+  }
+}
+
 pub fn check(
   rec: &Option<(Name, Cid, Cid)>,
   defs: &Defs,
+  metas: &mut Metas,
   ctx: &mut Ctx,
   uses: Uses,
   term: &Term,
   typ: &mut DAG,
 ) -> Result<(), CheckError> {
+  // The motive of a case compiled from a `match` is solved from the type the
+  // case is checked against before its branches are checked
+  if let Some((cse, motive)) = case_motive(metas, term) {
+    solve_motive(rec, defs, metas, ctx, cse, motive, typ)?;
+  }
   match term {
-    Term::Lam(pos, _, lam_bod) => {
+    Term::Lam(pos, _, lam_dom, lam_bod) => {
       let lam_bod = &**lam_bod;
-      typ.whnf(defs);
+      metas.whnf(defs, typ, *pos)?;
       match typ.head {
         DAGPtr::All(all_link) => {
           let All { uses: lam_uses, dom, img, .. } =
             unsafe { &mut *all_link.as_ptr() };
           let Lam { var: all_var, bod: img, .. } =
             unsafe { &mut *img.as_ptr() };
+          metas.record_uses(term, *lam_uses);
+          // An annotated binder must agree with the forall it is checked
+          // against
+          if let Some((ann_uses, ann_dom)) = lam_dom {
+            if *ann_uses != *lam_uses {
+              return Err(CheckError::LamUsesMismatch(
+                *pos,
+                error_context(&ctx),
+                all_var.nam.to_string(),
+                *lam_uses,
+                *ann_uses,
+              ));
+            }
+            let mut ann_dom_dag =
+              check_lam_dom(rec, defs, metas, ctx, ann_dom)?;
+            let mut all_dom = DAG::new(*dom);
+            let depth = ctx.len() as u64;
+            let open = metas.is_open(all_dom.head);
+            if !equal(defs, metas, &mut all_dom, &mut ann_dom_dag, depth) {
+              let expected = all_dom.to_term(false);
+              let detected = ann_dom_dag.to_term(false);
+              ann_dom_dag.free();
+              return Err(CheckError::TypeMismatch(
+                ann_dom.pos(),
+                error_context(&ctx),
+                expected,
+                detected,
+                Pos::None,
+              ));
+            }
+            if !open {
+              metas.redundant_annotations.push(*pos);
+            }
+            ann_dom_dag.free();
+          }
           // Annotate the depth of the node that binds each variable
-          (*all_var).dep = ctx.len() as u64;
+          set_dep(all_var, ctx.len() as u64);
           // Add the domain of the function to the context
           let mut img = DAG::new(*img);
           let mut bod_ctx = ctx.clone();
           div_use_ctx(uses, &mut bod_ctx);
           bod_ctx.push((all_var.nam.to_string(), *lam_uses, dom));
-          check(rec, defs, &mut bod_ctx, Uses::Once, lam_bod, &mut img)?;
+          check(rec, defs, metas, &mut bod_ctx, Uses::Once, lam_bod, &mut img)?;
           let (_, rest, _) = bod_ctx.last().unwrap();
           // Have to check whether the rest 'contains' zero (i.e., zero is less
           // than or equal to the rest), otherwise the variable was
@@ -153,7 +370,7 @@ pub fn check(
     }
     Term::Dat(pos, dat_bod) => {
       let dat_bod = &**dat_bod;
-      typ.whnf(defs);
+      metas.whnf(defs, typ, *pos)?;
       match typ.head {
         DAGPtr::Slf(slf_link) => {
           let Slf { var, bod: slf_bod, .. } =
@@ -174,7 +391,8 @@ pub fn check(
           let root = alloc_val(DLL::singleton(ParentPtr::Root));
           let mut new_bod =
             DAG::new(DAG::from_subdag(*slf_bod, &mut map, Some(root)));
-          check(rec, defs, ctx, uses, dat_bod, &mut new_bod)?;
+          record_first_uses(defs, metas, term, &mut new_bod)?;
+          check(rec, defs, metas, ctx, uses, dat_bod, &mut new_bod)?;
           new_bod.free();
           Ok(())
         }
@@ -190,17 +408,57 @@ pub fn check(
         }
       }
     }
-    Term::Let(pos, ..) => Err(CheckError::GenericError(
-      *pos,
-      error_context(&ctx),
-      "TODO: Let typecheck".to_string(),
-    )),
+    Term::Rcd(_, _, bod) => check(rec, defs, metas, ctx, uses, bod, typ),
+    Term::Let(pos, letrec, let_uses, nam, typ_exp_bod) => {
+      let (let_typ, exp, bod) = &**typ_exp_bod;
+      let mut let_typ_dag = check_let_exp(
+        rec, defs, metas, ctx, uses, *letrec, *let_uses, nam, let_typ, exp,
+      )?;
+      // The body is checked as if it were the body of a lambda bound to `exp`
+      let mut bod_ctx = ctx.clone();
+      div_use_ctx(uses, &mut bod_ctx);
+      bod_ctx.push((nam.to_string(), *let_uses, &mut let_typ_dag.head));
+      check(rec, defs, metas, &mut bod_ctx, Uses::Once, bod, typ)?;
+      let (_, rest, _) = bod_ctx.last().unwrap();
+      if !Uses::lte(Uses::None, *rest) {
+        return Err(CheckError::QuantityTooLittle(
+          *pos,
+          error_context(&bod_ctx),
+          nam.to_string(),
+          *let_uses,
+          *rest,
+        ));
+      }
+      bod_ctx.pop();
+      lam_rule(uses, ctx, &bod_ctx);
+      let_typ_dag.free();
+      Ok(())
+    }
+    Term::Hol(pos, nam, idx) => {
+      // A hole fits any type; it is recorded together with its goal so that
+      // it can be solved by unification or filled in later. The motive of a
+      // case is met again after `solve_motive` recorded it.
+      if metas.scope(*idx).is_none() {
+        let dep = ctx.len() as u64;
+        metas.register(*idx, *pos, nam, dep, typ, error_context(ctx));
+      }
+      Ok(())
+    }
     _ => {
       let depth = ctx.len();
+      // An annotated term is redundant where the type it's checked against is
+      // already known
+      let known = matches!(term, Term::Ann(..)) && {
+        metas.instantiate(typ);
+        !metas.is_open(typ.head)
+      };
       // TODO Should we clone ctx?
-      let mut infer_typ = infer(rec, defs, ctx, uses, term)?;
-      let eq = equal(defs, typ, &mut infer_typ, depth as u64);
+      let mut infer_typ = infer(rec, defs, metas, ctx, uses, term)?;
+      let eq = subsumes(defs, metas, typ, &mut infer_typ, depth as u64);
       if eq {
+        if known {
+          metas.redundant_annotations.push(term.pos());
+        }
         infer_typ.free();
         Ok(())
       }
@@ -213,6 +471,7 @@ pub fn check(
           error_context(&ctx),
           expected,
           detected,
+          Pos::None,
         ))
       }
     }
@@ -222,24 +481,30 @@ pub fn check(
 pub fn infer(
   rec: &Option<(Name, Cid, Cid)>,
   defs: &Defs,
+  metas: &mut Metas,
   ctx: &mut Ctx,
   uses: Uses,
   term: &Term,
 ) -> Result<DAG, CheckError> {
   match term {
-    Term::Rec(_) => {
-      if let Some((nam, exp, _)) = rec {
-        if let Some(def) = defs.defs.get(exp) {
-          Ok(DAG::from_term(&def.typ_))
-        }
-        else {
-          panic!("undefined runtime reference: {}, {}", nam, exp);
-        }
-      }
-      else {
-        panic!("undefined recursion");
-      }
+    // References to the other members of a `mutual` block are resolved before
+    // checking, unless the members are missing
+    Term::Mut(pos, nam, _) => {
+      Err(CheckError::UndefinedReference(*pos, nam.to_string()))
+    }
+    // Axioms have no value to infer a type of, and are only referred to
+    Term::Axm(pos, nam, _) => {
+      Err(CheckError::UndefinedReference(*pos, nam.to_string()))
     }
+    // A recursive reference outside of a definition, or to one missing from
+    // `defs`, refers to nothing
+    Term::Rec(pos) => match rec {
+      Some((nam, exp, _)) => match defs.defs.get(exp) {
+        Some(def) => Ok(DAG::from_term(&def.resolve(&def.typ_))),
+        None => Err(CheckError::UndefinedReference(*pos, nam.to_string())),
+      },
+      None => Err(CheckError::UndefinedReference(*pos, "#^".to_owned())),
+    },
     Term::Var(pos, nam, idx) => {
       let dep = ctx.len() - 1 - (*idx as usize);
       let bind = ctx.get(dep).ok_or_else(|| {
@@ -273,49 +538,17 @@ pub fn infer(
         .defs
         .get(def_link)
         .ok_or_else(|| CheckError::UndefinedReference(*pos, nam.to_string()))?;
-      let typ = DAG::from_term(&def.typ_);
+      let typ = DAG::from_term(&def.resolve(&def.typ_));
       Ok(typ)
     }
     Term::App(pos, fun_arg) => {
       let (fun, arg) = &**fun_arg;
-      let mut fun_typ = infer(rec, defs, ctx, uses, fun)?;
-      fun_typ.whnf(defs);
-      match fun_typ.head {
-        DAGPtr::All(link) => {
-          let All { uses: lam_uses, dom, img, .. } =
-            unsafe { &mut *link.as_ptr() };
-          let Lam { var, bod: img, .. } = unsafe { &mut *img.as_ptr() };
-          check(rec, defs, ctx, *lam_uses * uses, arg, &mut DAG::new(*dom))?;
-          let mut map = HashMap::new();
-          if var.parents.is_some() {
-            map.insert(
-              DAGPtr::Var(NonNull::new(var).unwrap()),
-              DAG::from_term_inner(
-                arg,
-                ctx.len() as u64,
-                VecDeque::new(),
-                None,
-                rec.clone(),
-              ),
-            );
-          }
-          let root = alloc_val(DLL::singleton(ParentPtr::Root));
-          let new_img = DAG::from_subdag(*img, &mut map, Some(root));
-          fun_typ.free();
-          Ok(DAG::new(new_img))
-        }
-        _ => Err(CheckError::AppFunMismatch(
-          *pos,
-          error_context(&ctx),
-          fun.clone(),
-          fun_typ.to_term(false),
-        )),
-      }
+      infer_app(rec, defs, metas, ctx, uses, term, *pos, fun, arg)
     }
     Term::Cse(pos, exp) => {
       let exp = &**exp;
-      let mut exp_typ = infer(rec, defs, ctx, uses, exp)?;
-      exp_typ.whnf(defs);
+      let mut exp_typ = infer(rec, defs, metas, ctx, uses, exp)?;
+      metas.whnf(defs, &mut exp_typ, *pos)?;
       match exp_typ.head {
         DAGPtr::Slf(link) => {
           let Slf { var, bod, .. } = unsafe { &mut *link.as_ptr() };
@@ -333,9 +566,11 @@ pub fn infer(
             );
           }
           let root = alloc_val(DLL::singleton(ParentPtr::Root));
-          let new_bod = DAG::from_subdag(*bod, &mut map, Some(root));
+          let mut new_bod =
+            DAG::new(DAG::from_subdag(*bod, &mut map, Some(root)));
           exp_typ.free();
-          Ok(DAG::new(new_bod))
+          record_first_uses(defs, metas, term, &mut new_bod)?;
+          Ok(new_bod)
         }
         DAGPtr::LTy(link) => {
           let LTy { lty, .. } = unsafe { &mut *link.as_ptr() };
@@ -347,14 +582,15 @@ pub fn infer(
               *lty,
             )),
             Some(ind) => {
-              let induction = DAG::from_term_inner(
+              let mut induction = DAG::new(DAG::from_term_inner(
                 &ind,
                 ctx.len() as u64,
                 VecDeque::new(),
                 Some(root),
                 None,
-              );
-              Ok(DAG::new(induction))
+              ));
+              record_first_uses(defs, metas, term, &mut induction)?;
+              Ok(induction)
             }
           }
         }
@@ -366,10 +602,9 @@ pub fn infer(
         )),
       }
     }
-    Term::All(_, _, nam, dom_img) => {
+    Term::All(_, _, _, nam, dom_img) => {
       let (dom, img) = &**dom_img;
-      let mut typ = DAG::from_term(&Term::Typ(Pos::None));
-      check(rec, defs, ctx, Uses::None, dom, &mut typ)?;
+      let dom_lvl = infer_sort(rec, defs, metas, ctx, dom)?;
       let mut dom_dag = DAG::from_term_inner(
         dom,
         ctx.len() as u64,
@@ -378,14 +613,16 @@ pub fn infer(
         rec.clone(),
       );
       ctx.push((nam.to_string(), Uses::None, &mut dom_dag));
-      check(rec, defs, ctx, Uses::None, img, &mut typ)?;
+      let img_lvl = infer_sort(rec, defs, metas, ctx, img)?;
       ctx.pop();
       free_dead_node(dom_dag);
-      Ok(typ)
+      // The lowest universe is impredicative, so that Self encodings, which
+      // quantify over their motives, are types of it like their fields
+      let lvl = if img_lvl == 0 { 0 } else { dom_lvl.max(img_lvl) };
+      Ok(DAG::from_term(&Term::Typ(Pos::None, lvl)))
     }
     Term::Slf(_, nam, bod) => {
       let bod = &**bod;
-      let mut typ = DAG::from_term(&Term::Typ(Pos::None));
       let mut term_dag = DAG::from_term_inner(
         term,
         ctx.len() as u64,
@@ -394,17 +631,32 @@ pub fn infer(
         rec.clone(),
       );
       ctx.push((nam.to_string(), Uses::None, &mut term_dag));
-      check(rec, defs, ctx, Uses::None, bod, &mut typ)?;
+      let lvl = infer_sort(rec, defs, metas, ctx, bod)?;
       ctx.pop();
       free_dead_node(term_dag);
-      Ok(typ)
+      Ok(DAG::from_term(&Term::Typ(Pos::None, lvl)))
     }
-    Term::Typ(_) => {
-      let typ = DAG::from_term(&Term::Typ(Pos::None));
-      Ok(typ)
+    Term::Prj(pos, field, prj_arg) => match prj_arg.as_ref() {
+      (Term::Hol(_, nam, idx), arg) if nam.is_empty() => {
+        infer_field(rec, defs, metas, ctx, uses, term, *pos, field, *idx, arg)
+      }
+      (prj, arg) => {
+        infer_app(rec, defs, metas, ctx, uses, term, *pos, prj, arg)
+      }
+    },
+    Term::Rcd(_, _, bod) => infer(rec, defs, metas, ctx, uses, bod),
+    Term::Typ(pos, lvl) => {
+      let lvl = if metas.opts.type_in_type {
+        *lvl
+      }
+      else {
+        lvl.checked_add(1).ok_or(CheckError::UniverseTooLarge(*pos, *lvl))?
+      };
+      Ok(DAG::from_term(&Term::Typ(Pos::None, lvl)))
     }
     Term::Ann(_, typ_exp) => {
       let (typ, exp) = &**typ_exp;
+      infer_sort(rec, defs, metas, ctx, typ)?;
       let root = alloc_val(DLL::singleton(ParentPtr::Root));
       let mut typ_dag = DAG::new(DAG::from_term_inner(
         typ,
@@ -413,24 +665,541 @@ pub fn infer(
         Some(root),
         rec.clone(),
       ));
-      check(rec, defs, ctx, uses, exp, &mut typ_dag)?;
+      // The annotation is the type whether or not the expression fits it
+      let res = check(rec, defs, metas, ctx, uses, exp, &mut typ_dag)
+        .map_err(|err| err.expected_from(exp.pos(), typ.pos()));
+      metas.recover(res);
       Ok(typ_dag)
     }
-    Term::Let(..) => {
-      panic!("TODO: Let inference")
+    Term::Let(pos, letrec, let_uses, nam, typ_exp_bod) => {
+      let (let_typ, exp, bod) = &**typ_exp_bod;
+      let mut let_typ_dag = check_let_exp(
+        rec, defs, metas, ctx, uses, *letrec, *let_uses, nam, let_typ, exp,
+      )?;
+      let level = ctx.len() as u64;
+      let mut bod_ctx = ctx.clone();
+      div_use_ctx(uses, &mut bod_ctx);
+      bod_ctx.push((nam.to_string(), *let_uses, &mut let_typ_dag.head));
+      let mut bod_typ = infer(rec, defs, metas, &mut bod_ctx, Uses::Once, bod)?;
+      let (_, rest, _) = bod_ctx.last().unwrap();
+      if !Uses::lte(Uses::None, *rest) {
+        return Err(CheckError::QuantityTooLittle(
+          *pos,
+          error_context(&bod_ctx),
+          nam.to_string(),
+          *let_uses,
+          *rest,
+        ));
+      }
+      bod_ctx.pop();
+      lam_rule(uses, ctx, &bod_ctx);
+      let_typ_dag.free();
+      // The type of the body may depend on the bound variable, which goes out
+      // of scope, so it is replaced by its definition
+      let val = if *letrec {
+        let var = Term::Var(*pos, nam.clone(), 0);
+        Term::Let(
+          *pos,
+          true,
+          *let_uses,
+          nam.clone(),
+          Box::new((let_typ.clone(), exp.clone(), var)),
+        )
+      }
+      else {
+        exp.clone()
+      };
+      subst_free_var(rec, &mut bod_typ, level, &val);
+      Ok(bod_typ)
     }
     Term::Lit(_, lit) => Ok(DAG::from_term(&infer_lit(lit.to_owned()))),
     Term::LTy(..) => Ok(DAG::from_term(&yatima!("Type"))),
     Term::Opr(_, opr) => Ok(DAG::from_term(&opr.type_of())),
+    Term::Eql(_, op) => Ok(DAG::from_term(&op.type_of())),
+    Term::Lam(pos, nam, Some((lam_uses, dom)), bod) => {
+      metas.record_uses(term, *lam_uses);
+      let level = ctx.len() as u64;
+      let mut dom_dag = check_lam_dom(rec, defs, metas, ctx, dom)?;
+      let mut bod_ctx = ctx.clone();
+      div_use_ctx(uses, &mut bod_ctx);
+      bod_ctx.push((nam.to_string(), *lam_uses, &mut dom_dag.head));
+      let img = infer(rec, defs, metas, &mut bod_ctx, Uses::Once, bod)?;
+      let (_, rest, _) = bod_ctx.last().unwrap();
+      if !Uses::lte(Uses::None, *rest) {
+        return Err(CheckError::QuantityTooLittle(
+          *pos,
+          error_context(&bod_ctx),
+          nam.to_string(),
+          *lam_uses,
+          *rest,
+        ));
+      }
+      bod_ctx.pop();
+      lam_rule(uses, ctx, &bod_ctx);
+      Ok(abstract_all(*lam_uses, nam, level, dom_dag, img))
+    }
     Term::Lam(..) => {
       Err(CheckError::UntypedLambda(term.pos(), error_context(&ctx)))
     }
-    Term::Dat(..) => {
-      Err(CheckError::UntypedData(term.pos(), error_context(&ctx)))
+    Term::Hol(pos, nam, _) => {
+      Err(CheckError::UntypedHole(*pos, error_context(&ctx), nam.to_string()))
+    }
+    Term::Dat(_, bod) => {
+      // Without an expected type, the best we can synthesize for `data x` is
+      // the non-dependent self type `@_ A` where `x : A`
+      let mut bod_typ = infer(rec, defs, metas, ctx, uses, bod)?;
+      record_first_uses(defs, metas, term, &mut bod_typ)?;
+      unsafe {
+        detach_root(&bod_typ);
+        let root = alloc_val(DLL::singleton(ParentPtr::Root));
+        let dep = ctx.len() as u64;
+        let slf =
+          alloc_slf(Name::from("_"), dep, None, bod_typ.head, Some(root));
+        let Slf { bod_ref, .. } = &mut *slf.as_ptr();
+        set_parents(bod_typ.head, NonNull::new(bod_ref));
+        Ok(DAG::new(DAGPtr::Slf(slf)))
+      }
     }
   }
 }
 
+// The codomain `img` of a forall whose variable is `var`, with the argument
+// `arg` it is applied to substituted for the variable
+fn apply_img(
+  rec: &Option<(Name, Cid, Cid)>,
+  ctx: &Ctx,
+  var: &mut Var,
+  img: DAGPtr,
+  arg: &Term,
+) -> DAG {
+  let mut map = HashMap::new();
+  if var.parents.is_some() {
+    map.insert(
+      DAGPtr::Var(NonNull::new(var).unwrap()),
+      DAG::from_term_inner(
+        arg,
+        ctx.len() as u64,
+        VecDeque::new(),
+        None,
+        rec.clone(),
+      ),
+    );
+  }
+  let root = alloc_val(DLL::singleton(ParentPtr::Root));
+  DAG::new(DAG::from_subdag(img, &mut map, Some(root)))
+}
+
+// The case and the motive of `term` if it's a case applied to a motive that is
+// an unsolved anonymous hole, as `match` leaves them
+fn case_motive<'a>(
+  metas: &Metas,
+  term: &'a Term,
+) -> Option<(&'a Term, &'a Term)> {
+  let (mut fun, mut motive) = (term, None);
+  while let Term::App(_, fun_arg) = fun {
+    fun = &fun_arg.0;
+    motive = Some(&fun_arg.1);
+  }
+  match (fun, motive) {
+    (Term::Cse(..), Some(hol @ Term::Hol(_, nam, idx)))
+      if nam.is_empty() && !metas.is_solved(*idx) =>
+    {
+      Some((fun, hol))
+    }
+    _ => None,
+  }
+}
+
+// Solves the motive hole of the case `cse` by abstracting `typ`, the type the
+// case applied to its branches is checked against, over the scrutinee, which
+// makes the type each branch is checked against depend on its constructor.
+// The motive is left unsolved if the scrutinee isn't a variable.
+fn solve_motive(
+  rec: &Option<(Name, Cid, Cid)>,
+  defs: &Defs,
+  metas: &mut Metas,
+  ctx: &mut Ctx,
+  cse: &Term,
+  motive: &Term,
+  typ: &mut DAG,
+) -> Result<(), CheckError> {
+  let (pos, scrut) = match cse {
+    Term::Cse(pos, scrut) => (*pos, &**scrut),
+    _ => return Ok(()),
+  };
+  let mut cse_typ = infer(rec, defs, metas, ctx, Uses::None, cse)?;
+  metas.whnf(defs, &mut cse_typ, pos)?;
+  if let DAGPtr::All(link) = cse_typ.head {
+    let mut dom = DAG::new(unsafe { (*link.as_ptr()).dom });
+    check(rec, defs, metas, ctx, Uses::None, motive, &mut dom)?;
+    metas.instantiate(typ);
+    // The type is the motive of an enclosing case applied to a constructor
+    // when the case is a branch of it
+    while let DAGPtr::App(app) = typ.head {
+      match unsafe { (*app.as_ptr()).fun } {
+        DAGPtr::Lam(lam) => typ.head = reduce_lam(app, lam),
+        _ => break,
+      }
+    }
+    let flex = Term::App(pos, Box::new((motive.clone(), scrut.clone())));
+    let root = alloc_val(DLL::singleton(ParentPtr::Root));
+    let flex = DAG::new(DAG::from_term_inner(
+      &flex,
+      ctx.len() as u64,
+      VecDeque::new(),
+      Some(root),
+      rec.clone(),
+    ));
+    metas.solve(flex.head, typ.head);
+    flex.free();
+  }
+  cse_typ.free();
+  Ok(())
+}
+
+// Infers the type of `term`, the application of `fun` to `arg`
+#[allow(clippy::too_many_arguments)]
+fn infer_app(
+  rec: &Option<(Name, Cid, Cid)>,
+  defs: &Defs,
+  metas: &mut Metas,
+  ctx: &mut Ctx,
+  uses: Uses,
+  term: &Term,
+  pos: Pos,
+  fun: &Term,
+  arg: &Term,
+) -> Result<DAG, CheckError> {
+  let mut fun_typ = infer(rec, defs, metas, ctx, uses, fun)?;
+  metas.whnf(defs, &mut fun_typ, pos)?;
+  match fun_typ.head {
+    DAGPtr::All(link) => {
+      let All { uses: lam_uses, dom, img, .. } = unsafe { &mut *link.as_ptr() };
+      let Lam { var, bod: img, .. } = unsafe { &mut *img.as_ptr() };
+      metas.record_uses(term, *lam_uses);
+      let arg_uses = *lam_uses * uses;
+      // The type of the application doesn't depend on the argument being
+      // well-typed, so an error in it doesn't stop the check
+      let mut dom = DAG::new(*dom);
+      let res = check(rec, defs, metas, ctx, arg_uses, arg, &mut dom)
+        .map_err(|err| err.expected_from(arg.pos(), fun.pos()));
+      metas.recover(res);
+      let new_img = apply_img(rec, ctx, var, *img, arg);
+      fun_typ.free();
+      Ok(new_img)
+    }
+    _ => Err(CheckError::AppFunMismatch(
+      pos,
+      error_context(ctx),
+      fun.clone(),
+      fun_typ.to_term(false),
+    )),
+  }
+}
+
+// Infers the type of `term`, the projection of a field several records have
+// out of `arg` by the anonymous hole `idx`. The record is the one the type of
+// `arg` is an instance of, and the hole is solved by its projection applied to
+// the parameters of that type.
+#[allow(clippy::too_many_arguments)]
+fn infer_field(
+  rec: &Option<(Name, Cid, Cid)>,
+  defs: &Defs,
+  metas: &mut Metas,
+  ctx: &mut Ctx,
+  uses: Uses,
+  term: &Term,
+  pos: Pos,
+  field: &Name,
+  idx: u64,
+  arg: &Term,
+) -> Result<DAG, CheckError> {
+  // Projections take the record they project out of any number of times
+  let mut arg_typ = infer(rec, defs, metas, ctx, Uses::Many * uses, arg)?;
+  metas.instantiate(&mut arg_typ);
+  let dep = ctx.len() as u64;
+  let (head, params) = match arg {
+    // The type inferred for an annotated term may come back unfolded, so the
+    // record is read from the annotation as written
+    Term::Ann(_, typ_exp) => {
+      let (mut typ, mut params) = (&typ_exp.0, vec![]);
+      while let Term::App(_, fun_arg) = typ {
+        params.push(fun_arg.1.clone());
+        typ = &fun_arg.0;
+      }
+      params.reverse();
+      (typ.clone(), params)
+    }
+    _ => {
+      let (head, params) = spine(arg_typ.head);
+      let head = metas.to_term(&DAG::new(head), dep, false);
+      let params = params
+        .iter()
+        .map(|param| metas.to_term(&DAG::new(*param), dep, false))
+        .collect();
+      (head, params)
+    }
+  };
+  let prj = match &head {
+    Term::Ref(_, typ, ..) => {
+      let nam = Name::from(format!("{}.{}", typ, field));
+      let has_field =
+        matches!(record_fields(defs, typ), Some(fs) if fs.contains(field));
+      defs.get(&nam).filter(|_| has_field).map(|def| (nam, def))
+    }
+    _ => None,
+  };
+  let (prj_nam, prj) = match prj {
+    Some(prj) => prj,
+    None => {
+      let err = CheckError::AmbiguousField(
+        pos,
+        error_context(ctx),
+        field.to_string(),
+        arg_typ.to_term(false),
+      );
+      arg_typ.free();
+      return Err(err);
+    }
+  };
+  let fun = params.into_iter().fold(
+    Term::Ref(pos, prj_nam, prj.def_cid, prj.ast_cid),
+    |fun, param| Term::App(pos, Box::new((fun, param))),
+  );
+  let mut fun_typ = infer(rec, defs, metas, ctx, Uses::None, &fun)?;
+  metas.whnf(defs, &mut fun_typ, pos)?;
+  let res = match fun_typ.head {
+    DAGPtr::All(link) => {
+      let All { uses: lam_uses, dom, img, .. } = unsafe { &mut *link.as_ptr() };
+      let Lam { var, bod: img, .. } = unsafe { &mut *img.as_ptr() };
+      metas.record_uses(term, *lam_uses);
+      let mut dom = DAG::new(*dom);
+      if subsumes(defs, metas, &mut dom, &mut arg_typ, dep) {
+        Ok(apply_img(rec, ctx, var, *img, arg))
+      }
+      else {
+        Err(CheckError::TypeMismatch(
+          arg.pos(),
+          error_context(ctx),
+          dom.to_term(false),
+          arg_typ.to_term(false),
+          Pos::None,
+        ))
+      }
+    }
+    _ => Err(CheckError::AppFunMismatch(
+      pos,
+      error_context(ctx),
+      fun.clone(),
+      fun_typ.to_term(false),
+    )),
+  };
+  if res.is_ok() {
+    let root = alloc_val(DLL::singleton(ParentPtr::Root));
+    let sol =
+      DAG::from_term_inner(&fun, dep, VecDeque::new(), Some(root), None);
+    metas.solutions.insert(idx, DAG::new(sol));
+  }
+  fun_typ.free();
+  arg_typ.free();
+  res
+}
+
+// Records the quantity of the first forall of the Self type a `data` or `case`
+// term is met at, which decides whether erasure keeps the motive of its Self
+// encoding
+fn record_first_uses(
+  defs: &Defs,
+  metas: &mut Metas,
+  term: &Term,
+  typ: &mut DAG,
+) -> Result<(), CheckError> {
+  metas.whnf(defs, typ, term.pos())?;
+  if let DAGPtr::All(link) = typ.head {
+    let uses = unsafe { (*link.as_ptr()).uses };
+    metas.record_uses(term, uses);
+  }
+  Ok(())
+}
+
+// Checks that a term is a type, returning the level of its universe
+pub fn infer_sort(
+  rec: &Option<(Name, Cid, Cid)>,
+  defs: &Defs,
+  metas: &mut Metas,
+  ctx: &mut Ctx,
+  term: &Term,
+) -> Result<u64, CheckError> {
+  let mut sort = DAG::from_term(&Term::Typ(Pos::None, 0));
+  // A hole standing for a type can only be given one of the lowest universe
+  if let Term::Hol(..) = term {
+    check(rec, defs, metas, ctx, Uses::None, term, &mut sort)?;
+    sort.free();
+    return Ok(0);
+  }
+  let mut typ = infer(rec, defs, metas, ctx, Uses::None, term)?;
+  metas.whnf(defs, &mut typ, term.pos())?;
+  let lvl = match typ.head {
+    DAGPtr::Typ(link) => Some(unsafe { (*link.as_ptr()).lvl }),
+    _ if metas.is_flex(typ.head) => {
+      equal(defs, metas, &mut sort, &mut typ, ctx.len() as u64).then(|| 0)
+    }
+    _ => None,
+  };
+  let res = lvl.ok_or_else(|| {
+    CheckError::TypeMismatch(
+      term.pos(),
+      error_context(&ctx),
+      Term::Typ(Pos::None, 0),
+      typ.to_term(false),
+      Pos::None,
+    )
+  });
+  sort.free();
+  typ.free();
+  res
+}
+
+// Checks that the annotated domain of a lambda is a type, returning its DAG
+pub fn check_lam_dom(
+  rec: &Option<(Name, Cid, Cid)>,
+  defs: &Defs,
+  metas: &mut Metas,
+  ctx: &mut Ctx,
+  dom: &Term,
+) -> Result<DAG, CheckError> {
+  infer_sort(rec, defs, metas, ctx, dom)?;
+  let root = alloc_val(DLL::singleton(ParentPtr::Root));
+  Ok(DAG::new(DAG::from_term_inner(
+    dom,
+    ctx.len() as u64,
+    VecDeque::new(),
+    Some(root),
+    rec.clone(),
+  )))
+}
+
+// Removes the root parent of a DAG so that its head can be installed as the
+// child of another node
+pub(crate) unsafe fn detach_root(dag: &DAG) {
+  if let Some(pref) = get_parents(dag.head) {
+    drop(Box::from_raw(pref.as_ptr()));
+    set_parents(dag.head, None);
+  }
+}
+
+// Builds the DAG of `∀ (uses nam: dom) -> img`, binding the free variables of
+// `img` at the given depth to the new binder
+pub fn abstract_all(
+  uses: Uses,
+  nam: &Name,
+  dep: u64,
+  dom: DAG,
+  img: DAG,
+) -> DAG {
+  let vars = free_vars_at(img.head, dep);
+  unsafe {
+    detach_root(&dom);
+    detach_root(&img);
+    let lam = alloc_lam(nam.clone(), dep, None, img.head, None);
+    let Lam { var, bod_ref, .. } = &mut *lam.as_ptr();
+    set_parents(img.head, NonNull::new(bod_ref));
+    let var = DAGPtr::Var(NonNull::new(var).unwrap());
+    for free in vars {
+      replace_child(free, var);
+      free_dead_node(free);
+    }
+    let root = alloc_val(DLL::singleton(ParentPtr::Root));
+    let all = alloc_all(false, uses, dom.head, lam, Some(root));
+    let All { dom_ref, img_ref, .. } = &mut *all.as_ptr();
+    set_parents(dom.head, NonNull::new(dom_ref));
+    (*lam.as_ptr()).parents = NonNull::new(img_ref);
+    DAG::new(DAGPtr::All(all))
+  }
+}
+
+// Checks the type annotation and the bound expression of a `let` or `letrec`,
+// returning the DAG of the annotated type. An error in the expression is
+// recorded in `metas`, since the body can still be checked against the
+// annotation.
+#[allow(clippy::too_many_arguments)]
+pub fn check_let_exp(
+  rec: &Option<(Name, Cid, Cid)>,
+  defs: &Defs,
+  metas: &mut Metas,
+  ctx: &mut Ctx,
+  uses: Uses,
+  letrec: bool,
+  let_uses: Uses,
+  nam: &Name,
+  typ: &Term,
+  exp: &Term,
+) -> Result<DAG, CheckError> {
+  infer_sort(rec, defs, metas, ctx, typ)?;
+  let root = alloc_val(DLL::singleton(ParentPtr::Root));
+  let mut typ_dag = DAG::new(DAG::from_term_inner(
+    typ,
+    ctx.len() as u64,
+    VecDeque::new(),
+    Some(root),
+    rec.clone(),
+  ));
+  if letrec {
+    // A recursive expression may refer to itself any number of times, and so
+    // may every variable it captures
+    let mut exp_typ = typ_dag.clone();
+    ctx.push((nam.to_string(), Uses::Many, &mut typ_dag.head));
+    let exp_uses = Uses::Many * let_uses * uses;
+    let res = check(rec, defs, metas, ctx, exp_uses, exp, &mut exp_typ);
+    ctx.pop();
+    exp_typ.free();
+    metas.recover(res.map_err(|err| err.expected_from(exp.pos(), typ.pos())));
+  }
+  else {
+    let mut exp_typ = typ_dag.clone();
+    let res = check(rec, defs, metas, ctx, let_uses * uses, exp, &mut exp_typ);
+    exp_typ.free();
+    metas.recover(res.map_err(|err| err.expected_from(exp.pos(), typ.pos())));
+  }
+  Ok(typ_dag)
+}
+
+// Replaces the free variable at the given depth by `val`
+pub fn subst_free_var(
+  rec: &Option<(Name, Cid, Cid)>,
+  dag: &mut DAG,
+  dep: u64,
+  val: &Term,
+) {
+  let vars = free_vars_at(dag.head, dep);
+  if vars.is_empty() {
+    return;
+  }
+  let val = DAG::from_term_inner(val, dep, VecDeque::new(), None, rec.clone());
+  for var in vars {
+    if dag.head == var {
+      dag.head = val;
+    }
+    replace_child(var, val);
+    free_dead_node(var);
+  }
+}
+
+// Collects the free variables of a DAG which stand for the binder at the given
+// depth
+pub fn free_vars_at(node: DAGPtr, dep: u64) -> Vec<DAGPtr> {
+  outer_vars(node)
+    .into_iter()
+    .filter(|var| match var {
+      DAGPtr::Var(link) => unsafe {
+        let Var { binder, dep: var_dep, .. } = link.as_ref();
+        *binder == BinderPtr::Free && *var_dep == dep
+      },
+      _ => false,
+    })
+    .collect()
+}
+
 pub fn infer_lit(lit: Literal) -> Term {
   match lit {
     Literal::Nat(_) => yatima!("#Nat"),
@@ -453,23 +1222,633 @@ pub fn infer_lit(lit: Literal) -> Term {
   }
 }
 
-pub fn infer_term(defs: &Defs, term: Term) -> Result<Term, CheckError> {
-  let typ_dag = infer(&None, &defs, &mut vec![].into(), Uses::Once, &term)?;
-  let typ = DAG::to_term(&typ_dag, true);
+pub fn infer_term(
+  defs: &Defs,
+  term: Term,
+) -> Result<(Term, Vec<Hole>), CheckError> {
+  let mut term = term;
+  number_holes(&mut term, &mut 1);
+  let mut metas = Metas::new();
+  let typ_dag =
+    infer(&None, &defs, &mut metas, &mut vec![].into(), Uses::Once, &term)?;
+  let typ = metas.to_term(&typ_dag, 0, true);
+  typ_dag.free();
+  Ok((typ, metas.holes()))
+}
+
+/// Infers the type of a term like `infer_term`, returning the term with its
+/// solved anonymous holes filled in, like `elaborate_def`
+pub fn elaborate_term(defs: &Defs, term: Term) -> Result<Term, CheckError> {
+  let mut term = term;
+  number_holes(&mut term, &mut 1);
+  let mut metas = Metas::new();
+  let typ_dag = infer(&None, defs, &mut metas, &mut vec![], Uses::Once, &term)?;
   typ_dag.free();
-  Ok(typ)
+  Ok(metas.zonk(&term, 0))
 }
 
-pub fn check_def(defs: &Defs, name: &str) -> Result<Term, CheckError> {
+// Checks that `typ_` is a type and that `term` has that type. Holes in the
+// type are met too, so that they are reported and may be solved.
+pub fn check_typed_def(
+  rec: &Option<(Name, Cid, Cid)>,
+  defs: &Defs,
+  metas: &mut Metas,
+  typ_: &Term,
+  term: &Term,
+) -> Result<(), CheckError> {
+  infer_sort(rec, defs, metas, &mut vec![].into(), typ_)?;
+  // An axiom only needs a type
+  if let Term::Axm(..) = term {
+    return Ok(());
+  }
+  let mut typ = DAG::from_term(typ_);
+  let ctx = &mut vec![].into();
+  check(rec, defs, metas, ctx, Uses::Once, term, &mut typ)
+    .map_err(|err| err.expected_from(term.pos(), typ_.pos()))?;
+  typ.free();
+  Ok(())
+}
+
+pub fn check_def(
+  defs: &Defs,
+  name: &str,
+) -> Result<(Term, Vec<Hole>), CheckError> {
+  check_def_with(defs, name, CheckOpts::default())
+}
+
+pub fn check_def_with(
+  defs: &Defs,
+  name: &str,
+  opts: CheckOpts,
+) -> Result<(Term, Vec<Hole>), CheckError> {
+  check_def_all(defs, name, opts).map_err(|mut errs| errs.swap_remove(0))
+}
+
+/// Checks a definition like `check_def_with`, but reports every error found
+/// in it instead of only the first. Independent parts of the definition, such
+/// as the arguments of an application or its termination and positivity, are
+/// checked even after one of them fails. The errors are never empty.
+pub fn check_def_all(
+  defs: &Defs,
+  name: &str,
+  opts: CheckOpts,
+) -> Result<(Term, Vec<Hole>), Vec<CheckError>> {
+  let (def, _, _, metas) = check_def_metas(defs, name, opts)?;
+  Ok((def.typ_.clone(), metas.holes()))
+}
+
+// Checks a definition like `check_def_all`, returning its type and value as
+// they were checked, with their references to the other members of its
+// `mutual` block resolved and their holes numbered, along with the
+// metavariable store it was checked with
+pub(crate) fn check_def_metas<'a>(
+  defs: &'a Defs,
+  name: &str,
+  opts: CheckOpts,
+) -> Result<(&'a Def, Term, Term, Metas), Vec<CheckError>> {
   let def = defs.get(&Name::from(name)).ok_or_else(|| {
-    CheckError::UndefinedReference(Pos::None, name.to_owned())
+    vec![CheckError::UndefinedReference(Pos::None, name.to_owned())]
   })?;
+  let (typ_, term, metas) = check_named_def(defs, name, def, opts)?;
+  Ok((def, typ_, term, metas))
+}
+
+// Checks `def` like `check_def_metas` as the definition `name` of `defs`,
+// which it needn't be, such as to try changes to it without making new
+// definitions
+pub(crate) fn check_named_def(
+  defs: &Defs,
+  name: &str,
+  def: &Def,
+  opts: CheckOpts,
+) -> Result<(Term, Term, Metas), Vec<CheckError>> {
   let (d, _, a) = def.embed();
   let def_cid = d.cid();
   let ast_cid = a.cid();
   let rec = Some((Name::from(name), def_cid, ast_cid));
-  let mut typ = DAG::from_term(&def.typ_);
-  check(&rec, &defs, &mut vec![].into(), Uses::Once, &def.term, &mut typ)?;
-  typ.free();
-  Ok(def.typ_.clone())
+  let mut typ_ = def.resolve(&def.typ_).into_owned();
+  let mut term = def.resolve(&def.term).into_owned();
+  let mut next = 1;
+  number_holes(&mut typ_, &mut next);
+  number_holes(&mut term, &mut next);
+  let mut metas = Metas::with_opts(opts);
+  let res = check_typed_def(&rec, &defs, &mut metas, &typ_, &term);
+  metas.record_paths(&term);
+  let mut errs = std::mem::take(&mut metas.errors);
+  errs.extend(res.err());
+  // Definitions marked `partial` may recurse without restriction
+  if !def.partial {
+    let name = Name::from(name);
+    errs.extend(check_positivity(&name, &def.term).err());
+    errs.extend(
+      check_termination(&defs, &recursive_members(defs, name, def)).err(),
+    );
+    errs.extend(check_total_refs(defs, &def.typ_).err());
+    errs.extend(check_total_refs(defs, &def.term).err());
+  }
+  if errs.is_empty() {
+    Ok((typ_, term, metas))
+  }
+  else {
+    Err(errs)
+  }
+}
+
+// The definitions a definition recurses through, which are the total members
+// of its `mutual` block if it is in one, with their names
+fn recursive_members<'a>(
+  defs: &'a Defs,
+  name: Name,
+  def: &'a Def,
+) -> Vec<(Name, &'a Term)> {
+  match &def.mutual {
+    None => vec![(name, &def.term)],
+    Some(mutual) => mutual
+      .members
+      .iter()
+      .filter_map(|(def_cid, _)| {
+        let member = defs.defs.get(def_cid).filter(|def| !def.partial)?;
+        let name = defs
+          .names
+          .iter()
+          .find(|(_, cid)| *cid == def_cid)
+          .map_or_else(|| Name::from("#^"), |(name, _)| name.clone());
+        Some((name, &member.term))
+      })
+      .collect(),
+  }
+}
+
+/// Checks a definition and returns it with its solved anonymous holes, such
+/// as the arguments inserted for implicit parameters, filled in, so that its
+/// value is fully explicit. It keeps the content ids it was written with,
+/// which the references to it are made by, and its references to the other
+/// members of its `mutual` block are resolved.
+pub fn elaborate_def(
+  defs: &Defs,
+  name: &str,
+  opts: CheckOpts,
+) -> Result<Def, Vec<CheckError>> {
+  let (def, typ_, term, metas) = check_def_metas(defs, name, opts)?;
+  let (typ_, term) = (metas.zonk(&typ_, 0), metas.zonk(&term, 0));
+  Ok(Def { typ_, term, ..def.clone() })
+}
+
+/// Elaborates the definitions `roots` and every definition they depend on
+/// with `elaborate_def`, under the same content ids. Fails with the name and
+/// errors of the first definition that doesn't check.
+pub fn elaborate_defs(
+  defs: &Defs,
+  roots: &[Name],
+  opts: CheckOpts,
+) -> Result<Defs, (Name, Vec<CheckError>)> {
+  let names = defs.closure(roots).map_err(|root| {
+    let err = CheckError::UndefinedReference(Pos::None, root.to_string());
+    (root, vec![err])
+  })?;
+  let mut todo = names;
+  let mut elaborated = Defs::new();
+  while let Some((name, def_cid)) = todo.pop() {
+    if elaborated.defs.contains_key(&def_cid) {
+      elaborated.names.insert(name, def_cid);
+      continue;
+    }
+    let def =
+      elaborate_def(defs, &name, opts).map_err(|errs| (name.clone(), errs))?;
+    // Holes may be solved by references the definition didn't have, such as
+    // the projections of fields several records have
+    todo.extend(defs.dependencies(&def));
+    elaborated.insert(name, def);
+  }
+  Ok(elaborated)
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::tests::parse_package;
+
+  pub fn check_defs(src: &str) -> Result<(), CheckError> {
+    let (defs, _) = parse_package(src).unwrap();
+    for name in defs.names() {
+      check_def(&defs, &name.to_string())?;
+    }
+    Ok(())
+  }
+
+  #[test]
+  pub fn check_let() {
+    assert!(
+      check_defs("def id: ∀ (A: Type) (x: A) -> A = λ A x => let y: A = x; y")
+        .is_ok()
+    );
+    assert!(
+      check_defs(
+        "def dup: ∀ (1 x: #Nat) -> #Nat = λ x => let 1 y: #Nat = x; \
+         #Nat.add y y"
+      )
+      .is_err()
+    );
+    assert!(
+      check_defs(
+        "def lin: ∀ (1 x: #Nat) -> #Nat = λ x => let 1 y: #Nat = x; y"
+      )
+      .is_ok()
+    );
+    assert!(
+      check_defs(
+        "def count: ∀ #Nat -> #Nat = λ n => letrec go: ∀ #Nat -> #Nat = \
+         λ m => (case m) (λ _ => #Nat) 0 (λ p => go p); go n"
+      )
+      .is_ok()
+    );
+    assert!(
+      check_defs("def bad: ∀ #Nat -> #Nat = λ n => let x: #Nat = Type; n")
+        .is_err()
+    );
+  }
+
+  #[test]
+  pub fn infer_lam() {
+    let defs = Defs::new();
+    let infer = |src: &str| {
+      let term = crate::parse::term::parse(src, defs.clone()).unwrap().1;
+      infer_term(&defs, term).ok().map(|(typ, _)| typ)
+    };
+    assert_eq!(
+      infer("λ (x: #Nat) => #Nat.add x 1"),
+      Some(yatima!("∀ (x: #Nat) -> #Nat"))
+    );
+    assert_eq!(
+      infer("λ (0 A: Type) (x: A) => x"),
+      Some(yatima!("∀ (0 A: Type) (x: A) -> A"))
+    );
+    // Untyped binders still need an expected type
+    assert_eq!(infer("λ (0 A: Type) x => x"), None);
+    // Linear binders must be used exactly once
+    assert_eq!(infer("λ (1 x: #Nat) => #Nat.add x x"), None);
+    assert_eq!(infer("data 1"), Some(yatima!("@_ #Nat")));
+    assert!(
+      check_defs(
+        "def id: ∀ (0 A: Type) (x: A) -> A = λ (0 A: Type) (x: A) => x"
+      )
+      .is_ok()
+    );
+    assert!(
+      check_defs("def id: ∀ (0 A: Type) (x: A) -> A = λ (A: Type) (x: A) => x")
+        .is_err()
+    );
+    assert!(
+      check_defs("def id: ∀ (0 A: Type) (x: A) -> A = λ A (x: #Nat) => x")
+        .is_err()
+    );
+  }
+
+  #[test]
+  pub fn check_hole() {
+    let src = "def f: ∀ (0 A: Type) (x: A) -> A = λ A x => ?goal";
+    let (defs, _) = parse_package(src).unwrap();
+    let (_, holes) = check_def(&defs, "f").unwrap();
+    assert_eq!(holes.len(), 1);
+    assert_eq!(holes[0].nam, Name::from("goal"));
+    assert_eq!(format!("{}", holes[0].goal), "A");
+    let ctx: Vec<String> = holes[0].ctx.iter().map(|x| x.0.clone()).collect();
+    assert_eq!(ctx, vec!["A", "x"]);
+    // Holes in the type are reported too, each with its own goal
+    let src = "def g: ∀ (x: ?) -> #Nat = λ x => #Nat.add ?a ?b";
+    let (defs, _) = parse_package(src).unwrap();
+    let (_, holes) = check_def(&defs, "g").unwrap();
+    let goals: Vec<String> =
+      holes.iter().map(|h| format!("?{} : {}", h.nam, h.goal)).collect();
+    assert_eq!(goals, vec!["? : Type", "?a : #Nat", "?b : #Nat"]);
+    // The type of a hole can't be inferred
+    let term = crate::parse::term::parse("?a 1", defs.clone()).unwrap().1;
+    assert!(infer_term(&defs, term).is_err());
+  }
+
+  #[test]
+  pub fn check_implicit() {
+    let src = "def id {0 A: Type} (x: A): A = x
+               def one: #Nat = id 1
+               def two: #Nat = id {#Nat} 2
+               def app {0 A B: Type} (f: ∀ A -> B) (x: A): B = f x
+               def suc: #Nat = app #Nat.suc 1";
+    let (defs, _) = parse_package(src).unwrap();
+    for name in defs.names() {
+      let (_, holes) = check_def(&defs, &name.to_string()).unwrap();
+      assert!(holes.is_empty());
+    }
+    // The inserted arguments are filled in by elaboration, which keeps the
+    // content ids of the definitions as written
+    let term = |name: &str| {
+      let def = elaborate_def(&defs, name, CheckOpts::default()).unwrap();
+      assert_eq!(def.def_cid, defs.get(&Name::from(name)).unwrap().def_cid);
+      format!("{}", def.term)
+    };
+    assert_eq!(
+      format!("{}", defs.get(&Name::from("one")).unwrap().term),
+      "id ? 1"
+    );
+    assert_eq!(term("one"), "id #Nat 1");
+    assert_eq!(term("two"), "id #Nat 2");
+    assert_eq!(term("suc"), "app #Nat #Nat #Nat.suc 1");
+    assert_eq!(
+      format!("{}", defs.get(&Name::from("id")).unwrap().typ_),
+      "∀ {0 A: Type} (x: A) -> A"
+    );
+    // Recursive references get arguments for implicit parameters too
+    let src = "partial def loop {0 A: Type} (x: A): A = loop x
+               partial def pool {0 A: Type} (x: A): A = pool {A} x";
+    let (defs, _) = parse_package(src).unwrap();
+    assert!(check_def(&defs, "loop").unwrap().1.is_empty());
+    assert!(check_def(&defs, "pool").unwrap().1.is_empty());
+    let def = elaborate_def(&defs, "loop", CheckOpts::default()).unwrap();
+    assert_eq!(format!("{}", def.term), "λ A x => (#^) A x");
+    // A named hole is reported along with its solution
+    let src = "def id {0 A: Type} (x: A): A = x
+               def one: #Nat = id {?a} 1";
+    let (defs, _) = parse_package(src).unwrap();
+    let (_, holes) = check_def(&defs, "one").unwrap();
+    assert_eq!(holes.len(), 1);
+    assert_eq!(holes[0].solution, Some(yatima!("#Nat")));
+    // An argument that can't be solved is reported as an anonymous hole
+    let src = "def const {0 A: Type} (x: #Nat): #Nat = x
+               def two: #Nat = const 2";
+    let (defs, _) = parse_package(src).unwrap();
+    let (_, holes) = check_def(&defs, "two").unwrap();
+    let goals: Vec<String> =
+      holes.iter().map(|h| format!("?{} : {}", h.nam, h.goal)).collect();
+    assert_eq!(goals, vec!["? : Type"]);
+    // Mismatched implicit arguments are still type errors
+    assert!(
+      check_defs(
+        "def id {0 A: Type} (x: A): A = x
+         def bad: #Nat = id {#Text} 1"
+      )
+      .is_err()
+    );
+    assert!(
+      check_defs(
+        "def id {0 A: Type} (x: A): A = x
+         def bad: #Text = id 1"
+      )
+      .is_err()
+    );
+    // Elaboration checks with the options it's given, and fails with the
+    // errors of the check
+    let src = "def id {0 A: Type} (x: A): A = x
+               def typ: Type = id Type";
+    let (defs, _) = parse_package(src).unwrap();
+    let type_in_type = CheckOpts { type_in_type: true };
+    let def = elaborate_def(&defs, "typ", type_in_type).unwrap();
+    assert_eq!(format!("{}", def.term), "id Type Type");
+    assert!(elaborate_def(&defs, "typ", CheckOpts::default()).is_err());
+  }
+
+  #[test]
+  pub fn infer_let() {
+    let defs = Defs::new();
+    let term = crate::parse::term::parse("let x: #Nat = 1; x", defs.clone())
+      .unwrap()
+      .1;
+    let typ = infer_term(&defs, term).ok().map(|(typ, _)| typ);
+    assert_eq!(typ, Some(yatima!("#Nat")));
+    // The bound variable is replaced by its definition in the inferred type
+    let term =
+      crate::parse::term::parse("let x: #Nat = 1; case x", defs.clone())
+        .unwrap()
+        .1;
+    assert_eq!(
+      infer_term(&defs, term).ok().map(|(typ, _)| typ),
+      Some(yatima!(
+        "∀ (0 P: ∀ #Nat -> Type)
+           (& zero: P 0)
+           (& succ: ∀ (pred: #Nat) -> P (#Nat.suc pred))
+         -> P 1"
+      ))
+    );
+  }
+
+  pub fn check_defs_with(src: &str, opts: CheckOpts) -> Result<(), CheckError> {
+    let (defs, _) = parse_package(src).unwrap();
+    for name in defs.names() {
+      check_def_with(&defs, &name.to_string(), opts)?;
+    }
+    Ok(())
+  }
+
+  #[test]
+  pub fn check_universes() {
+    assert!(check_defs("def t: Type1 = Type").is_ok());
+    assert!(check_defs("def t: Type2 = Type1").is_ok());
+    assert!(check_defs("def t: Type = Type").is_err());
+    assert!(check_defs("def t: Type1 = Type1").is_err());
+    // Universes are cumulative
+    assert!(check_defs("def t: Type1 = #Nat").is_ok());
+    assert!(check_defs("def t: Type2 = Type").is_ok());
+    assert!(check_defs("def t: Type = Type1").is_err());
+    // Quantifying over types stays in the lowest universe
+    assert!(
+      check_defs("def id: ∀ (A: Type) (x: A) -> A = λ A x => x").is_ok()
+    );
+    assert!(check_defs("def f: Type = ∀ (A: Type) -> A").is_ok());
+    // but a type of types does not
+    assert!(check_defs("def f: Type = ∀ (A: Type) -> Type").is_err());
+    assert!(check_defs("def f: Type1 = ∀ (A: Type) -> Type").is_ok());
+    assert!(check_defs("def f: Type2 = ∀ (A: Type1) -> A").is_ok());
+    assert!(check_defs("def f: Type1 = ∀ (A: Type1) -> A").is_err());
+    assert!(check_defs("def f: Type1 = ∀ (A: Type) -> Type1").is_err());
+    // Cumulativity extends to the codomains of foralls
+    let f = "def F (A: Type): Type = A";
+    assert!(check_defs(&format!("{} def G: ∀ Type -> Type1 = F", f)).is_ok());
+    assert!(check_defs(&format!("{} def G: ∀ Type1 -> Type1 = F", f)).is_err());
+    let f = "def F (A: Type): Type1 = A";
+    assert!(check_defs(&format!("{} def G: ∀ Type -> Type = F", f)).is_err());
+    // but not to the arguments of an application
+    let b = "axiom B: ∀ Type2 -> Type  axiom b: B Type";
+    assert!(check_defs(&format!("{} def c: B Type = b", b)).is_ok());
+    assert!(check_defs(&format!("{} def c: B Type1 = b", b)).is_err());
+    // The largest universe has no type
+    let max = format!("def t: Type = Type{}", u64::MAX);
+    match check_defs(&max) {
+      Err(CheckError::UniverseTooLarge(_, lvl)) => assert_eq!(lvl, u64::MAX),
+      res => panic!("unexpected {:?}", res),
+    }
+    let opts = CheckOpts { type_in_type: true };
+    assert!(check_defs_with("def t: Type = Type", opts).is_ok());
+    assert!(check_defs_with("def t: Type = Type1", opts).is_ok());
+    assert!(check_defs_with("def t: Type1 = Type1", opts).is_ok());
+  }
+
+  #[test]
+  pub fn check_opaque() {
+    assert!(check_defs("def N: Type = #Nat  def x: N = 1").is_ok());
+    assert!(check_defs("opaque def N: Type = #Nat").is_ok());
+    assert!(check_defs("opaque def N: Type = #Nat  def x: N = 1").is_err());
+    assert!(check_defs("opaque def N: Type = Type").is_err());
+    // Opaque definitions are still unfolded when evaluating
+    let src = "opaque def two: #Nat = 2  def four: #Nat = #Nat.add two two";
+    let (defs, _) = parse_package(src).unwrap();
+    let def = defs.get(&Name::from("four")).unwrap();
+    let mut dag = DAG::from_def(def, Name::from("four"));
+    dag.norm(&defs).unwrap();
+    assert_eq!(format!("{}", dag), "4");
+  }
+
+  #[test]
+  pub fn check_missing_definitions() {
+    // A recursive reference outside of a definition refers to nothing
+    assert!(matches!(
+      infer_term(&Defs::new(), Term::Rec(Pos::None)),
+      Err(CheckError::UndefinedReference(..))
+    ));
+    // nor does a reference to a definition that is missing
+    let src = "def T: Type = #Nat
+      def x: T = 1";
+    let (mut defs, _) = parse_package(src).unwrap();
+    let t = defs.names.remove(&Name::from("T")).unwrap();
+    defs.defs.remove(&t);
+    assert!(matches!(
+      check_def(&defs, "x"),
+      Err(CheckError::UndefinedReference(..))
+    ));
+  }
+
+  #[test]
+  pub fn check_axioms() {
+    let src = "axiom A: Type
+      axiom a: A
+      axiom P (x: A): Type
+      def f (x: A): A = x
+      def b: A = f a
+      def p: Type = P b";
+    assert!(check_defs(src).is_ok());
+    assert!(check_defs("axiom bad: 1").is_err());
+    assert!(check_defs("axiom A: Type  axiom x: A  def y: #Nat = x").is_err());
+    let (defs, _) = parse_package(src).unwrap();
+    let axioms = |name: &str| {
+      let axioms = defs.axioms(defs.get(&Name::from(name)).unwrap());
+      axioms.iter().map(|name| name.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(axioms("A"), Vec::<String>::new());
+    assert_eq!(axioms("f"), vec!["A"]);
+    assert_eq!(axioms("b"), vec!["A", "a"]);
+    assert_eq!(axioms("p"), vec!["A", "P", "a"]);
+    // Axioms are stuck when evaluating
+    let def = defs.get(&Name::from("b")).unwrap();
+    let mut dag = DAG::from_def(def, Name::from("b"));
+    dag.norm(&defs).unwrap();
+    assert_eq!(format!("{}", dag), "a");
+    // and axioms of distinct types are not convertible
+    assert!(check_defs("axiom A: Type axiom B: Type1 axiom a: A def b: B = a")
+      .is_err());
+    // Axioms are identified by their types, not by their names
+    let renamed = "axiom B: Type
+      axiom a: B
+      axiom P (x: B): Type
+      def f (x: B): B = x
+      def b: B = f a
+      def p: Type = P b";
+    let (other, _) = parse_package(renamed).unwrap();
+    let ast =
+      |defs: &Defs, name: &str| defs.get(&Name::from(name)).unwrap().ast_cid;
+    assert_eq!(ast(&defs, "A"), ast(&other, "B"));
+    assert_eq!(ast(&defs, "p"), ast(&other, "p"));
+    assert_ne!(ast(&defs, "A"), ast(&defs, "a"));
+  }
+
+  #[test]
+  pub fn check_equality() {
+    assert!(check_defs("def two: #Nat.add 2 2 == 4 = refl").is_ok());
+    assert!(check_defs("def bad: 2 == 3 = refl").is_err());
+    assert!(check_defs("def hi: #Text.append \"h\" \"i\" == \"hi\" = refl")
+      .is_ok());
+    assert!(
+      check_defs(
+        "def sym {0 A: Type} {0 a b: A} (e: a == b): b == a =
+           rewrite (λ x => x == a) e refl"
+      )
+      .is_ok()
+    );
+    assert!(
+      check_defs(
+        "def trans {0 A: Type} {0 a b c: A} (e: a == b) (f: b == c): a == c =
+           rewrite (λ x => a == x) f e"
+      )
+      .is_ok()
+    );
+    assert!(
+      check_defs(
+        "def wrong {0 A: Type} {0 a b: A} (e: a == b): b == a =
+           rewrite (λ x => a == x) e refl"
+      )
+      .is_err()
+    );
+    // `rewrite` along `refl` computes
+    let src = "def r: #Nat = rewrite (λ x => #Nat) (refl :: (1 == 1)) 5";
+    let (defs, _) = parse_package(src).unwrap();
+    assert!(check_def(&defs, "r").is_ok());
+    let def = defs.get(&Name::from("r")).unwrap();
+    let mut dag = DAG::from_def(def, Name::from("r"));
+    dag.norm(&defs).unwrap();
+    assert_eq!(format!("{}", dag), "5");
+  }
+
+  #[test]
+  pub fn check_all_errors() {
+    let src = "def f (x: #Nat): #Nat = x
+      def g: #Nat = #Nat.add (f \"a\") (f \"b\")
+      def h: #Nat = let x: #Nat = \"a\"; #Nat.add x \"b\"
+      def loop (n: #Nat): #Nat = loop \"a\"
+      def ok: #Nat = f 1";
+    let (defs, _) = parse_package(src).unwrap();
+    let count = |name| {
+      check_def_all(&defs, name, CheckOpts::default())
+        .map_or_else(|errs| errs.len(), |_| 0)
+    };
+    assert_eq!(count("g"), 2);
+    assert_eq!(count("h"), 2);
+    assert_eq!(count("ok"), 0);
+    let errs = check_def_all(&defs, "loop", CheckOpts::default()).unwrap_err();
+    assert!(matches!(errs[0], CheckError::TypeMismatch(..)));
+    assert!(matches!(errs[1], CheckError::NonTerminating(..)));
+    // The first error is the one `check_def` reports
+    let errs = check_def_all(&defs, "g", CheckOpts::default()).unwrap_err();
+    assert_eq!(check_def(&defs, "g").unwrap_err(), errs[0]);
+  }
+
+  #[test]
+  pub fn check_error_labels() {
+    let src = "def f (x: #Nat): #Nat = x
+      def g: #Nat = f \"a\"
+      def h: #Nat = (\"a\" :: #Text)
+      def twice (1 x: #Nat): #Nat = #Nat.add x x";
+    let (defs, _) = parse_package(src).unwrap();
+    let lines = |name| {
+      let err = check_def(&defs, name).unwrap_err();
+      err
+        .labels()
+        .iter()
+        .map(|(pos, _)| match pos {
+          Pos::Some(pos) => pos.from_line,
+          Pos::None => 0,
+        })
+        .collect::<Vec<u64>>()
+    };
+    // The argument, and the function whose parameter it is checked against
+    assert_eq!(lines("g"), vec![2, 2]);
+    // The annotated expression, and the type of the definition
+    assert_eq!(lines("h"), vec![3, 3]);
+    let err = check_def(&defs, "h").unwrap_err();
+    let (pos, label) = &err.labels()[1];
+    assert_eq!(label, "expected type from here");
+    if let Pos::Some(pos) = pos {
+      let (from, upto) = (pos.from_offset as usize, pos.upto_offset as usize);
+      assert_eq!(&src[from..upto], "#Nat");
+    }
+    assert!(matches!(
+      check_def(&defs, "twice").unwrap_err(),
+      CheckError::QuantityTooMuch(Pos::Some(_), ..)
+    ));
+  }
 }
@@ -8,29 +8,127 @@ use crate::{
   uses::Uses,
 };
 
-#[derive(Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub enum CheckError {
   UndefinedReference(Pos, String),
   UnboundVariable(Pos, ErrCtx, String, u64),
   UntypedLambda(Pos, ErrCtx),
   UntypedData(Pos, ErrCtx),
+  UntypedHole(Pos, ErrCtx, String),
   QuantityTooLittle(Pos, ErrCtx, String, Uses, Uses),
   QuantityTooMuch(Pos, ErrCtx, String, Uses, Uses),
-  TypeMismatch(Pos, ErrCtx, Term, Term),
+  // The last position is where the expected type comes from, if known
+  TypeMismatch(Pos, ErrCtx, Term, Term, Pos),
   LamAllMismatch(Pos, ErrCtx, Term, Term),
+  LamUsesMismatch(Pos, ErrCtx, String, Uses, Uses),
   DatSlfMismatch(Pos, ErrCtx, Term, Term),
   AppFunMismatch(Pos, ErrCtx, Term, Term),
   CseDatMismatch(Pos, ErrCtx, Term, Term),
   NonInductiveLitType(Pos, ErrCtx, LitType),
+  NonExhaustiveMatch(Pos, String),
+  OverlappingPattern(Pos, String),
+  NonTerminating(Pos, String),
+  NonPositive(Pos, String),
+  PartialReference(Pos, String),
+  UniverseTooLarge(Pos, u64),
+  AmbiguousField(Pos, ErrCtx, String, Term),
   GenericError(Pos, ErrCtx, String),
 }
 
 impl CheckError {
   pub fn pos(&self) -> Pos {
     match self {
+      Self::UndefinedReference(pos, ..) => *pos,
+      Self::UnboundVariable(pos, ..) => *pos,
+      Self::UntypedLambda(pos, ..) => *pos,
+      Self::UntypedData(pos, ..) => *pos,
+      Self::UntypedHole(pos, ..) => *pos,
+      Self::QuantityTooLittle(pos, ..) => *pos,
+      Self::QuantityTooMuch(pos, ..) => *pos,
       Self::TypeMismatch(pos, ..) => *pos,
+      Self::LamAllMismatch(pos, ..) => *pos,
+      Self::LamUsesMismatch(pos, ..) => *pos,
+      Self::DatSlfMismatch(pos, ..) => *pos,
+      Self::AppFunMismatch(pos, ..) => *pos,
+      Self::CseDatMismatch(pos, ..) => *pos,
+      Self::NonInductiveLitType(pos, ..) => *pos,
+      Self::NonExhaustiveMatch(pos, ..) => *pos,
+      Self::OverlappingPattern(pos, ..) => *pos,
+      Self::NonTerminating(pos, ..) => *pos,
+      Self::NonPositive(pos, ..) => *pos,
+      Self::PartialReference(pos, ..) => *pos,
+      Self::UniverseTooLarge(pos, ..) => *pos,
+      Self::AmbiguousField(pos, ..) => *pos,
       Self::GenericError(pos, ..) => *pos,
-      _ => Pos::None,
+    }
+  }
+
+  /// The spans of source the error points at, each with a short label. The
+  /// first is where the error occurred, and any others are related to it.
+  pub fn labels(&self) -> Vec<(Pos, String)> {
+    let primary = match self {
+      Self::UndefinedReference(..) => "not defined".to_owned(),
+      Self::UnboundVariable(..) => "not bound".to_owned(),
+      Self::UntypedLambda(..) => {
+        "the type of this lambda is unknown".to_owned()
+      }
+      Self::UntypedData(..) => "the type of this data is unknown".to_owned(),
+      Self::UntypedHole(..) => "the type of this hole is unknown".to_owned(),
+      Self::QuantityTooLittle(_, _, nam, ..) => {
+        format!("`{}` is not used enough in here", nam)
+      }
+      Self::QuantityTooMuch(_, _, nam, ..) => {
+        format!("`{}` is used too much here", nam)
+      }
+      Self::TypeMismatch(_, _, exp, det, _) => {
+        format!("expected {}, found {}", exp, det)
+      }
+      Self::LamAllMismatch(_, _, _, typ) => {
+        format!("expected {}, which is not a forall", typ)
+      }
+      Self::LamUsesMismatch(_, _, nam, exp, det) => {
+        format!("expected `{}` to have quantity {}, found {}", nam, exp, det)
+      }
+      Self::DatSlfMismatch(_, _, _, typ) => {
+        format!("expected {}, which is not a self type", typ)
+      }
+      Self::AppFunMismatch(_, _, _, typ) => {
+        format!("this has type {}, which is not a function", typ)
+      }
+      Self::CseDatMismatch(_, _, _, typ) => {
+        format!("this has type {}, which is not a datatype", typ)
+      }
+      Self::NonInductiveLitType(_, _, typ) => {
+        format!("{} is not an inductive literal", typ)
+      }
+      Self::NonExhaustiveMatch(_, pat) => format!("{} is not covered", pat),
+      Self::OverlappingPattern(..) => "unreachable pattern".to_owned(),
+      Self::NonTerminating(..) => "this call may not terminate".to_owned(),
+      Self::NonPositive(..) => "not strictly positive".to_owned(),
+      Self::PartialReference(_, nam) => format!("`{}` is partial", nam),
+      Self::UniverseTooLarge(..) => "no universe contains this".to_owned(),
+      Self::AmbiguousField(_, _, field, typ) => {
+        format!("this has type {}, not a record with a field {}", typ, field)
+      }
+      Self::GenericError(_, _, msg) => msg.clone(),
+    };
+    let mut labels = vec![(self.pos(), primary)];
+    if let Self::TypeMismatch(.., from) = self {
+      labels.push((*from, "expected type from here".to_owned()));
+    }
+    labels
+  }
+
+  /// Records where the expected type of a mismatch comes from, if the
+  /// mismatch is of the term at `at` itself and its origin isn't yet known
+  pub fn expected_from(self, at: Pos, from: Pos) -> Self {
+    match self {
+      Self::TypeMismatch(pos, ctx, exp, det, Pos::None)
+        if pos == at && at != Pos::None =>
+      {
+        Self::TypeMismatch(pos, ctx, exp, det, from)
+      }
+      err => err,
     }
   }
 }
@@ -89,6 +187,21 @@ impl fmt::Display for CheckError {
         }
         Ok(())
       }
+      CheckError::UntypedHole(pos, ctx, nam) => {
+        write!(
+          f,
+          "Cannot infer the type of the hole ?{} {}",
+          nam,
+          pretty_pos(*pos)
+        )?;
+        if !ctx.is_empty() {
+          writeln!(f, "• Context:")?;
+          for (n, uses, typ) in ctx {
+            writeln!(f, "  - {} {}: {}", uses, n, typ)?;
+          }
+        }
+        Ok(())
+      }
       CheckError::LamAllMismatch(pos, ctx, trm, typ) => {
         writeln!(
           f,
@@ -105,6 +218,24 @@ impl fmt::Display for CheckError {
         writeln!(f, "• Against: {}", typ)?;
         Ok(())
       }
+      CheckError::LamUsesMismatch(pos, ctx, nam, exp, det) => {
+        writeln!(
+          f,
+          "The quantity of the lambda binder `{}` does not match its forall \
+           (∀) {}",
+          nam,
+          pretty_pos(*pos)
+        )?;
+        if !ctx.is_empty() {
+          writeln!(f, "• Context:")?;
+          for (n, uses, typ) in ctx {
+            writeln!(f, "  - {} {}: {}", uses, n, typ)?;
+          }
+        }
+        writeln!(f, "• Expected: {}", exp)?;
+        writeln!(f, "• Detected: {}", det)?;
+        Ok(())
+      }
       CheckError::AppFunMismatch(pos, ctx, fun, typ) => {
         writeln!(
           f,
@@ -193,7 +324,7 @@ impl fmt::Display for CheckError {
         writeln!(f, "• Detected: {}", det)?;
         Ok(())
       }
-      CheckError::TypeMismatch(pos, ctx, exp, det) => {
+      CheckError::TypeMismatch(pos, ctx, exp, det, _) => {
         writeln!(f, "Type Mismatch {}", pretty_pos(*pos))?;
         if !ctx.is_empty() {
           writeln!(f, "• Context:")?;
@@ -205,6 +336,78 @@ impl fmt::Display for CheckError {
         writeln!(f, "• Detected: {}", det)?;
         Ok(())
       }
+      CheckError::NonExhaustiveMatch(pos, pat) => {
+        write!(
+          f,
+          "Non-exhaustive match, the pattern {} is not covered {}",
+          pat,
+          pretty_pos(*pos)
+        )
+      }
+      CheckError::OverlappingPattern(pos, pat) => {
+        write!(
+          f,
+          "The pattern {} is unreachable, as the patterns before it cover \
+           its values {}",
+          pat,
+          pretty_pos(*pos)
+        )
+      }
+      CheckError::NonTerminating(pos, call) => {
+        write!(
+          f,
+          "Cannot show that the recursive call {} terminates, as it does not \
+           pass a structurally smaller argument for a parameter every other \
+           recursive call decreases {}. Mark the definition `partial` to \
+           allow it",
+          call,
+          pretty_pos(*pos)
+        )
+      }
+      CheckError::NonPositive(pos, typ) => {
+        write!(
+          f,
+          "The Self type refers to itself in the field {}, which is not a \
+           strictly positive position {}. Mark the definition `partial` to \
+           allow it",
+          typ,
+          pretty_pos(*pos)
+        )
+      }
+      CheckError::PartialReference(pos, nam) => {
+        write!(
+          f,
+          "The definition refers to `{}` {}, which is marked `partial` and so \
+           may not terminate. Mark the definition `partial` too to allow it",
+          nam,
+          pretty_pos(*pos)
+        )
+      }
+      CheckError::UniverseTooLarge(pos, lvl) => {
+        write!(
+          f,
+          "The universe Type{} is the largest there is, so it has no type {}",
+          lvl,
+          pretty_pos(*pos)
+        )
+      }
+      CheckError::AmbiguousField(pos, ctx, field, typ) => {
+        writeln!(
+          f,
+          "The field {} belongs to several records, and the type of the term \
+           it is projected out of is none of them {}",
+          field,
+          pretty_pos(*pos)
+        )?;
+        if !ctx.is_empty() {
+          writeln!(f, "• Context:")?;
+          for (n, uses, typ) in ctx {
+            writeln!(f, "  - {} {}: {}", uses, n, typ)?;
+          }
+        }
+        writeln!(f, "• Type: {}", typ)?;
+        Ok(())
+      }
       CheckError::GenericError(pos, ctx, msg) => {
         writeln!(f, "{} {} ", msg, pretty_pos(*pos))?;
         if !ctx.is_empty() {
@@ -0,0 +1,37 @@
+use std::fmt;
+
+use crate::{
+  check::{
+    ctx::*,
+    error::pretty_pos,
+  },
+  name::Name,
+  position::Pos,
+  term::Term,
+};
+
+// A typed hole `?nam` met by the typechecker, along with the type it was
+// checked against, the local context at that point and, if unification found
+// one, the term it stands for
+#[derive(PartialEq, Clone, Debug)]
+pub struct Hole {
+  pub pos: Pos,
+  pub nam: Name,
+  pub goal: Term,
+  pub ctx: ErrCtx,
+  pub solution: Option<Term>,
+}
+
+impl fmt::Display for Hole {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "Hole ?{} {}", self.nam, pretty_pos(self.pos))?;
+    writeln!(f, "• Goal: {}", self.goal)?;
+    if let Some(solution) = &self.solution {
+      writeln!(f, "• Solution: {}", solution)?;
+    }
+    if !self.ctx.is_empty() {
+      write!(f, "• Context:\n{}", pretty_context(&self.ctx))?;
+    }
+    Ok(())
+  }
+}
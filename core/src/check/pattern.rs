@@ -0,0 +1,822 @@
+use std::{
+  cell::Cell,
+  fmt,
+};
+
+use num_bigint::BigUint;
+
+use crate::{
+  check::error::CheckError,
+  datatype::constructors,
+  defs::Defs,
+  name::Name,
+  position::Pos,
+  prim::{
+    nat::NatOp,
+    text::TextOp,
+    Op,
+  },
+  term::{
+    Literal,
+    Term,
+  },
+  uses::Uses,
+};
+
+/// A pattern of a `match` arm. Constructors are resolved by the parser to
+/// their datatype and their index in it
+#[derive(Clone, Debug)]
+pub enum Pattern {
+  Var(Pos, Name),
+  Ctor(Pos, Name, usize, Name, Vec<Pattern>),
+  Lit(Pos, Literal),
+}
+
+impl Pattern {
+  pub fn pos(&self) -> Pos {
+    match self {
+      Self::Var(pos, ..) | Self::Ctor(pos, ..) | Self::Lit(pos, ..) => *pos,
+    }
+  }
+
+  /// The variables bound by the pattern, from left to right
+  pub fn vars(&self) -> Vec<Name> {
+    match self {
+      Self::Var(_, nam) => vec![nam.clone()],
+      Self::Ctor(.., args) => args.iter().flat_map(Pattern::vars).collect(),
+      Self::Lit(..) => vec![],
+    }
+  }
+}
+
+impl fmt::Display for Pattern {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Var(_, nam) => write!(f, "{}", nam),
+      Self::Ctor(_, _, _, ctor, args) => {
+        write!(f, "{}", ctor)?;
+        for arg in args {
+          match arg {
+            Self::Ctor(.., args) if !args.is_empty() => {
+              write!(f, " ({})", arg)?
+            }
+            _ => write!(f, " {}", arg)?,
+          }
+        }
+        Ok(())
+      }
+      Self::Lit(_, lit) => write!(f, "{}", lit),
+    }
+  }
+}
+
+/// An arm `pat => bod` of a `match`, whose body is in the context extended by
+/// the variables of the pattern
+#[derive(Clone, Debug)]
+pub struct Arm {
+  pub pos: Pos,
+  pub pat: Pattern,
+  pub bod: Term,
+}
+
+// Where a matched value lives: the scrutinee itself, a field bound by a case
+// branch at a given depth below the match, a literal it was found equal to, or
+// the successor of a natural number
+#[derive(Clone, Debug)]
+enum Occ {
+  Scrut,
+  Field(Name, u64),
+  Lit(Literal),
+  Succ(Box<Occ>),
+}
+
+// Patterns whose variables are numbered in the order they are bound in the
+// body of their arm
+#[derive(Clone, Debug)]
+enum Pat {
+  Var(Option<usize>),
+  Ctor(Name, usize, Vec<Pat>),
+  Lit(Literal),
+}
+
+#[derive(Clone, Debug)]
+struct Row {
+  arm: usize,
+  pats: Vec<Pat>,
+  binds: Vec<Option<Occ>>,
+}
+
+// The compilation stops either on an error or on a vector of values, one per
+// column, that no arm matches
+enum Fail {
+  Missing(Vec<String>),
+  Error(CheckError),
+}
+
+struct Compiler<'a> {
+  defs: &'a Defs,
+  pos: Pos,
+  scrutinee: Term,
+  arms: Vec<Arm>,
+  vars: Vec<Vec<Name>>,
+  used: Vec<bool>,
+  scrutinee_uses: Cell<u64>,
+}
+
+/// Compiles a `match` into `case` applications, one per tested constructor or
+/// literal, over the scrutinee and the fields of its nested patterns. The
+/// motives are left as holes, which the checker solves by abstracting the type
+/// the match is checked against over the variable each case is on, so that
+/// the type of a match may depend on its scrutinee. The cases on other terms,
+/// such as the successors of numbers and the comparisons of texts, have
+/// constant motives `λ _ => ?` instead.
+///
+/// A scrutinee that isn't a variable is let-bound, so that it's evaluated
+/// once however many times the cases refer to it
+pub fn compile_match(
+  defs: &Defs,
+  pos: Pos,
+  scrutinee: Term,
+  arms: Vec<Arm>,
+) -> Result<Term, CheckError> {
+  let mut rows = vec![];
+  for (arm, Arm { pat, .. }) in arms.iter().enumerate() {
+    let mut vars = 0;
+    let pat = number(defs, pat, &mut vars)?;
+    rows.push(Row { arm, pats: vec![pat], binds: vec![None; vars] });
+  }
+  let vars: Vec<Vec<Name>> = arms.iter().map(|arm| arm.pat.vars()).collect();
+  let used = vec![false; arms.len()];
+  // Unless the scrutinee is a variable, it's bound by a let over which the
+  // bodies of the arms are lifted
+  let nam = Name::from("scrutinee");
+  let bound = !matches!(scrutinee, Term::Var(..));
+  let (arms, var) = if bound {
+    let arms = arms
+      .into_iter()
+      .zip(vars.iter())
+      .map(|(arm, vars)| {
+        let bod = arm.bod.shift(1, vars.len() as u64);
+        Arm { bod, ..arm }
+      })
+      .collect();
+    (arms, Term::Var(pos, nam.clone(), 0))
+  }
+  else {
+    (arms, scrutinee.clone())
+  };
+  let mut compiler = Compiler {
+    defs,
+    pos,
+    scrutinee: var,
+    arms,
+    vars,
+    used,
+    scrutinee_uses: Cell::new(0),
+  };
+  let term = match compiler.compile(rows, vec![Occ::Scrut], 0) {
+    Ok(term) if bound => {
+      let uses = match compiler.scrutinee_uses.get() {
+        0 => Uses::None,
+        1 => Uses::Once,
+        _ => Uses::Many,
+      };
+      let hol = Term::Hol(pos, Name::from(""), 0);
+      Term::Let(pos, false, uses, nam, Box::new((hol, scrutinee, term)))
+    }
+    Ok(term) => term,
+    Err(Fail::Missing(pats)) => {
+      return Err(CheckError::NonExhaustiveMatch(pos, pats.join(" ")));
+    }
+    Err(Fail::Error(err)) => return Err(err),
+  };
+  for (arm, used) in compiler.arms.iter().zip(compiler.used) {
+    if !used {
+      let pat = format!("{}", arm.pat);
+      return Err(CheckError::OverlappingPattern(arm.pos, pat));
+    }
+  }
+  Ok(term)
+}
+
+// Numbers the variables of a pattern and checks the arity of its
+// constructors
+fn number(
+  defs: &Defs,
+  pat: &Pattern,
+  vars: &mut usize,
+) -> Result<Pat, CheckError> {
+  match pat {
+    Pattern::Var(..) => {
+      *vars += 1;
+      Ok(Pat::Var(Some(*vars - 1)))
+    }
+    Pattern::Ctor(pos, typ, idx, ctor, args) => {
+      let fields = constructors(defs, typ).map(|cs| cs[*idx].1.len());
+      if fields != Some(args.len()) {
+        return Err(CheckError::GenericError(
+          *pos,
+          Default::default(),
+          format!(
+            "The constructor {} expects {} fields, but its pattern has {}",
+            ctor,
+            fields.unwrap_or(0),
+            args.len()
+          ),
+        ));
+      }
+      let mut pats = vec![];
+      for arg in args {
+        pats.push(number(defs, arg, vars)?);
+      }
+      Ok(Pat::Ctor(typ.clone(), *idx, pats))
+    }
+    Pattern::Lit(pos, lit) => match lit {
+      Literal::Nat(_) | Literal::Text(_) | Literal::Bool(_) => {
+        Ok(Pat::Lit(lit.clone()))
+      }
+      _ => Err(CheckError::GenericError(
+        *pos,
+        Default::default(),
+        format!("Cannot match on the literal {}", lit),
+      )),
+    },
+  }
+}
+
+impl<'a> Compiler<'a> {
+  fn occ(&self, occ: &Occ, dep: u64) -> Term {
+    match occ {
+      Occ::Scrut => {
+        self.scrutinee_uses.set(self.scrutinee_uses.get() + 1);
+        self.scrutinee.clone().shift(dep, 0)
+      }
+      Occ::Field(nam, lvl) => Term::Var(self.pos, nam.clone(), dep - 1 - lvl),
+      Occ::Lit(lit) => Term::Lit(self.pos, lit.clone()),
+      Occ::Succ(occ) => {
+        let suc = Term::Opr(self.pos, Op::Nat(NatOp::Suc));
+        Term::App(self.pos, Box::new((suc, self.occ(occ, dep))))
+      }
+    }
+  }
+
+  fn mismatch(&self, expected: &str) -> Fail {
+    Fail::Error(CheckError::GenericError(
+      self.pos,
+      Default::default(),
+      format!("Patterns of different types in a match, expected {}", expected),
+    ))
+  }
+
+  // `case occ ? branches...`, or `case occ (λ _ => ?) branches...` if `occ`
+  // isn't a variable
+  fn case(&self, scrut: Term, branches: Vec<Term>) -> Term {
+    let pos = self.pos;
+    let hol = Term::Hol(pos, Name::from(""), 0);
+    let motive = match scrut {
+      Term::Var(..) => hol,
+      _ => Term::Lam(pos, Name::from("_"), None, Box::new(hol)),
+    };
+    let cse = Term::Cse(pos, Box::new(scrut));
+    let cse = Term::App(pos, Box::new((cse, motive)));
+    branches
+      .into_iter()
+      .fold(cse, |acc, branch| Term::App(pos, Box::new((acc, branch))))
+  }
+
+  fn compile(
+    &mut self,
+    rows: Vec<Row>,
+    occs: Vec<Occ>,
+    dep: u64,
+  ) -> Result<Term, Fail> {
+    let first = match rows.first() {
+      Some(first) => first,
+      None => return Err(Fail::Missing(vec![String::from("_"); occs.len()])),
+    };
+    match first.pats.iter().position(|pat| !matches!(pat, Pat::Var(_))) {
+      None => {
+        let mut binds = first.binds.clone();
+        for (pat, occ) in first.pats.iter().zip(occs.iter()) {
+          if let Pat::Var(Some(var)) = pat {
+            binds[*var] = Some(occ.clone());
+          }
+        }
+        self.used[first.arm] = true;
+        let args: Vec<Term> = binds
+          .iter()
+          .map(|occ| self.occ(occ.as_ref().unwrap(), dep))
+          .collect();
+        Ok(instantiate(self.arms[first.arm].bod.clone(), &args, dep, 0))
+      }
+      Some(col) => match &first.pats[col] {
+        Pat::Ctor(typ, ..) => {
+          let typ = typ.clone();
+          self.split_ctor(rows, occs, dep, col, typ)
+        }
+        Pat::Lit(Literal::Bool(_)) => self.split_bool(rows, occs, dep, col),
+        Pat::Lit(Literal::Nat(_)) => self.split_nat(rows, occs, dep, col),
+        Pat::Lit(_) => self.split_text(rows, occs, dep, col),
+        Pat::Var(_) => unreachable!(),
+      },
+    }
+  }
+
+  // Removes the column `col` of a row, replacing it by `pats` and binding the
+  // variable it may be to `occ`
+  fn expand(row: &Row, col: usize, occ: &Occ, pats: Vec<Pat>) -> Row {
+    let mut row = row.clone();
+    if let Pat::Var(Some(var)) = row.pats[col] {
+      row.binds[var] = Some(occ.clone());
+    }
+    row.pats.splice(col..col + 1, pats);
+    row
+  }
+
+  fn split_ctor(
+    &mut self,
+    rows: Vec<Row>,
+    occs: Vec<Occ>,
+    dep: u64,
+    col: usize,
+    typ: Name,
+  ) -> Result<Term, Fail> {
+    let ctors = constructors(self.defs, &typ).unwrap_or_default();
+    let mut branches = vec![];
+    for (idx, (ctor, fields)) in ctors.iter().enumerate() {
+      let arity = fields.len();
+      let mut sub_rows = vec![];
+      for row in rows.iter() {
+        match &row.pats[col] {
+          Pat::Ctor(typ2, idx2, args) if *typ2 == typ => {
+            if *idx2 == idx {
+              sub_rows.push(Self::expand(row, col, &occs[col], args.clone()));
+            }
+          }
+          Pat::Var(_) => {
+            let wild = vec![Pat::Var(None); arity];
+            sub_rows.push(Self::expand(row, col, &occs[col], wild));
+          }
+          _ => return Err(self.mismatch(&typ)),
+        }
+      }
+      // Fields are named after the first variable they are bound to, if any
+      let fields: Vec<Name> = fields
+        .iter()
+        .enumerate()
+        .map(|(j, field)| {
+          let var = sub_rows.iter().find_map(|row| match row.pats[col + j] {
+            Pat::Var(Some(var)) if *self.vars[row.arm][var] != *"_" => {
+              Some(self.vars[row.arm][var].clone())
+            }
+            _ => None,
+          });
+          var.unwrap_or_else(|| field.clone())
+        })
+        .collect();
+      let mut sub_occs = occs.clone();
+      let fields_occs = fields
+        .iter()
+        .enumerate()
+        .map(|(j, field)| Occ::Field(field.clone(), dep + j as u64));
+      sub_occs.splice(col..col + 1, fields_occs);
+      let bod = match self.compile(sub_rows, sub_occs, dep + arity as u64) {
+        Ok(bod) => bod,
+        Err(Fail::Missing(mut pats)) => {
+          let mut pat = vec![ctor.to_string()];
+          for arg in pats.drain(col..col + arity) {
+            let nested = arg.contains(' ');
+            pat.push(if nested { format!("({})", arg) } else { arg });
+          }
+          pats.insert(col, pat.join(" "));
+          return Err(Fail::Missing(pats));
+        }
+        Err(err) => return Err(err),
+      };
+      let branch = fields.iter().rev().fold(bod, |acc, field| {
+        Term::Lam(self.pos, field.clone(), None, Box::new(acc))
+      });
+      branches.push(branch);
+    }
+    Ok(self.case(self.occ(&occs[col], dep), branches))
+  }
+
+  // Booleans are matched like a datatype with two constructors
+  fn split_bool(
+    &mut self,
+    rows: Vec<Row>,
+    occs: Vec<Occ>,
+    dep: u64,
+    col: usize,
+  ) -> Result<Term, Fail> {
+    let mut branches = vec![];
+    for val in [true, false] {
+      let mut sub_rows = vec![];
+      for row in rows.iter() {
+        match &row.pats[col] {
+          Pat::Lit(Literal::Bool(b)) => {
+            if *b == val {
+              sub_rows.push(Self::expand(row, col, &occs[col], vec![]));
+            }
+          }
+          Pat::Var(_) => {
+            sub_rows.push(Self::expand(row, col, &occs[col], vec![]));
+          }
+          _ => return Err(self.mismatch("#Bool")),
+        }
+      }
+      let mut sub_occs = occs.clone();
+      sub_occs.remove(col);
+      match self.compile(sub_rows, sub_occs, dep) {
+        Ok(branch) => branches.push(branch),
+        Err(Fail::Missing(mut pats)) => {
+          pats.insert(col, format!("{}", Literal::Bool(val)));
+          return Err(Fail::Missing(pats));
+        }
+        Err(err) => return Err(err),
+      }
+    }
+    Ok(self.case(self.occ(&occs[col], dep), branches))
+  }
+
+  // Natural numbers are matched like a datatype with the constructors zero
+  // and successor, which their case eliminates. The column is the successor
+  // of its value as many times as it was split already, and the literal
+  // patterns are compared with this count
+  fn split_nat(
+    &mut self,
+    rows: Vec<Row>,
+    occs: Vec<Occ>,
+    dep: u64,
+    col: usize,
+  ) -> Result<Term, Fail> {
+    let mut scrut = &occs[col];
+    let mut count = 0u64;
+    while let Occ::Succ(pred) = scrut {
+      scrut = pred;
+      count += 1;
+    }
+    let zero = Literal::Nat(BigUint::from(count));
+    let zero_occ = Occ::Lit(zero.clone());
+    let mut zero_rows = vec![];
+    let mut succ_rows = vec![];
+    for row in rows.iter() {
+      match &row.pats[col] {
+        Pat::Lit(lit @ Literal::Nat(_)) => {
+          if *lit == zero {
+            zero_rows.push(Self::expand(row, col, &zero_occ, vec![]));
+          }
+          else {
+            succ_rows.push(row.clone());
+          }
+        }
+        Pat::Var(_) => {
+          zero_rows.push(Self::expand(row, col, &zero_occ, vec![]));
+          succ_rows.push(row.clone());
+        }
+        _ => return Err(self.mismatch("#Nat")),
+      }
+    }
+    let mut zero_occs = occs.clone();
+    zero_occs.remove(col);
+    let zero_branch = match self.compile(zero_rows, zero_occs, dep) {
+      Ok(branch) => branch,
+      Err(Fail::Missing(mut pats)) => {
+        pats.insert(col, format!("{}", zero));
+        return Err(Fail::Missing(pats));
+      }
+      Err(err) => return Err(err),
+    };
+    let pred = Name::from("pred");
+    let mut succ_occs = occs.clone();
+    succ_occs[col] = (0..=count).fold(Occ::Field(pred.clone(), dep), |occ, _| {
+      Occ::Succ(Box::new(occ))
+    });
+    let succ_branch = self.compile(succ_rows, succ_occs, dep + 1)?;
+    let succ_branch = Term::Lam(self.pos, pred, None, Box::new(succ_branch));
+    Ok(self.case(self.occ(scrut, dep), vec![zero_branch, succ_branch]))
+  }
+
+  // Texts are compared one by one with the values of the patterns, in the
+  // order they appear in, and are otherwise matched by variables
+  fn split_text(
+    &mut self,
+    rows: Vec<Row>,
+    occs: Vec<Occ>,
+    dep: u64,
+    col: usize,
+  ) -> Result<Term, Fail> {
+    let mut vals: Vec<Literal> = vec![];
+    for row in rows.iter() {
+      if let Pat::Lit(lit) = &row.pats[col] {
+        if !vals.contains(lit) {
+          vals.push(lit.clone());
+        }
+      }
+    }
+    let (eql, typ) = (Op::Text(TextOp::Eql), "#Text");
+    let mut sub_occs = occs.clone();
+    sub_occs.remove(col);
+    let mut default = vec![];
+    for row in rows.iter() {
+      match &row.pats[col] {
+        Pat::Var(_) => {
+          default.push(Self::expand(row, col, &occs[col], vec![]))
+        }
+        Pat::Lit(Literal::Text(_)) => (),
+        _ => return Err(self.mismatch(typ)),
+      }
+    }
+    let mut term = match self.compile(default, sub_occs.clone(), dep) {
+      Ok(term) => term,
+      Err(Fail::Missing(mut pats)) => {
+        pats.insert(col, String::from("_"));
+        return Err(Fail::Missing(pats));
+      }
+      Err(err) => return Err(err),
+    };
+    for val in vals.iter().rev() {
+      let mut sub_rows = vec![];
+      for row in rows.iter() {
+        match &row.pats[col] {
+          Pat::Lit(lit) if lit != val => (),
+          _ => sub_rows.push(Self::expand(row, col, &occs[col], vec![])),
+        }
+      }
+      let then = match self.compile(sub_rows, sub_occs.clone(), dep) {
+        Ok(then) => then,
+        Err(Fail::Missing(mut pats)) => {
+          pats.insert(col, format!("{}", val));
+          return Err(Fail::Missing(pats));
+        }
+        Err(err) => return Err(err),
+      };
+      let pos = self.pos;
+      let fun_arg = (Term::Opr(pos, eql), self.occ(&occs[col], dep));
+      let lit = Term::Lit(pos, val.clone());
+      let fun_arg = (Term::App(pos, Box::new(fun_arg)), lit);
+      let test = Term::App(pos, Box::new(fun_arg));
+      term = self.case(test, vec![then, term]);
+    }
+    Ok(term)
+  }
+}
+
+// Replaces the variables of a pattern, the `args.len()` innermost ones of the
+// body of its arm, by the values they are bound to, and lifts the other
+// variables over the `inc` binders introduced by the compiled cases
+fn instantiate(term: Term, args: &[Term], inc: u64, dep: u64) -> Term {
+  let len = args.len() as u64;
+  let go = |term: Term, dep: u64| Box::new(instantiate(term, args, inc, dep));
+  match term {
+    Term::Var(pos, nam, idx) => {
+      if idx < dep {
+        Term::Var(pos, nam, idx)
+      }
+      else if idx - dep < len {
+        args[(len - 1 - (idx - dep)) as usize].clone().shift(dep, 0)
+      }
+      else {
+        Term::Var(pos, nam, idx - len + inc)
+      }
+    }
+    Term::Lam(pos, nam, dom, bod) => Term::Lam(
+      pos,
+      nam,
+      dom.map(|(uses, dom)| (uses, go(*dom, dep))),
+      go(*bod, dep + 1),
+    ),
+    Term::App(pos, fun_arg) => {
+      let (fun, arg) = *fun_arg;
+      Term::App(pos, Box::new((*go(fun, dep), *go(arg, dep))))
+    }
+    Term::All(pos, imp, uses, nam, dom_img) => {
+      let (dom, img) = *dom_img;
+      let dom_img = (*go(dom, dep), *go(img, dep + 1));
+      Term::All(pos, imp, uses, nam, Box::new(dom_img))
+    }
+    Term::Slf(pos, nam, bod) => Term::Slf(pos, nam, go(*bod, dep + 1)),
+    Term::Dat(pos, bod) => Term::Dat(pos, go(*bod, dep)),
+    Term::Cse(pos, bod) => Term::Cse(pos, go(*bod, dep)),
+    Term::Rcd(pos, nams, bod) => Term::Rcd(pos, nams, go(*bod, dep)),
+    Term::Prj(pos, nam, prj_arg) => {
+      let (prj, arg) = *prj_arg;
+      Term::Prj(pos, nam, Box::new((*go(prj, dep), *go(arg, dep))))
+    }
+    Term::Ann(pos, typ_exp) => {
+      let (typ, exp) = *typ_exp;
+      Term::Ann(pos, Box::new((*go(typ, dep), *go(exp, dep))))
+    }
+    Term::Let(pos, rec, uses, nam, typ_exp_bod) => {
+      let (typ, exp, bod) = *typ_exp_bod;
+      let exp = *go(exp, if rec { dep + 1 } else { dep });
+      Term::Let(
+        pos,
+        rec,
+        uses,
+        nam,
+        Box::new((*go(typ, dep), exp, *go(bod, dep + 1))),
+      )
+    }
+    term => term,
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::{
+    check::{
+      check_def,
+      elaborate_def,
+      CheckOpts,
+    },
+    dag::DAG,
+    parse::error::ParseErrorKind,
+    tests::parse_package,
+  };
+
+  const TYPES: &str = "type Bool { True, False }
+     type List (A: Type) { Nil, Cons (x: A) (xs: List A) }
+     type Pair (A: Type) (B: Type) { New (fst: A) (snd: B) }";
+
+  fn parse(src: &str) -> Result<Defs, ParseErrorKind> {
+    let src = format!("{}\n{}", TYPES, src);
+    match parse_package(&src) {
+      Ok((defs, _)) => Ok(defs),
+      Err(e) => Err(e.errors.last().unwrap().clone()),
+    }
+  }
+
+  fn term(defs: &Defs, name: &str) -> String {
+    let def = elaborate_def(defs, name, CheckOpts::default()).unwrap();
+    format!("{}", def.term)
+  }
+
+  #[test]
+  pub fn compile_matches() {
+    let defs = parse(
+      "def not (b: Bool): Bool = match b { True => Bool.False, _ => Bool.True }
+       def swap (p: Pair #Nat #Text): Pair #Text #Nat =
+         match p { New a b => Pair.New b a }
+       def sum (xs: List (Pair #Nat #Nat)): #Nat = match xs {
+         Cons (Pair.New a b) rest => #Nat.add a (#Nat.add b (sum rest)),
+         Nil => 0,
+       }
+       def two (n: #Nat) (xs: List #Nat): #Bool = match xs {
+         Cons _ (Cons _ _) => #Bool.true,
+         _ => #Nat.eql n 2,
+       }
+       def name (n: #Nat): #Text =
+         match n { 0 => \"zero\", 1 => \"one\", _ => \"many\" }
+       def neg (b: #Bool): #Bool =
+         match b { #Bool.true => #Bool.false, _ => #Bool.true }
+       def pred (1 n: #Nat): #Nat = match n { 0 => 0, 1 => 0, m => m }
+       def succ (n: #Nat): #Nat = match (#Nat.add n 1) { 0 => 0, m => m }",
+    )
+    .unwrap();
+    let names = ["not", "swap", "sum", "two", "name", "neg", "pred", "succ"];
+    for name in names {
+      let res = check_def(&defs, name);
+      assert!(res.is_ok(), "{}: {}", name, res.unwrap_err());
+      assert!(res.unwrap().1.is_empty());
+    }
+    assert_eq!(
+      term(&defs, "not"),
+      "λ b => (case b) (λ b => Bool) Bool.False Bool.True"
+    );
+    assert_eq!(
+      term(&defs, "swap"),
+      "λ p => (case p) (λ p => Pair #Text #Nat) (λ a b => Pair.New #Text #Nat \
+       b a)"
+    );
+    // Variables bound outside of the match are lifted over the fields
+    assert_eq!(
+      term(&defs, "two"),
+      "λ n xs => (case xs) (λ xs => #Bool) (#Nat.eql n 2) (λ x xs => (case \
+       xs) (λ xs => #Bool) (#Nat.eql n 2) (λ x xs => #Bool.true))"
+    );
+    assert_eq!(
+      term(&defs, "name"),
+      "λ n => (case n) (λ n => #Text) \"zero\" (λ pred => (case pred) (λ \
+       pred => #Text) \"one\" (λ pred => \"many\"))"
+    );
+    // Natural numbers are taken apart rather than compared, so that a linear
+    // scrutinee is used once
+    assert_eq!(
+      term(&defs, "pred"),
+      "λ n => (case n) (λ n => #Nat) 0 (λ pred => (case pred) (λ pred => \
+       #Nat) 0 (λ pred => #Nat.suc (#Nat.suc pred)))"
+    );
+    assert_eq!(
+      term(&defs, "succ"),
+      "λ n => let 1 scrutinee: #Nat = #Nat.add n 1; (case scrutinee) (λ \
+       scrutinee => #Nat) 0 (λ pred => #Nat.suc pred)"
+    );
+  }
+
+  #[test]
+  pub fn nat_patterns() {
+    // Literals are matched by taking their value apart once per unit of the
+    // largest one, which picks the first arm of the value
+    let defs = parse(
+      "def name (n: #Nat): #Text =
+         match n { 0 => \"zero\", 1 => \"one\", 3 => \"three\", _ => \"many\" }
+       def first (xs: List #Nat): #Nat = match xs {
+         Cons 1 _ => 10,
+         Cons m _ => #Nat.add m 1,
+         Nil => 0,
+       }",
+    )
+    .unwrap();
+    for name in ["name", "first"] {
+      let res = check_def(&defs, name);
+      assert!(res.is_ok(), "{}: {}", name, res.unwrap_err());
+    }
+    let eval = |src: &str| {
+      let (_, term) = crate::parse::term::parse(src, defs.clone()).unwrap();
+      let mut dag = DAG::from_term(&term);
+      dag.norm(&defs).unwrap();
+      let res = format!("{}", dag);
+      dag.free();
+      res
+    };
+    let names =
+      [(0, "zero"), (1, "one"), (2, "many"), (3, "three"), (4, "many")];
+    for (n, name) in names {
+      assert_eq!(eval(&format!("name {}", n)), format!("\"{}\"", name));
+    }
+    assert_eq!(eval("first (List.Cons 1 List.Nil)"), "10");
+    assert_eq!(eval("first (List.Cons 0 List.Nil)"), "1");
+    assert_eq!(eval("first (List.Cons 4 List.Nil)"), "5");
+    assert_eq!(eval("first List.Nil"), "0");
+  }
+
+  #[test]
+  pub fn match_errors() {
+    let error = |src| match parse(src) {
+      Err(ParseErrorKind::InvalidMatch(err)) => err,
+      res => panic!("{:?}", res.map(|_| ())),
+    };
+    let missing = |src| match error(src) {
+      CheckError::NonExhaustiveMatch(_, pat) => pat,
+      err => panic!("{}", err),
+    };
+    let overlapping = |src| match error(src) {
+      CheckError::OverlappingPattern(_, pat) => pat,
+      err => panic!("{}", err),
+    };
+    assert_eq!(
+      missing("def f (b: Bool): #Nat = match b { True => 1 }"),
+      "False"
+    );
+    assert_eq!(
+      missing(
+        "def f (xs: List #Nat): #Nat = match xs { Cons x Nil => x, Nil => 0 }"
+      ),
+      "Cons _ (Cons _ _)"
+    );
+    assert_eq!(missing("def f (n: #Nat): #Nat = match n { 0 => 1 }"), "_");
+    assert_eq!(
+      missing("def f (b: #Bool): #Nat = match b { #Bool.false => 1 }"),
+      "#Bool.true"
+    );
+    assert_eq!(
+      overlapping(
+        "def f (xs: List #Nat): #Nat = match xs { Nil => 1, _ => 2, Cons x y \
+         => 3 }"
+      ),
+      "Cons x y"
+    );
+    assert_eq!(
+      overlapping("def f (n: #Nat): #Nat = match n { x => x, 0 => 1 }"),
+      "0"
+    );
+    assert!(matches!(
+      error("def f (xs: List #Nat): #Nat = match xs { Cons x => 1, _ => 2 }"),
+      CheckError::GenericError(..)
+    ));
+    assert!(matches!(
+      error("def f (xs: List #Nat): #Nat = match xs { Nil => 0, 1 => 2 }"),
+      CheckError::GenericError(..)
+    ));
+  }
+
+  #[test]
+  pub fn dependent_match() {
+    // The motives are solved from the type of the match, which may depend on
+    // its scrutinee
+    let defs = parse(
+      "def elim (0 P: ∀ Bool -> Type) (t: P Bool.True) (f: P Bool.False)
+         (b: Bool): P b = match b { True => t, False => f }
+       def both (0 P: ∀ Bool -> Type) (x: ∀ (b: Bool) -> P b)
+         (b: Bool): P b = match b { _ => x b }",
+    )
+    .unwrap();
+    for name in ["elim", "both"] {
+      let res = check_def(&defs, name);
+      assert!(res.is_ok(), "{}: {}", name, res.unwrap_err());
+    }
+    assert_eq!(term(&defs, "elim"), "λ P t f b => (case b) (λ b => P b) t f");
+  }
+}
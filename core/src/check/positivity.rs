@@ -0,0 +1,177 @@
+use crate::{
+  check::error::CheckError,
+  name::Name,
+  term::Term,
+};
+
+/// Checks that the Self types in a definition only refer to the definition
+/// itself strictly positively. The body of a Self type binds a motive, whose
+/// domain is the type being defined, and then one case per constructor, `∀
+/// (fields) -> P (data ...)`. The type may appear in a field only as the
+/// result of it, and never in the domain of a forall or in an argument to
+/// another type, so that it can't be used to build an inhabitant of itself
+/// out of a function that consumes one.
+pub fn check_positivity(name: &Name, term: &Term) -> Result<(), CheckError> {
+  match term {
+    Term::Slf(_, _, bod) => {
+      check_self(name, bod)?;
+      check_positivity(name, bod)
+    }
+    Term::Lam(_, _, dom, bod) => {
+      if let Some((_, dom)) = dom {
+        check_positivity(name, dom)?;
+      }
+      check_positivity(name, bod)
+    }
+    Term::App(_, fun_arg) | Term::Prj(_, _, fun_arg) => {
+      check_positivity(name, &fun_arg.0)?;
+      check_positivity(name, &fun_arg.1)
+    }
+    Term::All(_, _, _, _, dom_img) => {
+      check_positivity(name, &dom_img.0)?;
+      check_positivity(name, &dom_img.1)
+    }
+    Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => check_positivity(name, bod),
+    Term::Let(_, _, _, _, typ_exp_bod) => {
+      let (typ, exp, bod) = &**typ_exp_bod;
+      check_positivity(name, typ)?;
+      check_positivity(name, exp)?;
+      check_positivity(name, bod)
+    }
+    Term::Ann(_, typ_exp) => {
+      check_positivity(name, &typ_exp.0)?;
+      check_positivity(name, &typ_exp.1)
+    }
+    _ => Ok(()),
+  }
+}
+
+fn check_self(name: &Name, bod: &Term) -> Result<(), CheckError> {
+  let mut term = bod;
+  while let Term::All(_, _, _, _, dom_img) = term {
+    let (dom, img) = &**dom_img;
+    if !is_motive(dom) {
+      let mut case = dom;
+      while let Term::All(_, _, _, _, dom_img) = case {
+        strictly_positive(name, &dom_img.0)?;
+        case = &dom_img.1;
+      }
+      strictly_positive(name, case)?;
+    }
+    term = img;
+  }
+  strictly_positive(name, term)
+}
+
+// Whether a binder of a Self type is a motive, a type family `∀ ... -> Type`
+fn is_motive(typ: &Term) -> bool {
+  let mut term = typ;
+  while let Term::All(_, _, _, _, dom_img) = term {
+    term = &dom_img.1;
+  }
+  matches!(term, Term::Typ(..))
+}
+
+fn strictly_positive(name: &Name, typ: &Term) -> Result<(), CheckError> {
+  let mut term = typ;
+  while let Term::All(_, _, _, _, dom_img) = term {
+    if occurs(&dom_img.0) {
+      return Err(non_positive(name, typ));
+    }
+    term = &dom_img.1;
+  }
+  let mut head = term;
+  while let Term::App(_, fun_arg) = head {
+    if occurs(&fun_arg.1) {
+      return Err(non_positive(name, typ));
+    }
+    head = &fun_arg.0;
+  }
+  match head {
+    Term::Rec(_) | Term::Mut(..) => Ok(()),
+    _ if occurs(head) => Err(non_positive(name, typ)),
+    _ => Ok(()),
+  }
+}
+
+fn non_positive(name: &Name, typ: &Term) -> CheckError {
+  CheckError::NonPositive(typ.pos(), typ.pretty(Some(&name.to_string())))
+}
+
+// Whether a term refers to the definition it is part of, or to another member
+// of its `mutual` block
+fn occurs(term: &Term) -> bool {
+  match term {
+    Term::Rec(_) | Term::Mut(..) => true,
+    Term::Lam(_, _, dom, bod) => {
+      dom.as_ref().map_or(false, |(_, dom)| occurs(dom)) || occurs(bod)
+    }
+    Term::App(_, fun_arg) | Term::Prj(_, _, fun_arg) => {
+      occurs(&fun_arg.0) || occurs(&fun_arg.1)
+    }
+    Term::All(_, _, _, _, dom_img) => occurs(&dom_img.0) || occurs(&dom_img.1),
+    Term::Slf(_, _, bod)
+    | Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => occurs(bod),
+    Term::Let(_, _, _, _, typ_exp_bod) => {
+      let (typ, exp, bod) = &**typ_exp_bod;
+      occurs(typ) || occurs(exp) || occurs(bod)
+    }
+    Term::Ann(_, typ_exp) => occurs(&typ_exp.0) || occurs(&typ_exp.1),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use crate::{
+    check::{
+      check_def,
+      error::CheckError,
+    },
+    defs::Defs,
+    tests::parse_package,
+  };
+
+  fn check(src: &str, name: &str) -> Result<(), CheckError> {
+    let (defs, _) = parse_package(src).unwrap();
+    check_def(&defs, name).map(|_| ())
+  }
+
+  #[test]
+  pub fn check_positive() {
+    for (src, name) in [
+      ("type List (A: Type) { Nil, Cons (x: A) (xs: List A) }", "List"),
+      ("type Tree { Leaf, Node (kids: ∀ #Nat -> Tree) }", "Tree"),
+      ("type Fun { Mk (f: ∀ #Nat -> #Nat) }", "Fun"),
+      ("partial type Bad { Mk (f: ∀ Bad -> #Nat) }", "Bad"),
+    ] {
+      let res = check(src, name);
+      assert!(res.is_ok(), "{}: {}", name, res.unwrap_err());
+    }
+  }
+
+  #[test]
+  pub fn check_non_positive() {
+    for (src, name) in [
+      ("type Bad { Mk (f: ∀ Bad -> #Nat) }", "Bad"),
+      ("type Bad { Mk (f: ∀ (∀ Bad -> #Nat) -> Bad) }", "Bad"),
+      (
+        "type Box (A: Type) { Mk (x: A) }
+         type Bad { Mk (b: Box (∀ Bad -> #Nat)) }",
+        "Bad",
+      ),
+    ] {
+      let res = check(src, name);
+      assert!(
+        matches!(res, Err(CheckError::NonPositive(..))),
+        "{}: {:?}",
+        name,
+        res
+      );
+    }
+  }
+}
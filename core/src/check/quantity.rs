@@ -0,0 +1,257 @@
+use crate::{
+  check::{
+    check_named_def,
+    error::CheckError,
+    CheckOpts,
+  },
+  defs::{
+    Def,
+    Defs,
+  },
+  name::Name,
+  position::Pos,
+  term::Term,
+  uses::Uses,
+};
+
+/// A binder of a definition, with the quantity it is annotated with and the
+/// tightest quantity it could be annotated with instead
+#[derive(PartialEq, Clone, Debug)]
+pub struct BinderUses {
+  pub name: Name,
+  /// The position of the `let` the binder belongs to, or `None` for a
+  /// parameter of the definition
+  pub pos: Option<Pos>,
+  pub written: Uses,
+  pub inferred: Uses,
+}
+
+// The quantities a binder is tried with, from the tightest to the loosest. A
+// binder that's never used at runtime is erased, one used exactly once is
+// linear, and one used at most once is affine.
+const CANDIDATES: [Uses; 4] = [Uses::None, Uses::Once, Uses::Affi, Uses::Many];
+
+/// Whether an error is only about the quantities of binders, and so might be
+/// fixed by changing them
+pub fn is_quantity_error(err: &CheckError) -> bool {
+  matches!(
+    err,
+    CheckError::QuantityTooLittle(..)
+      | CheckError::QuantityTooMuch(..)
+      | CheckError::LamUsesMismatch(..)
+  )
+}
+
+/// Infers the tightest quantity of every parameter and `let` binder of a
+/// definition, by checking it with each binder in turn, from the last to the
+/// first, given the tightest quantity that keeps the checker's usage
+/// accounting from failing. Returns `None` if the definition doesn't
+/// exist, is a member of a `mutual` block, fails to check for a reason other
+/// than quantities, or can't be made to check by changing them. The
+/// definitions that refer to this one aren't checked, and may rely on the
+/// looser quantities it's written with.
+pub fn infer_uses(
+  defs: &Defs,
+  name: &str,
+  opts: CheckOpts,
+) -> Option<Vec<BinderUses>> {
+  let name = Name::from(name);
+  let def = defs.get(&name)?;
+  if def.mutual.is_some() || def.is_axiom() {
+    return None;
+  }
+  let binders = binders(&def.typ_, &def.term);
+  let written: Vec<Uses> = binders.iter().map(|(_, uses, _)| *uses).collect();
+  let valid = |uses: &[Uses]| check_with(defs, &name, def, uses, opts);
+  // Other errors may stop the checker before it gets to count some uses, so
+  // nothing could be inferred from them
+  valid(&written)?;
+  // The uses of a binder are counted in the scopes of the binders after it,
+  // such as the expression of a later `let`, scaled by their quantities, but
+  // never the other way around. So each binder only has to be tried once,
+  // after the ones it's used in are given their quantities, while the ones
+  // before it are unrestricted.
+  let mut uses = vec![Uses::Many; written.len()];
+  for k in (0..uses.len()).rev() {
+    let tightest = CANDIDATES.iter().find(|cand| {
+      uses[k] = **cand;
+      valid(&uses) == Some(true)
+    });
+    uses[k] = tightest.copied().unwrap_or(Uses::Many);
+  }
+  if valid(&uses) != Some(true) {
+    return None;
+  }
+  let res = binders
+    .into_iter()
+    .zip(written.into_iter().zip(uses))
+    .map(|((name, _, pos), (written, inferred))| BinderUses {
+      name,
+      pos,
+      written,
+      inferred,
+    })
+    .collect();
+  Some(res)
+}
+
+// Whether a definition checks with its binders given the quantities `uses`,
+// as far as quantities go. Returns `None` if it fails for another reason.
+fn check_with(
+  defs: &Defs,
+  name: &Name,
+  def: &Def,
+  uses: &[Uses],
+  opts: CheckOpts,
+) -> Option<bool> {
+  let (typ_, term) = with_uses(&def.typ_, &def.term, uses);
+  let (new, _) = Def::make(def.pos, def.partial, def.opaque, typ_, term);
+  match check_named_def(defs, name.as_ref(), &new, opts) {
+    Ok(_) => Some(true),
+    Err(errs) if errs.iter().all(is_quantity_error) => Some(false),
+    Err(_) => None,
+  }
+}
+
+/// The binders of a definition whose quantities are inferred, with their
+/// names, quantities and the position of their `let`. These are its
+/// parameters, each binding both a forall of its type and a lambda of its
+/// term, followed by the `let`s of its term in order.
+pub fn binders(typ: &Term, term: &Term) -> Vec<(Name, Uses, Option<Pos>)> {
+  let mut res = Vec::new();
+  let (mut typ, mut term) = (typ, term);
+  while let (Term::All(_, _, uses, nam, dom_img), Term::Lam(_, _, _, bod)) =
+    (typ, term)
+  {
+    res.push((nam.clone(), *uses, None));
+    typ = &dom_img.1;
+    term = bod;
+  }
+  lets(term, &mut res);
+  res
+}
+
+fn lets(term: &Term, res: &mut Vec<(Name, Uses, Option<Pos>)>) {
+  match term {
+    Term::Let(pos, _, uses, nam, typ_exp_bod) => {
+      res.push((nam.clone(), *uses, Some(*pos)));
+      lets(&typ_exp_bod.1, res);
+      lets(&typ_exp_bod.2, res);
+    }
+    Term::Lam(_, _, _, bod)
+    | Term::Slf(_, _, bod)
+    | Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => lets(bod, res),
+    Term::App(_, fun_arg) | Term::Prj(_, _, fun_arg) => {
+      lets(&fun_arg.0, res);
+      lets(&fun_arg.1, res);
+    }
+    Term::Ann(_, typ_exp) => lets(&typ_exp.1, res),
+    _ => (),
+  }
+}
+
+// Gives the binders listed by `binders` the quantities `uses`, in order
+fn with_uses(typ: &Term, term: &Term, uses: &[Uses]) -> (Term, Term) {
+  let (mut typ, mut term) = (typ.clone(), term.clone());
+  set_params(&mut typ, &mut term, &mut uses.iter().copied());
+  (typ, term)
+}
+
+fn set_params(
+  typ: &mut Term,
+  term: &mut Term,
+  uses: &mut impl Iterator<Item = Uses>,
+) {
+  match (typ, term) {
+    (Term::All(_, _, all_uses, _, dom_img), Term::Lam(_, _, dom, bod)) => {
+      if let Some(new) = uses.next() {
+        *all_uses = new;
+        // An annotated lambda must agree with its forall
+        if let Some((lam_uses, _)) = dom {
+          *lam_uses = new;
+        }
+      }
+      set_params(&mut dom_img.1, bod, uses)
+    }
+    (_, term) => set_lets(term, uses),
+  }
+}
+
+fn set_lets(term: &mut Term, uses: &mut impl Iterator<Item = Uses>) {
+  match term {
+    Term::Let(_, _, let_uses, _, typ_exp_bod) => {
+      if let Some(new) = uses.next() {
+        *let_uses = new;
+      }
+      set_lets(&mut typ_exp_bod.1, uses);
+      set_lets(&mut typ_exp_bod.2, uses);
+    }
+    Term::Lam(_, _, _, bod)
+    | Term::Slf(_, _, bod)
+    | Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => set_lets(bod, uses),
+    Term::App(_, fun_arg) | Term::Prj(_, _, fun_arg) => {
+      set_lets(&mut fun_arg.0, uses);
+      set_lets(&mut fun_arg.1, uses);
+    }
+    Term::Ann(_, typ_exp) => set_lets(&mut typ_exp.1, uses),
+    _ => (),
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::tests::parse_package;
+
+  fn infer(src: &str, name: &str) -> Option<Vec<(String, Uses, Uses)>> {
+    let (defs, _) = parse_package(src).unwrap();
+    let res = infer_uses(&defs, name, CheckOpts::default())?;
+    Some(
+      res
+        .into_iter()
+        .map(|b| (b.name.to_string(), b.written, b.inferred))
+        .collect(),
+    )
+  }
+
+  #[test]
+  pub fn infer_quantities() {
+    let (zero, once, many) = (Uses::None, Uses::Once, Uses::Many);
+    assert_eq!(
+      infer("def id (A: Type) (x: A): A = x", "id"),
+      Some(vec![("A".to_owned(), many, zero), ("x".to_owned(), many, once)])
+    );
+    // Written quantities that don't check are loosened
+    assert_eq!(
+      infer("def dup (1 x: #Nat): #Nat = #Nat.add x x", "dup"),
+      Some(vec![("x".to_owned(), once, many)])
+    );
+    assert_eq!(
+      infer(
+        "def k (x: #Nat) (y: #Nat): #Nat =
+           let z: #Nat = #Nat.add x 1;
+           let w: #Nat = 2;
+           z",
+        "k"
+      ),
+      Some(vec![
+        // Primitive operations may use their arguments any number of times
+        ("x".to_owned(), many, many),
+        ("y".to_owned(), many, zero),
+        ("z".to_owned(), many, once),
+        ("w".to_owned(), many, zero),
+      ])
+    );
+    // A parameter only used by an erased `let` is erased along with it
+    assert_eq!(
+      infer("def f (1 x: #Nat): #Nat = let y: #Nat = x; 1", "f"),
+      Some(vec![("x".to_owned(), once, zero), ("y".to_owned(), many, zero)])
+    );
+    // Nothing can be inferred of a definition with other errors
+    assert_eq!(infer("def bad (x: #Nat): #Text = x", "bad"), None);
+  }
+}
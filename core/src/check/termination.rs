@@ -0,0 +1,456 @@
+use crate::{
+  check::error::CheckError,
+  defs::Defs,
+  name::Name,
+  term::Term,
+};
+
+// How a bound variable relates to the parameters of the function being checked
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Size {
+  // The parameter at this position
+  Param(usize),
+  // A strict subterm of the parameter at this position
+  Below(usize),
+  Unknown,
+}
+
+// The recursive references to the function being checked
+#[derive(Clone, Copy, Debug)]
+enum Target {
+  // `Term::Rec`, the definition itself, or `Term::Mut`, another member of its
+  // `mutual` block
+  Rec,
+  // The variable bound by a `letrec` at this de Bruijn level
+  Var(usize),
+}
+
+struct Function<'a> {
+  defs: &'a Defs,
+  name: Name,
+  target: Target,
+  // The positions of the parameters decreased by every call met so far
+  decreasing: Vec<usize>,
+}
+
+/// Checks that the recursive calls of a definition, and of the `letrec`s in
+/// it, terminate. There has to be a parameter every recursive call passes a
+/// structurally smaller argument for, where the variables bound by the cases
+/// of a `case` on a parameter, or on one of its subterms, are smaller than it.
+/// This covers both recursion on Self-encoded datatypes and on `#Nat`
+/// literals, whose predecessor is bound by the successor case.
+///
+/// The members of a `mutual` block are checked together, as calls between
+/// them have to decrease the parameter at the same position in all of them.
+pub fn check_termination(
+  defs: &Defs,
+  members: &[(Name, &Term)],
+) -> Result<(), CheckError> {
+  let params = members.iter().map(|(_, term)| params(term)).max();
+  let mut fun = Function {
+    defs,
+    name: Name::from(""),
+    target: Target::Rec,
+    decreasing: (0..params.unwrap_or(0)).collect(),
+  };
+  for (name, term) in members {
+    fun.name = name.clone();
+    fun.walk_function(0, term)?;
+  }
+  Ok(())
+}
+
+/// Checks that a definition that isn't `partial` refers to no definition
+/// that is, since a partial definition may loop in the place of a value of
+/// its type, and so of a proof
+pub fn check_total_refs(defs: &Defs, term: &Term) -> Result<(), CheckError> {
+  match term {
+    Term::Ref(pos, nam, def_cid, _) => match defs.defs.get(def_cid) {
+      Some(def) if def.partial => {
+        Err(CheckError::PartialReference(*pos, nam.to_string()))
+      }
+      _ => Ok(()),
+    },
+    Term::Lam(_, _, dom, bod) => {
+      if let Some((_, dom)) = dom {
+        check_total_refs(defs, dom)?;
+      }
+      check_total_refs(defs, bod)
+    }
+    Term::App(_, two)
+    | Term::All(_, _, _, _, two)
+    | Term::Ann(_, two)
+    | Term::Prj(_, _, two) => {
+      check_total_refs(defs, &two.0)?;
+      check_total_refs(defs, &two.1)
+    }
+    Term::Slf(_, _, bod)
+    | Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => check_total_refs(defs, bod),
+    Term::Let(_, _, _, _, typ_exp_bod) => {
+      check_total_refs(defs, &typ_exp_bod.0)?;
+      check_total_refs(defs, &typ_exp_bod.1)?;
+      check_total_refs(defs, &typ_exp_bod.2)
+    }
+    _ => Ok(()),
+  }
+}
+
+fn params(term: &Term) -> usize {
+  let mut params = 0;
+  let mut bod = term;
+  while let Term::Lam(_, _, _, lam_bod) = bod {
+    params += 1;
+    bod = lam_bod;
+  }
+  params
+}
+
+fn lookup(ctx: &[Size], idx: u64) -> Size {
+  match ctx.len().checked_sub(1 + idx as usize) {
+    Some(lvl) => ctx[lvl],
+    None => Size::Unknown,
+  }
+}
+
+// Splits an application into its head and its arguments
+fn spine(term: &Term) -> (&Term, Vec<&Term>) {
+  let mut args = vec![];
+  let mut head = term;
+  while let Term::App(_, fun_arg) = head {
+    args.push(&fun_arg.1);
+    head = &fun_arg.0;
+  }
+  args.reverse();
+  (head, args)
+}
+
+// The size of an argument, which is smaller than a parameter if its head is,
+// since applying a smaller function gives a smaller result
+fn size(ctx: &[Size], arg: &Term) -> Size {
+  match spine(arg).0 {
+    Term::Var(_, _, idx) => match lookup(ctx, *idx) {
+      Size::Below(i) => Size::Below(i),
+      _ => Size::Unknown,
+    },
+    _ => Size::Unknown,
+  }
+}
+
+// The number of arguments a motive returns a function of, which are passed
+// after the cases of a `case`
+fn arity<'a>(defs: &'a Defs, motive: &'a Term) -> usize {
+  let mut term = motive;
+  while let Term::Lam(_, _, _, bod) = term {
+    term = bod;
+  }
+  let mut n = 0;
+  loop {
+    match term {
+      Term::All(_, _, _, _, dom_img) => {
+        n += 1;
+        term = &dom_img.1;
+      }
+      Term::Ref(_, _, def_cid, _) => match defs.defs.get(def_cid) {
+        Some(def) => term = &def.term,
+        None => return n,
+      },
+      _ => return n,
+    }
+  }
+}
+
+impl<'a> Function<'a> {
+  // Walks the calls in `term`, whose leading lambdas bind the parameters,
+  // below `depth` binders
+  fn walk_function(
+    &mut self,
+    depth: usize,
+    term: &Term,
+  ) -> Result<(), CheckError> {
+    // Variables bound outside of the function are not related to its
+    // parameters
+    let mut ctx = vec![Size::Unknown; depth];
+    let mut bod = term;
+    while let Term::Lam(_, _, dom, lam_bod) = bod {
+      if let Some((_, dom)) = dom {
+        self.walk(&mut ctx, dom)?;
+      }
+      ctx.push(Size::Param(ctx.len() - depth));
+      bod = lam_bod;
+    }
+    self.walk(&mut ctx, bod)
+  }
+
+  fn is_target(&self, ctx: &[Size], term: &Term) -> bool {
+    match (self.target, term) {
+      (Target::Rec, Term::Rec(_) | Term::Mut(..)) => true,
+      (Target::Var(lvl), Term::Var(_, _, idx)) => {
+        ctx.len().checked_sub(1 + *idx as usize) == Some(lvl)
+      }
+      _ => false,
+    }
+  }
+
+  fn call(
+    &mut self,
+    ctx: &[Size],
+    term: &Term,
+    args: &[&Term],
+  ) -> Result<(), CheckError> {
+    self.decreasing.retain(|i| {
+      args.get(*i).map_or(false, |arg| size(ctx, arg) == Size::Below(*i))
+    });
+    if self.decreasing.is_empty() {
+      let call = term.pretty(Some(&self.name.to_string()));
+      Err(CheckError::NonTerminating(term.pos(), call))
+    }
+    else {
+      Ok(())
+    }
+  }
+
+  fn under(
+    &mut self,
+    ctx: &mut Vec<Size>,
+    size: Size,
+    bod: &Term,
+  ) -> Result<(), CheckError> {
+    ctx.push(size);
+    let res = self.walk(ctx, bod);
+    ctx.pop();
+    res
+  }
+
+  fn walk(
+    &mut self,
+    ctx: &mut Vec<Size>,
+    term: &Term,
+  ) -> Result<(), CheckError> {
+    match term {
+      Term::Rec(_) | Term::Mut(..) | Term::Var(..)
+        if self.is_target(ctx, term) =>
+      {
+        self.call(ctx, term, &[])
+      }
+      Term::App(..) => {
+        let (head, args) = spine(term);
+        match head {
+          Term::Cse(_, scrutinee) => {
+            self.walk(ctx, scrutinee)?;
+            return self.walk_cases(ctx, scrutinee, &args);
+          }
+          _ if self.is_target(ctx, head) => self.call(ctx, term, &args)?,
+          _ => self.walk(ctx, head)?,
+        }
+        for arg in args {
+          self.walk(ctx, arg)?;
+        }
+        Ok(())
+      }
+      Term::Lam(_, _, dom, bod) => {
+        if let Some((_, dom)) = dom {
+          self.walk(ctx, dom)?;
+        }
+        self.under(ctx, Size::Unknown, bod)
+      }
+      Term::All(_, _, _, _, dom_img) => {
+        self.walk(ctx, &dom_img.0)?;
+        self.under(ctx, Size::Unknown, &dom_img.1)
+      }
+      // Recursive occurrences in Self types are checked for positivity instead
+      Term::Slf(..) => Ok(()),
+      Term::Dat(_, bod)
+      | Term::Cse(_, bod)
+      | Term::Rcd(_, _, bod) => self.walk(ctx, bod),
+      Term::Ann(_, two) | Term::Prj(_, _, two) => {
+        self.walk(ctx, &two.0)?;
+        self.walk(ctx, &two.1)
+      }
+      Term::Let(_, letrec, _, _, typ_exp_bod) => {
+        let (typ, exp, bod) = &**typ_exp_bod;
+        self.walk(ctx, typ)?;
+        if *letrec {
+          // The expression of a `letrec` is a function of its own, which
+          // recurses through the variable it binds
+          let lvl = ctx.len();
+          self.under(ctx, Size::Unknown, exp)?;
+          let mut fun = Function {
+            defs: self.defs,
+            name: self.name.clone(),
+            target: Target::Var(lvl),
+            decreasing: (0..params(exp)).collect(),
+          };
+          fun.walk_function(lvl + 1, exp)?;
+        }
+        else {
+          self.walk(ctx, exp)?;
+        }
+        self.under(ctx, Size::Unknown, bod)
+      }
+      _ => Ok(()),
+    }
+  }
+
+  // Walks the arguments of `case scrutinee`, the first of which is the motive
+  fn walk_cases(
+    &mut self,
+    ctx: &mut Vec<Size>,
+    scrutinee: &Term,
+    args: &[&Term],
+  ) -> Result<(), CheckError> {
+    let field_size = match scrutinee {
+      Term::Var(_, _, idx) => match lookup(ctx, *idx) {
+        Size::Param(i) | Size::Below(i) => Size::Below(i),
+        Size::Unknown => Size::Unknown,
+      },
+      _ => Size::Unknown,
+    };
+    let extra = args.first().map_or(0, |motive| arity(self.defs, motive));
+    let cases = args.len().saturating_sub(1 + extra);
+    for (k, arg) in args.iter().enumerate() {
+      if k == 0 || k > cases {
+        self.walk(ctx, arg)?;
+        continue;
+      }
+      // The fields bound by a case are subterms of the scrutinee
+      let depth = ctx.len();
+      let mut bod = *arg;
+      let mut res = Ok(());
+      while let Term::Lam(_, _, dom, lam_bod) = bod {
+        if let Some((_, dom)) = dom {
+          res = res.and_then(|_| self.walk(ctx, dom));
+        }
+        ctx.push(field_size);
+        bod = lam_bod;
+      }
+      let res = res.and_then(|_| self.walk(ctx, bod));
+      ctx.truncate(depth);
+      res?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use crate::{
+    check::{
+      check_def,
+      error::CheckError,
+    },
+    defs::Defs,
+    name::Name,
+    tests::parse_package,
+  };
+
+  fn check(src: &str, name: &str) -> Result<(), CheckError> {
+    let (defs, _) = parse_package(src).unwrap();
+    check_def(&defs, name).map(|_| ())
+  }
+
+  #[test]
+  pub fn check_terminating() {
+    let list = "type List (A: Type) { Nil, Cons (x: A) (xs: List A) }\n";
+    let ok = |src: &str, name: &str| {
+      let res = check(&format!("{}{}", list, src), name);
+      assert!(res.is_ok(), "{}: {}", name, res.unwrap_err());
+    };
+    ok(
+      "def length (A: Type) (xs: List A): #Nat = match xs {
+         List.Nil => 0,
+         List.Cons _ rest => #Nat.add 1 (length A rest),
+       }",
+      "length",
+    );
+    ok(
+      "def fact (n: #Nat): #Nat =
+         (case n) (λ _ => #Nat) 1 (λ pred => #Nat.mul n (fact pred))",
+      "fact",
+    );
+    // Nested patterns and accumulators passed after the cases
+    ok(
+      "def pairs (A: Type) (xs: List A) (acc: #Nat): #Nat = match xs {
+         List.Cons _ (List.Cons _ rest) => pairs A rest (#Nat.add acc 1),
+         _ => acc,
+       }",
+      "pairs",
+    );
+    ok(
+      "def sum (xs: List #Nat): #Nat =
+         letrec go: ∀ (xs: List #Nat) (acc: #Nat) -> #Nat = λ xs acc =>
+           (case xs) (λ _ => #Nat) acc (λ x rest => go rest (#Nat.add acc x));
+         go xs 0",
+      "sum",
+    );
+    ok("partial def loop (n: #Nat): #Nat = loop n", "loop");
+    // Calls between the members of a `mutual` block
+    ok(
+      "mutual {
+         def even (n: #Nat): #Bool =
+           (case n) (λ _ => #Bool) #Bool.true (λ m => odd m)
+         def odd (n: #Nat): #Bool =
+           (case n) (λ _ => #Bool) #Bool.false (λ m => even m)
+       }",
+      "odd",
+    );
+  }
+
+  #[test]
+  pub fn check_nonterminating() {
+    let err = |src: &str, name: &str| {
+      let res = check(src, name);
+      assert!(
+        matches!(res, Err(CheckError::NonTerminating(..))),
+        "{}: {:?}",
+        name,
+        res
+      );
+    };
+    err("def loop (n: #Nat): #Nat = loop n", "loop");
+    err("def bot: #Nat = bot", "bot");
+    err(
+      "def grow (n: #Nat): #Nat =
+         (case n) (λ _ => #Nat) 0 (λ pred => grow (#Nat.suc n))",
+      "grow",
+    );
+    // Every call has to decrease the same parameter
+    err(
+      "def swap (m n: #Nat): #Nat =
+         (case m) (λ _ => #Nat) 0 (λ m' => (case n) (λ _ => #Nat) 0 (λ n' =>
+           #Nat.add (swap m' n) (swap m n')))",
+      "swap",
+    );
+    err(
+      "def sum (xs: #Nat): #Nat =
+         letrec go: ∀ (n: #Nat) -> #Nat = λ n => go n;
+         go xs",
+      "sum",
+    );
+    err(
+      "mutual {
+         def f (n: #Nat): #Nat = (case n) (λ _ => #Nat) 0 (λ m => g m)
+         def g (n: #Nat): #Nat = f (#Nat.suc n)
+       }",
+      "f",
+    );
+    let src = "partial def loop (n: #Nat): #Nat = loop n";
+    let (defs, _) = parse_package(src).unwrap();
+    let def = defs.get(&Name::from("loop")).unwrap();
+    assert!(def.partial);
+    assert!(def.embed().0.partial);
+  }
+
+  #[test]
+  pub fn check_partial_refs() {
+    let src = "partial def loop (0 A: Type): A = loop A
+               def bottom: ∀ (0 A: Type) -> A = λ A => loop A
+               partial def also: ∀ (0 A: Type) -> A = λ A => loop A";
+    match check(src, "bottom") {
+      Err(CheckError::PartialReference(_, nam)) => assert_eq!(nam, "loop"),
+      res => panic!("{:?}", res),
+    }
+    assert!(check(src, "also").is_ok());
+  }
+}
@@ -0,0 +1,520 @@
+use crate::{
+  check::{
+    ctx::ErrCtx,
+    error::CheckError,
+    CheckOpts,
+    detach_root,
+    free_vars_at,
+    hole::Hole,
+  },
+  dag::*,
+  defs::Defs,
+  dll::*,
+  eval::error::EvalError,
+  name::Name,
+  position::Pos,
+  term::Term,
+  uses::Uses,
+};
+
+use core::{
+  cell::RefCell,
+  ptr::NonNull,
+};
+use std::collections::{
+  HashMap,
+  HashSet,
+};
+
+// A hole met by the typechecker. Holes are numbered before checking starts, so
+// that every copy of a hole made while substituting into types can be traced
+// back to it. Holes numbered 0 come from outside the checked definition, such
+// as the types of references, and are never solved.
+pub struct MetaVar {
+  pub idx: u64,
+  pub pos: Pos,
+  pub nam: Name,
+  // The depth of the context the hole was met in; a solution may only refer
+  // to the variables bound at lower depths
+  pub dep: u64,
+  pub goal: DAG,
+  pub ctx: ErrCtx,
+}
+
+// The metavariable store threaded through the typechecker. Solutions are kept
+// as DAGs whose free variables are identified by their depth in the context,
+// like the types in `Ctx`.
+#[derive(Default)]
+pub struct Metas {
+  pub vars: Vec<MetaVar>,
+  pub solutions: HashMap<u64, DAG>,
+  // The options of the check the holes are met in
+  pub opts: CheckOpts,
+  // Errors met in parts of a term whose failure doesn't keep the rest of it
+  // from being checked, such as a single argument of an application
+  pub errors: Vec<CheckError>,
+  // The quantities erasure is directed by, keyed by the paths in the checked
+  // value of the terms they are met at: those of the foralls that lambdas are
+  // checked against and that applications are inferred through, and those of
+  // the first foralls of the Self types met at `data` and `case`
+  pub quantities: HashMap<Path, Uses>,
+  // The quantities met while checking, keyed by the address of their terms
+  // until `record_paths` finds the terms in the value
+  met_uses: HashMap<*const Term, Uses>,
+  // The positions of the lambda domains and annotated terms met where the
+  // type they give is the one expected, with no hole left in it for them to
+  // solve, which the term checks the same without
+  pub redundant_annotations: Vec<Pos>,
+  // The hole nodes `instantiate` took out of their DAGs. They are freed with
+  // the store rather than when they are replaced, since the conversion
+  // checker may still hold pointers to them
+  replaced: RefCell<HashSet<DAGPtr>>,
+}
+
+impl Metas {
+  pub fn new() -> Self { Self::default() }
+
+  pub fn with_opts(opts: CheckOpts) -> Self {
+    let mut metas = Self::default();
+    metas.opts = opts;
+    metas
+  }
+
+  pub fn register(
+    &mut self,
+    idx: u64,
+    pos: Pos,
+    nam: &Name,
+    dep: u64,
+    goal: &DAG,
+    ctx: ErrCtx,
+  ) {
+    let goal = goal.clone();
+    self.vars.push(MetaVar { idx, pos, nam: nam.clone(), dep, goal, ctx });
+  }
+
+  // Records the error of an independent part of a term, if there is one, so
+  // that checking can carry on past it
+  pub fn recover(&mut self, res: Result<(), CheckError>) {
+    if let Err(err) = res {
+      self.errors.push(err);
+    }
+  }
+
+  // Records the quantity `uses` met at `term`
+  pub fn record_uses(&mut self, term: &Term, uses: Uses) {
+    self.met_uses.insert(term, uses);
+  }
+
+  // Keys the quantities met while checking `term` by their paths in it, and
+  // forgets the ones met elsewhere, such as in its type
+  pub fn record_paths(&mut self, term: &Term) {
+    let mut stack = vec![(term, vec![])];
+    while let Some((term, path)) = stack.pop() {
+      if let Some(uses) = self.met_uses.get(&(term as *const Term)) {
+        self.quantities.insert(path.clone(), *uses);
+      }
+      for (slot, child) in children(term) {
+        let mut path = path.clone();
+        path.push(slot);
+        stack.push((child, path));
+      }
+    }
+    self.met_uses.clear();
+  }
+
+  pub fn scope(&self, idx: u64) -> Option<u64> {
+    self.vars.iter().find(|var| var.idx == idx).map(|var| var.dep)
+  }
+
+  pub fn is_solved(&self, idx: u64) -> bool {
+    self.solutions.contains_key(&idx)
+  }
+
+  // Replaces every solved hole of a DAG by a copy of its solution. The
+  // replaced nodes are freed when the store is dropped.
+  pub fn instantiate(&self, dag: &mut DAG) {
+    loop {
+      let solved: Vec<(DAGPtr, &DAG)> = holes_in(dag.head)
+        .into_iter()
+        .filter_map(|link| {
+          let idx = unsafe { (*link.as_ptr()).idx };
+          self.solutions.get(&idx).map(|sol| (DAGPtr::Hol(link), sol))
+        })
+        .collect();
+      if solved.is_empty() {
+        return;
+      }
+      for (node, sol) in solved {
+        let copy = DAG::from_subdag(sol.head, &mut HashMap::new(), None);
+        if dag.head == node {
+          dag.head = copy;
+        }
+        replace_child(node, copy);
+        self.replaced.borrow_mut().insert(node);
+      }
+    }
+  }
+
+  // Instantiates the solved holes of a DAG and reduces it to weak head normal
+  // form, without unfolding opaque definitions. A primitive operation applied
+  // to literals it's undefined on is left stuck, as a term that doesn't reduce
+  // further, but a reference to a definition missing from `defs` is an error
+  // of the term at `pos`.
+  pub fn whnf(
+    &self,
+    defs: &Defs,
+    dag: &mut DAG,
+    pos: Pos,
+  ) -> Result<(), CheckError> {
+    self.instantiate(dag);
+    match dag.whnf_with(defs, false) {
+      Err(EvalError::UndefinedReference(nam, _)) => {
+        Err(CheckError::UndefinedReference(pos, nam.to_string()))
+      }
+      _ => Ok(()),
+    }
+  }
+
+  // Whether a DAG mentions a hole that may still be solved
+  pub fn is_open(&self, node: DAGPtr) -> bool {
+    holes_in(node).into_iter().any(|link| unsafe {
+      let idx = (*link.as_ptr()).idx;
+      idx != 0 && !self.is_solved(idx)
+    })
+  }
+
+  // Whether a DAG is an unsolved hole applied to some arguments
+  pub fn is_flex(&self, node: DAGPtr) -> bool {
+    match spine(node).0 {
+      DAGPtr::Hol(link) => unsafe {
+        let idx = (*link.as_ptr()).idx;
+        idx != 0 && !self.is_solved(idx)
+      },
+      _ => false,
+    }
+  }
+
+  // Tries to solve `flex = rigid`, where `flex` is an unsolved hole applied to
+  // distinct variables. The solution abstracts over those variables, so it is
+  // the most general one (Miller's pattern fragment). Fails if the hole occurs
+  // in `rigid` or if `rigid` mentions variables out of the scope of the hole.
+  pub fn solve(&mut self, flex: DAGPtr, rigid: DAGPtr) -> bool {
+    let (head, args) = spine(flex);
+    let idx = match head {
+      DAGPtr::Hol(link) => unsafe { (*link.as_ptr()).idx },
+      _ => return false,
+    };
+    let scope = match self.scope(idx) {
+      Some(scope) if idx != 0 && !self.is_solved(idx) => scope,
+      _ => return false,
+    };
+    let mut params: Vec<(Name, u64)> = vec![];
+    for arg in args {
+      match arg {
+        DAGPtr::Var(link) => unsafe {
+          let Var { nam, dep, .. } = &*link.as_ptr();
+          if params.iter().any(|(_, lvl)| lvl == dep) {
+            return false;
+          }
+          params.push((nam.clone(), *dep));
+        },
+        _ => return false,
+      }
+    }
+    let occurs = holes_in(rigid)
+      .into_iter()
+      .any(|link| unsafe { (*link.as_ptr()).idx == idx });
+    if occurs {
+      return false;
+    }
+    for var in outer_vars(rigid) {
+      if let DAGPtr::Var(link) = var {
+        let dep = unsafe { (*link.as_ptr()).dep };
+        if dep >= scope && !params.iter().any(|(_, lvl)| *lvl == dep) {
+          return false;
+        }
+      }
+    }
+    let root = alloc_val(DLL::singleton(ParentPtr::Root));
+    let copy = DAG::from_subdag(rigid, &mut HashMap::new(), Some(root));
+    let sol = params
+      .iter()
+      .rev()
+      .fold(DAG::new(copy), |bod, (nam, dep)| abstract_lam(nam, *dep, bod));
+    self.solutions.insert(idx, sol);
+    true
+  }
+
+  // Reads back a DAG met at the given depth as a term
+  pub fn to_term(&self, dag: &DAG, dep: u64, re_rec: bool) -> Term {
+    let mut dag = dag.clone();
+    self.instantiate(&mut dag);
+    let mut map = HashMap::new();
+    for var in outer_vars(dag.head) {
+      if let DAGPtr::Var(link) = var {
+        map.insert(link.as_ptr(), unsafe { (*link.as_ptr()).dep });
+      }
+    }
+    let term = DAG::dag_ptr_to_term(&dag.head, &mut map, dep, re_rec);
+    dag.free();
+    term
+  }
+
+  // Replaces the solved anonymous holes of a term, found at the given depth,
+  // by their solutions. Named holes are left for the user to fill in.
+  pub fn zonk(&self, term: &Term, dep: u64) -> Term {
+    match term {
+      Term::Hol(_, nam, idx) if nam.is_empty() => {
+        match self.solutions.get(idx) {
+          Some(sol) => self.to_term(sol, dep, true),
+          None => term.clone(),
+        }
+      }
+      Term::Lam(pos, nam, dom, bod) => Term::Lam(
+        *pos,
+        nam.clone(),
+        dom.as_ref().map(|(uses, dom)| (*uses, Box::new(self.zonk(dom, dep)))),
+        Box::new(self.zonk(bod, dep + 1)),
+      ),
+      Term::App(pos, fun_arg) => {
+        let (fun, arg) = &**fun_arg;
+        let fun_arg = (self.zonk(fun, dep), self.zonk(arg, dep));
+        Term::App(*pos, Box::new(fun_arg))
+      }
+      Term::All(pos, imp, uses, nam, dom_img) => {
+        let (dom, img) = &**dom_img;
+        let dom_img = (self.zonk(dom, dep), self.zonk(img, dep + 1));
+        Term::All(*pos, *imp, *uses, nam.clone(), Box::new(dom_img))
+      }
+      Term::Slf(pos, nam, bod) => {
+        Term::Slf(*pos, nam.clone(), Box::new(self.zonk(bod, dep + 1)))
+      }
+      Term::Dat(pos, bod) => Term::Dat(*pos, Box::new(self.zonk(bod, dep))),
+      Term::Cse(pos, bod) => Term::Cse(*pos, Box::new(self.zonk(bod, dep))),
+      Term::Rcd(pos, nams, bod) => {
+        Term::Rcd(*pos, nams.clone(), Box::new(self.zonk(bod, dep)))
+      }
+      Term::Prj(pos, nam, prj_arg) => {
+        let prj_arg = (self.zonk(&prj_arg.0, dep), self.zonk(&prj_arg.1, dep));
+        Term::Prj(*pos, nam.clone(), Box::new(prj_arg))
+      }
+      Term::Let(pos, rec, uses, nam, typ_exp_bod) => {
+        let (typ, exp, bod) = &**typ_exp_bod;
+        let exp_dep = if *rec { dep + 1 } else { dep };
+        let typ_exp_bod = (
+          self.zonk(typ, dep),
+          self.zonk(exp, exp_dep),
+          self.zonk(bod, dep + 1),
+        );
+        Term::Let(*pos, *rec, *uses, nam.clone(), Box::new(typ_exp_bod))
+      }
+      Term::Ann(pos, typ_exp) => {
+        let (typ, exp) = &**typ_exp;
+        let typ_exp = (self.zonk(typ, dep), self.zonk(exp, dep));
+        Term::Ann(*pos, Box::new(typ_exp))
+      }
+      _ => term.clone(),
+    }
+  }
+
+  // The holes left for the user: named holes, along with their solutions if
+  // any, and anonymous holes that could not be solved
+  pub fn holes(&self) -> Vec<Hole> {
+    self
+      .vars
+      .iter()
+      .filter(|var| !var.nam.is_empty() || !self.is_solved(var.idx))
+      .map(|var| Hole {
+        pos: var.pos,
+        nam: var.nam.clone(),
+        goal: self.to_term(&var.goal, var.dep, false),
+        ctx: var.ctx.clone(),
+        solution: self
+          .solutions
+          .get(&var.idx)
+          .map(|sol| self.to_term(sol, var.dep, false)),
+      })
+      .collect()
+  }
+}
+
+impl Drop for Metas {
+  fn drop(&mut self) {
+    for var in self.vars.drain(..) {
+      var.goal.free();
+    }
+    for (_, sol) in self.solutions.drain() {
+      sol.free();
+    }
+    for node in self.replaced.get_mut().drain() {
+      free_dead_node(node);
+    }
+  }
+}
+
+// The path from the root of a term to one of its subterms, as the slots of
+// the children taken at each node
+pub type Path = Vec<u8>;
+
+// The children of a term with their slots, which are numbered in the order
+// `Term` lists them, so that a lambda's body is at the same slot whether or
+// not its domain is given
+pub fn children(term: &Term) -> Vec<(u8, &Term)> {
+  match term {
+    Term::Lam(_, _, dom, bod) => match dom {
+      Some((_, dom)) => vec![(0, dom), (1, bod)],
+      None => vec![(1, bod)],
+    },
+    Term::App(_, terms)
+    | Term::All(_, _, _, _, terms)
+    | Term::Ann(_, terms)
+    | Term::Prj(_, _, terms) => vec![(0, &terms.0), (1, &terms.1)],
+    Term::Let(_, _, _, _, terms) => {
+      vec![(0, &terms.0), (1, &terms.1), (2, &terms.2)]
+    }
+    Term::Slf(_, _, bod)
+    | Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => vec![(0, bod)],
+    _ => vec![],
+  }
+}
+
+// Gives a distinct number to every hole of a term, starting from `next`
+pub fn number_holes(term: &mut Term, next: &mut u64) {
+  match term {
+    Term::Hol(_, _, idx) => {
+      *idx = *next;
+      *next += 1;
+    }
+    Term::Lam(_, _, dom, bod) => {
+      if let Some((_, dom)) = dom {
+        number_holes(dom, next);
+      }
+      number_holes(bod, next);
+    }
+    Term::Slf(_, _, bod)
+    | Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => number_holes(bod, next),
+    Term::App(_, terms)
+    | Term::Prj(_, _, terms)
+    | Term::All(_, _, _, _, terms)
+    | Term::Ann(_, terms) => {
+      number_holes(&mut terms.0, next);
+      number_holes(&mut terms.1, next);
+    }
+    Term::Let(_, _, _, _, terms) => {
+      number_holes(&mut terms.0, next);
+      number_holes(&mut terms.1, next);
+      number_holes(&mut terms.2, next);
+    }
+    _ => (),
+  }
+}
+
+// Splits an application into its head and its arguments, in order
+pub fn spine(node: DAGPtr) -> (DAGPtr, Vec<DAGPtr>) {
+  let mut head = node;
+  let mut args = vec![];
+  while let DAGPtr::App(link) = head {
+    let App { fun, arg, .. } = unsafe { &*link.as_ptr() };
+    args.push(*arg);
+    head = *fun;
+  }
+  args.reverse();
+  (head, args)
+}
+
+// Splits an application into its head and the slots of its arguments, in
+// order
+fn arg_slots(node: DAGPtr) -> (DAGPtr, Vec<ParentPtr>) {
+  let mut head = node;
+  let mut slots = vec![];
+  while let DAGPtr::App(link) = head {
+    slots.push(ParentPtr::AppArg(link));
+    head = unsafe { (*link.as_ptr()).fun };
+  }
+  slots.reverse();
+  (head, slots)
+}
+
+// Pairs up the slots of the arguments of two applications of the same
+// definition, so that they can be unified without unfolding it. Recursive
+// types mentioning holes would otherwise be unfolded forever, since their
+// hashes can't be compared.
+pub fn same_head(
+  a: DAGPtr,
+  b: DAGPtr,
+) -> Option<Vec<(ParentPtr, ParentPtr)>> {
+  let (a_head, a_args) = arg_slots(a);
+  let (b_head, b_args) = arg_slots(b);
+  match (a_head, b_head) {
+    (DAGPtr::Ref(a_link), DAGPtr::Ref(b_link))
+      if unsafe { (*a_link.as_ptr()).exp == (*b_link.as_ptr()).exp }
+        && a_args.len() == b_args.len() =>
+    {
+      Some(a_args.into_iter().zip(b_args).collect())
+    }
+    _ => None,
+  }
+}
+
+// Collects the hole nodes of a DAG
+pub fn holes_in(node: DAGPtr) -> Vec<NonNull<Hol>> {
+  let mut holes = vec![];
+  let mut seen = HashSet::new();
+  let mut stack = vec![node];
+  while let Some(node) = stack.pop() {
+    if !seen.insert(node) {
+      continue;
+    }
+    unsafe {
+      match node {
+        DAGPtr::Hol(link) => holes.push(link),
+        DAGPtr::Lam(link) => stack.push((*link.as_ptr()).bod),
+        DAGPtr::Slf(link) => stack.push((*link.as_ptr()).bod),
+        DAGPtr::Let(link) => {
+          let Let { typ, exp, bod, .. } = *link.as_ptr();
+          stack.extend(vec![typ, exp, bod]);
+        }
+        DAGPtr::Dat(link) => stack.push((*link.as_ptr()).bod),
+        DAGPtr::Cse(link) => stack.push((*link.as_ptr()).bod),
+        DAGPtr::App(link) => {
+          let App { fun, arg, .. } = *link.as_ptr();
+          stack.extend(vec![fun, arg]);
+        }
+        DAGPtr::Ann(link) => {
+          let Ann { typ, exp, .. } = *link.as_ptr();
+          stack.extend(vec![typ, exp]);
+        }
+        DAGPtr::All(link) => {
+          let All { dom, img, .. } = *link.as_ptr();
+          stack.extend(vec![dom, DAGPtr::Lam(img)]);
+        }
+        _ => (),
+      }
+    }
+  }
+  holes
+}
+
+// Builds the DAG of `λ nam => bod`, binding the free variables of `bod` at the
+// given depth to the new binder
+pub fn abstract_lam(nam: &Name, dep: u64, bod: DAG) -> DAG {
+  let vars = free_vars_at(bod.head, dep);
+  unsafe {
+    detach_root(&bod);
+    let root = alloc_val(DLL::singleton(ParentPtr::Root));
+    let lam = alloc_lam(nam.clone(), dep, None, bod.head, Some(root));
+    let Lam { var, bod_ref, .. } = &mut *lam.as_ptr();
+    set_parents(bod.head, NonNull::new(bod_ref));
+    let var = DAGPtr::Var(NonNull::new(var).unwrap());
+    for free in vars {
+      replace_child(free, var);
+      free_dead_node(free);
+    }
+    DAG::new(DAGPtr::Lam(lam))
+  }
+}
@@ -1,9 +1,16 @@
 // Bottom-up reduction of lambda DAGs. Based on the paper by Olin Shivers and
 // Mitchel Wand "Bottom-up β-reduction: uplinks and λ-DAGs" (https://www.brics.dk/RS/04/38/BRICS-RS-04-38.pdf)
 
+pub mod hash;
+
 use crate::{
   defs::Def,
   dll::*,
+  eql::EqlOp,
+  eval::{
+    stats,
+    trace,
+  },
   literal::{
     LitType,
     Literal,
@@ -27,6 +34,11 @@ use std::{
 };
 
 use cid::Cid;
+use hash::{
+  clear_hash,
+  parent_node,
+  Cached,
+};
 
 pub struct DAG {
   pub head: DAGPtr,
@@ -49,6 +61,8 @@ pub enum DAGPtr {
   Lit(NonNull<Lit>),
   LTy(NonNull<LTy>),
   Opr(NonNull<Opr>),
+  Eql(NonNull<Eql>),
+  Hol(NonNull<Hol>),
 }
 
 // Doubly-linked list of parent nodes
@@ -93,6 +107,9 @@ pub struct Var {
   // The field `depth` is only used by the type checker to track free
   // variables. Otherwise it is irrelevant.
   pub dep: u64,
+  // Whether a cached hash may identify the variable by its depth, which has to
+  // be invalidated if the depth changes
+  pub hashed: bool,
   pub binder: BinderPtr,
   pub parents: Option<NonNull<Parents>>,
 }
@@ -102,6 +119,7 @@ pub struct Lam {
   pub bod: DAGPtr,
   pub bod_ref: Parents,
   pub var: Var,
+  pub hash: Option<Cached>,
   pub parents: Option<NonNull<Parents>>,
 }
 
@@ -112,17 +130,20 @@ pub struct App {
   pub fun_ref: Parents,
   pub arg_ref: Parents,
   pub copy: Option<NonNull<App>>,
+  pub hash: Option<Cached>,
   pub parents: Option<NonNull<Parents>>,
 }
 
 #[repr(C)]
 pub struct All {
+  pub imp: bool,
   pub uses: Uses,
   pub dom: DAGPtr,
   pub img: NonNull<Lam>,
   pub dom_ref: Parents,
   pub img_ref: Parents,
   pub copy: Option<NonNull<All>>,
+  pub hash: Option<Cached>,
   pub parents: Option<NonNull<Parents>>,
 }
 
@@ -131,6 +152,7 @@ pub struct Slf {
   pub bod: DAGPtr,
   pub bod_ref: Parents,
   pub var: Var,
+  pub hash: Option<Cached>,
   pub parents: Option<NonNull<Parents>>,
 }
 
@@ -138,6 +160,7 @@ pub struct Slf {
 pub struct Dat {
   pub bod: DAGPtr,
   pub bod_ref: Parents,
+  pub hash: Option<Cached>,
   pub parents: Option<NonNull<Parents>>,
 }
 
@@ -145,6 +168,7 @@ pub struct Dat {
 pub struct Cse {
   pub bod: DAGPtr,
   pub bod_ref: Parents,
+  pub hash: Option<Cached>,
   pub parents: Option<NonNull<Parents>>,
 }
 
@@ -155,12 +179,15 @@ pub struct Ann {
   pub typ_ref: Parents,
   pub exp_ref: Parents,
   pub copy: Option<NonNull<Ann>>,
+  pub hash: Option<Cached>,
   pub parents: Option<NonNull<Parents>>,
 }
 
 #[repr(C)]
 pub struct Let {
   pub uses: Uses,
+  // Whether the bound variable also scopes over `exp`, i.e. a `letrec`
+  pub rec: bool,
   pub typ: DAGPtr,
   pub exp: DAGPtr,
   pub bod: DAGPtr,
@@ -169,6 +196,7 @@ pub struct Let {
   pub bod_ref: Parents,
   pub copy: Option<NonNull<Let>>,
   pub var: Var,
+  pub hash: Option<Cached>,
   pub parents: Option<NonNull<Parents>>,
 }
 
@@ -183,6 +211,7 @@ pub struct Ref {
 
 #[repr(C)]
 pub struct Typ {
+  pub lvl: u64,
   pub parents: Option<NonNull<Parents>>,
 }
 
@@ -204,10 +233,34 @@ pub struct Opr {
   pub parents: Option<NonNull<Parents>>,
 }
 
+#[repr(C)]
+pub struct Eql {
+  pub op: EqlOp,
+  pub parents: Option<NonNull<Parents>>,
+}
+
+#[repr(C)]
+pub struct Hol {
+  pub nam: Name,
+  pub idx: u64,
+  pub parents: Option<NonNull<Parents>>,
+}
+
 // Auxiliary allocation functions
 #[inline]
 pub fn alloc_val<T>(val: T) -> NonNull<T> {
-  NonNull::new(Box::leak(Box::new(val))).unwrap()
+  stats::record(|stats| stats.allocs += 1);
+  let link = NonNull::new(Box::leak(Box::new(val))).unwrap();
+  trace::record(|events| events.allocated(link.as_ptr() as usize));
+  link
+}
+
+// Free a node allocated with `alloc_val`
+#[inline]
+unsafe fn free_val<T>(link: NonNull<T>) {
+  stats::record(|stats| stats.frees += 1);
+  trace::record(|events| events.freed(link.as_ptr() as usize));
+  drop(Box::from_raw(link.as_ptr()));
 }
 
 #[inline]
@@ -225,10 +278,12 @@ pub fn alloc_lam(
         rec: false,
         dep: var_dep,
         binder: mem::zeroed(),
+        hashed: false,
         parents: var_parents,
       },
       bod,
       bod_ref: mem::zeroed(),
+      hash: None,
       parents,
     });
     (*lam.as_ptr()).var.binder = BinderPtr::Lam(lam);
@@ -252,10 +307,12 @@ pub fn alloc_slf(
         rec: false,
         dep: var_dep,
         binder: mem::zeroed(),
+        hashed: false,
         parents: var_parents,
       },
       bod,
       bod_ref: mem::zeroed(),
+      hash: None,
       parents,
     });
     (*slf.as_ptr()).var.binder = BinderPtr::Slf(slf);
@@ -270,7 +327,8 @@ pub fn alloc_dat(
   parents: Option<NonNull<Parents>>,
 ) -> NonNull<Dat> {
   unsafe {
-    let dat = alloc_val(Dat { bod, bod_ref: mem::zeroed(), parents });
+    let dat =
+      alloc_val(Dat { bod, bod_ref: mem::zeroed(), hash: None, parents });
     (*dat.as_ptr()).bod_ref = DLL::singleton(ParentPtr::DatBod(dat));
     dat
   }
@@ -282,7 +340,8 @@ pub fn alloc_cse(
   parents: Option<NonNull<Parents>>,
 ) -> NonNull<Cse> {
   unsafe {
-    let cse = alloc_val(Cse { bod, bod_ref: mem::zeroed(), parents });
+    let cse =
+      alloc_val(Cse { bod, bod_ref: mem::zeroed(), hash: None, parents });
     (*cse.as_ptr()).bod_ref = DLL::singleton(ParentPtr::CseBod(cse));
     cse
   }
@@ -290,6 +349,7 @@ pub fn alloc_cse(
 
 #[inline]
 pub fn alloc_all(
+  imp: bool,
   uses: Uses,
   dom: DAGPtr,
   img: NonNull<Lam>,
@@ -297,12 +357,14 @@ pub fn alloc_all(
 ) -> NonNull<All> {
   unsafe {
     let all = alloc_val(All {
+      imp,
       uses,
       dom,
       img,
       copy: None,
       dom_ref: mem::zeroed(),
       img_ref: mem::zeroed(),
+      hash: None,
       parents,
     });
     (*all.as_ptr()).dom_ref = DLL::singleton(ParentPtr::AllDom(all));
@@ -324,6 +386,7 @@ pub fn alloc_app(
       copy: None,
       fun_ref: mem::zeroed(),
       arg_ref: mem::zeroed(),
+      hash: None,
       parents,
     });
     (*app.as_ptr()).fun_ref = DLL::singleton(ParentPtr::AppFun(app));
@@ -345,6 +408,7 @@ pub fn alloc_ann(
       copy: None,
       typ_ref: mem::zeroed(),
       exp_ref: mem::zeroed(),
+      hash: None,
       parents,
     });
     (*ann.as_ptr()).typ_ref = DLL::singleton(ParentPtr::AnnTyp(ann));
@@ -359,6 +423,7 @@ pub fn alloc_let(
   var_nam: Name,
   var_dep: u64,
   var_parents: Option<NonNull<Parents>>,
+  rec: bool,
   uses: Uses,
   typ: DAGPtr,
   exp: DAGPtr,
@@ -372,9 +437,11 @@ pub fn alloc_let(
         rec: false,
         dep: var_dep,
         binder: mem::zeroed(),
+        hashed: false,
         parents: var_parents,
       },
       uses,
+      rec,
       typ,
       exp,
       bod,
@@ -382,6 +449,7 @@ pub fn alloc_let(
       typ_ref: mem::zeroed(),
       exp_ref: mem::zeroed(),
       bod_ref: mem::zeroed(),
+      hash: None,
       parents,
     });
     (*let_.as_ptr()).var.binder = BinderPtr::Let(let_);
@@ -411,6 +479,8 @@ pub fn get_parents(term: DAGPtr) -> Option<NonNull<Parents>> {
       DAGPtr::Lit(link) => (*link.as_ptr()).parents,
       DAGPtr::LTy(link) => (*link.as_ptr()).parents,
       DAGPtr::Opr(link) => (*link.as_ptr()).parents,
+      DAGPtr::Eql(link) => (*link.as_ptr()).parents,
+      DAGPtr::Hol(link) => (*link.as_ptr()).parents,
     }
   }
 }
@@ -433,6 +503,30 @@ pub fn set_parents(term: DAGPtr, pref: Option<NonNull<Parents>>) {
       DAGPtr::Lit(link) => (*link.as_ptr()).parents = pref,
       DAGPtr::LTy(link) => (*link.as_ptr()).parents = pref,
       DAGPtr::Opr(link) => (*link.as_ptr()).parents = pref,
+      DAGPtr::Eql(link) => (*link.as_ptr()).parents = pref,
+      DAGPtr::Hol(link) => (*link.as_ptr()).parents = pref,
+    }
+  }
+}
+
+/// The child a parent pointer points to, if it isn't the root
+pub fn get_child(parent: ParentPtr) -> Option<DAGPtr> {
+  unsafe {
+    match parent {
+      ParentPtr::LamBod(parent) => Some((*parent.as_ptr()).bod),
+      ParentPtr::SlfBod(parent) => Some((*parent.as_ptr()).bod),
+      ParentPtr::DatBod(parent) => Some((*parent.as_ptr()).bod),
+      ParentPtr::CseBod(parent) => Some((*parent.as_ptr()).bod),
+      ParentPtr::AppFun(parent) => Some((*parent.as_ptr()).fun),
+      ParentPtr::AppArg(parent) => Some((*parent.as_ptr()).arg),
+      ParentPtr::AllDom(parent) => Some((*parent.as_ptr()).dom),
+      ParentPtr::AllImg(parent) => Some(DAGPtr::Lam((*parent.as_ptr()).img)),
+      ParentPtr::AnnExp(parent) => Some((*parent.as_ptr()).exp),
+      ParentPtr::AnnTyp(parent) => Some((*parent.as_ptr()).typ),
+      ParentPtr::LetExp(parent) => Some((*parent.as_ptr()).exp),
+      ParentPtr::LetTyp(parent) => Some((*parent.as_ptr()).typ),
+      ParentPtr::LetBod(parent) => Some((*parent.as_ptr()).bod),
+      ParentPtr::Root => None,
     }
   }
 }
@@ -460,6 +554,9 @@ pub fn install_child(parent: &mut ParentPtr, newchild: DAGPtr) {
       ParentPtr::Root => (),
     }
   }
+  if let Some(node) = parent_node(*parent) {
+    clear_hash(node);
+  }
 }
 // Replace one child w/another in the tree.
 pub fn replace_child(oldchild: DAGPtr, newchild: DAGPtr) {
@@ -504,7 +601,7 @@ pub fn free_dead_node(node: DAGPtr) {
         if new_bod_parents.is_none() {
           free_dead_node(*bod)
         }
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Slf(mut link) => {
         let Slf { bod, bod_ref, .. } = &link.as_mut();
@@ -513,7 +610,7 @@ pub fn free_dead_node(node: DAGPtr) {
         if new_bod_parents.is_none() {
           free_dead_node(*bod)
         }
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Cse(link) => {
         let Cse { bod, bod_ref, .. } = link.as_ref();
@@ -522,7 +619,7 @@ pub fn free_dead_node(node: DAGPtr) {
         if new_bod_parents.is_none() {
           free_dead_node(*bod)
         }
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Dat(link) => {
         let Dat { bod, bod_ref, .. } = &link.as_ref();
@@ -531,7 +628,7 @@ pub fn free_dead_node(node: DAGPtr) {
         if new_bod_parents.is_none() {
           free_dead_node(*bod)
         }
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::All(link) => {
         let All { dom, img, dom_ref, img_ref, .. } = link.as_ref();
@@ -546,7 +643,7 @@ pub fn free_dead_node(node: DAGPtr) {
         if new_img_parents.is_none() {
           free_dead_node(img)
         }
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::App(link) => {
         let App { fun, arg, fun_ref, arg_ref, .. } = link.as_ref();
@@ -560,7 +657,7 @@ pub fn free_dead_node(node: DAGPtr) {
         if new_arg_parents.is_none() {
           free_dead_node(*arg)
         }
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Ann(link) => {
         let Ann { exp, typ, exp_ref, typ_ref, .. } = link.as_ref();
@@ -574,7 +671,7 @@ pub fn free_dead_node(node: DAGPtr) {
         if new_typ_parents.is_none() {
           free_dead_node(*typ)
         }
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Let(link) => {
         let Let { exp, typ, exp_ref, typ_ref, bod, bod_ref, .. } =
@@ -594,34 +691,91 @@ pub fn free_dead_node(node: DAGPtr) {
         if new_bod_parents.is_none() {
           free_dead_node(*bod)
         }
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Var(link) => {
         let Var { binder, .. } = link.as_ref();
         // only free Free variables, bound variables are freed with their binder
         if let BinderPtr::Free = binder {
-          Box::from_raw(link.as_ptr());
+          free_val(link);
         }
       }
       DAGPtr::Ref(link) => {
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Typ(link) => {
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Lit(link) => {
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::LTy(link) => {
-        Box::from_raw(link.as_ptr());
+        free_val(link);
       }
       DAGPtr::Opr(link) => {
-        Box::from_raw(link.as_ptr());
+        free_val(link);
+      }
+      DAGPtr::Eql(link) => {
+        free_val(link);
+      }
+      DAGPtr::Hol(link) => {
+        free_val(link);
       }
     }
   }
 }
 
+// Collect the variables occurring in `node` that are not bound inside of it
+pub fn outer_vars(node: DAGPtr) -> Vec<DAGPtr> {
+  let mut binders: HashSet<BinderPtr> = HashSet::new();
+  let mut vars = vec![];
+  let mut seen = HashSet::new();
+  let mut stack = vec![node];
+  while let Some(node) = stack.pop() {
+    if !seen.insert(node) {
+      continue;
+    }
+    unsafe {
+      match node {
+        DAGPtr::Var(link) => vars.push((node, (*link.as_ptr()).binder)),
+        DAGPtr::Lam(link) => {
+          binders.insert(BinderPtr::Lam(link));
+          stack.push((*link.as_ptr()).bod);
+        }
+        DAGPtr::Slf(link) => {
+          binders.insert(BinderPtr::Slf(link));
+          stack.push((*link.as_ptr()).bod);
+        }
+        DAGPtr::Let(link) => {
+          let Let { typ, exp, bod, .. } = *link.as_ptr();
+          binders.insert(BinderPtr::Let(link));
+          stack.extend(vec![typ, exp, bod]);
+        }
+        DAGPtr::Dat(link) => stack.push((*link.as_ptr()).bod),
+        DAGPtr::Cse(link) => stack.push((*link.as_ptr()).bod),
+        DAGPtr::App(link) => {
+          let App { fun, arg, .. } = *link.as_ptr();
+          stack.extend(vec![fun, arg]);
+        }
+        DAGPtr::Ann(link) => {
+          let Ann { typ, exp, .. } = *link.as_ptr();
+          stack.extend(vec![typ, exp]);
+        }
+        DAGPtr::All(link) => {
+          let All { dom, img, .. } = *link.as_ptr();
+          stack.extend(vec![dom, DAGPtr::Lam(img)]);
+        }
+        _ => (),
+      }
+    }
+  }
+  vars
+    .into_iter()
+    .filter(|(_, binder)| !binders.contains(binder))
+    .map(|(var, _)| var)
+    .collect()
+}
+
 impl DAG {
   pub fn new(head: DAGPtr) -> DAG { DAG { head } }
 
@@ -642,6 +796,23 @@ impl DAG {
     depth: u64,
     re_rec: bool,
   ) -> Term {
+    DAG::dag_ptr_to_term_marked(node, map, depth, re_rec, None)
+  }
+
+  // Converts the DAG at `node` to a term, putting the subterm at `mark` in
+  // brackets. The bracketed subterm is a variable named after it, so the
+  // term is only good for printing.
+  fn dag_ptr_to_term_marked(
+    node: &DAGPtr,
+    map: &mut HashMap<*mut Var, u64>,
+    depth: u64,
+    re_rec: bool,
+    mark: Option<DAGPtr>,
+  ) -> Term {
+    if mark == Some(*node) {
+      let term = DAG::dag_ptr_to_term(node, map, depth, re_rec);
+      return Term::Var(Pos::None, Name::from(format!("⟦{}⟧", term)), 0);
+    }
     match node {
       DAGPtr::Var(link) => {
         let Var { nam, dep: var_depth, rec, .. } = unsafe { link.as_ref() };
@@ -655,7 +826,10 @@ impl DAG {
           Term::Var(Pos::None, nam.clone(), *var_depth)
         }
       }
-      DAGPtr::Typ(_) => Term::Typ(Pos::None),
+      DAGPtr::Typ(link) => {
+        let Typ { lvl, .. } = unsafe { link.as_ref() };
+        Term::Typ(Pos::None, *lvl)
+      }
       DAGPtr::LTy(link) => {
         let LTy { lty, .. } = unsafe { link.as_ref() };
         Term::LTy(Pos::None, *lty)
@@ -668,6 +842,14 @@ impl DAG {
         let Opr { opr, .. } = unsafe { link.as_ref() };
         Term::Opr(Pos::None, *opr)
       }
+      DAGPtr::Eql(link) => {
+        let Eql { op, .. } = unsafe { link.as_ref() };
+        Term::Eql(Pos::None, *op)
+      }
+      DAGPtr::Hol(link) => {
+        let Hol { nam, idx, .. } = unsafe { link.as_ref() };
+        Term::Hol(Pos::None, nam.clone(), *idx)
+      }
       DAGPtr::Ref(link) => {
         let Ref { nam, exp, ast, rec, .. } = unsafe { link.as_ref() };
         if *rec && re_rec {
@@ -681,28 +863,30 @@ impl DAG {
         let Lam { var, bod, .. } = unsafe { &mut *link.as_ptr() };
         let nam = var.nam.clone();
         map.insert(var, depth);
-        let body = DAG::dag_ptr_to_term(bod, map, depth + 1, re_rec);
-        Term::Lam(Pos::None, nam, Box::new(body))
+        let body =
+          DAG::dag_ptr_to_term_marked(bod, map, depth + 1, re_rec, mark);
+        Term::Lam(Pos::None, nam, None, Box::new(body))
       }
       DAGPtr::Slf(link) => {
         let Slf { var, bod, .. } = unsafe { &mut *link.as_ptr() };
         let nam = var.nam.clone();
         map.insert(var, depth);
-        let body = DAG::dag_ptr_to_term(bod, map, depth + 1, re_rec);
+        let body =
+          DAG::dag_ptr_to_term_marked(bod, map, depth + 1, re_rec, mark);
         Term::Slf(Pos::None, nam, Box::new(body))
       }
       DAGPtr::Cse(link) => {
         let Cse { bod, .. } = unsafe { link.as_ref() };
         Term::Cse(
           Pos::None,
-          Box::new(DAG::dag_ptr_to_term(bod, map, depth, re_rec)),
+          Box::new(DAG::dag_ptr_to_term_marked(bod, map, depth, re_rec, mark)),
         )
       }
       DAGPtr::Dat(link) => {
         let Dat { bod, .. } = unsafe { link.as_ref() };
         Term::Dat(
           Pos::None,
-          Box::new(DAG::dag_ptr_to_term(bod, map, depth, re_rec)),
+          Box::new(DAG::dag_ptr_to_term_marked(bod, map, depth, re_rec, mark)),
         )
       }
       DAGPtr::App(link) => {
@@ -711,8 +895,8 @@ impl DAG {
         Term::App(
           Pos::None,
           Box::new((
-            DAG::dag_ptr_to_term(fun, fun_map, depth, re_rec),
-            DAG::dag_ptr_to_term(arg, map, depth, re_rec),
+            DAG::dag_ptr_to_term_marked(fun, fun_map, depth, re_rec, mark),
+            DAG::dag_ptr_to_term_marked(arg, map, depth, re_rec, mark),
           )),
         )
       }
@@ -722,13 +906,13 @@ impl DAG {
         Term::Ann(
           Pos::None,
           Box::new((
-            DAG::dag_ptr_to_term(typ, typ_map, depth, re_rec),
-            DAG::dag_ptr_to_term(exp, map, depth, re_rec),
+            DAG::dag_ptr_to_term_marked(typ, typ_map, depth, re_rec, mark),
+            DAG::dag_ptr_to_term_marked(exp, map, depth, re_rec, mark),
           )),
         )
       }
       DAGPtr::All(link) => {
-        let All { uses, dom, img: lam_link, .. } =
+        let All { imp, uses, dom, img: lam_link, .. } =
           unsafe { &mut *link.as_ptr() };
         let Lam { var, bod: img, .. } = unsafe { &mut *lam_link.as_ptr() };
         let nam = var.nam.clone();
@@ -736,30 +920,38 @@ impl DAG {
         map.insert(var, depth);
         Term::All(
           Pos::None,
+          *imp,
           *uses,
           nam,
           Box::new((
-            DAG::dag_ptr_to_term(dom, dom_map, depth, re_rec),
-            DAG::dag_ptr_to_term(img, map, depth + 1, re_rec),
+            DAG::dag_ptr_to_term_marked(dom, dom_map, depth, re_rec, mark),
+            DAG::dag_ptr_to_term_marked(img, map, depth + 1, re_rec, mark),
           )),
         )
       }
       DAGPtr::Let(link) => {
-        let Let { var, uses, typ, exp, bod, .. } =
+        let Let { var, uses, rec, typ, exp, bod, .. } =
           unsafe { &mut *link.as_ptr() };
         let nam = var.nam.clone();
         let typ_map = &mut map.clone();
         let exp_map = &mut map.clone();
         map.insert(var, depth);
+        let exp = if *rec {
+          let exp_map = &mut map.clone();
+          DAG::dag_ptr_to_term_marked(exp, exp_map, depth + 1, re_rec, mark)
+        }
+        else {
+          DAG::dag_ptr_to_term_marked(exp, exp_map, depth, re_rec, mark)
+        };
         Term::Let(
           Pos::None,
-          false,
+          *rec,
           *uses,
           nam,
           Box::new((
-            DAG::dag_ptr_to_term(typ, typ_map, depth, re_rec),
-            DAG::dag_ptr_to_term(exp, exp_map, depth, re_rec),
-            DAG::dag_ptr_to_term(bod, map, depth + 1, re_rec),
+            DAG::dag_ptr_to_term_marked(typ, typ_map, depth, re_rec, mark),
+            exp,
+            DAG::dag_ptr_to_term_marked(bod, map, depth + 1, re_rec, mark),
           )),
         )
       }
@@ -771,6 +963,13 @@ impl DAG {
     DAG::dag_ptr_to_term(&self.head, &mut map, 0, re_rec)
   }
 
+  /// Converts the DAG to a term for printing, with the subterm at `mark`
+  /// shown in `⟦ ⟧` brackets
+  pub fn to_term_marked(&self, re_rec: bool, mark: DAGPtr) -> Term {
+    let mut map = HashMap::new();
+    DAG::dag_ptr_to_term_marked(&self.head, &mut map, 0, re_rec, Some(mark))
+  }
+
   pub fn from_term(tree: &Term) -> Self {
     let root = alloc_val(DLL::singleton(ParentPtr::Root));
     DAG::new(DAG::from_term_inner(tree, 0, VecDeque::new(), Some(root), None))
@@ -782,7 +981,7 @@ impl DAG {
     let def_cid = d.cid();
     let ast_cid = a.cid();
     DAG::new(DAG::from_term_inner(
-      &def.term,
+      &def.resolve(&def.term),
       0,
       VecDeque::new(),
       Some(root),
@@ -798,7 +997,7 @@ impl DAG {
     parents: Option<NonNull<Parents>>,
   ) -> DAGPtr {
     DAG::from_term_inner(
-      &def.term,
+      &def.resolve(&def.term),
       0,
       VecDeque::new(),
       parents,
@@ -825,11 +1024,36 @@ impl DAG {
             rec: true,
             dep: depth,
             binder: BinderPtr::Free,
+            hashed: false,
             parents,
           });
           DAGPtr::Var(var)
         }
       },
+      // An axiom stands for a reference to itself, which is never unfolded
+      Term::Axm(_, nam, _) => match rec_ref {
+        Some((nam, exp, ast)) => {
+          DAGPtr::Ref(alloc_val(Ref { nam, rec: false, exp, ast, parents }))
+        }
+        None => DAGPtr::Var(alloc_val(Var {
+          nam: nam.clone(),
+          rec: false,
+          dep: depth,
+          binder: BinderPtr::Free,
+          hashed: false,
+          parents,
+        })),
+      },
+      // Only members of a `mutual` block whose other members are missing have
+      // unresolved references to them
+      Term::Mut(_, nam, _) => DAGPtr::Var(alloc_val(Var {
+        nam: nam.clone(),
+        rec: false,
+        dep: depth,
+        binder: BinderPtr::Free,
+        hashed: false,
+        parents,
+      })),
       Term::Var(_, name, idx) => match ctx.get(*idx as usize) {
         Some(val) => {
           if let Some(parents) = parents {
@@ -847,17 +1071,22 @@ impl DAG {
             rec: false,
             dep: depth - 1 - idx,
             binder: BinderPtr::Free,
+            hashed: false,
             parents,
           });
           DAGPtr::Var(var)
         }
       },
-      Term::Typ(_) => DAGPtr::Typ(alloc_val(Typ { parents })),
+      Term::Typ(_, lvl) => DAGPtr::Typ(alloc_val(Typ { lvl: *lvl, parents })),
       Term::LTy(_, lty) => DAGPtr::LTy(alloc_val(LTy { lty: *lty, parents })),
       Term::Lit(_, lit) => {
         DAGPtr::Lit(alloc_val(Lit { lit: lit.clone(), parents }))
       }
       Term::Opr(_, opr) => DAGPtr::Opr(alloc_val(Opr { opr: *opr, parents })),
+      Term::Eql(_, op) => DAGPtr::Eql(alloc_val(Eql { op: *op, parents })),
+      Term::Hol(_, nam, idx) => {
+        DAGPtr::Hol(alloc_val(Hol { nam: nam.clone(), idx: *idx, parents }))
+      }
       Term::Ref(_, nam, exp, ast) => DAGPtr::Ref(alloc_val(Ref {
         nam: nam.clone(),
         rec: false,
@@ -865,7 +1094,9 @@ impl DAG {
         ast: *ast,
         parents,
       })),
-      Term::Lam(_, nam, bod) => unsafe {
+      // Binder annotations are only read by the typechecker, so the domain of a
+      // typed lambda is dropped here
+      Term::Lam(_, nam, _, bod) => unsafe {
         let lam = alloc_lam(nam.clone(), 0, None, mem::zeroed(), parents);
         let Lam { var, bod_ref, .. } = &mut *lam.as_ptr();
         ctx.push_front(DAGPtr::Var(NonNull::new(var).unwrap()));
@@ -893,6 +1124,9 @@ impl DAG {
         (*slf.as_ptr()).bod = bod;
         DAGPtr::Slf(slf)
       },
+      Term::Rcd(_, _, bod) => {
+        DAG::from_term_inner(bod, depth, ctx, parents, rec_ref)
+      }
       Term::Dat(_, bod) => unsafe {
         let dat = alloc_dat(mem::zeroed(), parents);
         let Dat { bod_ref, .. } = &mut *dat.as_ptr();
@@ -919,9 +1153,10 @@ impl DAG {
         (*cse.as_ptr()).bod = bod;
         DAGPtr::Cse(cse)
       },
-      Term::All(_, uses, nam, dom_img) => unsafe {
+      Term::All(_, imp, uses, nam, dom_img) => unsafe {
         let (dom, img) = (**dom_img).clone();
-        let all = alloc_all(*uses, mem::zeroed(), NonNull::dangling(), parents);
+        let all =
+          alloc_all(*imp, *uses, mem::zeroed(), NonNull::dangling(), parents);
         let All { dom_ref, img_ref, .. } = &mut *all.as_ptr();
         let lam =
           alloc_lam(nam.clone(), 0, None, mem::zeroed(), NonNull::new(img_ref));
@@ -947,7 +1182,7 @@ impl DAG {
         (*lam.as_ptr()).bod = img;
         DAGPtr::All(all)
       },
-      Term::App(_, fun_arg) => unsafe {
+      Term::App(_, fun_arg) | Term::Prj(_, _, fun_arg) => unsafe {
         let (fun, arg) = (**fun_arg).clone();
         let app = alloc_app(mem::zeroed(), mem::zeroed(), parents);
         let App { fun_ref, arg_ref, .. } = &mut *app.as_ptr();
@@ -991,15 +1226,13 @@ impl DAG {
         (*ann.as_ptr()).exp = exp;
         DAGPtr::Ann(ann)
       },
-      Term::Let(_, true, _uses, _name, _typ_exp_bod) => {
-        panic!("letrec not implemented")
-      }
-      Term::Let(_, false, uses, nam, typ_exp_bod) => unsafe {
+      Term::Let(_, rec, uses, nam, typ_exp_bod) => unsafe {
         let (typ, exp, bod) = (**typ_exp_bod).clone();
         let let_ = alloc_let(
           nam.clone(),
           0,
           None,
+          *rec,
           *uses,
           mem::zeroed(),
           mem::zeroed(),
@@ -1014,14 +1247,28 @@ impl DAG {
           NonNull::new(typ_ref),
           rec_ref.clone(),
         );
-        let exp = DAG::from_term_inner(
-          &exp,
-          depth,
-          ctx.clone(),
-          NonNull::new(exp_ref),
-          rec_ref.clone(),
-        );
-        ctx.push_front(DAGPtr::Var(NonNull::new(var).unwrap()));
+        let var = DAGPtr::Var(NonNull::new(var).unwrap());
+        let exp = if *rec {
+          let mut exp_ctx = ctx.clone();
+          exp_ctx.push_front(var);
+          DAG::from_term_inner(
+            &exp,
+            depth + 1,
+            exp_ctx,
+            NonNull::new(exp_ref),
+            rec_ref.clone(),
+          )
+        }
+        else {
+          DAG::from_term_inner(
+            &exp,
+            depth,
+            ctx.clone(),
+            NonNull::new(exp_ref),
+            rec_ref.clone(),
+          )
+        };
+        ctx.push_front(var);
         let bod = DAG::from_term_inner(
           &bod,
           depth + 1,
@@ -1060,6 +1307,7 @@ impl DAG {
           rec: *rec,
           dep: *dep,
           binder: BinderPtr::Free,
+          hashed: false,
           parents,
         });
         DAGPtr::Var(var)
@@ -1125,8 +1373,9 @@ impl DAG {
         DAGPtr::App(app)
       },
       DAGPtr::All(link) => unsafe {
-        let All { uses, dom, img, .. } = &mut *link.as_ptr();
-        let all = alloc_all(*uses, mem::zeroed(), NonNull::dangling(), parents);
+        let All { imp, uses, dom, img, .. } = &mut *link.as_ptr();
+        let all =
+          alloc_all(*imp, *uses, mem::zeroed(), NonNull::dangling(), parents);
         let All { dom: new_dom, dom_ref, img: new_img, img_ref, .. } =
           &mut *all.as_ptr();
         *new_dom = DAG::from_subdag(*dom, map, NonNull::new(dom_ref));
@@ -1139,11 +1388,12 @@ impl DAG {
         DAGPtr::All(all)
       },
       DAGPtr::Let(link) => unsafe {
-        let Let { var, uses, typ, exp, bod, .. } = &mut *link.as_ptr();
+        let Let { var, uses, rec, typ, exp, bod, .. } = &mut *link.as_ptr();
         let let_ = alloc_let(
           var.nam.clone(),
           var.dep,
           None,
+          *rec,
           *uses,
           mem::zeroed(),
           mem::zeroed(),
@@ -1193,10 +1443,21 @@ impl DAG {
         let node = alloc_val(Opr { opr, parents });
         DAGPtr::Opr(node)
       },
-      DAGPtr::Typ(_) => {
-        let node = alloc_val(Typ { parents });
+      DAGPtr::Eql(link) => unsafe {
+        let Eql { op, .. } = *link.as_ptr();
+        let node = alloc_val(Eql { op, parents });
+        DAGPtr::Eql(node)
+      },
+      DAGPtr::Typ(link) => {
+        let lvl = unsafe { (*link.as_ptr()).lvl };
+        let node = alloc_val(Typ { lvl, parents });
         DAGPtr::Typ(node)
-      } // _ => panic!("TODO"),
+      }
+      DAGPtr::Hol(link) => unsafe {
+        let Hol { nam, idx, .. } = &*link.as_ptr();
+        let node = alloc_val(Hol { nam: nam.clone(), idx: *idx, parents });
+        DAGPtr::Hol(node)
+      }, // _ => panic!("TODO"),
     };
     // Map `node` to `new_node`
     map.insert(node, new_node);
@@ -1305,6 +1566,24 @@ impl fmt::Debug for DAG {
             format_parents(*parents)
           )
         }
+        DAGPtr::Eql(link) => {
+          let Eql { op, parents } = unsafe { link.as_ref() };
+          format!(
+            "\nEql<{:?}> {} parents: {}",
+            (link.as_ptr()),
+            op,
+            format_parents(*parents)
+          )
+        }
+        DAGPtr::Hol(link) => {
+          let Hol { nam, parents, .. } = unsafe { link.as_ref() };
+          format!(
+            "\nHol<{:?}> ?{} parents: {}",
+            (link.as_ptr()),
+            nam,
+            format_parents(*parents)
+          )
+        }
         DAGPtr::Ref(link) => {
           let Ref { nam, parents, .. } = unsafe { link.as_ref() };
           format!(
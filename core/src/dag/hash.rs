@@ -0,0 +1,405 @@
+// Structural hashes of λ-DAG nodes, which the typechecker compares to decide
+// the equality of terms without walking them. A variable bound inside the
+// hashed node is hashed as its de Bruijn index, so that the same term hashes
+// the same at any depth, and a variable free in it is identified by its `dep`,
+// as set by the checker when entering its binder.
+//
+// Every node with children caches its hash. The hash of a node is only reused
+// when it's closed, that is when the variables it refers to are either bound
+// inside of it or free in the whole term being hashed, as the indices of the
+// others depend on the path it was reached through. Changing a child of a
+// node forgets the cached hashes above it, and changing the depth of a free
+// variable some cached hash identifies it by, or binding it, forgets the
+// cached hashes above its occurrences.
+
+use crate::{
+  anon::Anon,
+  dag::*,
+};
+
+use multihash::{
+  Blake2b256,
+  StatefulHasher,
+};
+
+use std::collections::HashMap;
+
+/// A structural hash of a node
+pub type Digest = [u8; 32];
+
+/// The hash of a node as cached on it
+#[derive(Clone, Copy, Debug)]
+pub struct Cached {
+  closed: bool,
+  digest: Digest,
+}
+
+// The tags distinguishing the kinds of nodes in their hashes
+const VAR: u8 = 0;
+const REC: u8 = 1;
+const REF: u8 = 2;
+const TYP: u8 = 3;
+const HOL: u8 = 4;
+const ANON: u8 = 5;
+const LAM: u8 = 6;
+const SLF: u8 = 7;
+const DAT: u8 = 8;
+const CSE: u8 = 9;
+const ALL: u8 = 10;
+const APP: u8 = 11;
+const ANN: u8 = 12;
+const LET: u8 = 13;
+const FREE: u8 = 14;
+
+fn digest(parts: &[&[u8]]) -> Digest {
+  let mut hasher = Blake2b256::default();
+  for part in parts {
+    hasher.update(part);
+  }
+  let mut res = [0; 32];
+  res.copy_from_slice(hasher.finalize().as_ref());
+  res
+}
+
+// Constants are hashed through their anonymous terms
+fn anon(anon: Anon) -> Digest {
+  digest(&[&[ANON], &anon.cid().to_bytes()])
+}
+
+/// Sets the depth of a variable. The cached hashes identifying the variable by
+/// its previous depth are forgotten.
+pub fn set_dep(var: &mut Var, dep: u64) {
+  if var.dep != dep {
+    var.dep = dep;
+    forget_dep(var);
+  }
+}
+
+// Forgets the cached hashes that may identify a variable by its depth, which
+// are those of the nodes above its occurrences
+fn forget_dep(var: &mut Var) {
+  if var.hashed {
+    var.hashed = false;
+    for parent in DLL::iter_option(var.parents) {
+      if let Some(node) = parent_node(*parent) {
+        clear_hash(node);
+      }
+    }
+  }
+}
+
+/// The structural hash of a node at depth `dep`. Two nodes at the same depth
+/// have the same hash if and only if they are the same term up to the names of
+/// their variables, barring collisions.
+pub fn hash(node: DAGPtr, dep: u64) -> Digest {
+  hash_at(node, dep, &mut HashMap::new()).0
+}
+
+// The levels of the binders between the node being hashed and the root
+type Levels = HashMap<*const Var, u64>;
+
+// The hash of a node, along with the lowest level of the binders it refers to,
+// which is `u64::MAX` if it refers to none
+fn hash_at(node: DAGPtr, dep: u64, levels: &mut Levels) -> (Digest, u64) {
+  unsafe {
+    match node {
+      DAGPtr::Var(link) => {
+        let var = &mut *link.as_ptr();
+        if var.rec {
+          return (digest(&[&[REC]]), u64::MAX);
+        }
+        match levels.get(&(link.as_ptr() as *const Var)) {
+          Some(lvl) => {
+            let idx = dep - lvl - 1;
+            (digest(&[&[VAR], &idx.to_be_bytes()]), *lvl)
+          }
+          None => {
+            var.hashed = true;
+            (digest(&[&[FREE], &var.dep.to_be_bytes()]), u64::MAX)
+          }
+        }
+      }
+      DAGPtr::Ref(link) => {
+        let Ref { rec, ast, .. } = &*link.as_ptr();
+        let digest = if *rec {
+          digest(&[&[REC]])
+        }
+        else {
+          digest(&[&[REF], &ast.to_bytes()])
+        };
+        (digest, u64::MAX)
+      }
+      DAGPtr::Typ(link) => {
+        let lvl = (*link.as_ptr()).lvl;
+        (digest(&[&[TYP], &lvl.to_be_bytes()]), u64::MAX)
+      }
+      // Holes are only compared structurally, as in anonymous terms
+      DAGPtr::Hol(_) => (digest(&[&[HOL]]), u64::MAX),
+      DAGPtr::Lit(link) => {
+        (anon(Anon::Lit((*link.as_ptr()).lit.clone())), u64::MAX)
+      }
+      DAGPtr::LTy(link) => (anon(Anon::LTy((*link.as_ptr()).lty)), u64::MAX),
+      DAGPtr::Opr(link) => (anon(Anon::Opr((*link.as_ptr()).opr)), u64::MAX),
+      DAGPtr::Eql(link) => (anon(Anon::Eql((*link.as_ptr()).op)), u64::MAX),
+      DAGPtr::Lam(link) => cached(&mut (*link.as_ptr()).hash, dep, || {
+        let Lam { var, bod, .. } = &mut *link.as_ptr();
+        let (bod, low) =
+          bind(var, dep, levels, |ls| hash_at(*bod, dep + 1, ls));
+        (digest(&[&[LAM], &bod]), low)
+      }),
+      DAGPtr::Slf(link) => cached(&mut (*link.as_ptr()).hash, dep, || {
+        let Slf { var, bod, .. } = &mut *link.as_ptr();
+        let (bod, low) =
+          bind(var, dep, levels, |ls| hash_at(*bod, dep + 1, ls));
+        (digest(&[&[SLF], &bod]), low)
+      }),
+      DAGPtr::Dat(link) => cached(&mut (*link.as_ptr()).hash, dep, || {
+        let (bod, low) = hash_at((*link.as_ptr()).bod, dep, levels);
+        (digest(&[&[DAT], &bod]), low)
+      }),
+      DAGPtr::Cse(link) => cached(&mut (*link.as_ptr()).hash, dep, || {
+        let (bod, low) = hash_at((*link.as_ptr()).bod, dep, levels);
+        (digest(&[&[CSE], &bod]), low)
+      }),
+      DAGPtr::All(link) => cached(&mut (*link.as_ptr()).hash, dep, || {
+        let All { uses, dom, img, .. } = *link.as_ptr();
+        let (dom, dom_low) = hash_at(dom, dep, levels);
+        let (img, img_low) = hash_at(DAGPtr::Lam(img), dep, levels);
+        (digest(&[&[ALL, uses as u8], &dom, &img]), dom_low.min(img_low))
+      }),
+      DAGPtr::App(link) => cached(&mut (*link.as_ptr()).hash, dep, || {
+        let App { fun, arg, .. } = *link.as_ptr();
+        let (fun, fun_low) = hash_at(fun, dep, levels);
+        let (arg, arg_low) = hash_at(arg, dep, levels);
+        (digest(&[&[APP], &fun, &arg]), fun_low.min(arg_low))
+      }),
+      DAGPtr::Ann(link) => cached(&mut (*link.as_ptr()).hash, dep, || {
+        let Ann { typ, exp, .. } = *link.as_ptr();
+        let (typ, typ_low) = hash_at(typ, dep, levels);
+        let (exp, exp_low) = hash_at(exp, dep, levels);
+        (digest(&[&[ANN], &typ, &exp]), typ_low.min(exp_low))
+      }),
+      DAGPtr::Let(link) => cached(&mut (*link.as_ptr()).hash, dep, || {
+        let Let { var, uses, rec, typ, exp, bod, .. } = &mut *link.as_ptr();
+        let (typ, typ_low) = hash_at(*typ, dep, levels);
+        let (exp, exp_low) = if *rec {
+          bind(var, dep, levels, |ls| hash_at(*exp, dep + 1, ls))
+        }
+        else {
+          hash_at(*exp, dep, levels)
+        };
+        let (bod, bod_low) =
+          bind(var, dep, levels, |ls| hash_at(*bod, dep + 1, ls));
+        let tag = [LET, *rec as u8, *uses as u8];
+        let low = typ_low.min(exp_low).min(bod_low);
+        (digest(&[&tag, &typ, &exp, &bod]), low)
+      }),
+    }
+  }
+}
+
+// Reuses the hash cached on a node if it's closed, and otherwise computes and
+// caches it. The cache is passed as a raw
+// pointer, since computing the hash goes through the same node.
+fn cached(
+  cache: *mut Option<Cached>,
+  dep: u64,
+  compute: impl FnOnce() -> (Digest, u64),
+) -> (Digest, u64) {
+  unsafe {
+    if let Some(cached) = *cache {
+      if cached.closed {
+        return (cached.digest, u64::MAX);
+      }
+    }
+    let (digest, low) = compute();
+    // Open nodes are cached too, though never reused, so that the nodes above
+    // a cached one always have a cache to forget
+    *cache = Some(Cached { closed: low >= dep, digest });
+    (digest, low)
+  }
+}
+
+// Hashes the body of a binder with its variable at level `lvl`
+fn bind(
+  var: &mut Var,
+  lvl: u64,
+  levels: &mut Levels,
+  hash_bod: impl FnOnce(&mut Levels) -> (Digest, u64),
+) -> (Digest, u64) {
+  // The cached hashes identifying the variable as a free one don't agree with
+  // this walk, which binds it
+  forget_dep(var);
+  let ptr = var as *const Var;
+  let outer = levels.insert(ptr, lvl);
+  let res = hash_bod(levels);
+  match outer {
+    Some(outer) => levels.insert(ptr, outer),
+    None => levels.remove(&ptr),
+  };
+  res
+}
+
+/// The node a parent pointer points to, if it isn't the root
+pub fn parent_node(parent: ParentPtr) -> Option<DAGPtr> {
+  match parent {
+    ParentPtr::Root => None,
+    ParentPtr::LamBod(link) => Some(DAGPtr::Lam(link)),
+    ParentPtr::SlfBod(link) => Some(DAGPtr::Slf(link)),
+    ParentPtr::DatBod(link) => Some(DAGPtr::Dat(link)),
+    ParentPtr::CseBod(link) => Some(DAGPtr::Cse(link)),
+    ParentPtr::AppFun(link) | ParentPtr::AppArg(link) => {
+      Some(DAGPtr::App(link))
+    }
+    ParentPtr::AllDom(link) | ParentPtr::AllImg(link) => {
+      Some(DAGPtr::All(link))
+    }
+    ParentPtr::AnnTyp(link) | ParentPtr::AnnExp(link) => {
+      Some(DAGPtr::Ann(link))
+    }
+    ParentPtr::LetTyp(link)
+    | ParentPtr::LetExp(link)
+    | ParentPtr::LetBod(link) => Some(DAGPtr::Let(link)),
+  }
+}
+
+fn cache_of(node: DAGPtr) -> Option<*mut Option<Cached>> {
+  unsafe {
+    match node {
+      DAGPtr::Lam(link) => Some(&mut (*link.as_ptr()).hash),
+      DAGPtr::Slf(link) => Some(&mut (*link.as_ptr()).hash),
+      DAGPtr::Dat(link) => Some(&mut (*link.as_ptr()).hash),
+      DAGPtr::Cse(link) => Some(&mut (*link.as_ptr()).hash),
+      DAGPtr::All(link) => Some(&mut (*link.as_ptr()).hash),
+      DAGPtr::App(link) => Some(&mut (*link.as_ptr()).hash),
+      DAGPtr::Ann(link) => Some(&mut (*link.as_ptr()).hash),
+      DAGPtr::Let(link) => Some(&mut (*link.as_ptr()).hash),
+      _ => None,
+    }
+  }
+}
+
+/// Forgets the cached hashes of a node whose children changed and of the
+/// nodes above it. A node without a cache has none above it either, since
+/// hashing a node caches the hashes of all the nodes below it.
+pub fn clear_hash(node: DAGPtr) {
+  let mut todo = vec![node];
+  while let Some(node) = todo.pop() {
+    if let Some(cache) = cache_of(node) {
+      unsafe {
+        if (*cache).take().is_some() {
+          for parent in DLL::iter_option(get_parents(node)) {
+            todo.extend(parent_node(*parent));
+          }
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::{
+    term::tests::test_defs,
+    yatima,
+  };
+
+  fn hash_of(term: &crate::term::Term) -> Digest {
+    let dag = DAG::from_term(term);
+    let res = hash(dag.head, 0);
+    dag.free();
+    res
+  }
+
+  #[test]
+  pub fn hash_structure() {
+    // Names don't matter, only which binder each variable refers to
+    assert_eq!(
+      hash_of(&yatima!("λ x y => x")),
+      hash_of(&yatima!("λ a b => a"))
+    );
+    assert_ne!(
+      hash_of(&yatima!("λ x y => x")),
+      hash_of(&yatima!("λ x y => y"))
+    );
+    assert_ne!(
+      hash_of(&yatima!("∀ (x: Type) -> Type")),
+      hash_of(&yatima!("∀ (0 x: Type) -> Type"))
+    );
+    // A closed subterm hashes the same wherever it occurs
+    let dag = DAG::from_term(&yatima!("λ f => f (λ x => x) (λ y => y)"));
+    unsafe {
+      let bod = match dag.head {
+        DAGPtr::Lam(link) => (*link.as_ptr()).bod,
+        _ => unreachable!(),
+      };
+      let (fun, arg) = match bod {
+        DAGPtr::App(link) => ((*link.as_ptr()).fun, (*link.as_ptr()).arg),
+        _ => unreachable!(),
+      };
+      let inner = match fun {
+        DAGPtr::App(link) => (*link.as_ptr()).arg,
+        _ => unreachable!(),
+      };
+      assert_eq!(hash(inner, 1), hash(arg, 1));
+    }
+    dag.free();
+    // and at any depth
+    let (a, b) = (yatima!("λ x y => x"), yatima!("λ x y => x"));
+    let (a, b) = (DAG::from_term(&a), DAG::from_term(&b));
+    assert_eq!(hash(a.head, 0), hash(b.head, 3));
+    a.free();
+    b.free();
+  }
+
+  #[test]
+  pub fn hash_reduction() {
+    // The cached hashes of the nodes above a reduced one are forgotten
+    let defs = test_defs();
+    let mut dag = DAG::from_term(&yatima!("λ x => (λ y => y) x"));
+    let before = hash(dag.head, 0);
+    assert_eq!(before, hash(dag.head, 0));
+    dag.norm(&defs).unwrap();
+    let mut id = DAG::from_term(&yatima!("λ x => x"));
+    assert_eq!(hash(dag.head, 0), hash(id.head, 0));
+    assert_ne!(hash(dag.head, 0), before);
+    // Changing the depth of a free variable changes the hashes it occurs in
+    unsafe {
+      let var = match id.head {
+        DAGPtr::Lam(link) => &mut (*link.as_ptr()).var,
+        _ => unreachable!(),
+      };
+      let bod = match id.head {
+        DAGPtr::Lam(link) => (*link.as_ptr()).bod,
+        _ => unreachable!(),
+      };
+      set_dep(var, 3);
+      let at_3 = hash(bod, 4);
+      set_dep(var, 5);
+      assert_ne!(at_3, hash(bod, 4));
+    }
+    // but leaves the cached hashes it doesn't occur in
+    let open = DAG::from_term(&yatima!("λ x => (λ y => y) (λ z => x)"));
+    unsafe {
+      let (var, bod) = match open.head {
+        DAGPtr::Lam(link) => (&mut (*link.as_ptr()).var, (*link.as_ptr()).bod),
+        _ => unreachable!(),
+      };
+      let (fun, arg) = match bod {
+        DAGPtr::App(link) => ((*link.as_ptr()).fun, (*link.as_ptr()).arg),
+        _ => unreachable!(),
+      };
+      set_dep(var, 0);
+      let at_0 = hash(bod, 1);
+      set_dep(var, 2);
+      assert!(matches!(cache_of(fun), Some(cache) if (*cache).is_some()));
+      assert!(matches!(cache_of(arg), Some(cache) if (*cache).is_none()));
+      assert_ne!(at_0, hash(bod, 1));
+    }
+    open.free();
+    id.whnf(&defs).unwrap();
+    id.free();
+    dag.free();
+  }
+}
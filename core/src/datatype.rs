@@ -0,0 +1,564 @@
+use crate::{
+  defs::{
+    Def,
+    Defs,
+  },
+  name::Name,
+  package::Entry,
+  position::Pos,
+  term::{
+    Term,
+    Uses,
+  },
+};
+
+/// A datatype declaration `type Name (params) { Ctor (fields), ... }`. Field
+/// types live in the context of the parameters and of the previous fields, and
+/// refer to the declared type itself with `Term::Rec`.
+///
+/// A record declaration `record Name (params) { field: Type, ... }` is a
+/// datatype with the single constructor `New`, whose fields are also listed
+/// in `fields` with their types in the context of the parameters alone, so
+/// that each of them gets a projection `Name.field`.
+#[derive(Clone, Debug)]
+pub struct Datatype {
+  pub pos: Pos,
+  pub partial: bool,
+  pub name: Name,
+  pub params: Vec<(Uses, Name, Term)>,
+  pub ctors: Vec<(Name, Vec<(bool, Uses, Name, Term)>)>,
+  pub fields: Vec<(Name, Term)>,
+}
+
+impl Datatype {
+  /// The names of the generated definitions, in the order they are defined
+  pub fn names(&self) -> Vec<Name> {
+    let mut names = vec![self.name.clone()];
+    for (ctor, _) in &self.ctors {
+      names.push(self.member(ctor));
+    }
+    names.push(self.member("elim"));
+    for (field, _) in &self.fields {
+      names.push(self.member(field));
+    }
+    names
+  }
+
+  pub fn member(&self, nam: &str) -> Name {
+    Name::from(format!("{}.{}", self.name, nam))
+  }
+
+  /// Elaborates the declaration into the Self-typed definition of the type,
+  /// one definition per constructor and an eliminator, `Name.elim x : ∀ (0 P:
+  /// ∀ (Name params) -> Type) (cases...) -> P x`, which is just `case x`
+  pub fn defs(&self) -> Vec<(Name, Def, Entry)> {
+    let pos = self.pos;
+    // Only the type itself is recursive
+    let (typ_def, typ_entry) = Def::make(
+      pos,
+      self.partial,
+      false,
+      self.alls(false, Term::Typ(pos, 0)),
+      self.lams(self.params.iter().map(|(_, nam, _)| nam), self.self_type()),
+    );
+    let ref_ =
+      Term::Ref(pos, self.name.clone(), typ_def.def_cid, typ_def.ast_cid);
+    let mut defs = vec![(self.name.clone(), typ_def, typ_entry)];
+    for (k, (ctor, fields)) in self.ctors.iter().enumerate() {
+      let r = fields.len() as u64;
+      let res = self.apply_params(ref_.clone(), r);
+      let typ_ = fields.iter().rev().fold(res, |acc, (imp, uses, nam, typ)| {
+        let typ = unrec(typ, &ref_);
+        Term::All(pos, *imp, *uses, nam.clone(), Box::new((typ, acc)))
+      });
+      let binders = self
+        .params
+        .iter()
+        .map(|(_, nam, _)| nam)
+        .chain(fields.iter().map(|(_, _, nam, _)| nam));
+      let (def, entry) = Def::make(
+        pos,
+        false,
+        false,
+        self.alls(true, typ_),
+        self.lams(binders, self.data(k)),
+      );
+      defs.push((self.member(ctor), def, entry));
+    }
+    // The body of the Self type has the same context as the image of the
+    // eliminator's type, with the scrutinee in place of the self variable
+    let scrutinee = Name::from("self");
+    let dom_img =
+      (self.apply_params(ref_.clone(), 0), unrec(&self.body(), &ref_));
+    let elim_typ =
+      Term::All(pos, false, Uses::Many, scrutinee.clone(), Box::new(dom_img));
+    let binders = self
+      .params
+      .iter()
+      .map(|(_, nam, _)| nam)
+      .chain(std::iter::once(&scrutinee));
+    let elim = Term::Cse(pos, Box::new(Term::Var(pos, scrutinee.clone(), 0)));
+    let (def, entry) = Def::make(
+      pos,
+      false,
+      false,
+      self.alls(true, elim_typ),
+      self.lams(binders, elim),
+    );
+    defs.push((self.member("elim"), def, entry));
+    for j in 0..self.fields.len() {
+      let (def, entry) = self.projection(&ref_, j);
+      defs.push((self.member(&self.fields[j].0), def, entry));
+    }
+    defs
+  }
+
+  // The projection of the field `j` of a record, `Name.field self : ∀ {0
+  // params} (self: Name params) -> Type`, which is `case self (λ _ => Type)
+  // (λ fields => field)`
+  fn projection(&self, ref_: &Term, j: usize) -> (Def, Entry) {
+    let pos = self.pos;
+    let scrutinee = Name::from("self");
+    let typ = unrec(&self.fields[j].1, ref_);
+    let dom_img = (self.apply_params(ref_.clone(), 0), typ.clone().shift(1, 0));
+    let typ_ =
+      Term::All(pos, false, Uses::Many, scrutinee.clone(), Box::new(dom_img));
+    let motive =
+      Term::Lam(pos, Name::from("_"), None, Box::new(typ.shift(2, 0)));
+    let r = self.fields.len();
+    let field = Term::Var(pos, self.fields[j].0.clone(), (r - 1 - j) as u64);
+    let case = self.lams(self.fields.iter().map(|(nam, _)| nam), field);
+    let self_ = Term::Var(pos, scrutinee.clone(), 0);
+    let cse = Term::Cse(pos, Box::new(self_));
+    let cse = Term::App(pos, Box::new((cse, motive)));
+    let bod = Term::App(pos, Box::new((cse, case)));
+    let binders = self
+      .params
+      .iter()
+      .map(|(_, nam, _)| nam)
+      .chain(std::iter::once(&scrutinee));
+    Def::make(
+      pos,
+      false,
+      false,
+      self.alls(true, typ_),
+      self.lams(binders, bod),
+    )
+  }
+
+  // `@self ∀ (0 P: ∀ (Name params) -> Type) (& Ctor: case)... -> P self`, in
+  // the context of the parameters
+  pub fn self_type(&self) -> Term {
+    Term::Slf(self.pos, Name::from("self"), Box::new(self.body()))
+  }
+
+  // The body of the Self type, in the context of the parameters and of the
+  // self variable
+  fn body(&self) -> Term {
+    let pos = self.pos;
+    let m = self.ctors.len() as u64;
+    let motive = Term::All(
+      pos,
+      false,
+      Uses::Many,
+      Name::from(""),
+      Box::new((self.apply_params(Term::Rec(pos), 1), Term::Typ(pos, 0))),
+    );
+    let res = Term::App(
+      pos,
+      Box::new((
+        Term::Var(pos, Name::from("P"), m),
+        Term::Var(pos, Name::from("self"), m + 1),
+      )),
+    );
+    let cases = self.ctors.iter().enumerate().rev().fold(res, |acc, (k, ctor)| {
+      let case_acc = Box::new((self.case_type(k), acc));
+      Term::All(pos, false, Uses::Affi, ctor.0.clone(), case_acc)
+    });
+    let motive_cases = Box::new((motive, cases));
+    Term::All(pos, false, Uses::None, Name::from("P"), motive_cases)
+  }
+
+  // The type of the case for the constructor `k`, `∀ (fields) -> P (data λ P
+  // cases... => Ctor fields)`, below the parameters, the self variable, the
+  // motive and the previous cases
+  fn case_type(&self, k: usize) -> Term {
+    let pos = self.pos;
+    let fields = &self.ctors[k].1;
+    let r = fields.len() as u64;
+    let res = Term::App(
+      pos,
+      Box::new((Term::Var(pos, Name::from("P"), r + k as u64), self.data(k))),
+    );
+    fields.iter().enumerate().rev().fold(res, |acc, (j, (_, uses, nam, typ))| {
+      let typ = typ.clone().shift(2 + k as u64, j as u64);
+      Term::All(pos, false, *uses, nam.clone(), Box::new((typ, acc)))
+    })
+  }
+
+  // `data λ P cases... => Ctor fields`, the value built by the constructor
+  // `k`, below its fields
+  fn data(&self, k: usize) -> Term {
+    let pos = self.pos;
+    let m = self.ctors.len() as u64;
+    let (ctor, fields) = &self.ctors[k];
+    let r = fields.len() as u64;
+    let head = Term::Var(pos, ctor.clone(), m - 1 - k as u64);
+    let app = fields.iter().enumerate().fold(head, |acc, (j, (_, _, nam, _))| {
+      let field = Term::Var(pos, nam.clone(), m + r - j as u64);
+      Term::App(pos, Box::new((acc, field)))
+    });
+    let binders = std::iter::once(Name::from("P"))
+      .chain(self.ctors.iter().map(|(ctor, _)| ctor.clone()))
+      .collect::<Vec<_>>();
+    Term::Dat(pos, Box::new(self.lams(binders.iter(), app)))
+  }
+
+  // Applies `head` to the parameters, found below `offset` other binders
+  fn apply_params(&self, head: Term, offset: u64) -> Term {
+    let n = self.params.len() as u64;
+    self.params.iter().enumerate().fold(head, |acc, (i, (_, nam, _))| {
+      let param = Term::Var(self.pos, nam.clone(), offset + n - 1 - i as u64);
+      Term::App(self.pos, Box::new((acc, param)))
+    })
+  }
+
+  // Binds the parameters with foralls, which are implicit and erased in the
+  // types of the constructors and of the eliminator
+  fn alls(&self, implicit: bool, img: Term) -> Term {
+    self.params.iter().rev().fold(img, |acc, (uses, nam, typ)| {
+      let uses = if implicit { Uses::None } else { *uses };
+      let dom_img = Box::new((typ.clone(), acc));
+      Term::All(self.pos, implicit, uses, nam.clone(), dom_img)
+    })
+  }
+
+  fn lams<'a>(
+    &self,
+    binders: impl DoubleEndedIterator<Item = &'a Name>,
+    bod: Term,
+  ) -> Term {
+    binders.rev().fold(bod, |acc, nam| {
+      Term::Lam(self.pos, nam.clone(), None, Box::new(acc))
+    })
+  }
+}
+
+/// Reads the constructors of a datatype, with the names of their fields,
+/// back from the Self type of its definition
+pub fn constructors(
+  defs: &Defs,
+  name: &Name,
+) -> Option<Vec<(Name, Vec<Name>)>> {
+  let mut term = &defs.get(name)?.term;
+  while let Term::Lam(_, _, _, bod) = term {
+    term = bod;
+  }
+  let mut cases = match term {
+    Term::Slf(_, _, bod) => match &**bod {
+      Term::All(_, false, Uses::None, _, motive_cases) => &motive_cases.1,
+      _ => return None,
+    },
+    _ => return None,
+  };
+  let mut ctors = vec![];
+  while let Term::All(_, _, _, ctor, case_rest) = cases {
+    let mut fields = vec![];
+    let mut case = &case_rest.0;
+    while let Term::All(_, _, _, field, dom_img) = case {
+      fields.push(field.clone());
+      case = &dom_img.1;
+    }
+    // Only declared datatypes have their constructors defined alongside
+    defs.get(&Name::from(format!("{}.{}", name, ctor)))?;
+    ctors.push((ctor.clone(), fields));
+    cases = &case_rest.1;
+  }
+  Some(ctors)
+}
+
+/// Reads the fields of a record back from its definitions: a record is a
+/// datatype whose only constructor is `New`, with a projection defined for
+/// each of its fields
+pub fn record_fields(defs: &Defs, name: &Name) -> Option<Vec<Name>> {
+  match constructors(defs, name)?.as_slice() {
+    [(ctor, fields)] if ctor.to_string() == "New" => {
+      for field in fields {
+        defs.get(&Name::from(format!("{}.{}", name, field)))?;
+      }
+      Some(fields.clone())
+    }
+    _ => None,
+  }
+}
+
+/// The records with exactly the given fields, in any order, along with their
+/// fields in the order they are declared
+pub fn find_records(defs: &Defs, fields: &[Name]) -> Vec<(Name, Vec<Name>)> {
+  let mut found: Vec<(Name, Vec<Name>)> = defs
+    .names()
+    .into_iter()
+    .filter_map(|typ| record_fields(defs, &typ).map(|fs| (typ, fs)))
+    .filter(|(_, fs)| {
+      fs.len() == fields.len() && fs.iter().all(|f| fields.contains(f))
+    })
+    .collect();
+  found.sort_by(|a, b| a.0.cmp(&b.0));
+  found
+}
+
+/// The records which have a field of the given name
+pub fn find_field(defs: &Defs, field: &Name) -> Vec<Name> {
+  let mut found: Vec<Name> = defs
+    .names()
+    .into_iter()
+    .filter(|typ| {
+      record_fields(defs, typ).map_or(false, |fs| fs.contains(field))
+    })
+    .collect();
+  found.sort();
+  found
+}
+
+/// Finds the datatype a constructor name, either qualified as `Type.Ctor` or
+/// bare if no other datatype has a constructor of that name, belongs to.
+/// Returns the name of the datatype and the index of the constructor
+pub fn find_constructor(defs: &Defs, nam: &Name) -> Option<(Name, usize)> {
+  let index = |typ: &Name, ctor: &str| {
+    constructors(defs, typ)?.iter().position(|(c, _)| c.to_string() == ctor)
+  };
+  let qualified = nam.to_string().rsplit_once('.').and_then(|(typ, ctor)| {
+    let typ = Name::from(typ);
+    index(&typ, ctor).map(|idx| (typ, idx))
+  });
+  if qualified.is_some() {
+    return qualified;
+  }
+  let mut found = defs
+    .names()
+    .into_iter()
+    .filter_map(|typ| index(&typ, nam.as_ref()).map(|idx| (typ, idx)));
+  match (found.next(), found.next()) {
+    (Some(res), None) => Some(res),
+    _ => None,
+  }
+}
+
+// Replaces the recursive references of a term by `ref_`
+pub fn unrec(term: &Term, ref_: &Term) -> Term {
+  match term {
+    Term::Rec(_) => ref_.clone(),
+    Term::Lam(pos, nam, dom, bod) => Term::Lam(
+      *pos,
+      nam.clone(),
+      dom.as_ref().map(|(uses, dom)| (*uses, Box::new(unrec(dom, ref_)))),
+      Box::new(unrec(bod, ref_)),
+    ),
+    Term::App(pos, fun_arg) => {
+      let fun_arg = (unrec(&fun_arg.0, ref_), unrec(&fun_arg.1, ref_));
+      Term::App(*pos, Box::new(fun_arg))
+    }
+    Term::All(pos, imp, uses, nam, dom_img) => {
+      let dom_img = (unrec(&dom_img.0, ref_), unrec(&dom_img.1, ref_));
+      Term::All(*pos, *imp, *uses, nam.clone(), Box::new(dom_img))
+    }
+    Term::Slf(pos, nam, bod) => {
+      Term::Slf(*pos, nam.clone(), Box::new(unrec(bod, ref_)))
+    }
+    Term::Dat(pos, bod) => Term::Dat(*pos, Box::new(unrec(bod, ref_))),
+    Term::Cse(pos, bod) => Term::Cse(*pos, Box::new(unrec(bod, ref_))),
+    Term::Rcd(pos, nams, bod) => {
+      Term::Rcd(*pos, nams.clone(), Box::new(unrec(bod, ref_)))
+    }
+    Term::Prj(pos, nam, prj_arg) => {
+      let prj_arg = (unrec(&prj_arg.0, ref_), unrec(&prj_arg.1, ref_));
+      Term::Prj(*pos, nam.clone(), Box::new(prj_arg))
+    }
+    Term::Let(pos, rec, uses, nam, typ_exp_bod) => {
+      let (typ, exp, bod) = &**typ_exp_bod;
+      let typ_exp_bod = (unrec(typ, ref_), unrec(exp, ref_), unrec(bod, ref_));
+      Term::Let(*pos, *rec, *uses, nam.clone(), Box::new(typ_exp_bod))
+    }
+    Term::Ann(pos, typ_exp) => {
+      let typ_exp = (unrec(&typ_exp.0, ref_), unrec(&typ_exp.1, ref_));
+      Term::Ann(*pos, Box::new(typ_exp))
+    }
+    _ => term.clone(),
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use crate::{
+    check::{
+      check_def,
+      elaborate_def,
+      error::CheckError,
+      CheckOpts,
+    },
+    dag::DAG,
+    defs::Defs,
+    name::Name,
+    term::Term,
+    tests::parse_package,
+  };
+
+  fn parse(src: &str) -> Defs {
+    let (defs, _) = parse_package(src).unwrap();
+    defs
+  }
+
+  #[test]
+  pub fn check_datatypes() {
+    let defs = parse(
+      "type Bool { True, False }
+       type List (A: Type) { Nil, Cons (x: A) (xs: List A) }
+       type Pair (A: Type) (B: Type) { New (fst: A) (snd: B) }
+       def not (b: Bool): Bool = Bool.elim b (λ _ => Bool) Bool.False Bool.True
+       def list: List #Nat = List.Cons 1 (List.Cons 2 List.Nil)
+       def fst (p: Pair #Nat #Text): #Nat =
+         Pair.elim p (λ _ => #Nat) (λ x y => x)",
+    );
+    let names: Vec<String> =
+      defs.names().iter().map(|n| n.to_string()).collect();
+    for name in [
+      "Bool",
+      "Bool.True",
+      "Bool.False",
+      "Bool.elim",
+      "List",
+      "List.Nil",
+      "List.Cons",
+      "List.elim",
+      "Pair.New",
+      "Pair.elim",
+    ] {
+      assert!(names.contains(&String::from(name)), "missing {}", name);
+    }
+    for name in names {
+      let res = check_def(&defs, &name);
+      assert!(res.is_ok(), "{}: {}", name, res.unwrap_err());
+    }
+    let def = |name: &str| defs.get(&Name::from(name)).unwrap();
+    assert_eq!(
+      format!("{}", def("List.Cons").typ_),
+      "∀ {0 A: Type} (x: A) (xs: List A) -> List A"
+    );
+    let list = elaborate_def(&defs, "list", CheckOpts::default()).unwrap();
+    assert_eq!(
+      format!("{}", list.term),
+      "List.Cons #Nat 1 (List.Cons #Nat 2 (List.Nil #Nat))"
+    );
+  }
+
+  #[test]
+  pub fn check_records() {
+    let defs = parse(
+      "record Point { x: #Nat, y: #Nat }
+       record Box (A: Type) { val: A }
+       def p: Point = { y = 2, x = 1 }
+       def q: Point = Point.New 1 2
+       def px: #Nat = p.x
+       def sum (r: Point): #Nat = #Nat.add r.x r.y
+       def b: Box #Text = { val = \"hi\" }
+       def bv: #Text = b.val
+       def lit: #Nat = { x = 3, y = 4 }.y
+       def app: #Nat = (Point.New 5 6).x",
+    );
+    for name in defs.names() {
+      let res = check_def(&defs, name.as_ref());
+      assert!(res.is_ok(), "{}: {}", name, res.unwrap_err());
+    }
+    let def = |name: &str| defs.get(&Name::from(name)).unwrap();
+    assert_eq!(
+      format!("{}", def("Box.val").typ_),
+      "∀ {0 A: Type} (self: Box A) -> A"
+    );
+    // Field names are kept for printing, but don't change the content id
+    assert_eq!(format!("{}", def("p").term), "{ x = 1, y = 2 }");
+    assert_eq!(format!("{}", def("sum").term), "λ r => #Nat.add r.x r.y");
+    assert_eq!(format!("{}", def("app").term), "(Point.New 5 6).x");
+    assert_eq!(def("p").ast_cid, def("q").ast_cid);
+    let vals = [("px", "1"), ("bv", "\"hi\""), ("lit", "4"), ("app", "5")];
+    for (name, val) in vals {
+      let mut dag = DAG::from_def(def(name), Name::from(name));
+      dag.norm(&defs).unwrap();
+      assert_eq!(format!("{}", dag), val);
+    }
+  }
+
+  #[test]
+  pub fn check_shared_fields() {
+    let defs = parse(
+      "record A { x: #Nat }
+       record B (T: Type) { x: T, y: #Nat }
+       def a: A = { x = 1 }
+       def b: B #Text = B.New \"hi\" 2
+       def ax: #Nat = a.x
+       def bx: #Text = b.x
+       def sum (r: B #Nat): #Nat = #Nat.add r.x r.y
+       def ann: #Nat = (A.New 3 :: A).x",
+    );
+    for name in defs.names() {
+      let res = check_def(&defs, name.as_ref());
+      assert!(res.is_ok(), "{}: {}", name, res.unwrap_err());
+    }
+    // The field is projected by the record of the type of the projected term
+    let elaborate = |name: &str| {
+      let def = elaborate_def(&defs, name, CheckOpts::default()).unwrap();
+      match def.term {
+        Term::Prj(_, _, prj_arg) => format!("{} {}", prj_arg.0, prj_arg.1),
+        term => format!("{}", term),
+      }
+    };
+    assert_eq!(elaborate("ax"), "A.x a");
+    assert_eq!(elaborate("bx"), "B.x #Text b");
+    // Projections embed as applications, with their records chosen or not
+    for name in &["ax", "sum"] {
+      let def = elaborate_def(&defs, name, CheckOpts::default()).unwrap();
+      for term in &[&defs.get(&Name::from(*name)).unwrap().term, &def.term] {
+        let (anon, meta) = term.embed();
+        assert_eq!(Term::unembed(&anon, &meta).as_ref(), Ok(*term));
+      }
+    }
+    let vals = [("ax", "1"), ("bx", "\"hi\""), ("ann", "3")];
+    for (name, val) in vals {
+      let def = elaborate_def(&defs, name, CheckOpts::default()).unwrap();
+      let mut dag = DAG::from_def(&def, Name::from(name));
+      dag.norm(&defs).unwrap();
+      assert_eq!(format!("{}", dag), val);
+    }
+    let src = "record A { x: #Nat }
+               record B { x: #Nat }
+               def f (n: #Nat): #Nat = n.x";
+    let defs = parse(src);
+    match check_def(&defs, "f") {
+      Err(CheckError::AmbiguousField(_, _, field, _)) => assert_eq!(field, "x"),
+      res => panic!("unexpected {:?}", res.map(|(typ, _)| typ)),
+    }
+  }
+
+  #[test]
+  pub fn parse_record_errors() {
+    for src in [
+      "record Point { x: #Nat, y: #Nat }  def p: Point = { x = 1 }",
+      "record Point { x: #Nat }  def p: Point = { x = 1, x = 2 }",
+      "record A { x: #Nat }  def f (a: A): #Nat = a.y",
+      "record A { x: #Nat }  record B { x: #Nat }  def a: A = { x = 1 }",
+      "record A { New: #Nat }",
+      "record A { x: #Nat, x: #Nat }",
+    ] {
+      let res = parse_package(src);
+      assert!(res.is_err(), "{}", src);
+    }
+  }
+
+  #[test]
+  pub fn parse_datatype_errors() {
+    let src = "type Bool { True, False }
+               def Bool.True: Type = Type";
+    assert!(parse_package(src).is_err());
+    let src = "type Bool { True (x: Missing) }";
+    assert!(parse_package(src).is_err());
+  }
+}
@@ -12,14 +12,27 @@ use crate::{
 };
 
 use cid::Cid;
+use sp_ipld::{
+  dag_cbor::cid,
+  Ipld,
+};
 
-use std::collections::HashMap;
+use std::{
+  borrow::Cow,
+  collections::{
+    HashMap,
+    HashSet,
+  },
+};
 
 use std::fmt;
 
 #[derive(Clone, Debug)]
 pub struct Def {
   pub pos: Pos,
+  pub partial: bool,
+  pub opaque: bool,
+  pub mutual: Option<Mutual>,
   pub def_cid: Cid,
   pub ast_cid: Cid,
   pub typ_: Term,
@@ -29,12 +42,41 @@ pub struct Def {
 impl PartialEq for Def {
   fn eq(&self, other: &Def) -> bool {
     self.def_cid == other.def_cid
+      && self.partial == other.partial
+      && self.opaque == other.opaque
+      && self.mutual.as_ref().map(|m| (m.block, m.idx))
+        == other.mutual.as_ref().map(|m| (m.block, m.idx))
       && self.ast_cid == other.ast_cid
       && self.typ_ == other.typ_
       && self.term == other.term
   }
 }
 
+/// The place of a definition in a `mutual` block. The members of a block
+/// refer to each other with `Term::Mut` by their index in it, and the block is
+/// identified by the anonymous terms and types of all of them, so that their
+/// content ids don't depend on each other's.
+#[derive(Clone, Debug)]
+pub struct Mutual {
+  pub block: Cid,
+  pub idx: u64,
+  /// The definition and AST content ids of the members, by index
+  pub members: Vec<(Cid, Cid)>,
+}
+
+impl Mutual {
+  /// Replaces the references to other members of the block by `Term::Ref`s
+  pub fn resolve(&self, term: &Term) -> Term {
+    term.replace(&|term| match term {
+      Term::Mut(pos, nam, idx) => {
+        let (def, ast) = self.members.get(*idx as usize)?;
+        Some(Term::Ref(*pos, nam.clone(), *def, *ast))
+      }
+      _ => None,
+    })
+  }
+}
+
 /// A map of content-ids to defs, with content ids for the def
 #[derive(PartialEq, Clone, Debug)]
 pub struct Defs {
@@ -43,26 +85,119 @@ pub struct Defs {
 }
 
 impl Def {
-  pub fn make(pos: Pos, typ_: Term, term: Term) -> (Self, Entry) {
+  pub fn make(
+    pos: Pos,
+    partial: bool,
+    opaque: bool,
+    typ_: Term,
+    term: Term,
+  ) -> (Self, Entry) {
     let (type_anon, type_meta) = typ_.embed();
     let (term_anon, term_meta) = term.embed();
     let ast_cid = term_anon.cid();
     let defn = Entry {
       pos,
+      partial,
+      opaque,
+      mutual: None,
       type_anon: type_anon.cid(),
       type_meta,
       term_anon: ast_cid,
       term_meta,
     };
-    let def = Def { pos, def_cid: defn.cid(), ast_cid, typ_, term };
+    let def = Def {
+      pos,
+      partial,
+      opaque,
+      mutual: None,
+      def_cid: defn.cid(),
+      ast_cid,
+      typ_,
+      term,
+    };
     (def, defn)
   }
 
+  /// Makes the definitions of the members of a `mutual` block, given as their
+  /// positions, partiality, opacity, types and terms, which refer to each
+  /// other with `Term::Mut`
+  pub fn make_mutual(
+    members: Vec<(Pos, bool, bool, Term, Term)>,
+  ) -> Vec<(Self, Entry)> {
+    let embeds: Vec<_> = members
+      .iter()
+      .map(|(_, _, _, typ_, term)| (typ_.embed(), term.embed()))
+      .collect();
+    let block = cid(&Ipld::List(
+      embeds
+        .iter()
+        .map(|((type_anon, _), (term_anon, _))| {
+          Ipld::List(vec![
+            Ipld::Link(type_anon.cid()),
+            Ipld::Link(term_anon.cid()),
+          ])
+        })
+        .collect(),
+    ));
+    let entries: Vec<Entry> = members
+      .iter()
+      .zip(embeds)
+      .enumerate()
+      .map(|(idx, ((pos, partial, opaque, ..), embeds))| {
+        let ((type_anon, type_meta), (term_anon, term_meta)) = embeds;
+        Entry {
+          pos: *pos,
+          partial: *partial,
+          opaque: *opaque,
+          mutual: Some((block, idx as u64)),
+          type_anon: type_anon.cid(),
+          term_anon: term_anon.cid(),
+          type_meta,
+          term_meta,
+        }
+      })
+      .collect();
+    let cids: Vec<(Cid, Cid)> =
+      entries.iter().map(|entry| (entry.cid(), entry.term_anon)).collect();
+    members
+      .into_iter()
+      .zip(entries)
+      .enumerate()
+      .map(|(idx, ((pos, partial, opaque, typ_, term), entry))| {
+        let mutual =
+          Mutual { block, idx: idx as u64, members: cids.clone() };
+        let def = Def {
+          pos,
+          partial,
+          opaque,
+          mutual: Some(mutual),
+          def_cid: cids[idx].0,
+          ast_cid: cids[idx].1,
+          typ_,
+          term,
+        };
+        (def, entry)
+      })
+      .collect()
+  }
+
+  /// Resolves the references to the other members of the `mutual` block of
+  /// the definition in its term or type
+  pub fn resolve<'a>(&self, term: &'a Term) -> Cow<'a, Term> {
+    match &self.mutual {
+      Some(mutual) => Cow::Owned(mutual.resolve(term)),
+      None => Cow::Borrowed(term),
+    }
+  }
+
   pub fn embed(&self) -> (Entry, Anon, Anon) {
     let (type_anon, type_meta) = self.typ_.embed();
     let (term_anon, term_meta) = self.term.embed();
     let d = Entry {
       pos: self.pos,
+      partial: self.partial,
+      opaque: self.opaque,
+      mutual: self.mutual.as_ref().map(|m| (m.block, m.idx)),
       type_anon: type_anon.cid(),
       term_anon: self.ast_cid,
       type_meta,
@@ -78,8 +213,18 @@ impl Def {
   ) -> Result<Self, EmbedError> {
     let typ_ = Term::unembed(&type_anon, &def.type_meta)?;
     let term = Term::unembed(&term_anon, &def.term_meta)?;
+    // The other members of a block are only known once they are all loaded,
+    // see `Defs::link_mutual`
+    let mutual = def.mutual.map(|(block, idx)| Mutual {
+      block,
+      idx,
+      members: vec![],
+    });
     Ok(Def {
       pos: def.pos,
+      partial: def.partial,
+      opaque: def.opaque,
+      mutual,
       def_cid: def.cid(),
       ast_cid: def.term_anon,
       typ_,
@@ -87,9 +232,17 @@ impl Def {
     })
   }
 
+  /// Whether the definition is an axiom, which has a type but no value
+  pub fn is_axiom(&self) -> bool { matches!(self.term, Term::Axm(..)) }
+
   pub fn pretty(&self, name: String) -> String {
+    if self.is_axiom() {
+      return format!("axiom {} : {}", name, self.typ_.pretty(Some(&name)));
+    }
     format!(
-      "def {} : {} = {}",
+      "{}{}def {} : {} = {}",
+      if self.partial { "partial " } else { "" },
+      if self.opaque { "opaque " } else { "" },
       name,
       self.typ_.pretty(Some(&name)),
       self.term.pretty(Some(&name))
@@ -97,6 +250,36 @@ impl Def {
   }
 }
 
+// Pushes the references in a term, with the names they are made by
+fn refs(term: &Term, res: &mut Vec<(Name, Cid)>) {
+  match term {
+    Term::Ref(_, nam, def_cid, _) => res.push((nam.clone(), *def_cid)),
+    Term::Lam(_, _, dom, bod) => {
+      if let Some((_, dom)) = dom {
+        refs(dom, res);
+      }
+      refs(bod, res);
+    }
+    Term::App(_, two)
+    | Term::All(_, _, _, _, two)
+    | Term::Ann(_, two)
+    | Term::Prj(_, _, two) => {
+      refs(&two.0, res);
+      refs(&two.1, res);
+    }
+    Term::Slf(_, _, bod)
+    | Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => refs(bod, res),
+    Term::Let(_, _, _, _, typ_exp_bod) => {
+      refs(&typ_exp_bod.0, res);
+      refs(&typ_exp_bod.1, res);
+      refs(&typ_exp_bod.2, res);
+    }
+    _ => (),
+  }
+}
+
 impl Defs {
   pub fn new() -> Self { Defs { defs: HashMap::new(), names: HashMap::new() } }
 
@@ -126,6 +309,83 @@ impl Defs {
     self.defs.get(&def_cid)
   }
 
+  /// The definitions a definition depends on, through the references in its
+  /// type or value and in theirs, other than itself, with the names they are
+  /// referred to by. References to definitions missing from `self` are listed
+  /// without being followed.
+  pub fn dependencies(&self, def: &Def) -> Vec<(Name, Cid)> {
+    let mut seen = HashSet::new();
+    seen.insert(def.def_cid);
+    let mut todo = vec![];
+    refs(&def.resolve(&def.typ_), &mut todo);
+    refs(&def.resolve(&def.term), &mut todo);
+    let mut deps = vec![];
+    while let Some((nam, def_cid)) = todo.pop() {
+      if !seen.insert(def_cid) {
+        continue;
+      }
+      if let Some(def) = self.defs.get(&def_cid) {
+        refs(&def.resolve(&def.typ_), &mut todo);
+        refs(&def.resolve(&def.term), &mut todo);
+      }
+      deps.push((nam, def_cid));
+    }
+    deps
+  }
+
+  /// The names and content ids of the definitions `roots` and of every
+  /// definition they depend on, sorted by name. Fails with the first root
+  /// that isn't defined.
+  pub fn closure(&self, roots: &[Name]) -> Result<Vec<(Name, Cid)>, Name> {
+    let mut needed = HashSet::new();
+    for root in roots {
+      let def = self.get(root).ok_or_else(|| root.clone())?;
+      needed.insert(def.def_cid);
+      needed.extend(self.dependencies(def).into_iter().map(|(_, cid)| cid));
+    }
+    let mut names: Vec<(Name, Cid)> = self
+      .names
+      .iter()
+      .filter(|(_, cid)| needed.contains(cid))
+      .map(|(name, cid)| (name.clone(), *cid))
+      .collect();
+    names.sort();
+    Ok(names)
+  }
+
+  /// The names of the axioms a definition depends on, through the references
+  /// in its type or value and in theirs, other than itself
+  pub fn axioms(&self, def: &Def) -> Vec<Name> {
+    let mut axioms: Vec<Name> = self
+      .dependencies(def)
+      .into_iter()
+      .filter(|(_, cid)| self.defs.get(cid).map_or(false, Def::is_axiom))
+      .map(|(nam, _)| nam)
+      .collect();
+    axioms.sort();
+    axioms
+  }
+
+  /// Links the members of the `mutual` blocks of unembedded definitions to
+  /// each other
+  pub fn link_mutual(&mut self) {
+    let mut blocks: HashMap<Cid, Vec<(u64, Cid, Cid)>> = HashMap::new();
+    for def in self.defs.values() {
+      if let Some(mutual) = &def.mutual {
+        let members = blocks.entry(mutual.block).or_default();
+        members.push((mutual.idx, def.def_cid, def.ast_cid));
+      }
+    }
+    for def in self.defs.values_mut() {
+      if let Some(mutual) = &mut def.mutual {
+        let mut members = blocks[&mutual.block].clone();
+        members.sort_by_key(|(idx, ..)| *idx);
+        mutual.members =
+          members.into_iter().map(|(_, def, ast)| (def, ast)).collect();
+      }
+    }
+  }
+
   pub fn merge(self, other: Defs, import: &Import) -> Self {
     let mut defs = self.defs;
     for (k, v) in other.defs {
@@ -169,9 +429,12 @@ impl fmt::Display for Defs {
 #[cfg(test)]
 pub mod tests {
   use super::*;
-  use crate::term::tests::{
-    arbitrary_term,
-    test_defs,
+  use crate::{
+    term::tests::{
+      arbitrary_term,
+      test_defs,
+    },
+    tests::parse_package,
   };
   use quickcheck::{
     Arbitrary,
@@ -182,7 +445,13 @@ pub mod tests {
     let typ_: Term = Arbitrary::arbitrary(g);
     let term =
       arbitrary_term(g, true, test_defs(), std::collections::VecDeque::new());
-    Def::make(Pos::None, typ_, term)
+    Def::make(
+      Pos::None,
+      bool::arbitrary(g),
+      bool::arbitrary(g),
+      typ_,
+      term,
+    )
   }
 
   impl Arbitrary for Def {
@@ -212,4 +481,60 @@ pub mod tests {
       }
     }
   }
+
+  const EVEN_ODD: &str = "mutual {
+      def even (n: #Nat): #Bool =
+        (case n) (λ _ => #Bool) #Bool.true (λ m => odd m)
+      def odd (n: #Nat): #Bool =
+        (case n) (λ _ => #Bool) #Bool.false (λ m => even m)
+    }";
+
+  fn parse(src: &str) -> Defs { parse_package(src).unwrap().0 }
+
+  #[test]
+  pub fn mutual_blocks() {
+    let defs = parse(EVEN_ODD);
+    let even = defs.get(&Name::from("even")).unwrap();
+    let odd = defs.get(&Name::from("odd")).unwrap();
+    assert_eq!(format!("{}", odd.term), "λ n => (case n) (λ _ => #Bool) \
+       #Bool.false (λ m => even m)");
+    let (even_mut, odd_mut) = match (&even.mutual, &odd.mutual) {
+      (Some(even_mut), Some(odd_mut)) => (even_mut, odd_mut),
+      _ => panic!("not in a mutual block"),
+    };
+    assert_eq!(even_mut.block, odd_mut.block);
+    assert_eq!((even_mut.idx, odd_mut.idx), (0, 1));
+    assert_eq!(even_mut.members, vec![
+      (even.def_cid, even.ast_cid),
+      (odd.def_cid, odd.ast_cid)
+    ]);
+    // The block is identified by the anonymous terms of its members, not by
+    // where it is parsed
+    let src = format!("def before: #Nat = 1\n{}", EVEN_ODD);
+    let other = parse(&src);
+    let other_even = other.get(&Name::from("even")).unwrap();
+    assert_eq!(other_even.ast_cid, even.ast_cid);
+    assert_eq!(other_even.mutual.as_ref().unwrap().block, even_mut.block);
+    // The block is restored from the entries of its members
+    let mut loaded = Defs::new();
+    for (name, def) in defs.named_defs() {
+      let (entry, type_anon, term_anon) = def.embed();
+      let entry = Entry::from_ipld(&entry.to_ipld()).unwrap();
+      loaded.insert(name, Def::unembed(entry, type_anon, term_anon).unwrap());
+    }
+    loaded.link_mutual();
+    let loaded_even = loaded.get(&Name::from("even")).unwrap();
+    assert_eq!(loaded_even, even);
+    assert_eq!(loaded_even.mutual.as_ref().unwrap().members, even_mut.members);
+    let term = Term::App(
+      Pos::None,
+      Box::new((
+        Term::Ref(Pos::None, Name::from("even"), even.def_cid, even.ast_cid),
+        Term::Lit(Pos::None, crate::literal::Literal::Nat(3u64.into())),
+      )),
+    );
+    let mut dag = crate::dag::DAG::from_term(&term);
+    dag.norm(&loaded).unwrap();
+    assert_eq!(format!("{}", dag), "#Bool.false");
+  }
 }
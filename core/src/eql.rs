@@ -0,0 +1,103 @@
+use sp_ipld::Ipld;
+
+use std::fmt;
+
+use crate::{
+  ipld_error::IpldError,
+  term::Term,
+  yatima,
+};
+
+/// The constants of the identity type. `#Eq A a b`, written `a == b`, is the
+/// type of proofs that `a` and `b` are equal, whose only canonical proof is
+/// `refl`. Proofs are used with `rewrite P e x`, which turns `x: P a` into
+/// `P b` given `e: a == b`, and which reduces to `x` once `e` is `refl`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EqlOp {
+  Eq,
+  Refl,
+  Rewrite,
+}
+
+impl EqlOp {
+  pub fn symbol(self) -> String {
+    match self {
+      Self::Eq => "#Eq".to_owned(),
+      Self::Refl => "refl".to_owned(),
+      Self::Rewrite => "rewrite".to_owned(),
+    }
+  }
+
+  pub fn type_of(self) -> Term {
+    match self {
+      Self::Eq => yatima!("∀ {0 A: Type} (a b: A) -> Type"),
+      Self::Refl => yatima!("∀ {0 A: Type} {0 a: A} -> #Eq {A} a a"),
+      Self::Rewrite => yatima!(
+        "∀ {0 A: Type} {0 a b: A} (0 P: ∀ (x: A) -> Type) (0 e: #Eq {A} a b)
+           (x: P a)
+         -> P b"
+      ),
+    }
+  }
+
+  /// The number of arguments the constant takes, implicit ones included
+  pub fn arity(self) -> usize {
+    match self {
+      Self::Eq => 3,
+      Self::Refl => 2,
+      Self::Rewrite => 6,
+    }
+  }
+
+  pub fn to_ipld(self) -> Ipld {
+    match self {
+      Self::Eq => Ipld::Integer(0),
+      Self::Refl => Ipld::Integer(1),
+      Self::Rewrite => Ipld::Integer(2),
+    }
+  }
+
+  pub fn from_ipld(ipld: &Ipld) -> Result<Self, IpldError> {
+    match ipld {
+      Ipld::Integer(0) => Ok(Self::Eq),
+      Ipld::Integer(1) => Ok(Self::Refl),
+      Ipld::Integer(2) => Ok(Self::Rewrite),
+      xs => Err(IpldError::EqlOp(xs.to_owned())),
+    }
+  }
+}
+
+impl fmt::Display for EqlOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.symbol())
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use quickcheck::{
+    Arbitrary,
+    Gen,
+  };
+  use rand::Rng;
+
+  impl Arbitrary for EqlOp {
+    fn arbitrary(_g: &mut Gen) -> Self {
+      let mut rng = rand::thread_rng();
+      match rng.gen_range(0..3) {
+        0 => Self::Eq,
+        1 => Self::Refl,
+        _ => Self::Rewrite,
+      }
+    }
+  }
+
+  #[quickcheck]
+  fn eql_op_ipld(x: EqlOp) -> bool {
+    match EqlOp::from_ipld(&x.to_ipld()) {
+      Ok(y) => x == y,
+      _ => false,
+    }
+  }
+}
@@ -0,0 +1,397 @@
+use crate::{
+  check::{
+    check_def_metas,
+    error::CheckError,
+    unify::{
+      Metas,
+      Path,
+    },
+    CheckOpts,
+  },
+  defs::{
+    Def,
+    Defs,
+  },
+  eql::EqlOp,
+  name::Name,
+  position::Pos,
+  term::Term,
+  uses::Uses,
+};
+
+/// Checks a definition and returns its value with the binders and arguments
+/// of quantity `0` taken out, which evaluates to the same data as the value
+/// without doing the work of building proofs and types that are never used.
+/// The value has its references to the members of its `mutual` block
+/// resolved.
+pub fn erase_def(
+  defs: &Defs,
+  name: &str,
+  opts: CheckOpts,
+) -> Result<Term, Vec<CheckError>> {
+  let (_, _, term, metas) = check_def_metas(defs, name, opts)?;
+  let mut erasure = Erasure { metas: &metas, path: Some(vec![]) };
+  Ok(erasure.erase(&term, &mut vec![]))
+}
+
+/// Erases the definitions `roots` and every definition they depend on with
+/// `erase_def`, into definitions that evaluate like the originals. The erased
+/// definitions have the content ids of their erased types and values, but are
+/// kept under the content ids of the definitions they stand in for, which the
+/// references to them have. Fails with the name and errors of the first
+/// definition that doesn't check.
+pub fn erase_defs(
+  defs: &Defs,
+  roots: &[Name],
+  opts: CheckOpts,
+) -> Result<Defs, (Name, Vec<CheckError>)> {
+  let names = defs.closure(roots).map_err(|root| {
+    let err = CheckError::UndefinedReference(Pos::None, root.to_string());
+    (root, vec![err])
+  })?;
+  let mut todo = names;
+  let mut erased = Defs::new();
+  while let Some((name, def_cid)) = todo.pop() {
+    if erased.defs.contains_key(&def_cid) {
+      erased.names.insert(name, def_cid);
+      continue;
+    }
+    let def = &defs.defs[&def_cid];
+    let term =
+      erase_def(defs, &name, opts).map_err(|errs| (name.clone(), errs))?;
+    let mut def = Def { term, ..def.clone() };
+    def.ast_cid = def.term.embed().0.cid();
+    def.def_cid = def.embed().0.cid();
+    // Solved holes may refer to definitions the original didn't
+    todo.extend(defs.dependencies(&def));
+    erased.names.insert(name, def_cid);
+    erased.defs.insert(def_cid, def);
+  }
+  Ok(erased)
+}
+
+// What a binder in scope of the term being erased became
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Binder {
+  Kept,
+  Erased,
+  // A binder that's not in the original term, added by the erasure
+  Added,
+}
+
+struct Erasure<'a> {
+  metas: &'a Metas,
+  // The path of the term being erased in the checked value, or `None` in the
+  // solution of a hole, which the checker recorded no quantities in
+  path: Option<Path>,
+}
+
+// What's left of a variable or type annotation that was erased. Since only
+// types and proofs are erased, it's never inspected by an evaluation.
+fn erased(pos: Pos) -> Term { Term::Typ(pos, 0) }
+
+impl<'a> Erasure<'a> {
+  // Whether the term at the given slots below the one being erased has
+  // quantity `0`
+  fn erases(&self, slots: &[u8]) -> bool {
+    match &self.path {
+      Some(path) => {
+        let path = [path.as_slice(), slots].concat();
+        self.metas.quantities.get(&path) == Some(&Uses::None)
+      }
+      None => false,
+    }
+  }
+
+  // Erases the term at the given slots below the one being erased
+  fn child(
+    &mut self,
+    slots: &[u8],
+    term: &Term,
+    ctx: &mut Vec<Binder>,
+  ) -> Term {
+    if let Some(path) = &mut self.path {
+      path.extend(slots);
+    }
+    let res = self.erase(term, ctx);
+    if let Some(path) = &mut self.path {
+      path.truncate(path.len() - slots.len());
+    }
+    res
+  }
+
+  fn under(
+    &mut self,
+    binder: Binder,
+    slots: &[u8],
+    term: &Term,
+    ctx: &mut Vec<Binder>,
+  ) -> Term {
+    ctx.push(binder);
+    let res = self.child(slots, term, ctx);
+    ctx.pop();
+    res
+  }
+
+  fn erase(&mut self, term: &Term, ctx: &mut Vec<Binder>) -> Term {
+    match term {
+      Term::Var(pos, nam, idx) => {
+        let mut skip = *idx;
+        let mut new_idx = 0;
+        for binder in ctx.iter().rev() {
+          match binder {
+            Binder::Added => new_idx += 1,
+            Binder::Erased if skip == 0 => return erased(*pos),
+            Binder::Kept if skip == 0 => {
+              return Term::Var(*pos, nam.clone(), new_idx);
+            }
+            Binder::Erased => skip -= 1,
+            Binder::Kept => {
+              skip -= 1;
+              new_idx += 1;
+            }
+          }
+        }
+        // A variable bound outside of the term
+        Term::Var(*pos, nam.clone(), new_idx + skip)
+      }
+      Term::Lam(pos, nam, _, bod) => {
+        if self.erases(&[]) {
+          self.under(Binder::Erased, &[1], bod, ctx)
+        }
+        else {
+          let bod = self.under(Binder::Kept, &[1], bod, ctx);
+          Term::Lam(*pos, nam.clone(), None, Box::new(bod))
+        }
+      }
+      Term::App(pos, fun_arg) => {
+        let (fun, arg) = fun_arg.as_ref();
+        if self.erases(&[]) {
+          self.child(&[0], fun, ctx)
+        }
+        else {
+          let fun = self.child(&[0], fun, ctx);
+          let arg = self.child(&[1], arg, ctx);
+          Term::App(*pos, Box::new((fun, arg)))
+        }
+      }
+      Term::All(pos, imp, uses, nam, dom_img) => {
+        let (dom, img) = dom_img.as_ref();
+        if *uses == Uses::None {
+          self.under(Binder::Erased, &[1], img, ctx)
+        }
+        else {
+          let dom = self.child(&[0], dom, ctx);
+          let img = self.under(Binder::Kept, &[1], img, ctx);
+          Term::All(*pos, *imp, *uses, nam.clone(), Box::new((dom, img)))
+        }
+      }
+      Term::Slf(pos, nam, bod) => {
+        let bod = self.under(Binder::Kept, &[0], bod, ctx);
+        Term::Slf(*pos, nam.clone(), Box::new(bod))
+      }
+      // The first binder of a Self type is the motive, which `case` applies
+      // the value to whether or not it's erased, so the value has to keep
+      // taking it
+      Term::Dat(pos, bod) if self.erases(&[]) => match bod.as_ref() {
+        Term::Lam(lam_pos, nam, _, lam_bod) if self.erases(&[0]) => {
+          let lam_bod = self.under(Binder::Kept, &[0, 1], lam_bod, ctx);
+          let lam = Term::Lam(*lam_pos, nam.clone(), None, Box::new(lam_bod));
+          Term::Dat(*pos, Box::new(lam))
+        }
+        _ => {
+          let bod = self.under(Binder::Added, &[0], bod, ctx);
+          let lam = Term::Lam(*pos, Name::from("_"), None, Box::new(bod));
+          Term::Dat(*pos, Box::new(lam))
+        }
+      },
+      Term::Dat(pos, bod) => {
+        Term::Dat(*pos, Box::new(self.child(&[0], bod, ctx)))
+      }
+      Term::Cse(pos, bod) => {
+        let cse = Term::Cse(*pos, Box::new(self.child(&[0], bod, ctx)));
+        if self.erases(&[]) {
+          Term::App(*pos, Box::new((cse, erased(*pos))))
+        }
+        else {
+          cse
+        }
+      }
+      Term::Let(pos, rec, uses, nam, typ_exp_bod) => {
+        let (typ, exp, bod) = typ_exp_bod.as_ref();
+        if *uses == Uses::None {
+          self.under(Binder::Erased, &[2], bod, ctx)
+        }
+        else {
+          let exp = if *rec {
+            self.under(Binder::Kept, &[1], exp, ctx)
+          }
+          else {
+            self.child(&[1], exp, ctx)
+          };
+          let bod = self.under(Binder::Kept, &[2], bod, ctx);
+          let typ = erased(typ.pos());
+          Term::Let(*pos, *rec, *uses, nam.clone(), Box::new((typ, exp, bod)))
+        }
+      }
+      Term::Ann(_, typ_exp) => self.child(&[1], &typ_exp.1, ctx),
+      Term::Eql(pos, EqlOp::Rewrite) => {
+        let var = Term::Var(*pos, Name::from("x"), 0);
+        Term::Lam(*pos, Name::from("x"), None, Box::new(var))
+      }
+      Term::Rcd(pos, nams, bod) => {
+        Term::Rcd(*pos, nams.clone(), Box::new(self.child(&[0], bod, ctx)))
+      }
+      Term::Prj(pos, nam, prj_arg) => {
+        let (prj, arg) = prj_arg.as_ref();
+        let mut erased_prj = self.child(&[0], prj, ctx);
+        // The projection chosen while checking, for a field several records
+        // have, is applied to the parameters of its record, which are erased
+        if let Term::Hol(..) = prj {
+          while let Term::App(_, fun_arg) = erased_prj {
+            erased_prj = fun_arg.0;
+          }
+        }
+        if self.erases(&[]) {
+          erased_prj
+        }
+        else {
+          let arg = self.child(&[1], arg, ctx);
+          Term::Prj(*pos, nam.clone(), Box::new((erased_prj, arg)))
+        }
+      }
+      // A solved anonymous hole, such as an argument inserted for an implicit
+      // parameter, is erased as its solution
+      Term::Hol(..) => {
+        let dep = ctx.iter().filter(|b| **b != Binder::Added).count() as u64;
+        match self.metas.zonk(term, dep) {
+          Term::Hol(..) => term.clone(),
+          sol => {
+            let path = self.path.take();
+            let res = self.erase(&sol, ctx);
+            self.path = path;
+            res
+          }
+        }
+      }
+      _ => term.clone(),
+    }
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::{
+    check::elaborate_defs,
+    dag::DAG,
+    tests::parse_package,
+  };
+
+  fn defs(src: &str) -> Defs {
+    let (defs, _) = parse_package(src).unwrap();
+    defs
+  }
+
+  fn norm(defs: &Defs, name: &str) -> String {
+    let def = defs.get(&Name::from(name)).unwrap();
+    let mut dag = DAG::from_term(&def.term);
+    dag.norm(defs).unwrap();
+    format!("{}", dag)
+  }
+
+  // Normalizes `main` in `src`, elaborated as it is before running it, with
+  // and without erasing it first, and checks both agree on `expected`
+  fn assert_runs(src: &str, expected: &str) {
+    let defs = defs(src);
+    let roots = [Name::from("main")];
+    let elaborated =
+      elaborate_defs(&defs, &roots, CheckOpts::default()).unwrap();
+    let erased = erase_defs(&defs, &roots, CheckOpts::default()).unwrap();
+    assert_eq!(norm(&elaborated, "main"), expected);
+    assert_eq!(norm(&erased, "main"), expected);
+    // The erasure of `main` is found under its content id, but has its own
+    let main = defs.get(&roots[0]).unwrap();
+    let erased_main = &erased.defs[&main.def_cid];
+    assert_eq!(erased_main.ast_cid, erased_main.term.embed().0.cid());
+    assert_eq!(erased_main.def_cid, erased_main.embed().0.cid());
+  }
+
+  #[test]
+  fn erase_copy() {
+    // Quantities are recorded by the paths of their terms in the value, so a
+    // copy of the value is erased like the value itself
+    let defs = defs("def const (0 A: Type) (x: A): A = x");
+    let (_, _, term, metas) =
+      check_def_metas(&defs, "const", CheckOpts::default()).unwrap();
+    assert_eq!(metas.quantities.get(&vec![]), Some(&Uses::None));
+    assert_eq!(metas.quantities.get(&vec![1]), Some(&Uses::Many));
+    let copy = term.clone();
+    drop(term);
+    let mut erasure = Erasure { metas: &metas, path: Some(vec![]) };
+    assert_eq!(format!("{}", erasure.erase(&copy, &mut vec![])), "λ x => x");
+  }
+
+  #[test]
+  fn erase_binders() {
+    let defs = defs(
+      "def id {0 A: Type} (x: A): A = x
+       def const (0 A: Type) (x: A) (0 y: A): A = x
+       def ann (x: #Nat): #Nat = let 0 y: #Nat = x; (x :: #Nat)
+       def size {A: Type} (x: A): #Nat = 1
+       def one: #Nat = size 2",
+    );
+    let erase = |name| {
+      format!("{}", erase_def(&defs, name, CheckOpts::default()).unwrap())
+    };
+    assert_eq!(erase("id"), "λ x => x");
+    assert_eq!(erase("const"), "λ x => x");
+    assert_eq!(erase("ann"), "λ x => x");
+    // An argument inserted for an implicit parameter that isn't erased is
+    // replaced by its solution
+    assert_eq!(erase("one"), "size #Nat 2");
+  }
+
+  #[test]
+  fn erase_runs() {
+    assert_runs(
+      "def id {0 A: Type} (x: A): A = x
+       def pred (n: #Nat): #Nat = (case n) (λ _ => #Nat) 0 (λ m => m)
+       def main: #Nat = pred (id 5)",
+      "4",
+    );
+    assert_runs(
+      "type Bool { True, False }
+       type List (A: Type) { Nil, Cons (x: A) (xs: List A) }
+       def not (b: Bool): Bool = Bool.elim b (λ _ => Bool) Bool.False Bool.True
+       def head {0 A: Type} (x: A) (xs: List A): A =
+         List.elim {A} xs (λ _ => A) x (λ y ys => y)
+       def isZero (n: #Nat): Bool =
+         (case n) (λ _ => Bool) Bool.True (λ m => Bool.False)
+       def main: #Nat =
+         Bool.elim (not (isZero (head 0 (List.Cons 3 List.Nil))))
+           (λ _ => #Nat) 1 2",
+      "1",
+    );
+    // The projection of a field several records have drops the parameters
+    // of the record it is chosen from
+    assert_runs(
+      "record A { x: #Nat }
+       record B (T: Type) { x: T }
+       def b: B #Nat = B.New 7
+       def main: #Nat = b.x",
+      "7",
+    );
+    // An annotated lambda's quantity is known from inferring it
+    assert_runs(
+      "def main: #Nat = (λ (0 A: Type) (x: #Nat) => x) #Nat 1",
+      "1",
+    );
+    assert_runs(
+      "def cast (0 e: 1 == 1) (x: #Nat): #Nat =
+         rewrite (λ _ => #Nat) e x
+       def main: #Nat = cast refl 7",
+      "7",
+    );
+  }
+}
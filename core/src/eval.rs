@@ -1,13 +1,32 @@
 use core::ptr::NonNull;
 
+pub mod error;
+pub mod stats;
+pub mod trace;
+
 use crate::{
   dag::*,
-  defs::Defs,
+  defs::{
+    Def,
+    Defs,
+  },
   dll::*,
+  eql::EqlOp,
+  name::Name,
+  prim::Op,
   upcopy::*,
 };
 
-use std::collections::VecDeque;
+use error::EvalError;
+
+use std::{
+  collections::{
+    HashMap,
+    HashSet,
+    VecDeque,
+  },
+  mem,
+};
 
 enum Single {
   Lam(Var),
@@ -66,8 +85,8 @@ pub fn subst(bod: DAGPtr, var: &Var, arg: DAGPtr, fix: bool) -> DAGPtr {
         break;
       }
       DAGPtr::All(link) => {
-        let All { uses, dom, img, .. } = unsafe { link.as_ref() };
-        let new_all = alloc_all(*uses, *dom, *img, None);
+        let All { imp, uses, dom, img, .. } = unsafe { link.as_ref() };
+        let new_all = alloc_all(*imp, *uses, *dom, *img, None);
         unsafe {
           (*link.as_ptr()).copy = Some(new_all);
         }
@@ -92,11 +111,20 @@ pub fn subst(bod: DAGPtr, var: &Var, arg: DAGPtr, fix: bool) -> DAGPtr {
         break;
       }
       DAGPtr::Let(link) => {
-        let Let { var: old_var, uses, typ, exp, bod, .. } =
+        let Let { var: old_var, uses, rec, typ, exp, bod, .. } =
           unsafe { link.as_ref() };
         let Var { nam, dep, .. } = old_var;
-        let new_let =
-          alloc_let(nam.clone(), *dep, None, *uses, *typ, *exp, *bod, None);
+        let new_let = alloc_let(
+          nam.clone(),
+          *dep,
+          None,
+          *rec,
+          *uses,
+          *typ,
+          *exp,
+          *bod,
+          None,
+        );
         unsafe {
           (*link.as_ptr()).copy = Some(new_let);
           let ptr: *mut Var = &mut (*new_let.as_ptr()).var;
@@ -251,6 +279,58 @@ pub fn reduce_lam(redex: NonNull<App>, lam: NonNull<Lam>) -> DAGPtr {
   top_node
 }
 
+// Build the node `letrec x = exp; x` out of a `letrec x = exp; bod` node,
+// copying the nodes of `exp` that depend on `x`. Variables bound outside of
+// `exp` are shared with the original.
+pub fn unroll_letrec(link: NonNull<Let>) -> DAGPtr {
+  unsafe {
+    let Let { var, uses, typ, exp, .. } = &mut *link.as_ptr();
+    let fix = alloc_let(
+      var.nam.clone(),
+      var.dep,
+      None,
+      true,
+      *uses,
+      *typ,
+      mem::zeroed(),
+      mem::zeroed(),
+      None,
+    );
+    let Let { var: fix_var, typ_ref, exp: fix_exp, exp_ref, bod, bod_ref, .. } =
+      &mut *fix.as_ptr();
+    let fix_var = DAGPtr::Var(NonNull::new(fix_var).unwrap());
+    add_to_parents(*typ, NonNull::new(typ_ref).unwrap());
+    let mut map = HashMap::new();
+    for outer in outer_vars(*exp) {
+      map.insert(outer, outer);
+    }
+    map.insert(DAGPtr::Var(NonNull::new(var).unwrap()), fix_var);
+    *fix_exp = DAG::from_subdag(*exp, &mut map, NonNull::new(exp_ref));
+    *bod = fix_var;
+    add_to_parents(fix_var, NonNull::new(bod_ref).unwrap());
+    DAGPtr::Let(fix)
+  }
+}
+
+// Replaces a reference by the DAG of the definition it refers to
+pub fn expand_ref(link: NonNull<Ref>, def: &Def) -> DAGPtr {
+  let Ref { nam, exp, ast, parents: ref_parents, .. } =
+    unsafe { &mut *link.as_ptr() };
+  let parents = *ref_parents;
+  *ref_parents = None;
+  let node = DAG::from_ref(def, nam.clone(), *exp, *ast, parents);
+  free_dead_node(DAGPtr::Ref(link));
+  for parent in DLL::iter_option(parents) {
+    install_child(parent, node);
+  }
+  node
+}
+
+// Counts an application of a primitive operation
+fn count_prim(opr: Op) {
+  stats::record(|stats| *stats.prims.entry(opr.symbol()).or_default() += 1)
+}
+
 pub fn print_trail(trail: &Vec<NonNull<App>>) -> Vec<String> {
   let mut res: Vec<String> = vec![];
   for link in trail {
@@ -260,107 +340,200 @@ pub fn print_trail(trail: &Vec<NonNull<App>>) -> Vec<String> {
   res
 }
 
+/// A budget of reduction steps for an evaluation: beta reductions, those of
+/// `let` and `case` included, expansions of references, and applications of
+/// primitives
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fuel {
+  /// The steps taken so far
+  pub steps: u64,
+  /// The most steps that may be taken, if there's a limit
+  pub limit: Option<u64>,
+}
+
+impl Fuel {
+  pub fn new(limit: u64) -> Self { Fuel { steps: 0, limit: Some(limit) } }
+
+  pub fn unlimited() -> Self { Fuel { steps: 0, limit: None } }
+
+  /// Raises the limit by `steps`, so that an evaluation that ran out of fuel
+  /// can be resumed
+  pub fn refuel(&mut self, steps: u64) {
+    self.limit = self.limit.map(|limit| limit + steps);
+  }
+
+  // Takes a step reducing `redex`, unless the limit is reached
+  fn burn(&mut self, redex: DAGPtr) -> Result<(), EvalError> {
+    match self.limit {
+      Some(limit) if self.steps >= limit => {
+        trace::record(|events| events.next = Some(redex));
+        Err(EvalError::OutOfFuel(limit))
+      }
+      _ => {
+        self.steps += 1;
+        trace::record(|events| events.redex = Some(redex));
+        Ok(())
+      }
+    }
+  }
+}
+
 impl DAG {
-  // Reduce term to its weak head normal form
-  pub fn whnf(&mut self, defs: &Defs) {
+  /// Reduces the term to its weak head normal form
+  pub fn whnf(&mut self, defs: &Defs) -> Result<(), EvalError> {
+    self.whnf_fueled(defs, true, &mut Fuel::unlimited())
+  }
+
+  /// Reduces the term to its weak head normal form, leaving references to
+  /// opaque definitions folded unless `unfold_opaque` is set. Axioms are
+  /// never unfolded. Where evaluation fails, the term is left where it got
+  /// stuck.
+  pub fn whnf_with(
+    &mut self,
+    defs: &Defs,
+    unfold_opaque: bool,
+  ) -> Result<(), EvalError> {
+    self.whnf_fueled(defs, unfold_opaque, &mut Fuel::unlimited())
+  }
+
+  /// Reduces the term to its weak head normal form, taking no more steps
+  /// than `fuel` allows, and leaving references to opaque definitions folded
+  /// unless `unfold_opaque` is set
+  pub fn whnf_fueled(
+    &mut self,
+    defs: &Defs,
+    unfold_opaque: bool,
+    fuel: &mut Fuel,
+  ) -> Result<(), EvalError> {
     let mut node = self.head;
     let mut trail: Vec<NonNull<App>> = vec![];
-    loop {
-      match node {
-        DAGPtr::App(link) => {
-          let App { fun, .. } = unsafe { link.as_ref() };
-          trail.push(link);
-          node = *fun;
-        }
-        DAGPtr::Lam(link) => {
-          if let Some(app_link) = trail.pop() {
-            node = reduce_lam(app_link, link);
-          }
-          else {
-            break;
+    // The reduction stops, leaving `node` and `trail` where it got, at a
+    // term it can't reduce or at the first error
+    let mut reduce = || -> Result<(), EvalError> {
+      loop {
+        match node {
+          DAGPtr::App(link) => {
+            let App { fun, .. } = unsafe { link.as_ref() };
+            trail.push(link);
+            node = *fun;
           }
-        }
-        DAGPtr::Ann(link) => {
-          let Ann { exp, .. } = unsafe { link.as_ref() };
-          replace_child(node, *exp);
-          free_dead_node(node);
-          node = *exp;
-        }
-        DAGPtr::Cse(link) => {
-          let mut body = unsafe { DAG::new((*link.as_ptr()).bod) };
-          body.whnf(defs);
-          match body.head {
-            DAGPtr::Dat(body_link) => {
-              let Dat { bod: single_body, .. } = unsafe { body_link.as_ref() };
-              replace_child(node, *single_body);
-              free_dead_node(node);
-              node = *single_body;
+          DAGPtr::Lam(link) => {
+            if trail.is_empty() {
+              break;
             }
-            DAGPtr::Lit(link) => {
-              let Lit { lit, parents, .. } = unsafe { link.as_ref() };
-              match &lit.clone().expand() {
-                None => break,
-                Some(expand) => {
-                  let expand = DAG::from_term_inner(
-                    expand,
-                    0,
-                    VecDeque::new(),
-                    *parents,
-                    None,
-                  );
-                  replace_child(node, expand);
-                  free_dead_node(node);
-                  node = expand;
+            fuel.burn(DAGPtr::App(*trail.last().unwrap()))?;
+            stats::record(|stats| stats.betas += 1);
+            node = reduce_lam(trail.pop().unwrap(), link);
+          }
+          DAGPtr::Ann(link) => {
+            let exp = unsafe { link.as_ref().exp };
+            replace_child(node, exp);
+            free_dead_node(node);
+            node = exp;
+          }
+          DAGPtr::Cse(link) => {
+            let mut body = unsafe { DAG::new((*link.as_ptr()).bod) };
+            body.whnf_fueled(defs, unfold_opaque, fuel)?;
+            match body.head {
+              DAGPtr::Dat(body_link) => {
+                fuel.burn(node)?;
+                // The body is read before the `case` and `data` nodes are freed
+                let single_body = unsafe { body_link.as_ref().bod };
+                replace_child(node, single_body);
+                free_dead_node(node);
+                node = single_body;
+              }
+              DAGPtr::Lit(link) => {
+                let Lit { lit, parents, .. } = unsafe { link.as_ref() };
+                match &lit.clone().expand() {
+                  None => break,
+                  Some(expand) => {
+                    fuel.burn(node)?;
+                    stats::record(|stats| stats.lit_expansions += 1);
+                    let expand = DAG::from_term_inner(
+                      expand,
+                      0,
+                      VecDeque::new(),
+                      *parents,
+                      None,
+                    );
+                    replace_child(node, expand);
+                    free_dead_node(node);
+                    node = expand;
+                  }
                 }
               }
+              _ => break,
             }
-            _ => break,
           }
-        }
-        DAGPtr::Let(link) => {
-          let Let { var, exp, bod, .. } = unsafe { &mut *link.as_ptr() };
-          replace_child(node, *bod);
-          replace_child(DAGPtr::Var(NonNull::new(var).unwrap()), *exp);
-          free_dead_node(node);
-          node = *bod;
-        }
-        DAGPtr::Ref(link) => {
-          let Ref { nam, exp, ast, parents: ref_parents, .. } =
-            unsafe { &mut *link.as_ptr() };
-          if let Some(def) = defs.defs.get(exp) {
-            let parents = *ref_parents;
-            *ref_parents = None;
-            let ref_node = node;
-            node = DAG::from_ref(&def, nam.clone(), *exp, *ast, parents);
-            free_dead_node(ref_node);
-            for parent in DLL::iter_option(parents) {
-              install_child(parent, node);
+          DAGPtr::Let(link) => {
+            fuel.burn(node)?;
+            let Let { var, rec, exp, bod, .. } = unsafe { &mut *link.as_ptr() };
+            let var = DAGPtr::Var(NonNull::new(var).unwrap());
+            let (exp, bod) = (*exp, *bod);
+            if *rec {
+              // A `letrec` is unrolled once: every occurrence of the variable,
+              // whether in the expression or in the body, is replaced by a
+              // fresh `letrec x = exp; x` node.
+              let fix = unroll_letrec(link);
+              replace_child(var, fix);
+              if bod == var {
+                // Unrolling `letrec x = exp; x` itself must continue with the
+                // expression, otherwise it would unroll forever
+                replace_child(node, exp);
+                free_dead_node(node);
+                node = exp;
+              }
+              else {
+                replace_child(node, bod);
+                free_dead_node(node);
+                node = bod;
+              }
+            }
+            else {
+              replace_child(node, bod);
+              replace_child(var, exp);
+              free_dead_node(node);
+              node = if bod == var { exp } else { bod };
             }
           }
-          else {
-            panic!("undefined runtime reference: {}, {}", nam, exp);
-          }
-        }
-        DAGPtr::Opr(link) => {
-          let opr = unsafe { (*link.as_ptr()).opr };
-          let len = trail.len();
-          if len == 0 && opr.arity() == 0 {
-            let res = opr.apply0();
-            if let Some(res) = res {
-              node = DAGPtr::Lit(alloc_val(Lit { lit: res, parents: None }));
+          DAGPtr::Ref(link) => {
+            let Ref { nam, exp, .. } = unsafe { link.as_ref() };
+            if let Some(def) = defs.defs.get(exp) {
+              if def.is_axiom() || (def.opaque && !unfold_opaque) {
+                break;
+              }
+              fuel.burn(node)?;
+              stats::record(|stats| {
+                *stats.refs.entry(nam.clone()).or_default() += 1
+              });
+              node = expand_ref(link, def);
             }
             else {
-              break;
+              return Err(EvalError::UndefinedReference(nam.clone(), *exp));
             }
           }
-          else if len >= 1 && opr.arity() == 1 {
-            let mut arg = unsafe { DAG::new((*trail[len - 1].as_ptr()).arg) };
-            arg.whnf(defs);
-            match arg.head {
-              DAGPtr::Lit(link) => {
-                let x = unsafe { &(*link.as_ptr()).lit };
-                let res = opr.apply1(x);
-                if let Some(res) = res {
+          DAGPtr::Opr(link) => {
+            let opr = unsafe { (*link.as_ptr()).opr };
+            let len = trail.len();
+            if len == 0 && opr.arity() == 0 {
+              let res = opr.apply0();
+              let res = res.ok_or(EvalError::PrimitiveFailure(opr, vec![]))?;
+              fuel.burn(node)?;
+              count_prim(opr);
+              node = DAGPtr::Lit(alloc_val(Lit { lit: res, parents: None }));
+            }
+            else if len >= 1 && opr.arity() == 1 {
+              let mut arg = unsafe { DAG::new((*trail[len - 1].as_ptr()).arg) };
+              arg.whnf_fueled(defs, unfold_opaque, fuel)?;
+              match arg.head {
+                DAGPtr::Lit(link) => {
+                  let x = unsafe { &(*link.as_ptr()).lit };
+                  let res = opr.apply1(x).ok_or_else(|| {
+                    EvalError::PrimitiveFailure(opr, vec![x.clone()])
+                  })?;
+                  fuel.burn(DAGPtr::App(trail[len - 1]))?;
+                  count_prim(opr);
                   let top = DAGPtr::App(trail.pop().unwrap());
                   let new_node =
                     DAGPtr::Lit(alloc_val(Lit { lit: res, parents: None }));
@@ -368,24 +541,27 @@ impl DAG {
                   free_dead_node(top);
                   node = new_node;
                 }
-                else {
-                  break;
-                }
+                _ => break,
               }
-              _ => break,
             }
-          }
-          else if len >= 2 && opr.arity() == 2 {
-            let mut arg1 = unsafe { DAG::new((*trail[len - 1].as_ptr()).arg) };
-            let mut arg2 = unsafe { DAG::new((*trail[len - 2].as_ptr()).arg) };
-            arg1.whnf(defs);
-            arg2.whnf(defs);
-            match (arg1.head, arg2.head) {
-              (DAGPtr::Lit(x_link), DAGPtr::Lit(y_link)) => {
-                let x = unsafe { &(*x_link.as_ptr()).lit };
-                let y = unsafe { &(*y_link.as_ptr()).lit };
-                let res = opr.apply2(x, y);
-                if let Some(res) = res {
+            else if len >= 2 && opr.arity() == 2 {
+              // Each argument is read once the ones before it are reduced,
+              // since reducing them can replace arguments they share
+              let mut arg1 =
+                unsafe { DAG::new((*trail[len - 1].as_ptr()).arg) };
+              arg1.whnf_fueled(defs, unfold_opaque, fuel)?;
+              let mut arg2 =
+                unsafe { DAG::new((*trail[len - 2].as_ptr()).arg) };
+              arg2.whnf_fueled(defs, unfold_opaque, fuel)?;
+              match (arg1.head, arg2.head) {
+                (DAGPtr::Lit(x_link), DAGPtr::Lit(y_link)) => {
+                  let x = unsafe { &(*x_link.as_ptr()).lit };
+                  let y = unsafe { &(*y_link.as_ptr()).lit };
+                  let res = opr.apply2(x, y).ok_or_else(|| {
+                    EvalError::PrimitiveFailure(opr, vec![x.clone(), y.clone()])
+                  })?;
+                  fuel.burn(DAGPtr::App(trail[len - 2]))?;
+                  count_prim(opr);
                   trail.pop();
                   let top = DAGPtr::App(trail.pop().unwrap());
                   let new_node =
@@ -394,31 +570,34 @@ impl DAG {
                   free_dead_node(top);
                   node = new_node;
                 }
-                else {
-                  break;
-                }
+                _ => break,
               }
-              _ => break,
             }
-          }
-          else if len >= 3 && opr.arity() == 3 {
-            let mut arg1 = unsafe { DAG::new((*trail[len - 1].as_ptr()).arg) };
-            let mut arg2 = unsafe { DAG::new((*trail[len - 2].as_ptr()).arg) };
-            let mut arg3 = unsafe { DAG::new((*trail[len - 3].as_ptr()).arg) };
-            arg1.whnf(defs);
-            arg2.whnf(defs);
-            arg3.whnf(defs);
-            match (arg1.head, arg2.head, arg3.head) {
-              (
-                DAGPtr::Lit(x_link),
-                DAGPtr::Lit(y_link),
-                DAGPtr::Lit(z_link),
-              ) => {
-                let x = unsafe { &(*x_link.as_ptr()).lit };
-                let y = unsafe { &(*y_link.as_ptr()).lit };
-                let z = unsafe { &(*z_link.as_ptr()).lit };
-                let res = opr.apply3(x, y, z);
-                if let Some(res) = res {
+            else if len >= 3 && opr.arity() == 3 {
+              let mut arg1 =
+                unsafe { DAG::new((*trail[len - 1].as_ptr()).arg) };
+              arg1.whnf_fueled(defs, unfold_opaque, fuel)?;
+              let mut arg2 =
+                unsafe { DAG::new((*trail[len - 2].as_ptr()).arg) };
+              arg2.whnf_fueled(defs, unfold_opaque, fuel)?;
+              let mut arg3 =
+                unsafe { DAG::new((*trail[len - 3].as_ptr()).arg) };
+              arg3.whnf_fueled(defs, unfold_opaque, fuel)?;
+              match (arg1.head, arg2.head, arg3.head) {
+                (
+                  DAGPtr::Lit(x_link),
+                  DAGPtr::Lit(y_link),
+                  DAGPtr::Lit(z_link),
+                ) => {
+                  let x = unsafe { &(*x_link.as_ptr()).lit };
+                  let y = unsafe { &(*y_link.as_ptr()).lit };
+                  let z = unsafe { &(*z_link.as_ptr()).lit };
+                  let res = opr.apply3(x, y, z).ok_or_else(|| {
+                    let args = vec![x.clone(), y.clone(), z.clone()];
+                    EvalError::PrimitiveFailure(opr, args)
+                  })?;
+                  fuel.burn(DAGPtr::App(trail[len - 3]))?;
+                  count_prim(opr);
                   trail.pop();
                   trail.pop();
                   let top = DAGPtr::App(trail.pop().unwrap());
@@ -428,40 +607,83 @@ impl DAG {
                   free_dead_node(top);
                   node = new_node;
                 }
-                else {
-                  break;
-                }
+                _ => break,
               }
-              _ => break,
+            }
+            else {
+              break;
             }
           }
-          else {
-            break;
+          DAGPtr::Eql(link) => {
+            let op = unsafe { (*link.as_ptr()).op };
+            let len = trail.len();
+            // `rewrite {A} {a} {b} P e x` reduces to `x` once `e` is `refl`
+            if op == EqlOp::Rewrite && len >= op.arity() {
+              let mut eq = unsafe { DAG::new((*trail[len - 5].as_ptr()).arg) };
+              eq.whnf_fueled(defs, unfold_opaque, fuel)?;
+              let mut head = eq.head;
+              while let DAGPtr::App(app) = head {
+                head = unsafe { (*app.as_ptr()).fun };
+              }
+              match head {
+                DAGPtr::Eql(refl) if unsafe { (*refl.as_ptr()).op }
+                  == EqlOp::Refl =>
+                {
+                  fuel.burn(DAGPtr::App(trail[len - op.arity()]))?;
+                  for _ in 1..op.arity() {
+                    trail.pop();
+                  }
+                  let top = trail.pop().unwrap();
+                  let x = unsafe { (*top.as_ptr()).arg };
+                  let top = DAGPtr::App(top);
+                  replace_child(top, x);
+                  free_dead_node(top);
+                  node = x;
+                }
+                _ => break,
+              }
+            }
+            else {
+              break;
+            }
           }
+          _ => break,
         }
-        _ => break,
       }
-    }
+      Ok(())
+    };
+    let res = reduce();
     if trail.is_empty() {
       self.head = node;
     }
     else {
       self.head = DAGPtr::App(trail[0]);
     }
+    res
+  }
+
+  /// Reduces the term to its normal form
+  pub fn norm(&mut self, defs: &Defs) -> Result<(), EvalError> {
+    self.norm_fueled(defs, &mut Fuel::unlimited())
   }
 
-  // Reduce term to its normal form
-  pub fn norm(&mut self, defs: &Defs) {
-    self.whnf(defs);
+  /// Reduces the term to its normal form, taking no more steps than `fuel`
+  /// allows
+  pub fn norm_fueled(
+    &mut self,
+    defs: &Defs,
+    fuel: &mut Fuel,
+  ) -> Result<(), EvalError> {
+    self.whnf_fueled(defs, true, fuel)?;
     let mut trail = vec![self.head];
     while let Some(node) = trail.pop() {
       match node {
         DAGPtr::App(link) => unsafe {
           let app = link.as_ptr();
           let mut fun = DAG::new((*app).fun);
+          fun.whnf_fueled(defs, true, fuel)?;
           let mut arg = DAG::new((*app).arg);
-          fun.whnf(defs);
-          arg.whnf(defs);
+          arg.whnf_fueled(defs, true, fuel)?;
           trail.push(fun.head);
           trail.push(arg.head);
         },
@@ -469,38 +691,94 @@ impl DAG {
           let all = link.as_ptr();
           let mut dom = DAG::new((*all).dom);
           let mut img = DAG::new(DAGPtr::Lam((*all).img));
-          dom.whnf(defs);
-          img.whnf(defs);
+          dom.whnf_fueled(defs, true, fuel)?;
+          img.whnf_fueled(defs, true, fuel)?;
           trail.push(dom.head);
           trail.push(img.head);
         },
         DAGPtr::Lam(link) => unsafe {
           let lam = link.as_ptr();
           let mut body = DAG::new((*lam).bod);
-          body.whnf(defs);
+          body.whnf_fueled(defs, true, fuel)?;
           trail.push(body.head);
         },
         DAGPtr::Slf(link) => unsafe {
           let slf = link.as_ptr();
           let mut body = DAG::new((*slf).bod);
-          body.whnf(defs);
+          body.whnf_fueled(defs, true, fuel)?;
           trail.push(body.head);
         },
         DAGPtr::Cse(link) => unsafe {
           let cse = link.as_ptr();
           let mut body = DAG::new((*cse).bod);
-          body.whnf(defs);
+          body.whnf_fueled(defs, true, fuel)?;
           trail.push(body.head);
         },
         DAGPtr::Dat(link) => unsafe {
           let dat = link.as_ptr();
           let mut body = DAG::new((*dat).bod);
-          body.whnf(defs);
+          body.whnf_fueled(defs, true, fuel)?;
           trail.push(body.head);
         },
         _ => (),
       }
     }
+    Ok(())
+  }
+
+  /// Expands every reference to the definition `name` in the term, without
+  /// reducing anything, and returns how many there were
+  pub fn expand_refs(
+    &mut self,
+    defs: &Defs,
+    name: &Name,
+  ) -> Result<usize, EvalError> {
+    let mut refs = vec![];
+    let mut seen = HashSet::new();
+    let mut stack = vec![self.head];
+    while let Some(node) = stack.pop() {
+      if !seen.insert(node) {
+        continue;
+      }
+      unsafe {
+        match node {
+          DAGPtr::Ref(link) if (*link.as_ptr()).nam == *name => refs.push(link),
+          DAGPtr::Lam(link) => stack.push((*link.as_ptr()).bod),
+          DAGPtr::Slf(link) => stack.push((*link.as_ptr()).bod),
+          DAGPtr::Dat(link) => stack.push((*link.as_ptr()).bod),
+          DAGPtr::Cse(link) => stack.push((*link.as_ptr()).bod),
+          DAGPtr::App(link) => {
+            let App { fun, arg, .. } = *link.as_ptr();
+            stack.extend(vec![fun, arg]);
+          }
+          DAGPtr::Ann(link) => {
+            let Ann { typ, exp, .. } = *link.as_ptr();
+            stack.extend(vec![typ, exp]);
+          }
+          DAGPtr::Let(link) => {
+            let Let { typ, exp, bod, .. } = *link.as_ptr();
+            stack.extend(vec![typ, exp, bod]);
+          }
+          DAGPtr::All(link) => {
+            let All { dom, img, .. } = *link.as_ptr();
+            stack.extend(vec![dom, DAGPtr::Lam(img)]);
+          }
+          _ => (),
+        }
+      }
+    }
+    for link in &refs {
+      let Ref { nam, exp, .. } = unsafe { link.as_ref() };
+      let def = defs
+        .defs
+        .get(exp)
+        .ok_or_else(|| EvalError::UndefinedReference(nam.clone(), *exp))?;
+      let node = expand_ref(*link, def);
+      if self.head == DAGPtr::Ref(*link) {
+        self.head = node;
+      }
+    }
+    Ok(refs.len())
   }
 }
 
@@ -550,7 +828,7 @@ pub mod test {
   fn norm_assert(input: &str, result: &str) {
     match parse(&input) {
       Ok((_, mut dag)) => {
-        dag.norm(&Defs::new());
+        dag.norm(&Defs::new()).unwrap();
         assert_eq!(format!("{}", dag), result)
       }
       Err(_) => panic!("Did not parse."),
@@ -559,7 +837,7 @@ pub mod test {
   fn norm_assert_defs(input: &str, result: &str, defs: Defs) {
     match parse(&input) {
       Ok((_, mut dag)) => {
-        dag.norm(&defs);
+        dag.norm(&defs).unwrap();
         assert_eq!(format!("{}", dag), result)
       }
       Err(_) => panic!("Did not parse."),
@@ -617,6 +895,21 @@ pub mod test {
     );
   }
 
+  #[test]
+  pub fn reduce_test_letrec() {
+    norm_assert(
+      "letrec f: ∀ #Nat -> #Nat = λ n => (case n) (λ _ => #Nat) 0 (λ p => \
+       #Nat.add 2 (f p)); f 3",
+      "6",
+    );
+    norm_assert(
+      "λ x => letrec f: ∀ #Nat -> Type = λ n => (case n) (λ _ => Type) x \
+       (λ p => f p); f 2",
+      "λ x => x",
+    );
+    norm_assert("letrec f: Type = Type; f", "Type");
+  }
+
   #[test]
   pub fn reduce_test() {
     // Already normalized
@@ -652,4 +945,90 @@ pub mod test {
     // assert_eq!(true, false);
     norm_assert(trm_str, id);
   }
+
+  #[test]
+  pub fn reduce_test_fuel() {
+    use super::{
+      error::EvalError,
+      Fuel,
+    };
+    let defs = Defs::new();
+    let omega = "(λ x => x x) (λ x => x x)";
+    let (_, mut dag) = parse(omega).unwrap();
+    let mut fuel = Fuel::new(100);
+    let res = dag.norm_fueled(&defs, &mut fuel);
+    assert_eq!(res, Err(EvalError::OutOfFuel(100)));
+    fuel.refuel(10);
+    assert_eq!(
+      dag.whnf_fueled(&defs, true, &mut fuel),
+      Err(EvalError::OutOfFuel(110))
+    );
+    assert_eq!(fuel.steps, 110);
+    // Resuming an evaluation one step at a time takes the same steps to the
+    // same result as evaluating it at once
+    let input = "(λ f => f (f (f 1))) (λ x => #Nat.mul 2 (#Nat.add x 1))";
+    let (_, mut dag) = parse(input).unwrap();
+    let mut fuel = Fuel::unlimited();
+    assert_eq!(dag.norm_fueled(&defs, &mut fuel), Ok(()));
+    assert_eq!(format!("{}", dag), "22");
+    let (_, mut resumed) = parse(input).unwrap();
+    let mut steps = Fuel::new(0);
+    while let Err(EvalError::OutOfFuel(_)) =
+      resumed.norm_fueled(&defs, &mut steps)
+    {
+      steps.refuel(1);
+    }
+    assert_eq!(format!("{}", resumed), "22");
+    assert_eq!(steps.steps, fuel.steps);
+  }
+
+  #[test]
+  pub fn reduce_test_errors() {
+    use super::error::EvalError;
+    use crate::{
+      literal::Literal,
+      name::Name,
+      prim::{
+        nat::NatOp,
+        Op,
+      },
+    };
+    // Arguments shared by a primitive are reduced once
+    norm_assert("(λ x => #Nat.add x x) (#Nat.add 2 2)", "8");
+    let (_, mut dag) = parse("#Nat.div 1 0").unwrap();
+    let div = Op::Nat(NatOp::Div);
+    let args = vec![Literal::Nat(1u64.into()), Literal::Nat(0u64.into())];
+    assert_eq!(
+      dag.norm(&Defs::new()),
+      Err(EvalError::PrimitiveFailure(div, args))
+    );
+    let (_, mut defs) = parse_defs(
+      "def one: #Nat = 1
+       def two: #Nat = #Nat.add one one",
+    )
+    .unwrap();
+    let one = defs.names.remove(&Name::from("one")).unwrap();
+    defs.defs.remove(&one);
+    let two = defs.get(&Name::from("two")).unwrap();
+    let mut dag = DAG::from_term(&two.term);
+    assert_eq!(
+      dag.norm(&defs),
+      Err(EvalError::UndefinedReference(Name::from("one"), one))
+    );
+  }
+
+  #[test]
+  pub fn reduce_test_expand_refs() {
+    use crate::name::Name;
+    let (_, defs) = parse_defs(
+      "def id (x: #Nat): #Nat = x
+       def main: #Nat = id (id 1)",
+    )
+    .unwrap();
+    let main = defs.get(&Name::from("main")).unwrap();
+    let mut dag = DAG::from_term(&main.term);
+    assert_eq!(dag.expand_refs(&defs, &Name::from("main")), Ok(0));
+    assert_eq!(dag.expand_refs(&defs, &Name::from("id")), Ok(2));
+    assert_eq!(format!("{}", dag), "(λ x => x) ((λ x => x) 1)");
+  }
 }
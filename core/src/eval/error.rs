@@ -0,0 +1,41 @@
+use std::fmt;
+
+use cid::Cid;
+
+use crate::{
+  literal::Literal,
+  name::Name,
+  prim::Op,
+};
+
+/// Why an evaluation stopped before reaching the form it was asked for. The
+/// DAG is left reduced as far as the evaluation got.
+#[derive(PartialEq, Clone, Debug)]
+pub enum EvalError {
+  /// A reference to a definition missing from the definitions evaluated with
+  UndefinedReference(Name, Cid),
+  /// A primitive operation applied to literals it's undefined on, such as a
+  /// division by zero
+  PrimitiveFailure(Op, Vec<Literal>),
+  /// The step budget ran out after this many steps. Evaluating the DAG again
+  /// with more fuel resumes the evaluation where it stopped.
+  OutOfFuel(u64),
+}
+
+impl fmt::Display for EvalError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::UndefinedReference(nam, cid) => {
+        write!(f, "Undefined reference {} to {}", nam, cid)
+      }
+      Self::PrimitiveFailure(opr, args) => {
+        write!(f, "Primitive {} failed on", opr)?;
+        for arg in args {
+          write!(f, " {}", arg)?;
+        }
+        Ok(())
+      }
+      Self::OutOfFuel(steps) => write!(f, "Out of fuel after {} steps", steps),
+    }
+  }
+}
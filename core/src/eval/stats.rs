@@ -0,0 +1,139 @@
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  fmt,
+};
+
+use crate::name::Name;
+
+/// Counts of the work done by evaluations, collected with `collect`
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Stats {
+  /// Beta reductions of lambdas
+  pub betas: u64,
+  /// Nodes copied up the DAG by substitutions
+  pub upcopies: u64,
+  /// Expansions of references, by the name of the definition referred to
+  pub refs: HashMap<Name, u64>,
+  /// Expansions of literals into their inductive form, by `case`
+  pub lit_expansions: u64,
+  /// Applications of primitive operations, by their symbol
+  pub prims: HashMap<String, u64>,
+  /// Heap allocations of nodes, and of the roots of DAGs
+  pub allocs: u64,
+  /// Nodes freed
+  pub frees: u64,
+}
+
+impl Stats {
+  fn merge(&mut self, other: Stats) {
+    self.betas += other.betas;
+    self.upcopies += other.upcopies;
+    for (nam, count) in other.refs {
+      *self.refs.entry(nam).or_default() += count;
+    }
+    self.lit_expansions += other.lit_expansions;
+    for (opr, count) in other.prims {
+      *self.prims.entry(opr).or_default() += count;
+    }
+    self.allocs += other.allocs;
+    self.frees += other.frees;
+  }
+}
+
+thread_local! {
+  // The counts of the innermost `collect` running on this thread, if any.
+  // Nothing is counted outside of one.
+  static STATS: RefCell<Option<Stats>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, counting the work done by the evaluations in it on this thread.
+/// The counts are also added to those of any `collect` it runs within.
+pub fn collect<T>(f: impl FnOnce() -> T) -> (T, Stats) {
+  let outer = STATS.with(|stats| stats.replace(Some(Stats::default())));
+  let res = f();
+  let stats = STATS.with(|stats| stats.replace(outer)).unwrap_or_default();
+  record(|outer| outer.merge(stats.clone()));
+  (res, stats)
+}
+
+// Updates the counts of the `collect` running on this thread, if any
+pub(crate) fn record(f: impl FnOnce(&mut Stats)) {
+  STATS.with(|stats| {
+    if let Some(stats) = stats.borrow_mut().as_mut() {
+      f(stats)
+    }
+  })
+}
+
+// The entries of a map of counts, the largest first
+fn sorted<K: ToString>(counts: &HashMap<K, u64>) -> Vec<(String, u64)> {
+  let mut entries: Vec<(String, u64)> =
+    counts.iter().map(|(key, count)| (key.to_string(), *count)).collect();
+  entries.sort_by(|(ka, ca), (kb, cb)| cb.cmp(ca).then(ka.cmp(kb)));
+  entries
+}
+
+impl fmt::Display for Stats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "beta reductions:      {}", self.betas)?;
+    writeln!(f, "upcopies:             {}", self.upcopies)?;
+    writeln!(f, "literal expansions:   {}", self.lit_expansions)?;
+    writeln!(f, "allocations:          {}", self.allocs)?;
+    writeln!(f, "frees:                {}", self.frees)?;
+    let prims = sorted(&self.prims);
+    let total: u64 = prims.iter().map(|(_, count)| count).sum();
+    writeln!(f, "primitive operations: {}", total)?;
+    for (opr, count) in prims {
+      writeln!(f, "  {:>10}  {}", count, opr)?;
+    }
+    let refs = sorted(&self.refs);
+    let total: u64 = refs.iter().map(|(_, count)| count).sum();
+    write!(f, "reference expansions: {}", total)?;
+    for (nam, count) in refs {
+      write!(f, "\n  {:>10}  {}", count, nam)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::{
+    dag::DAG,
+    eval::test::parse_defs,
+  };
+
+  #[test]
+  pub fn stats_count() {
+    let (_, defs) = parse_defs(
+      "def double (x: #Nat): #Nat = #Nat.add x x
+       def pred (n: #Nat): #Nat = (case n) (λ _ => #Nat) 0 (λ m => m)
+       def main: #Nat = pred (double (double 3))",
+    )
+    .unwrap();
+    let main = defs.get(&Name::from("main")).unwrap();
+    let mut dag = DAG::from_term(&main.term);
+    let (res, stats) = collect(|| dag.norm(&defs));
+    assert_eq!(res, Ok(()));
+    assert_eq!(format!("{}", dag), "11");
+    assert_eq!(stats.refs[&Name::from("double")], 2);
+    assert_eq!(stats.refs[&Name::from("pred")], 1);
+    assert_eq!(stats.prims["#Nat.add"], 2);
+    assert_eq!(stats.lit_expansions, 1);
+    // `double` twice and `pred`, then the literal's expansion taking the
+    // motive and both cases, and the successor case taking the predecessor
+    assert_eq!(stats.betas, 7);
+    assert!(stats.allocs > 0 && stats.frees > 0);
+    let report = format!("{}", stats);
+    let double = report.find("double").unwrap();
+    let pred = report.find("pred").unwrap();
+    assert!(double < pred);
+    // Counts of nested collections are added to the outer ones
+    let (inner, outer) = collect(|| collect(|| record(|s| s.betas += 1)));
+    assert_eq!(inner.1, outer);
+    assert_eq!(outer.betas, 1);
+    assert!(STATS.with(|stats| stats.borrow().is_none()));
+  }
+}
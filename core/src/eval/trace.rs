@@ -0,0 +1,362 @@
+use std::{
+  cell::RefCell,
+  collections::{
+    HashMap,
+    HashSet,
+  },
+  fmt::Write,
+};
+
+use petgraph::{
+  graph::{
+    DefaultIx,
+    NodeIndex,
+  },
+  visit::EdgeRef,
+};
+
+use crate::{
+  dag::{
+    BinderPtr,
+    DAGPtr,
+    DAG,
+  },
+  eval::{
+    error::EvalError,
+    Fuel,
+  },
+  graph::{
+    self,
+    Graph,
+  },
+};
+
+/// The graph of a DAG between two reduction steps of a trace, with the nodes
+/// the steps around it touch
+pub struct Frame {
+  pub graph: Graph,
+  /// The node the next step reduces, if there's a next step
+  pub redex: Option<NodeIndex<DefaultIx>>,
+  /// The nodes the next step frees
+  pub freed: Vec<NodeIndex<DefaultIx>>,
+  /// The nodes the previous step allocated: the copies its substitution made
+  /// up the DAG, or the nodes of the reference or literal it expanded
+  pub allocated: Vec<NodeIndex<DefaultIx>>,
+}
+
+impl Frame {
+  /// Renders the frame's graph in DOT, with the redex in red, the nodes
+  /// about to be freed in grey and the newly allocated ones in green
+  pub fn to_dot(&self) -> String {
+    graph::to_dot_colored(&self.graph, |ix| {
+      if self.redex == Some(ix) {
+        Some("red")
+      }
+      else if self.freed.contains(&ix) {
+        Some("grey")
+      }
+      else if self.allocated.contains(&ix) {
+        Some("green")
+      }
+      else {
+        None
+      }
+    })
+  }
+}
+
+// What the evaluation did during a step of a trace
+#[derive(Default)]
+pub(crate) struct Events {
+  pub redex: Option<DAGPtr>,
+  // The node the step the evaluation ran out of fuel at would have reduced
+  pub next: Option<DAGPtr>,
+  // The addresses of the allocations still live, and of the ones freed
+  allocated: HashSet<usize>,
+  freed: HashSet<usize>,
+}
+
+impl Events {
+  pub fn allocated(&mut self, addr: usize) { self.allocated.insert(addr); }
+
+  pub fn freed(&mut self, addr: usize) {
+    self.allocated.remove(&addr);
+    self.freed.insert(addr);
+  }
+}
+
+thread_local! {
+  // The events of the step a `trace` on this thread is taking, if any
+  static EVENTS: RefCell<Option<Events>> = const { RefCell::new(None) };
+}
+
+// Updates the events of the step being traced on this thread, if any
+pub(crate) fn record(f: impl FnOnce(&mut Events)) {
+  EVENTS.with(|events| {
+    if let Some(events) = events.borrow_mut().as_mut() {
+      f(events)
+    }
+  })
+}
+
+// Runs `f`, recording the events of the evaluations in it
+fn with_events<T>(f: impl FnOnce() -> T) -> (T, Events) {
+  let outer = EVENTS.with(|events| events.replace(Some(Events::default())));
+  let res = f();
+  let events = EVENTS.with(|events| events.replace(outer)).unwrap();
+  (res, events)
+}
+
+// The address of the allocation a node lives in. Bound variables live in
+// their binders.
+fn alloc_addr(node: DAGPtr) -> usize {
+  match node {
+    DAGPtr::Var(link) => match unsafe { link.as_ref().binder } {
+      BinderPtr::Free => link.as_ptr() as usize,
+      BinderPtr::Lam(link) => link.as_ptr() as usize,
+      BinderPtr::Slf(link) => link.as_ptr() as usize,
+      BinderPtr::Let(link) => link.as_ptr() as usize,
+    },
+    DAGPtr::Lam(link) => link.as_ptr() as usize,
+    DAGPtr::App(link) => link.as_ptr() as usize,
+    DAGPtr::All(link) => link.as_ptr() as usize,
+    DAGPtr::Slf(link) => link.as_ptr() as usize,
+    DAGPtr::Dat(link) => link.as_ptr() as usize,
+    DAGPtr::Cse(link) => link.as_ptr() as usize,
+    DAGPtr::Ref(link) => link.as_ptr() as usize,
+    DAGPtr::Let(link) => link.as_ptr() as usize,
+    DAGPtr::Typ(link) => link.as_ptr() as usize,
+    DAGPtr::Ann(link) => link.as_ptr() as usize,
+    DAGPtr::Lit(link) => link.as_ptr() as usize,
+    DAGPtr::LTy(link) => link.as_ptr() as usize,
+    DAGPtr::Opr(link) => link.as_ptr() as usize,
+    DAGPtr::Eql(link) => link.as_ptr() as usize,
+    DAGPtr::Hol(link) => link.as_ptr() as usize,
+  }
+}
+
+// The nodes of a graph, by their pointers and by the addresses of the
+// allocations they live in. The addresses are found when the graph is made,
+// since the nodes may be freed by the time they're looked up.
+struct NodeMap {
+  ptrs: HashMap<DAGPtr, NodeIndex<DefaultIx>>,
+  addrs: Vec<(usize, NodeIndex<DefaultIx>)>,
+}
+
+fn snapshot(dag: &DAG) -> (Graph, NodeMap) {
+  let mut graph = Graph::new();
+  let mut ptrs = HashMap::new();
+  graph::from_dag_ptr(&dag.head, &mut ptrs, &mut graph);
+  let addrs = ptrs.iter().map(|(node, ix)| (alloc_addr(*node), *ix)).collect();
+  (graph, NodeMap { ptrs, addrs })
+}
+
+// The nodes of a graph that live in the allocations at `addrs`
+fn nodes_at(map: &NodeMap, addrs: &HashSet<usize>) -> Vec<NodeIndex> {
+  let mut nodes: Vec<NodeIndex> = map
+    .addrs
+    .iter()
+    .filter(|(addr, _)| addrs.contains(addr))
+    .map(|(_, ix)| *ix)
+    .collect();
+  nodes.sort();
+  nodes
+}
+
+/// Runs `eval` on `dag` one reduction step at a time, as far as `fuel`
+/// allows, and returns a frame of the graph before the first step and after
+/// each of them, with the result of the evaluation. `eval` has to reduce with
+/// the fuel it's given, like `DAG::norm_fueled`.
+pub fn trace(
+  dag: &mut DAG,
+  fuel: &mut Fuel,
+  mut eval: impl FnMut(&mut DAG, &mut Fuel) -> Result<(), EvalError>,
+) -> (Vec<Frame>, Result<(), EvalError>) {
+  let (graph, mut map) = snapshot(dag);
+  let mut frames =
+    vec![Frame { graph, redex: None, freed: vec![], allocated: vec![] }];
+  loop {
+    let at_limit = matches!(fuel.limit, Some(limit) if fuel.steps >= limit);
+    // One step more than taken so far, unless that's past the limit, where
+    // `eval` stops with `fuel`'s own error
+    let mut step = if at_limit {
+      *fuel
+    }
+    else {
+      Fuel { steps: fuel.steps, limit: Some(fuel.steps + 1) }
+    };
+    let (res, events) = with_events(|| eval(dag, &mut step));
+    if step.steps > fuel.steps {
+      fuel.steps = step.steps;
+      let before = frames.last_mut().unwrap();
+      before.redex =
+        events.redex.and_then(|redex| map.ptrs.get(&redex).copied());
+      before.freed = nodes_at(&map, &events.freed);
+      let (graph, after) = snapshot(dag);
+      let allocated = nodes_at(&after, &events.allocated);
+      map = after;
+      frames.push(Frame { graph, redex: None, freed: vec![], allocated });
+    }
+    match res {
+      Err(EvalError::OutOfFuel(_)) if !at_limit => continue,
+      res => return (frames, res),
+    }
+  }
+}
+
+/// The node the next reduction step of `eval` on `dag` reduces, found by
+/// running it without any fuel. `None` once `eval` is done, and its error if
+/// it's stuck at one.
+pub fn next_redex(
+  dag: &mut DAG,
+  eval: impl FnOnce(&mut DAG, &mut Fuel) -> Result<(), EvalError>,
+) -> Result<Option<DAGPtr>, EvalError> {
+  let (res, events) = with_events(|| eval(dag, &mut Fuel::new(0)));
+  match res {
+    Err(EvalError::OutOfFuel(_)) => Ok(events.next),
+    res => res.map(|()| None),
+  }
+}
+
+// A string literal in JSON
+fn json_string(s: &str) -> String {
+  let mut res = String::from("\"");
+  for c in s.chars() {
+    match c {
+      '"' => res.push_str("\\\""),
+      '\\' => res.push_str("\\\\"),
+      '\n' => res.push_str("\\n"),
+      c if (c as u32) < 0x20 => {
+        write!(res, "\\u{:04x}", c as u32).unwrap();
+      }
+      c => res.push(c),
+    }
+  }
+  res.push('"');
+  res
+}
+
+fn json_indices(nodes: &[NodeIndex]) -> String {
+  let nodes: Vec<String> =
+    nodes.iter().map(|ix| ix.index().to_string()).collect();
+  format!("[{}]", nodes.join(","))
+}
+
+/// Renders the frames of a trace as a JSON array with an object for each,
+/// listing the nodes and edges of its graph, and the indices of the nodes
+/// highlighted by `Frame::to_dot`
+pub fn to_json(frames: &[Frame]) -> String {
+  let mut res = String::from("[");
+  for (step, frame) in frames.iter().enumerate() {
+    if step > 0 {
+      res.push(',');
+    }
+    let nodes: Vec<String> = frame
+      .graph
+      .node_indices()
+      .map(|ix| {
+        let label = json_string(&frame.graph[ix].to_string());
+        format!("{{\"id\":{},\"label\":{}}}", ix.index(), label)
+      })
+      .collect();
+    let edges: Vec<String> = frame
+      .graph
+      .edge_references()
+      .map(|edge| {
+        format!(
+          "{{\"source\":{},\"target\":{},\"kind\":\"{:?}\"}}",
+          edge.source().index(),
+          edge.target().index(),
+          edge.weight()
+        )
+      })
+      .collect();
+    let redex =
+      frame.redex.map_or("null".to_string(), |ix| ix.index().to_string());
+    write!(
+      res,
+      "\n{{\"step\":{},\"nodes\":[{}],\"edges\":[{}],\"redex\":{},\
+       \"freed\":{},\"allocated\":{}}}",
+      step,
+      nodes.join(","),
+      edges.join(","),
+      redex,
+      json_indices(&frame.freed),
+      json_indices(&frame.allocated),
+    )
+    .unwrap();
+  }
+  res.push_str("\n]");
+  res
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::{
+    defs::Defs,
+    eval::test::parse,
+    graph::Node,
+  };
+
+  #[test]
+  pub fn trace_steps() {
+    let defs = Defs::new();
+    let (_, mut dag) = parse("(λ x => x x) (λ y z => y)").unwrap();
+    let mut fuel = Fuel::unlimited();
+    let (frames, res) =
+      trace(&mut dag, &mut fuel, |dag, fuel| dag.norm_fueled(&defs, fuel));
+    assert_eq!(res, Ok(()));
+    assert_eq!(format!("{}", dag), "λ z y z => y");
+    assert_eq!(fuel.steps, 2);
+    assert_eq!(frames.len(), 3);
+    let first = &frames[0];
+    let redex = first.redex.unwrap();
+    assert!(matches!(first.graph[redex], Node::App));
+    assert!(first.freed.contains(&redex));
+    // The lambda is the only parent of `x x`, which is reduced in place
+    assert!(frames[1].allocated.is_empty());
+    assert!(frames[1].redex.is_some());
+    // Once both sides of the application share the lambda, its body is
+    // copied to substitute the argument in
+    let last = &frames[2];
+    let copied = |ix: &NodeIndex| matches!(last.graph[*ix], Node::Lam { .. });
+    assert!(last.allocated.iter().any(copied));
+    assert!(last.redex.is_none() && last.freed.is_empty());
+    assert!(first.to_dot().contains("fillcolor = red"));
+    let json = to_json(&frames);
+    assert!(json.starts_with("[\n{\"step\":0,"));
+    assert!(json.contains("\"label\":\"λ z\""));
+    // A trace stops where the fuel runs out
+    let (_, mut dag) = parse("(λ x => x x) (λ y => y)").unwrap();
+    let mut fuel = Fuel::new(1);
+    let (frames, res) =
+      trace(&mut dag, &mut fuel, |dag, fuel| dag.norm_fueled(&defs, fuel));
+    assert_eq!(res, Err(EvalError::OutOfFuel(1)));
+    assert_eq!(frames.len(), 2);
+    assert!(EVENTS.with(|events| events.borrow().is_none()));
+  }
+
+  #[test]
+  pub fn trace_next_redex() {
+    let defs = Defs::new();
+    let norm = |dag: &mut DAG, fuel: &mut Fuel| dag.norm_fueled(&defs, fuel);
+    let (_, mut dag) = parse("λ z => (λ x => x) ((λ y => y) z)").unwrap();
+    let redex = next_redex(&mut dag, norm).unwrap().unwrap();
+    // Finding the redex doesn't reduce it
+    assert_eq!(
+      format!("{}", dag.to_term_marked(false, redex)),
+      "λ z => ⟦(λ x => x) ((λ y => y) z)⟧"
+    );
+    dag.norm_fueled(&defs, &mut Fuel::new(1)).unwrap_err();
+    let redex = next_redex(&mut dag, norm).unwrap().unwrap();
+    assert_eq!(
+      format!("{}", dag.to_term_marked(false, redex)),
+      "λ z => ⟦(λ y => y) z⟧"
+    );
+    dag.norm(&defs).unwrap();
+    assert!(matches!(next_redex(&mut dag, norm), Ok(None)));
+    let (_, mut dag) = parse("#Nat.div 1 0").unwrap();
+    assert!(next_redex(&mut dag, norm).is_err());
+  }
+}
@@ -11,6 +11,7 @@ use petgraph::{
 use crate::{
   dag::*,
   dll::DLL,
+  eql::EqlOp,
   literal::{
     LitType,
     Literal,
@@ -34,11 +35,13 @@ pub enum Node {
   Cse,
   Ref { name: Name, rec: bool, exp: Cid, ast: Cid },
   Let { uses: Uses, name: Name },
-  Typ,
+  Typ { lvl: u64 },
   Ann,
   Lit { lit: Literal },
   LTy { lty: LitType },
   Opr { opr: Op },
+  Eql { op: EqlOp },
+  Hol { name: Name },
 }
 
 impl fmt::Display for Node {
@@ -60,12 +63,15 @@ impl fmt::Display for Node {
       Self::Let { uses: Uses::Once, name } => write!(f, "let1 {}", name),
       Self::Let { uses: Uses::Affi, name } => write!(f, "let& {}", name),
       Self::Let { uses: Uses::Many, name } => write!(f, "letω {}", name),
-      Self::Typ => write!(f, "Type"),
+      Self::Typ { lvl: 0 } => write!(f, "Type"),
+      Self::Typ { lvl } => write!(f, "Type{}", lvl),
       Self::Ann => write!(f, "::"),
       Self::App => write!(f, "( )"),
       Self::Lit { lit } => write!(f, "{}", lit),
       Self::LTy { lty } => write!(f, "{}", lty),
       Self::Opr { opr } => write!(f, "{}", opr),
+      Self::Eql { op } => write!(f, "{}", op),
+      Self::Hol { name } => write!(f, "?{}", name),
     }
   }
 }
@@ -365,8 +371,8 @@ pub fn from_dag_ptr(
         *ix
       }
       else {
-        let Typ { parents, .. } = unsafe { &mut *link.as_ptr() };
-        let ix = graph.add_node(Node::Typ);
+        let Typ { lvl, parents } = unsafe { &mut *link.as_ptr() };
+        let ix = graph.add_node(Node::Typ { lvl: *lvl });
         map.insert(*node, ix);
         add_parent_edges(ix, map, graph, *parents);
         ix
@@ -408,6 +414,30 @@ pub fn from_dag_ptr(
         ix
       }
     }
+    DAGPtr::Eql(link) => {
+      if let Some(ix) = map.get(node) {
+        *ix
+      }
+      else {
+        let Eql { op, parents } = unsafe { &mut *link.as_ptr() };
+        let ix = graph.add_node(Node::Eql { op: *op });
+        map.insert(*node, ix);
+        add_parent_edges(ix, map, graph, *parents);
+        ix
+      }
+    }
+    DAGPtr::Hol(link) => {
+      if let Some(ix) = map.get(node) {
+        *ix
+      }
+      else {
+        let Hol { nam, parents, .. } = unsafe { &mut *link.as_ptr() };
+        let ix = graph.add_node(Node::Hol { name: nam.clone() });
+        map.insert(*node, ix);
+        add_parent_edges(ix, map, graph, *parents);
+        ix
+      }
+    }
     DAGPtr::Ref(link) => {
       if let Some(ix) = map.get(node) {
         *ix
@@ -429,42 +459,74 @@ pub fn from_dag_ptr(
   }
 }
 
+// The attributes an edge is drawn with
+fn edge_attrs(edge: &Edge) -> String {
+  match edge {
+    Edge::Root => format!(", weight = 2] {{rankdir = TB}} ["),
+    Edge::Downlink => format!(", weight = 2"),
+    Edge::Copy => format!(", weight = 0"),
+    Edge::BindFree
+    | Edge::BindLam
+    | Edge::BindAll
+    | Edge::BindSlf
+    | Edge::BindLet => format!(", weight = 0"),
+    Edge::LamBod
+    | Edge::SlfBod
+    | Edge::DatBod
+    | Edge::CseBod
+    | Edge::AppFun
+    | Edge::AppArg
+    | Edge::AllDom
+    | Edge::AllImg
+    | Edge::AnnTyp
+    | Edge::AnnExp
+    | Edge::LetTyp
+    | Edge::LetExp
+    | Edge::LetBod => format!(", weight = 1"),
+  }
+}
+
+// The attributes a node is drawn with, closing them with its rank, since
+// variables and references are ranked at the bottom
+fn node_attrs(ix: NodeIndex<DefaultIx>, node: &Node, attrs: &str) -> String {
+  match node {
+    Node::Var { .. } | Node::Ref { .. } => {
+      format!("{}] {{rank = max; {}}} [", attrs, ix.index())
+    }
+    _ => attrs.to_string(),
+  }
+}
+
 pub fn to_dot<'a>(graph: &'a Graph) -> Dot<'a, &'a Graph> {
   Dot::with_attr_getters(
     graph,
     &[],
-    &|_, e| match e.weight() {
-      Edge::Root => format!(", weight = 2] {{rankdir = TB}} ["),
-      Edge::Downlink => format!(", weight = 2"),
-      Edge::Copy => format!(", weight = 0"),
-      Edge::BindFree
-      | Edge::BindLam
-      | Edge::BindAll
-      | Edge::BindSlf
-      | Edge::BindLet => format!(", weight = 0"),
-      Edge::LamBod
-      | Edge::SlfBod
-      | Edge::DatBod
-      | Edge::CseBod
-      | Edge::AppFun
-      | Edge::AppArg
-      | Edge::AllDom
-      | Edge::AllImg
-      | Edge::AnnTyp
-      | Edge::AnnExp
-      | Edge::LetTyp
-      | Edge::LetExp
-      | Edge::LetBod => format!(", weight = 1"),
-    },
-    &|_, n| match n.1 {
-      Node::Var { .. } | Node::Ref { .. } => {
-        format!("] {{rank = max; {}}} [", n.0.index())
-      }
-      _ => "".to_string(),
-    },
+    &|_, e| edge_attrs(e.weight()),
+    &|_, n| node_attrs(n.0, n.1, ""),
   )
 }
 
+/// Renders a graph like `to_dot`, filling in each node `color` gives a color
+/// to with it
+pub fn to_dot_colored(
+  graph: &Graph,
+  color: impl Fn(NodeIndex<DefaultIx>) -> Option<&'static str>,
+) -> String {
+  let node_attrs = |_, n: (NodeIndex<DefaultIx>, &Node)| {
+    let attrs = color(n.0).map_or(String::new(), |color| {
+      format!(", style = filled, fillcolor = {}", color)
+    });
+    node_attrs(n.0, n.1, &attrs)
+  };
+  let dot = Dot::with_attr_getters(
+    graph,
+    &[],
+    &|_, e| edge_attrs(e.weight()),
+    &node_attrs,
+  );
+  format!("{}", dot)
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
@@ -13,6 +13,7 @@ pub enum IpldError {
   Literal(Ipld),
   LitType(Ipld),
   PrimOp(Ipld),
+  EqlOp(Ipld),
   NatOp(Ipld),
   IntOp(Ipld),
   BytesOp(Ipld),
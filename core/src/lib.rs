@@ -9,12 +9,16 @@ extern crate rand;
 pub mod anon;
 pub mod check;
 pub mod dag;
+pub mod datatype;
 pub mod defs;
 pub mod dll;
 pub mod embed_error;
+pub mod erase;
+pub mod eql;
 pub mod eval;
 pub mod graph;
 pub mod ipld_error;
+pub mod lint;
 pub mod literal;
 pub mod meta;
 pub mod name;
@@ -40,6 +44,17 @@ pub mod tests {
   };
   use rand::Rng;
 
+  use crate::{
+    defs::Defs,
+    package::Index,
+    parse::{
+      error::ParseError,
+      package::parse_defs,
+      span::Span,
+      term::input_cid,
+    },
+  };
+
   pub fn arbitrary_cid(g: &mut Gen) -> Cid {
     let mut bytes: [u8; 32] = [0; 32];
     for x in bytes.iter_mut() {
@@ -69,4 +84,16 @@ pub mod tests {
     }
     panic!("Calculation error for weight = {}", weight);
   }
+
+  /// Parses the definitions of a package without imports, failing with the
+  /// error its parse stopped on
+  pub fn parse_package(
+    src: &str,
+  ) -> Result<(Defs, Index), ParseError<Span<'_>>> {
+    match parse_defs(input_cid(src), Defs::new())(Span::new(src)) {
+      Ok((_, res)) => Ok(res),
+      Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => Err(err),
+      Err(nom::Err::Incomplete(_)) => panic!("Incomplete input {}", src),
+    }
+  }
 }
@@ -0,0 +1,438 @@
+use crate::{
+  check::{
+    check_def_metas,
+    error::pretty_pos,
+    CheckOpts,
+  },
+  defs::{
+    Def,
+    Defs,
+    Mutual,
+  },
+  name::Name,
+  package::Index,
+  position::Pos,
+  term::Term,
+};
+
+use cid::Cid;
+
+use std::{
+  collections::HashSet,
+  fmt,
+};
+
+/// A warning about a definition that checks, but has parts that are likely
+/// mistakes or could be left out
+#[derive(PartialEq, Clone, Debug)]
+pub enum Lint {
+  /// A lambda, forall or let binder that's never referred to, with the kind
+  /// of binder it is
+  UnusedBinder(Pos, &'static str, Name),
+  /// A binder hiding the imported definition of the same name
+  ShadowedImport(Pos, Name),
+  /// A type annotation, or the domain of a lambda, the definition checks
+  /// without, as the checker can synthesize it
+  RedundantAnnotation(Pos),
+  /// A definition `main` doesn't depend on, directly or not
+  Unreachable(Pos, Name),
+}
+
+impl Lint {
+  pub fn pos(&self) -> Pos {
+    match self {
+      Lint::UnusedBinder(pos, ..) => *pos,
+      Lint::ShadowedImport(pos, _) => *pos,
+      Lint::RedundantAnnotation(pos) => *pos,
+      Lint::Unreachable(pos, _) => *pos,
+    }
+  }
+}
+
+impl fmt::Display for Lint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Lint::UnusedBinder(pos, kind, name) => {
+        write!(f, "Unused {} binder \"{}\" {}", kind, name, pretty_pos(*pos))
+      }
+      Lint::ShadowedImport(pos, name) => write!(
+        f,
+        "Binder \"{}\" shadows an imported definition {}",
+        name,
+        pretty_pos(*pos)
+      ),
+      Lint::RedundantAnnotation(pos) => {
+        write!(f, "Redundant type annotation {}", pretty_pos(*pos))
+      }
+      Lint::Unreachable(pos, name) => write!(
+        f,
+        "Definition \"{}\" is unreachable from main {}",
+        name,
+        pretty_pos(*pos)
+      ),
+    }
+  }
+}
+
+/// Lints the definitions of a package's index, given the definitions of the
+/// package and its imports. Every definition of a package is exported, so
+/// unreachable ones are only reported for packages with a `main`.
+pub fn lint_package(
+  defs: &Defs,
+  index: &Index,
+  opts: CheckOpts,
+) -> Vec<(Name, Lint)> {
+  let local: HashSet<&Name> = index.0.iter().map(|(name, _)| name).collect();
+  let imported: HashSet<Name> =
+    defs.names.keys().filter(|name| !local.contains(name)).cloned().collect();
+  let mut res = Vec::new();
+  for (name, _) in &index.0 {
+    let def = match defs.get(name) {
+      Some(def) => def,
+      None => continue,
+    };
+    // The definitions generated by a `type` or `record` declaration share its
+    // position, and their binders aren't the ones written in it
+    let generated = def.pos == Pos::None
+      || index.0.iter().any(|(other, _)| {
+        other != name && defs.get(other).map(|d| d.pos) == Some(def.pos)
+      });
+    if generated {
+      continue;
+    }
+    let mut lints = lint_binders(def, &imported);
+    lints.extend(redundant_annotations(defs, name, opts));
+    lints.sort_by_key(|lint| match lint.pos() {
+      Pos::Some(pos) => pos.from_offset,
+      Pos::None => 0,
+    });
+    res.extend(lints.into_iter().map(|lint| (name.clone(), lint)));
+  }
+  res.extend(unreachable(defs, index));
+  res
+}
+
+/// The unused binders of a definition, and the ones shadowing the imported
+/// names. The parameters of a definition bind a forall in its type as well as
+/// a lambda in its term, and count as used if either of them is.
+pub fn lint_binders(def: &Def, imported: &HashSet<Name>) -> Vec<Lint> {
+  let mut params = 0;
+  let (mut typ, mut term) = (&def.typ_, &def.term);
+  while let (Term::All(_, _, _, _, dom_img), Term::Lam(_, _, _, bod)) =
+    (typ, term)
+  {
+    params += 1;
+    typ = &dom_img.1;
+    term = bod;
+  }
+  let mut scope = Scope { imported, used: Vec::new(), lints: Vec::new() };
+  let mut used = Vec::new();
+  scope.params(&def.typ_, params, &mut used);
+  used.reverse();
+  scope.lams(&def.term, &used);
+  scope.lints
+}
+
+// The binders enclosing a subterm, innermost last, with whether each is used
+struct Scope<'a> {
+  imported: &'a HashSet<Name>,
+  used: Vec<bool>,
+  lints: Vec<Lint>,
+}
+
+impl<'a> Scope<'a> {
+  // Runs `f` with a variable bound, returning whether it used it
+  fn bind(&mut self, f: impl FnOnce(&mut Self)) -> bool {
+    self.used.push(false);
+    f(self);
+    self.used.pop().unwrap_or(true)
+  }
+
+  // Reports a binder shadowing an import, or one that isn't `used`. Binders
+  // that are anonymous or start with `_` are meant to be unused.
+  fn check(&mut self, pos: Pos, kind: &'static str, nam: &Name, used: bool) {
+    if self.imported.contains(nam) {
+      self.lints.push(Lint::ShadowedImport(pos, nam.clone()));
+    }
+    if !used && !nam.is_empty() && !nam.starts_with('_') {
+      self.lints.push(Lint::UnusedBinder(pos, kind, nam.clone()));
+    }
+  }
+
+  // Walks the type of a definition, whose first `params` foralls are its
+  // parameters, pushing whether each of them is used, innermost first
+  fn params(&mut self, typ: &Term, params: usize, used: &mut Vec<bool>) {
+    match typ {
+      Term::All(_, _, _, _, dom_img) if params > 0 => {
+        self.walk(&dom_img.0);
+        let img_used = self.bind(|s| s.params(&dom_img.1, params - 1, used));
+        used.push(img_used);
+      }
+      _ => self.walk(typ),
+    }
+  }
+
+  // Walks the term of a definition, whose first lambdas are its parameters,
+  // given whether the type uses each of them
+  fn lams(&mut self, term: &Term, typ_used: &[bool]) {
+    match (term, typ_used) {
+      (Term::Lam(pos, nam, dom, bod), [typ_used, rest @ ..]) => {
+        if let Some((_, dom)) = dom {
+          self.walk(dom);
+        }
+        let used = self.bind(|s| s.lams(bod, rest));
+        self.check(*pos, "lambda", nam, used || *typ_used);
+      }
+      _ => self.walk(term),
+    }
+  }
+
+  fn walk(&mut self, term: &Term) {
+    match term {
+      Term::Var(_, _, idx) => {
+        if let Some(dep) = self.used.len().checked_sub(*idx as usize + 1) {
+          self.used[dep] = true;
+        }
+      }
+      Term::Lam(pos, nam, dom, bod) => {
+        if let Some((_, dom)) = dom {
+          self.walk(dom);
+        }
+        let used = self.bind(|s| s.walk(bod));
+        self.check(*pos, "lambda", nam, used);
+      }
+      Term::All(pos, _, _, nam, dom_img) => {
+        self.walk(&dom_img.0);
+        let used = self.bind(|s| s.walk(&dom_img.1));
+        self.check(*pos, "forall", nam, used);
+      }
+      Term::Let(pos, rec, _, nam, typ_exp_bod) => {
+        let (typ, exp, bod) = &**typ_exp_bod;
+        self.walk(typ);
+        // A recursive `let` referring to itself doesn't count as a use
+        if *rec {
+          self.bind(|s| s.walk(exp));
+        }
+        else {
+          self.walk(exp);
+        }
+        let used = self.bind(|s| s.walk(bod));
+        self.check(*pos, "let", nam, used);
+      }
+      // The self variable of a self type is often only there for the types
+      // that depend on it
+      Term::Slf(_, _, bod) => {
+        self.bind(|s| s.walk(bod));
+      }
+      Term::App(_, fun_arg) | Term::Prj(_, _, fun_arg) => {
+        self.walk(&fun_arg.0);
+        self.walk(&fun_arg.1);
+      }
+      Term::Ann(_, typ_exp) => {
+        self.walk(&typ_exp.0);
+        self.walk(&typ_exp.1);
+      }
+      Term::Dat(_, bod)
+      | Term::Cse(_, bod)
+      | Term::Rcd(_, _, bod) => self.walk(bod),
+      _ => (),
+    }
+  }
+}
+
+/// The type annotations and lambda domains of a definition that give the
+/// types they are checked against, which the definition checks the same
+/// without. Definitions that don't check, axioms and members of `mutual`
+/// blocks have none.
+pub fn redundant_annotations(
+  defs: &Defs,
+  name: &Name,
+  opts: CheckOpts,
+) -> Vec<Lint> {
+  match defs.get(name) {
+    Some(def) if def.mutual.is_none() && !def.is_axiom() => (),
+    _ => return vec![],
+  }
+  let metas = match check_def_metas(defs, name.as_ref(), opts) {
+    Ok((.., metas)) => metas,
+    Err(_) => return vec![],
+  };
+  let mut lints = Vec::new();
+  for pos in &metas.redundant_annotations {
+    let lint = Lint::RedundantAnnotation(*pos);
+    if !lints.contains(&lint) {
+      lints.push(lint);
+    }
+  }
+  lints
+}
+
+// The definitions of an index the others are reached from: its `main`, or if
+// it has none the definitions it exports, which are all of them
+fn roots(index: &Index) -> Vec<Cid> {
+  let main = Name::from("main");
+  match index.0.iter().find(|(name, _)| *name == main) {
+    Some((_, cid)) => vec![*cid],
+    None => index.0.iter().map(|(_, cid)| *cid).collect(),
+  }
+}
+
+/// The definitions of an index its roots don't refer to, directly or through
+/// other definitions. The roots are its `main`, or its exports if it has no
+/// `main`. The definitions generated by a declaration are reachable together.
+pub fn unreachable(defs: &Defs, index: &Index) -> Vec<(Name, Lint)> {
+  let mut reached = HashSet::new();
+  let mut todo = roots(index);
+  while let Some(cid) = todo.pop() {
+    if !reached.insert(cid) {
+      continue;
+    }
+    let def = match defs.defs.get(&cid) {
+      Some(def) => def,
+      None => continue,
+    };
+    refs(&def.typ_, &def.mutual, &mut todo);
+    refs(&def.term, &def.mutual, &mut todo);
+    if def.pos != Pos::None {
+      for (_, other) in &index.0 {
+        if defs.defs.get(other).map(|d| d.pos) == Some(def.pos) {
+          todo.push(*other);
+        }
+      }
+    }
+  }
+  index
+    .0
+    .iter()
+    .filter(|(_, cid)| !reached.contains(cid))
+    .filter_map(|(name, cid)| {
+      let def = defs.defs.get(cid)?;
+      Some((name.clone(), Lint::Unreachable(def.pos, name.clone())))
+    })
+    .collect()
+}
+
+// The content ids of the definitions a term refers to
+fn refs(term: &Term, mutual: &Option<Mutual>, res: &mut Vec<Cid>) {
+  match term {
+    Term::Ref(_, _, def, _) => res.push(*def),
+    Term::Mut(_, _, idx) => {
+      if let Some(member) =
+        mutual.as_ref().and_then(|m| m.members.get(*idx as usize))
+      {
+        res.push(member.0);
+      }
+    }
+    Term::Lam(_, _, dom, bod) => {
+      if let Some((_, dom)) = dom {
+        refs(dom, mutual, res);
+      }
+      refs(bod, mutual, res);
+    }
+    Term::App(_, two)
+    | Term::All(_, _, _, _, two)
+    | Term::Ann(_, two)
+    | Term::Prj(_, _, two) => {
+      refs(&two.0, mutual, res);
+      refs(&two.1, mutual, res);
+    }
+    Term::Let(_, _, _, _, typ_exp_bod) => {
+      refs(&typ_exp_bod.0, mutual, res);
+      refs(&typ_exp_bod.1, mutual, res);
+      refs(&typ_exp_bod.2, mutual, res);
+    }
+    Term::Slf(_, _, bod)
+    | Term::Dat(_, bod)
+    | Term::Cse(_, bod)
+    | Term::Rcd(_, _, bod) => refs(bod, mutual, res),
+    _ => (),
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::{
+    parse::{
+      package::parse_defs,
+      span::Span,
+      term::input_cid,
+    },
+    tests::parse_package,
+  };
+
+  fn lint(src: &str) -> Vec<String> {
+    let (defs, index) = parse_package(src).unwrap();
+    lint_package(&defs, &index, CheckOpts::default())
+      .into_iter()
+      .map(|(name, lint)| match lint {
+        Lint::UnusedBinder(_, kind, nam) => {
+          format!("{}: unused {} {}", name, kind, nam)
+        }
+        Lint::ShadowedImport(_, nam) => format!("{}: shadowed {}", name, nam),
+        Lint::RedundantAnnotation(_) => format!("{}: redundant", name),
+        Lint::Unreachable(..) => format!("{}: unreachable", name),
+      })
+      .collect()
+  }
+
+  #[test]
+  pub fn lint_binders() {
+    // Parameters only need to be used in the term
+    assert_eq!(
+      lint("def const (A B: Type) (x: A) (y: B): A = x"),
+      vec!["const: unused lambda y"]
+    );
+    assert_eq!(
+      lint(
+        "def f (x: #Nat): #Nat =
+           let y: #Nat = x;
+           let _z: #Nat = x;
+           let w: #Nat = (λ (0 v: #Nat) => x) 1;
+           w
+         def g: #Nat = (λ (h: ∀ (n: #Nat) -> #Nat) => h 1) (λ m => m)"
+      ),
+      vec!["f: unused let y", "f: unused lambda v", "g: unused forall n"]
+    );
+    // The members of a declaration are neither linted nor unreachable on
+    // their own
+    assert_eq!(lint("type Bool { True, False }"), Vec::<String>::new());
+    let src = "def two: #Nat = 2";
+    let (imports, _) = parse_package(src).unwrap();
+    let src = "def double (two: #Nat): #Nat = #Nat.add two two";
+    let (_, (defs, index)) =
+      parse_defs(input_cid(src), imports)(Span::new(src)).unwrap();
+    let lints = lint_package(&defs, &index, CheckOpts::default());
+    assert!(matches!(
+      lints.as_slice(),
+      [(_, Lint::ShadowedImport(_, nam))] if &**nam == "two"
+    ));
+  }
+
+  #[test]
+  pub fn lint_annotations_and_reachability() {
+    assert_eq!(
+      lint(
+        "def a: #Nat = (1 :: #Nat)
+         def b: ∀ #Nat -> #Nat = λ x => #Nat.add x (1 :: #Nat)
+         def c: #Nat = (λ (x: #Nat) => x) 1
+         def d: ∀ #Nat -> #Nat = λ (x: #Nat) => x"
+      ),
+      vec!["a: redundant", "b: redundant", "d: redundant"]
+    );
+    assert_eq!(
+      lint(
+        "type Bool { True, False }
+         def used: Bool = Bool.True
+         def unused: Bool = Bool.False
+         def main: Bool = used"
+      ),
+      vec!["unused: unreachable"]
+    );
+    // Without a `main`, every definition is exported and so reachable
+    assert_eq!(
+      lint("def used: #Nat = 1 def unused: #Nat = 2"),
+      Vec::<String>::new()
+    );
+  }
+}
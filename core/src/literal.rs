@@ -398,6 +398,8 @@ impl Literal {
 
 impl LitType {
   pub fn induction(self, val: Term) -> Option<Term> {
+    // `val` is placed under the binders of the motive and of the cases
+    let val = |binders| val.clone().shift(binders, 0);
     match self {
       Self::Nat => Some(yatima!(
         "∀ (0 P: ∀ #Nat -> Type)
@@ -405,14 +407,14 @@ impl LitType {
              (& succ: ∀ (pred: #Nat) -> P (#Nat.suc pred))
            -> P #$0
           ",
-        val
+        val(3)
       )),
       Self::Int => Some(yatima!(
         "∀ (0 P: ∀ #Int -> Type)
              (& int: ∀ (sign: #Bool) (abs: #Nat) -> P (#Int.new sign abs))
            -> P #$0
           ",
-        val
+        val(2)
       )),
       Self::Bytes => Some(yatima!(
         "∀ (0 P: ∀ #Bytes -> Type)
@@ -420,7 +422,7 @@ impl LitType {
              (& cons: ∀ (x: #U8) (xs: #Bytes) -> P (#Bytes.cons x xs))
            -> P #$0
           ",
-        val
+        val(3)
       )),
       Self::Bits => Some(yatima!(
         "∀ (0 P: ∀ #Bits -> Type)
@@ -428,7 +430,7 @@ impl LitType {
              (& cons: ∀ (x: #Bool) (xs: #Bits) -> P (#Bits.cons x xs))
            -> P #$0
           ",
-        val
+        val(3)
       )),
       Self::Text => Some(yatima!(
         "∀ (0 P: ∀ #Text -> Type)
@@ -436,7 +438,7 @@ impl LitType {
              (& cons: ∀ (x: #Char) (xs: #Text) -> P (#Text.cons x xs))
            -> P #$0
           ",
-        val
+        val(3)
       )),
       Self::Bool => Some(yatima!(
         "∀ (0 P: ∀ #Bool -> Type)
@@ -444,7 +446,7 @@ impl LitType {
              (& f: P #Bool.false)
            -> P #$0
           ",
-        val
+        val(3)
       )),
       _ => None,
     }
@@ -9,9 +9,9 @@ use sp_ipld::Ipld;
 #[derive(PartialEq, Clone, Debug)]
 pub enum Meta {
   Var(Pos, Name),
-  Lam(Pos, Name, Box<Meta>),
+  Lam(Pos, Name, Option<Box<Meta>>, Box<Meta>),
   App(Pos, Box<(Meta, Meta)>),
-  All(Pos, Name, Box<(Meta, Meta)>),
+  All(Pos, bool, Name, Box<(Meta, Meta)>),
   Slf(Pos, Name, Box<Meta>),
   Dat(Pos, Box<Meta>),
   Cse(Pos, Box<Meta>),
@@ -22,7 +22,13 @@ pub enum Meta {
   Lit(Pos),
   LTy(Pos),
   Opr(Pos),
+  Eql(Pos),
   Rec(Pos),
+  Hol(Pos, Name),
+  Mut(Pos, Name),
+  Axm(Pos, Name),
+  Rcd(Pos, Vec<Name>, Box<Meta>),
+  Prj(Pos, Name, Box<Meta>),
 }
 
 impl Meta {
@@ -33,12 +39,19 @@ impl Meta {
         pos.to_ipld(),
         Ipld::String(nam.to_string()),
       ]),
-      Self::Lam(pos, nam, bod) => Ipld::List(vec![
+      Self::Lam(pos, nam, None, bod) => Ipld::List(vec![
         Ipld::Integer(1),
         pos.to_ipld(),
         Ipld::String(nam.to_string()),
         bod.to_ipld(),
       ]),
+      Self::Lam(pos, nam, Some(dom), bod) => Ipld::List(vec![
+        Ipld::Integer(1),
+        pos.to_ipld(),
+        Ipld::String(nam.to_string()),
+        dom.to_ipld(),
+        bod.to_ipld(),
+      ]),
       Self::App(pos, fun_arg) => {
         let (fun, arg) = (*fun_arg).as_ref();
         Ipld::List(vec![
@@ -48,12 +61,23 @@ impl Meta {
           arg.to_ipld(),
         ])
       }
-      Self::All(pos, nam, dom_img) => {
+      Self::All(pos, false, nam, dom_img) => {
+        let (dom, img) = (*dom_img).as_ref();
+        Ipld::List(vec![
+          Ipld::Integer(3),
+          pos.to_ipld(),
+          Ipld::String(nam.to_string()),
+          dom.to_ipld(),
+          img.to_ipld(),
+        ])
+      }
+      Self::All(pos, true, nam, dom_img) => {
         let (dom, img) = (*dom_img).as_ref();
         Ipld::List(vec![
           Ipld::Integer(3),
           pos.to_ipld(),
           Ipld::String(nam.to_string()),
+          Ipld::Bool(true),
           dom.to_ipld(),
           img.to_ipld(),
         ])
@@ -101,6 +125,36 @@ impl Meta {
       Self::LTy(pos) => Ipld::List(vec![Ipld::Integer(12), pos.to_ipld()]),
       Self::Opr(pos) => Ipld::List(vec![Ipld::Integer(13), pos.to_ipld()]),
       Self::Rec(pos) => Ipld::List(vec![Ipld::Integer(14), pos.to_ipld()]),
+      Self::Hol(pos, nam) => Ipld::List(vec![
+        Ipld::Integer(15),
+        pos.to_ipld(),
+        Ipld::String(nam.to_string()),
+      ]),
+      Self::Mut(pos, nam) => Ipld::List(vec![
+        Ipld::Integer(16),
+        pos.to_ipld(),
+        Ipld::String(nam.to_string()),
+      ]),
+      Self::Axm(pos, nam) => Ipld::List(vec![
+        Ipld::Integer(17),
+        pos.to_ipld(),
+        Ipld::String(nam.to_string()),
+      ]),
+      Self::Eql(pos) => Ipld::List(vec![Ipld::Integer(18), pos.to_ipld()]),
+      Self::Rcd(pos, nams, bod) => Ipld::List(vec![
+        Ipld::Integer(19),
+        pos.to_ipld(),
+        Ipld::List(
+          nams.iter().map(|nam| Ipld::String(nam.to_string())).collect(),
+        ),
+        bod.to_ipld(),
+      ]),
+      Self::Prj(pos, nam, bod) => Ipld::List(vec![
+        Ipld::Integer(20),
+        pos.to_ipld(),
+        Ipld::String(nam.to_string()),
+        bod.to_ipld(),
+      ]),
     }
   }
 
@@ -114,7 +168,18 @@ impl Meta {
         [Ipld::Integer(1), pos, Ipld::String(nam), bod] => {
           let pos = Pos::from_ipld(pos)?;
           let bod = Meta::from_ipld(bod)?;
-          Ok(Meta::Lam(pos, Name::from(nam.clone()), Box::new(bod)))
+          Ok(Meta::Lam(pos, Name::from(nam.clone()), None, Box::new(bod)))
+        }
+        [Ipld::Integer(1), pos, Ipld::String(nam), dom, bod] => {
+          let pos = Pos::from_ipld(pos)?;
+          let dom = Meta::from_ipld(dom)?;
+          let bod = Meta::from_ipld(bod)?;
+          Ok(Meta::Lam(
+            pos,
+            Name::from(nam.clone()),
+            Some(Box::new(dom)),
+            Box::new(bod),
+          ))
         }
         [Ipld::Integer(2), pos, fun, arg] => {
           let pos = Pos::from_ipld(pos)?;
@@ -126,7 +191,22 @@ impl Meta {
           let pos = Pos::from_ipld(pos)?;
           let dom = Meta::from_ipld(dom)?;
           let img = Meta::from_ipld(img)?;
-          Ok(Meta::All(pos, Name::from(nam.clone()), Box::new((dom, img))))
+          let nam = Name::from(nam.clone());
+          Ok(Meta::All(pos, false, nam, Box::new((dom, img))))
+        }
+        [
+          Ipld::Integer(3),
+          pos,
+          Ipld::String(nam),
+          Ipld::Bool(true),
+          dom,
+          img,
+        ] => {
+          let pos = Pos::from_ipld(pos)?;
+          let dom = Meta::from_ipld(dom)?;
+          let img = Meta::from_ipld(img)?;
+          let nam = Name::from(nam.clone());
+          Ok(Meta::All(pos, true, nam, Box::new((dom, img))))
         }
         [Ipld::Integer(4), pos, Ipld::String(nam), bod] => {
           let pos = Pos::from_ipld(pos)?;
@@ -180,6 +260,39 @@ impl Meta {
           let pos = Pos::from_ipld(pos)?;
           Ok(Self::Rec(pos))
         }
+        [Ipld::Integer(15), pos, Ipld::String(nam)] => {
+          let pos = Pos::from_ipld(pos)?;
+          Ok(Self::Hol(pos, Name::from(nam.clone())))
+        }
+        [Ipld::Integer(16), pos, Ipld::String(nam)] => {
+          let pos = Pos::from_ipld(pos)?;
+          Ok(Self::Mut(pos, Name::from(nam.clone())))
+        }
+        [Ipld::Integer(17), pos, Ipld::String(nam)] => {
+          let pos = Pos::from_ipld(pos)?;
+          Ok(Self::Axm(pos, Name::from(nam.clone())))
+        }
+        [Ipld::Integer(18), pos] => {
+          let pos = Pos::from_ipld(pos)?;
+          Ok(Self::Eql(pos))
+        }
+        [Ipld::Integer(19), pos, Ipld::List(nams), bod] => {
+          let pos = Pos::from_ipld(pos)?;
+          let nams = nams
+            .iter()
+            .map(|nam| match nam {
+              Ipld::String(nam) => Ok(Name::from(nam.clone())),
+              xs => Err(IpldError::Meta(xs.to_owned())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+          let bod = Meta::from_ipld(bod)?;
+          Ok(Self::Rcd(pos, nams, Box::new(bod)))
+        }
+        [Ipld::Integer(20), pos, Ipld::String(nam), bod] => {
+          let pos = Pos::from_ipld(pos)?;
+          let bod = Meta::from_ipld(bod)?;
+          Ok(Self::Prj(pos, Name::from(nam.clone()), Box::new(bod)))
+        }
         xs => Err(IpldError::Meta(Ipld::List(xs.to_owned()))),
       },
       xs => Err(IpldError::Meta(xs.to_owned())),
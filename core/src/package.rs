@@ -10,6 +10,7 @@ use sp_ipld::{
   dag_cbor::cid,
   Ipld,
 };
+use std::convert::TryInto;
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct Package {
@@ -33,6 +34,14 @@ pub struct Index(pub Vec<(Name, Cid)>);
 #[derive(PartialEq, Clone, Debug)]
 pub struct Entry {
   pub pos: Pos,
+  // Whether the definition is exempt from the termination and positivity
+  // checks
+  pub partial: bool,
+  // Whether the typechecker never unfolds the definition
+  pub opaque: bool,
+  // The content id of the `mutual` block the definition is a member of, and
+  // its index in it
+  pub mutual: Option<(Cid, u64)>,
   pub type_anon: Cid,
   pub term_anon: Cid,
   pub type_meta: Meta,
@@ -41,13 +50,27 @@ pub struct Entry {
 
 impl Entry {
   pub fn to_ipld(&self) -> Ipld {
-    Ipld::List(vec![
+    let mut xs = vec![
       self.pos.to_ipld(),
       Ipld::Link(self.type_anon),
       Ipld::Link(self.term_anon),
       self.type_meta.to_ipld(),
       self.term_meta.to_ipld(),
-    ])
+    ];
+    // Only partial or opaque entries carry their markers, as the bits of an
+    // integer, and only members of a `mutual` block their place in it, so that
+    // other entries keep the encoding from before them
+    let marks = (self.partial as i128) | (self.opaque as i128) << 1;
+    if marks != 0 {
+      xs.push(Ipld::Integer(marks));
+    }
+    if let Some((block, idx)) = self.mutual {
+      xs.push(Ipld::List(vec![
+        Ipld::Link(block),
+        Ipld::Integer(idx as i128),
+      ]));
+    }
+    Ipld::List(xs)
   }
 
   pub fn from_ipld(ipld: &Ipld) -> Result<Self, IpldError> {
@@ -59,12 +82,33 @@ impl Entry {
           Ipld::Link(term_anon),
           type_meta,
           term_meta,
+          rest @ ..
         ] => {
           let pos = Pos::from_ipld(pos)?;
+          // Partial entries were marked by `true` before opaque ones were
+          let (marks, rest) = match rest {
+            [Ipld::Integer(marks @ 1..=3), rest @ ..] => (*marks, rest),
+            [Ipld::Bool(true), rest @ ..] => (1, rest),
+            rest => (0, rest),
+          };
+          let mutual = match rest {
+            [] => None,
+            [Ipld::List(block_idx)] => match block_idx.as_slice() {
+              [Ipld::Link(block), Ipld::Integer(idx)] => {
+                let idx: u64 = (*idx).try_into().map_err(IpldError::U64)?;
+                Some((*block, idx))
+              }
+              _ => return Err(IpldError::Entry(Ipld::List(xs.to_owned()))),
+            },
+            _ => return Err(IpldError::Entry(Ipld::List(xs.to_owned()))),
+          };
           let type_meta = Meta::from_ipld(type_meta)?;
           let term_meta = Meta::from_ipld(term_meta)?;
           Ok(Entry {
             pos,
+            partial: marks & 1 != 0,
+            opaque: marks & 2 != 0,
+            mutual,
             type_anon: *type_anon,
             term_anon: *term_anon,
             type_meta,
@@ -275,6 +319,19 @@ pub mod tests {
     }
   }
   #[quickcheck]
+  fn entry_ipld_partial_bool(x: Entry) -> bool {
+    let x = Entry { partial: true, opaque: false, ..x };
+    // The marks follow the position, content ids and metadata
+    let mut ipld = x.to_ipld();
+    if let Ipld::List(xs) = &mut ipld {
+      xs[5] = Ipld::Bool(true);
+    }
+    match Entry::from_ipld(&ipld) {
+      Ok(y) => x == y,
+      _ => false,
+    }
+  }
+  #[quickcheck]
   fn index_ipld(x: Index) -> bool {
     match Index::from_ipld(&x.to_ipld()) {
       Ok(y) => x == y,
@@ -5,4 +5,5 @@ pub mod op;
 pub mod package;
 pub mod span;
 pub mod string;
+pub mod suggest;
 pub mod term;
@@ -1,8 +1,14 @@
 use crate::{
+  check::error::CheckError,
   name::Name,
   parse::{
     base,
+    op::op_symbols,
     span::Span,
+    suggest::{
+      did_you_mean,
+      suggest,
+    },
   },
   term::{
     LitType,
@@ -28,7 +34,8 @@ use std::{
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum ParseErrorKind {
-  UndefinedReference(Name, VecDeque<Name>),
+  // The name, the local variables in scope, and the names it may be a typo of
+  UndefinedReference(Name, VecDeque<Name>, Vec<Name>),
   TopLevelRedefinition(Name),
   UnknownLiteralType(String),
   InvalidBaseEncoding(base::LitBase),
@@ -61,14 +68,21 @@ pub enum ParseErrorKind {
   UnknownI64Op(Name),
   UnknownI128Op(Name),
   InvalidSymbol(String),
+  UnexpectedImplicitArg,
+  InvalidMatch(CheckError),
+  UnknownField(Name),
+  UnknownRecord(Vec<Name>),
+  AmbiguousRecord(Vec<Name>),
+  DuplicateField(Name),
   Nom(ErrorKind),
 }
 
 impl<'a> fmt::Display for ParseErrorKind {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
-      Self::UndefinedReference(name, _) => {
-        write!(f, "Undefined reference {}", name)
+      Self::UndefinedReference(name, _, sugs) => {
+        let sugs: Vec<String> = sugs.iter().map(|n| n.to_string()).collect();
+        write!(f, "Undefined reference {}{}", name, did_you_mean(&sugs))
       }
       Self::TopLevelRedefinition(name) => {
         write!(
@@ -103,34 +117,79 @@ impl<'a> fmt::Display for ParseErrorKind {
       Self::InvalidSymbol(name) => {
         write!(
           f,
-          "The symbol {} contains a reserved character ':', '(', ')', ',', or \
-           whitespace or control character.",
+          "The symbol {} contains a reserved character ':', '(', ')', '{{', \
+           '}}', ',', or whitespace or control character.",
           name
         )
       }
-      Self::LiteralLacksWhitespaceTermination(x) => {
-        write!(f, "Literal {} must be terminated by whitespace or eof", x)
+      Self::UnexpectedImplicitArg => {
+        write!(
+          f,
+          "Implicit arguments `{{x}}` may only follow a reference with \
+           implicit parameters left to fill"
+        )
       }
-      Self::LitTypeLacksWhitespaceTermination(x) => {
-        write!(f, "Literal type {} must be terminated by whitespace or eof", x)
+      Self::InvalidMatch(err) => write!(f, "{}", err),
+      Self::UnknownField(field) => {
+        write!(f, "No record has a field named {}", field)
       }
-      Self::UnknownNatOp(x) => {
-        write!(f, "Unknown primitive Nat operation #Nat.{}", x)
+      Self::UnknownRecord(fields) => {
+        let fields: Vec<String> =
+          fields.iter().map(|n| n.to_string()).collect();
+        write!(f, "No record has exactly the fields {}", fields.join(", "))
       }
-      Self::UnknownIntOp(x) => {
-        write!(f, "Unknown primitive Int operation #Int.{}", x)
+      Self::AmbiguousRecord(typs) => {
+        let typs: Vec<String> = typs.iter().map(|t| t.to_string()).collect();
+        write!(f, "The record literal could build any of {}", typs.join(", "))
       }
-      Self::UnknownBytesOp(x) => {
-        write!(f, "Unknown primitive Bytes operation #Bytes.{}", x)
+      Self::DuplicateField(field) => {
+        write!(f, "The field {} is given more than once", field)
       }
-      Self::UnknownTextOp(x) => {
-        write!(f, "Unknown primitive Nat operation #Text.{}", x)
+      Self::LiteralLacksWhitespaceTermination(x) => {
+        write!(f, "Literal {} must be terminated by whitespace or eof", x)
       }
+      Self::LitTypeLacksWhitespaceTermination(x) => {
+        write!(f, "Literal type {} must be terminated by whitespace or eof", x)
+      }
+      Self::UnknownNatOp(x) => unknown_op(f, "Nat", x),
+      Self::UnknownIntOp(x) => unknown_op(f, "Int", x),
+      Self::UnknownBitsOp(x) => unknown_op(f, "Bits", x),
+      Self::UnknownBytesOp(x) => unknown_op(f, "Bytes", x),
+      Self::UnknownBoolOp(x) => unknown_op(f, "Bool", x),
+      Self::UnknownTextOp(x) => unknown_op(f, "Text", x),
+      Self::UnknownCharOp(x) => unknown_op(f, "Char", x),
+      Self::UnknownU8Op(x) => unknown_op(f, "U8", x),
+      Self::UnknownU16Op(x) => unknown_op(f, "U16", x),
+      Self::UnknownU32Op(x) => unknown_op(f, "U32", x),
+      Self::UnknownU64Op(x) => unknown_op(f, "U64", x),
+      Self::UnknownU128Op(x) => unknown_op(f, "U128", x),
+      Self::UnknownI8Op(x) => unknown_op(f, "I8", x),
+      Self::UnknownI16Op(x) => unknown_op(f, "I16", x),
+      Self::UnknownI32Op(x) => unknown_op(f, "I32", x),
+      Self::UnknownI64Op(x) => unknown_op(f, "I64", x),
+      Self::UnknownI128Op(x) => unknown_op(f, "I128", x),
       _ => write!(f, "internal parser error"),
     }
   }
 }
 
+// Describes an unknown operation of a primitive type, along with the
+// operations of that type it may be a typo of
+fn unknown_op(f: &mut fmt::Formatter<'_>, typ: &str, op: &Name) -> fmt::Result {
+  let sugs: Vec<String> = suggest(op, op_symbols(typ))
+    .iter()
+    .map(|sug| format!("#{}.{}", typ, sug))
+    .collect();
+  write!(
+    f,
+    "Unknown primitive {} operation #{}.{}{}",
+    typ,
+    typ,
+    op,
+    did_you_mean(&sugs)
+  )
+}
+
 impl ParseErrorKind {
   pub fn is_nom_err(&self) -> bool { matches!(self, Self::Nom(_)) }
 }
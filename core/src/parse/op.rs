@@ -1,4 +1,5 @@
 use crate::{
+  ipld_error::IpldError,
   parse::{
     error::{
       ParseError,
@@ -31,6 +32,7 @@ use crate::{
 };
 
 use cid::Cid;
+use sp_ipld::Ipld;
 
 use crate::parse::span::Span;
 
@@ -246,6 +248,43 @@ pub fn parse_i64_op() -> impl Fn(Span) -> IResult<Span, Op, ParseError<Span>> {
 //  }
 //}
 
+// The symbols of the operations of a primitive type, enumerated through the
+// tags they are serialized with, which count up from 0
+fn symbols<T>(
+  from_ipld: impl Fn(&Ipld) -> Result<T, IpldError>,
+  symbol: impl Fn(T) -> String,
+) -> Vec<String> {
+  (0..)
+    .map(|tag| from_ipld(&Ipld::Integer(tag)))
+    .take_while(Result::is_ok)
+    .flatten()
+    .map(symbol)
+    .collect()
+}
+
+/// The symbols of every operation of the primitive type named `typ`, such as
+/// `len_chars` for `Text`, which unknown operations are compared against
+pub fn op_symbols(typ: &str) -> Vec<String> {
+  match typ {
+    "Nat" => symbols(NatOp::from_ipld, NatOp::symbol),
+    "Int" => symbols(IntOp::from_ipld, IntOp::symbol),
+    "Bool" => symbols(BoolOp::from_ipld, BoolOp::symbol),
+    "Bits" => symbols(BitsOp::from_ipld, BitsOp::symbol),
+    "Bytes" => symbols(BytesOp::from_ipld, BytesOp::symbol),
+    "Text" => symbols(TextOp::from_ipld, TextOp::symbol),
+    "Char" => symbols(CharOp::from_ipld, CharOp::symbol),
+    "U8" => symbols(U8Op::from_ipld, U8Op::symbol),
+    "U16" => symbols(U16Op::from_ipld, U16Op::symbol),
+    "U32" => symbols(U32Op::from_ipld, U32Op::symbol),
+    "U64" => symbols(U64Op::from_ipld, U64Op::symbol),
+    "I8" => symbols(I8Op::from_ipld, I8Op::symbol),
+    "I16" => symbols(I16Op::from_ipld, I16Op::symbol),
+    "I32" => symbols(I32Op::from_ipld, I32Op::symbol),
+    "I64" => symbols(I64Op::from_ipld, I64Op::symbol),
+    _ => vec![],
+  }
+}
+
 pub fn parse_opr(
   input: Cid,
 ) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
@@ -273,3 +312,19 @@ pub fn parse_opr(
     Ok((upto, Term::Opr(pos, op)))
   }
 }
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  #[test]
+  fn symbol_tables() {
+    // Every operation is enumerated, since their tags have no gaps
+    assert_eq!(op_symbols("Text").len(), 24);
+    assert_eq!(op_symbols("Bool").len(), 9);
+    for sym in op_symbols("Nat") {
+      assert_eq!(NatOp::from_symbol(&sym).map(NatOp::symbol), Some(sym));
+    }
+    assert!(op_symbols("Foo").is_empty());
+  }
+}
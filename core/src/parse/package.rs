@@ -1,4 +1,5 @@
 use crate::{
+  datatype::Datatype,
   defs::{
     Def,
     Defs,
@@ -22,24 +23,40 @@ use crate::{
 };
 
 use std::{
-  collections::VecDeque,
+  collections::{
+    HashSet,
+    VecDeque,
+  },
   convert::TryFrom,
   rc::Rc,
 };
 
 use nom::{
-  bytes::complete::tag,
+  branch::alt,
+  bytes::complete::{
+    tag,
+    take,
+  },
   combinator::{
     eof,
     opt,
+    success,
   },
   multi::separated_list0,
-  sequence::terminated,
+  sequence::{
+    preceded,
+    terminated,
+  },
   Err,
   IResult,
+  Slice,
 };
 
 use cid::Cid;
+use sp_ipld::{
+  dag_cbor::cid,
+  Ipld,
+};
 
 pub fn parse_link(from: Span) -> IResult<Span, Cid, ParseError<Span>> {
   let (upto, (_, bytes)) = parse_multibase()(from)?;
@@ -83,7 +100,9 @@ pub fn parse_entry(
   defs: Defs,
 ) -> impl Fn(Span) -> IResult<Span, (Name, Def, Entry), ParseError<Span>> {
   move |from: Span| {
-    let (i, _) = tag("def")(from)?;
+    let (i, partial) = opt(terminated(tag("partial"), parse_space1))(from)?;
+    let (i, opaque) = opt(terminated(tag("opaque"), parse_space1))(i)?;
+    let (i, _) = tag("def")(i)?;
     let (i, _) = parse_space(i)?;
     let (i, nam) = parse_name(i)?;
     if defs.names.get(&nam.clone()).is_some() {
@@ -104,12 +123,559 @@ pub fn parse_entry(
         false,
       )(i)?;
       let pos = Pos::from_upto(input, from, upto);
-      let (def, entry) = Def::make(pos, typ_, term);
+      // Arguments to implicit parameters are left as holes, which are solved
+      // when the definition is checked
+      let (partial, opaque) = (partial.is_some(), opaque.is_some());
+      let (def, entry) = Def::make(pos, partial, opaque, typ_, term);
       Ok((upto, (nam, def, entry)))
     }
   }
 }
 
+pub fn parse_axiom(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, (Name, Def, Entry), ParseError<Span>> {
+  move |from: Span| {
+    let (i, _) = tag("axiom")(from)?;
+    let (i, _) = parse_space1(i)?;
+    let (i, nam) = parse_name(i)?;
+    if defs.names.get(&nam).is_some() {
+      return Err(Err::Error(ParseError::new(
+        from,
+        ParseErrorKind::TopLevelRedefinition(nam),
+      )));
+    }
+    let (i, _) = parse_space(i)?;
+    let (i, bs) = opt(terminated(
+      parse_binders(
+        input,
+        defs.clone(),
+        None,
+        Rc::new(VecDeque::new()),
+        VecDeque::new(),
+        false,
+      ),
+      parse_space,
+    ))(i)?;
+    let bs = bs.unwrap_or_default();
+    let (i, _) = terminated(tag(":"), parse_space)(i)?;
+    let mut ctx = VecDeque::new();
+    for (_, _, n, _) in bs.iter() {
+      ctx.push_front(n.clone());
+    }
+    let (upto, typ_) = parse_expression(
+      input,
+      defs.clone(),
+      None,
+      Rc::new(ctx),
+      VecDeque::new(),
+    )(i)?;
+    let pos = Pos::from_upto(input, from, upto);
+    let typ_ = bs.into_iter().rev().fold(typ_, |acc, (b, u, n, t)| {
+      Term::All(pos, b, u, n, Box::new((t, acc)))
+    });
+    let term = Term::Axm(pos, nam.clone(), typ_.embed().0.cid());
+    let (def, entry) = Def::make(pos, false, false, typ_, term);
+    Ok((upto, (nam, def, entry)))
+  }
+}
+
+pub fn parse_ctor(
+  input: Cid,
+  defs: Defs,
+  rec: Name,
+  ctx: VecDeque<Name>,
+) -> impl Fn(Span) -> IResult<Span, (Name, Binders), ParseError<Span>> {
+  move |i: Span| {
+    let (i, nam) = parse_name(i)?;
+    let (i, fields) = opt(preceded(
+      parse_space,
+      parse_binders(
+        input,
+        defs.clone(),
+        Some(rec.clone()),
+        Rc::new(ctx.clone()),
+        VecDeque::new(),
+        false,
+      ),
+    ))(i)?;
+    let (i, _) = parse_space(i)?;
+    let (i, _) = opt(terminated(tag(","), parse_space))(i)?;
+    Ok((i, (nam, fields.unwrap_or_default())))
+  }
+}
+
+// The parameters of a datatype or record declaration. Those of a record can
+// only be explicit, as its body would read as an implicit binder otherwise
+fn parse_params(
+  input: Cid,
+  defs: Defs,
+  nam: Name,
+  record: bool,
+) -> impl Fn(Span) -> IResult<Span, Vec<(Uses, Name, Term)>, ParseError<Span>>
+{
+  move |i: Span| {
+    if record {
+      let mut params: Vec<(Uses, Name, Term)> = Vec::new();
+      let mut i = i;
+      loop {
+        match terminated(
+          parse_binder_full(
+            input,
+            defs.clone(),
+            Some(nam.clone()),
+            Rc::new(params_ctx(&params)),
+            VecDeque::new(),
+          ),
+          parse_space,
+        )(i)
+        {
+          Err(Err::Error(_)) => return Ok((i, params)),
+          Err(e) => return Err(e),
+          Ok((i2, bs)) => {
+            params.extend(bs);
+            i = i2;
+          }
+        }
+      }
+    }
+    let (i, params) = opt(terminated(
+      parse_binders(
+        input,
+        defs.clone(),
+        Some(nam.clone()),
+        Rc::new(VecDeque::new()),
+        VecDeque::new(),
+        false,
+      ),
+      parse_space,
+    ))(i)?;
+    let params = params
+      .unwrap_or_default()
+      .into_iter()
+      .map(|(_, u, n, t)| (u, n, t))
+      .collect();
+    Ok((i, params))
+  }
+}
+
+// The context the parameters of a declaration make for the rest of it
+fn params_ctx(params: &[(Uses, Name, Term)]) -> VecDeque<Name> {
+  let mut ctx = VecDeque::new();
+  for (_, n, _) in params.iter() {
+    ctx.push_front(n.clone());
+  }
+  ctx
+}
+
+// Every generated name is a new top level definition
+fn check_decl_names<'a>(
+  from: Span<'a>,
+  defs: &Defs,
+  decl: &Datatype,
+) -> Result<(), Err<ParseError<Span<'a>>>> {
+  let mut seen = HashSet::new();
+  for name in decl.names() {
+    if defs.names.get(&name).is_some() || !seen.insert(name.clone()) {
+      return Err(Err::Error(ParseError::new(
+        from,
+        ParseErrorKind::TopLevelRedefinition(name),
+      )));
+    }
+  }
+  Ok(())
+}
+
+pub fn parse_type_decl(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Datatype, ParseError<Span>> {
+  move |from: Span| {
+    let (i, partial) = opt(terminated(tag("partial"), parse_space1))(from)?;
+    let (i, _) = tag("type")(i)?;
+    let (i, _) = parse_space1(i)?;
+    let (i, nam) = parse_name(i)?;
+    let (i, _) = parse_space(i)?;
+    let (i, params) =
+      parse_params(input, defs.clone(), nam.clone(), false)(i)?;
+    let ctx = params_ctx(&params);
+    let (mut i, _) = terminated(tag("{"), parse_space)(i)?;
+    let mut ctors = Vec::new();
+    loop {
+      if let Ok((upto, _)) = tag::<&str, Span, ParseError<Span>>("}")(i) {
+        let pos = Pos::from_upto(input, from, upto);
+        let partial = partial.is_some();
+        let fields = vec![];
+        let decl = Datatype { pos, partial, name: nam, params, ctors, fields };
+        check_decl_names(from, &defs, &decl)?;
+        return Ok((upto, decl));
+      }
+      let (i2, ctor) =
+        parse_ctor(input, defs.clone(), nam.clone(), ctx.clone())(i)?;
+      ctors.push(ctor);
+      i = i2;
+    }
+  }
+}
+
+pub fn parse_record_field(
+  input: Cid,
+  defs: Defs,
+  rec: Name,
+  ctx: VecDeque<Name>,
+) -> impl Fn(Span) -> IResult<Span, (Name, Term), ParseError<Span>> {
+  move |i: Span| {
+    let (i, nam) = parse_name(i)?;
+    let (i, _) = parse_space(i)?;
+    let (i, _) = terminated(tag(":"), parse_space)(i)?;
+    let (i, typ) = parse_expression(
+      input,
+      defs.clone(),
+      Some(rec.clone()),
+      Rc::new(ctx.clone()),
+      VecDeque::new(),
+    )(i)?;
+    let (i, _) = parse_space(i)?;
+    let (i, _) = opt(terminated(tag(","), parse_space))(i)?;
+    Ok((i, (nam, typ)))
+  }
+}
+
+/// Parses `record Name (params) { field: Type, ... }`. The types of the
+/// fields may refer to the parameters, but not to each other
+pub fn parse_record_decl(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Datatype, ParseError<Span>> {
+  move |from: Span| {
+    let (i, partial) = opt(terminated(tag("partial"), parse_space1))(from)?;
+    let (i, _) = tag("record")(i)?;
+    let (i, _) = parse_space1(i)?;
+    let (i, nam) = parse_name(i)?;
+    let (i, _) = parse_space(i)?;
+    let (i, params) =
+      parse_params(input, defs.clone(), nam.clone(), true)(i)?;
+    let ctx = params_ctx(&params);
+    let (mut i, _) = terminated(tag("{"), parse_space)(i)?;
+    let mut fields: Vec<(Name, Term)> = Vec::new();
+    loop {
+      if let Ok((upto, _)) = tag::<&str, Span, ParseError<Span>>("}")(i) {
+        let pos = Pos::from_upto(input, from, upto);
+        let partial = partial.is_some();
+        // The constructor binds the fields one after the other
+        let ctor_fields = fields
+          .iter()
+          .enumerate()
+          .map(|(j, (nam, typ))| {
+            (false, Uses::Many, nam.clone(), typ.clone().shift(j as u64, 0))
+          })
+          .collect();
+        let ctors = vec![(Name::from("New"), ctor_fields)];
+        let decl = Datatype { pos, partial, name: nam, params, ctors, fields };
+        check_decl_names(from, &defs, &decl)?;
+        return Ok((upto, decl));
+      }
+      let (i2, field) =
+        parse_record_field(input, defs.clone(), nam.clone(), ctx.clone())(i)?;
+      fields.push(field);
+      i = i2;
+    }
+  }
+}
+
+// A member of a `mutual` block, as parsed
+#[derive(Clone)]
+struct Member {
+  pos: Pos,
+  partial: bool,
+  opaque: bool,
+  name: Name,
+  typ_: Term,
+  term: Term,
+}
+
+fn parse_member(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Member, ParseError<Span>> {
+  move |from: Span| {
+    let (i, partial) = opt(terminated(tag("partial"), parse_space1))(from)?;
+    let (i, opaque) = opt(terminated(tag("opaque"), parse_space1))(i)?;
+    let (i, _) = tag("def")(i)?;
+    let (i, _) = parse_space(i)?;
+    let (i, name) = parse_name(i)?;
+    let (i, _) = parse_space(i)?;
+    let (upto, (typ_, term)) = parse_bound_expression(
+      input,
+      defs.to_owned(),
+      Some(name.clone()),
+      Rc::new(VecDeque::new()),
+      VecDeque::new(),
+      name.clone(),
+      false,
+    )(i)?;
+    let pos = Pos::from_upto(input, from, upto);
+    let (partial, opaque) = (partial.is_some(), opaque.is_some());
+    Ok((upto, Member { pos, partial, opaque, name, typ_, term }))
+  }
+}
+
+fn parse_members(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Vec<Member>, ParseError<Span>> {
+  move |i: Span| {
+    let mut members = vec![];
+    let mut i = i;
+    loop {
+      if let Ok((upto, _)) = tag::<&str, Span, ParseError<Span>>("}")(i) {
+        return Ok((upto, members));
+      }
+      let (i2, member) = parse_member(input, defs.clone())(i)?;
+      let (i2, _) = parse_space(i2)?;
+      let (i2, _) = opt(terminated(tag(";"), parse_space))(i2)?;
+      members.push(member);
+      i = i2;
+    }
+  }
+}
+
+// The content id a member of a `mutual` block is referred to by until the
+// block is hashed
+fn placeholder(input: Cid, name: &Name) -> Cid {
+  cid(&Ipld::List(vec![Ipld::Link(input), Ipld::String(name.to_string())]))
+}
+
+// Adds a definition for each member of a `mutual` block, under its
+// placeholder content id
+fn with_members(input: Cid, defs: &Defs, members: &[Member]) -> Defs {
+  let mut defs = defs.clone();
+  for member in members {
+    let cid = placeholder(input, &member.name);
+    defs.insert(member.name.clone(), Def {
+      pos: member.pos,
+      partial: member.partial,
+      opaque: member.opaque,
+      mutual: None,
+      def_cid: cid,
+      ast_cid: cid,
+      typ_: member.typ_.clone(),
+      term: member.term.clone(),
+    });
+  }
+  defs
+}
+
+// A member of a `mutual` block that is referred to before it is parsed, of
+// type `typ_`, or of a hole if its type didn't parse
+fn placeholder_member(name: &Name, typ_: Option<Term>) -> Member {
+  let hol = Term::Hol(Pos::None, Name::from(""), 0);
+  Member {
+    pos: Pos::None,
+    partial: false,
+    opaque: false,
+    name: name.clone(),
+    typ_: typ_.unwrap_or_else(|| hol.clone()),
+    term: hol,
+  }
+}
+
+// The names of the members of the `mutual` block starting at `i`, each with
+// the rest of its declaration after the name. They are found by scanning for
+// the `def` keywords directly in the block, outside of comments, literals and
+// nested braces, without parsing the members.
+fn member_names(i: Span) -> Vec<(Name, Span)> {
+  let text: &str = i.fragment();
+  let is_delim = |c: char| {
+    c.is_whitespace() || ":;(){},".contains(c) || c == '"' || c == '\''
+  };
+  let mut names = vec![];
+  let mut depth = 1;
+  let mut k = 0;
+  while let Some(c) = text[k..].chars().next() {
+    if text[k..].starts_with("//") {
+      k += text[k..].find('\n').unwrap_or(text.len() - k);
+    }
+    else if c == '"' || c == '\'' {
+      // Skips the literal up to its closing quote, past escaped ones
+      let mut chars = text[k..].char_indices().skip(1);
+      let mut end = text.len() - k;
+      while let Some((j, d)) = chars.next() {
+        if d == '\\' {
+          chars.next();
+        }
+        else if d == c {
+          end = j + 1;
+          break;
+        }
+      }
+      k += end;
+    }
+    else if c == '{' || c == '}' {
+      depth += if c == '{' { 1 } else { -1 };
+      if depth == 0 {
+        break;
+      }
+      k += 1;
+    }
+    else if is_delim(c) {
+      k += c.len_utf8();
+    }
+    else {
+      let len = text[k..].find(is_delim).unwrap_or(text.len() - k);
+      let word = &text[k..k + len];
+      k += len;
+      if depth == 1 && word == "def" {
+        let rest = i.slice(k..);
+        if let Ok((rest, name)) = preceded(parse_space, parse_name)(rest) {
+          names.push((name, rest));
+        }
+      }
+    }
+  }
+  names
+}
+
+// Parses the type of a member of a `mutual` block, from after its name
+fn parse_signature(
+  input: Cid,
+  defs: Defs,
+  name: Name,
+) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
+  move |i: Span| {
+    let rec = Some(name.clone());
+    let (i, _) = parse_space(i)?;
+    let (i, bs) = alt((
+      terminated(
+        parse_binders(
+          input,
+          defs.clone(),
+          rec.clone(),
+          Rc::new(VecDeque::new()),
+          VecDeque::new(),
+          false,
+        ),
+        parse_space,
+      ),
+      success(Vec::new()),
+    ))(i)?;
+    let (i, _) = tag(":")(i)?;
+    let (i, _) = parse_space(i)?;
+    let mut ctx = VecDeque::new();
+    for (_, _, n, _) in bs.iter() {
+      ctx.push_front(n.clone());
+    }
+    let ctx = Rc::new(ctx);
+    let (upto, typ) =
+      parse_expression(input, defs.clone(), rec, ctx, VecDeque::new())(i)?;
+    let typ = bs.into_iter().rev().fold(typ, |acc, (b, u, n, t)| {
+      Term::All(Pos::None, b, u, n, Box::new((t, acc)))
+    });
+    Ok((upto, typ))
+  }
+}
+
+pub fn parse_mutual(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Vec<(Name, Def, Entry)>, ParseError<Span>>
+{
+  move |from: Span| {
+    let (i, _) = tag("mutual")(from)?;
+    let (i, _) = parse_space(i)?;
+    let (i, _) = terminated(tag("{"), parse_space)(i)?;
+    // Members may refer to the ones after them, so the block is parsed with
+    // placeholders for all of them, whose types, which decide the implicit
+    // arguments inserted for references to them, are parsed first
+    let names = member_names(i);
+    let holes: Vec<Member> =
+      names.iter().map(|(name, _)| placeholder_member(name, None)).collect();
+    let sig_defs = with_members(input, &defs, &holes);
+    let sigs: Vec<Member> = names
+      .iter()
+      .map(|(name, rest)| {
+        let sig = parse_signature(input, sig_defs.clone(), name.clone());
+        placeholder_member(name, sig(*rest).ok().map(|(_, typ_)| typ_))
+      })
+      .collect();
+    let block_defs = with_members(input, &defs, &sigs);
+    let (upto, members) = parse_members(input, block_defs)(i)?;
+    for (k, member) in members.iter().enumerate() {
+      let redefined = defs.names.get(&member.name).is_some()
+        || members[..k].iter().any(|m| m.name == member.name);
+      if redefined {
+        return Err(Err::Error(ParseError::new(
+          from,
+          ParseErrorKind::TopLevelRedefinition(member.name.clone()),
+        )));
+      }
+    }
+    // The placeholders are replaced by the indices of the members
+    let cids: Vec<Cid> =
+      members.iter().map(|m| placeholder(input, &m.name)).collect();
+    let to_mut = |term: &Term| match term {
+      Term::Ref(pos, name, def_cid, _) => {
+        let idx = cids.iter().position(|cid| cid == def_cid)?;
+        Some(Term::Mut(*pos, name.clone(), idx as u64))
+      }
+      _ => None,
+    };
+    let defs = Def::make_mutual(
+      members
+        .iter()
+        .map(|m| {
+          let (typ_, term) = (m.typ_.replace(&to_mut), m.term.replace(&to_mut));
+          (m.pos, m.partial, m.opaque, typ_, term)
+        })
+        .collect(),
+    );
+    let names = members.into_iter().map(|m| m.name);
+    let block = names
+      .zip(defs)
+      .map(|(name, (def, entry))| (name, def, entry))
+      .collect();
+    Ok((upto, block))
+  }
+}
+
+// Parses a single top-level declaration, which may define several names
+pub fn parse_decl(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Vec<(Name, Def, Entry)>, ParseError<Span>>
+{
+  move |i: Span| {
+    let decl: IResult<Span, Span, ParseError<Span>> =
+      preceded(opt(terminated(tag("partial"), parse_space1)), tag("type"))(i);
+    let record: IResult<Span, Span, ParseError<Span>> = preceded(
+      opt(terminated(tag("partial"), parse_space1)),
+      tag("record"),
+    )(i);
+    let mutual: IResult<Span, Span, ParseError<Span>> = tag("mutual")(i);
+    let axiom: IResult<Span, Span, ParseError<Span>> = tag("axiom")(i);
+    if axiom.is_ok() {
+      let (i, def) = parse_axiom(input, defs.clone())(i)?;
+      Ok((i, vec![def]))
+    }
+    else if mutual.is_ok() {
+      parse_mutual(input, defs.clone())(i)
+    }
+    else if record.is_ok() {
+      let (i, decl) = parse_record_decl(input, defs.clone())(i)?;
+      Ok((i, decl.defs()))
+    }
+    else if decl.is_ok() {
+      let (i, decl) = parse_type_decl(input, defs.clone())(i)?;
+      Ok((i, decl.defs()))
+    }
+    else {
+      let (i, def) = parse_entry(input, defs.clone())(i)?;
+      Ok((i, vec![def]))
+    }
+  }
+}
+
 pub fn parse_defs(
   input: Cid,
   import_defs: Defs,
@@ -125,16 +691,95 @@ pub fn parse_defs(
       if end.is_ok() {
         return Ok((i2, (defs, Index(ind))));
       }
-      else {
-        let (i2, (name, def, _)) = parse_entry(input, defs.clone())(i)?;
+      let (i2, decl) = parse_decl(input, defs.clone())(i)?;
+      for (name, def, _) in decl {
         ind.push((name.clone(), def.def_cid));
         defs.insert(name, def);
-        i = i2;
+      }
+      i = i2;
+    }
+  }
+}
+
+/// Parses the declarations of a package like `parse_defs`, but instead of
+/// stopping at the first error it records it, skips ahead to the next line
+/// starting a declaration and carries on, so that every error is reported
+pub fn parse_defs_recover(
+  input: Cid,
+  import_defs: Defs,
+) -> impl Fn(
+  Span,
+) -> IResult<
+  Span,
+  (Defs, Index, Vec<ParseError<Span>>),
+  ParseError<Span>,
+> {
+  move |i: Span| {
+    let mut defs: Defs = import_defs.clone();
+    let mut ind: Vec<(Name, Cid)> = Vec::new();
+    let mut errs = Vec::new();
+    let mut i = i;
+    loop {
+      // Whitespace never fails to parse, but a comment may be unterminated
+      i = match parse_space(i) {
+        Ok((i2, _)) => i2,
+        Err(Err::Error(e)) | Err(Err::Failure(e)) => {
+          errs.push(e);
+          return Ok((i, (defs, Index(ind), errs)));
+        }
+        Err(Err::Incomplete(_)) => return Ok((i, (defs, Index(ind), errs))),
+      };
+      let end: IResult<Span, Span, ParseError<Span>> = eof(i);
+      if end.is_ok() {
+        return Ok((i, (defs, Index(ind), errs)));
+      }
+      match parse_decl(input, defs.clone())(i) {
+        Ok((i2, decl)) => {
+          for (name, def, _) in decl {
+            ind.push((name.clone(), def.def_cid));
+            defs.insert(name, def);
+          }
+          i = i2;
+        }
+        Err(Err::Error(e)) | Err(Err::Failure(e)) => {
+          errs.push(e);
+          i = skip_to_decl(i);
+        }
+        Err(Err::Incomplete(_)) => {
+          i = skip_to_decl(i);
+        }
       }
     }
   }
 }
 
+// The keywords a top-level declaration can start with
+const DECL_KEYWORDS: [&str; 7] =
+  ["def", "type", "record", "axiom", "mutual", "partial", "opaque"];
+
+// Skips past the current line to the start of the next line that begins with
+// a declaration keyword, or to the end of the input if there is none
+fn skip_to_decl(i: Span) -> Span {
+  let txt: &str = i.fragment();
+  let mut offset = txt.len();
+  let mut start = 0;
+  for line in txt.split_inclusive('\n') {
+    let trimmed = line.trim_start();
+    let is_decl = DECL_KEYWORDS.iter().any(|key| {
+      trimmed
+        .strip_prefix(key)
+        .map_or(false, |rest| rest.starts_with(char::is_whitespace))
+    });
+    if start > 0 && is_decl {
+      offset = start + (line.len() - trimmed.len());
+      break;
+    }
+    start += line.len();
+  }
+  let res: IResult<Span, Span, ParseError<Span>> = take(offset)(i);
+  res.map_or(i, |(rest, _)| rest)
+}
+
 // #[cfg(test)]
 // pub mod tests {
 //  use super::*;
@@ -155,3 +800,46 @@ pub fn parse_defs(
 //    assert!(res.is_ok());
 //  }
 // }
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::tests::parse_package;
+
+  #[test]
+  fn parse_defs_recovery() {
+    let src = "def a: #Nat = 1
+      def b: #Nat = )
+      def c: #Nat = a
+      def d =
+      partial def e: #Nat = c
+      def a: #Nat = 2";
+    let (_, (defs, ind, errs)) =
+      parse_defs_recover(input_cid(src), Defs::new())(Span::new(src)).unwrap();
+    let names: Vec<String> = ind.0.iter().map(|(n, _)| n.to_string()).collect();
+    assert_eq!(names, vec!["a", "c", "e"]);
+    assert!(defs.get(&Name::from("e")).unwrap().partial);
+    let lines: Vec<u32> =
+      errs.iter().map(|e| e.input.location_line()).collect();
+    assert_eq!(lines, vec![2, 4, 6]);
+    assert!(parse_package(src).is_err());
+  }
+
+  #[test]
+  fn parse_mutual_members() {
+    let src = "mutual {
+        // def commented: a member in a comment
+        def f (n: #Nat): #Nat = g \"def quoted }\" n
+        def g {A: Type} (s: #Text) (x: A): #Nat = f 0
+      } def after: #Nat = 1";
+    let names: Vec<String> = member_names(Span::new(&src[8..]))
+      .into_iter()
+      .map(|(name, _)| name.to_string())
+      .collect();
+    assert_eq!(names, vec!["f", "g"]);
+    let (defs, _) = parse_package(src).unwrap();
+    // The implicit argument of `g` is inserted before it is parsed
+    let f = defs.get(&Name::from("f")).unwrap();
+    assert_eq!(format!("{}", f.term), "λ n => g ? \"def quoted }\" n");
+  }
+}
@@ -0,0 +1,91 @@
+use std::cmp::min;
+
+// The edit distance between two strings, counting the insertion, deletion or
+// substitution of a character and the swap of two adjacent characters as one
+// edit each
+pub fn edit_distance(a: &str, b: &str) -> usize {
+  let a: Vec<char> = a.chars().collect();
+  let b: Vec<char> = b.chars().collect();
+  // The distances between the prefixes of `a` and the prefixes of `b`, two
+  // rows at a time
+  let mut prev2: Vec<usize> = vec![0; b.len() + 1];
+  let mut prev: Vec<usize> = (0..=b.len()).collect();
+  for i in 1..=a.len() {
+    let mut curr = vec![i; b.len() + 1];
+    for j in 1..=b.len() {
+      let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+      curr[j] = min(min(prev[j] + 1, curr[j - 1] + 1), prev[j - 1] + cost);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        curr[j] = min(curr[j], prev2[j - 2] + 1);
+      }
+    }
+    prev2 = prev;
+    prev = curr;
+  }
+  prev[b.len()]
+}
+
+/// The candidates closest to `name` by edit distance, sorted, if they are
+/// close enough for `name` to plausibly be a typo of them. A candidate
+/// prefixed or suffixed by `name`, such as `List.map` for `map`, counts as
+/// close too.
+pub fn suggest<I, S>(name: &str, candidates: I) -> Vec<String>
+where
+  I: IntoIterator<Item = S>,
+  S: AsRef<str>,
+{
+  // Roughly one typo per three characters is allowed
+  let max = (name.chars().count() / 3).max(1);
+  let mut best = max + 1;
+  let mut res: Vec<String> = Vec::new();
+  for candidate in candidates {
+    let candidate = candidate.as_ref();
+    if candidate == name {
+      continue;
+    }
+    let last = candidate.rsplit('.').next().unwrap_or(candidate);
+    let dist = edit_distance(name, candidate).min(edit_distance(name, last));
+    if dist < best {
+      best = dist;
+      res.clear();
+    }
+    if dist == best && !res.iter().any(|x| x == candidate) {
+      res.push(candidate.to_owned());
+    }
+  }
+  res.sort();
+  res.truncate(3);
+  res
+}
+
+/// A hint naming the suggestions, to be appended to an error message, which
+/// is empty if there are none
+pub fn did_you_mean(suggestions: &[String]) -> String {
+  match suggestions {
+    [] => String::new(),
+    [sug] => format!(". Did you mean {}?", sug),
+    sugs => format!(". Did you mean one of {}?", sugs.join(", ")),
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  #[test]
+  fn suggestions() {
+    assert_eq!(edit_distance("lenchars", "len_chars"), 1);
+    assert_eq!(edit_distance("mpa", "map"), 1);
+    assert_eq!(edit_distance("", "abc"), 3);
+    let names = ["List.map", "List.filter", "Nat.add", "Nat.sub"];
+    assert_eq!(suggest("List.mapp", names), vec!["List.map"]);
+    assert_eq!(suggest("Nat.ad", names), vec!["Nat.add"]);
+    assert_eq!(suggest("Text.len", names), Vec::<String>::new());
+    assert_eq!(suggest("fitler", names), vec!["List.filter"]);
+    assert_eq!(did_you_mean(&[]), "");
+    assert_eq!(
+      did_you_mean(&["a".to_owned(), "b".to_owned()]),
+      ". Did you mean one of a, b?"
+    );
+  }
+}
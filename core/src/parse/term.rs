@@ -1,5 +1,19 @@
 use crate::{
-  defs::Defs,
+  check::pattern::{
+    compile_match,
+    Arm,
+    Pattern,
+  },
+  datatype::{
+    find_constructor,
+    find_field,
+    find_records,
+  },
+  defs::{
+    Def,
+    Defs,
+  },
+  eql::EqlOp,
   name::Name,
   parse::{
     error::{
@@ -9,6 +23,7 @@ use crate::{
     },
     literal::*,
     op::parse_opr,
+    suggest::suggest,
   },
   position::Pos,
   term::{
@@ -55,7 +70,6 @@ use nom::{
   multi::{
     many0,
     many1,
-    separated_list1,
   },
   sequence::{
     delimited,
@@ -68,6 +82,8 @@ use nom::{
 use std::collections::VecDeque;
 
 type Ctx = Rc<VecDeque<Name>>;
+type LamBinders = Vec<(Name, Option<(Uses, Term)>)>;
+pub type Binders = Vec<(bool, Uses, Name, Term)>;
 
 pub fn reserved_symbols() -> VecDeque<String> {
   VecDeque::from(vec![
@@ -88,9 +104,17 @@ pub fn reserved_symbols() -> VecDeque<String> {
     String::from("in"),
     String::from("type"),
     String::from("data"),
+    String::from("record"),
     String::from("def"),
+    String::from("partial"),
+    String::from("opaque"),
+    String::from("axiom"),
+    String::from("mutual"),
     String::from("open"),
     String::from("case"),
+    String::from("match"),
+    String::from("refl"),
+    String::from("rewrite"),
     String::from("Type"),
   ])
 }
@@ -119,6 +143,8 @@ pub fn parse_name(from: Span) -> IResult<Span, Name, ParseError<Span>> {
       | (x == ';')
       | (x == ')')
       | (x == '(')
+      | (x == '{')
+      | (x == '}')
       | (x == ',')
   })(from)?;
   let s: String = String::from(s.fragment().to_owned());
@@ -179,6 +205,8 @@ pub fn is_valid_symbol_char(c: char) -> bool {
     && c != ';'
     && c != '('
     && c != ')'
+    && c != '{'
+    && c != '}'
     && c != ','
     && !char::is_whitespace(c)
     && !char::is_control(c)
@@ -188,6 +216,7 @@ pub fn is_valid_symbol_string(s: &str) -> bool {
   let invalid_chars = s.starts_with('"')
     || s.starts_with('\'')
     || s.starts_with('#')
+    || s.starts_with('?')
     || s.chars().any(|x| !is_valid_symbol_char(x));
   !s.is_empty() && !invalid_chars
 }
@@ -212,6 +241,7 @@ pub fn parse_antiquote(
         ParseErrorKind::UndefinedReference(
           Name::from(format!("#${}", nam.to_string())),
           ctx.as_ref().clone(),
+          vec![],
         ),
       )))
     }
@@ -227,28 +257,205 @@ pub fn parse_var(
   move |from: Span| {
     let (upto, nam) = context("local or global reference", parse_name)(from)?;
     let pos = Pos::from_upto(input, from, upto);
-    let is_rec_name = match rec.clone() {
-      Some(rec_ref) => nam == rec_ref,
-      _ => false,
+    let resolve = |nam: &Name| {
+      if let Some((idx, _)) = ctx.iter().enumerate().find(|(_, x)| *x == nam) {
+        Some(Term::Var(pos, nam.clone(), idx as u64))
+      }
+      else if rec.as_ref() == Some(nam) {
+        // The definition being defined is in `defs` if its type has implicit
+        // parameters, which its recursive references get arguments for too
+        let trm = Term::Rec(pos);
+        Some(match defs.get(nam) {
+          Some(def) => insert_implicits(pos, &def.typ_, trm),
+          None => trm,
+        })
+      }
+      else if let Some(def) = defs.get(nam) {
+        let trm = Term::Ref(pos, nam.clone(), def.def_cid, def.ast_cid);
+        Some(insert_implicits(pos, &def.typ_, trm))
+      }
+      else {
+        None
+      }
     };
-    if let Some((idx, _)) = ctx.iter().enumerate().find(|(_, x)| **x == nam) {
-      Ok((upto, Term::Var(pos, nam.clone(), idx as u64)))
+    if let Some(trm) = resolve(&nam) {
+      return Ok((upto, trm));
     }
-    else if is_rec_name {
-      Ok((upto, Term::Rec(pos)))
+    // Otherwise `r.x.y` projects the fields `x` and `y` out of the longest
+    // prefix `r` that is defined
+    let segments: Vec<&str> = nam.split('.').collect();
+    for k in (1..segments.len()).rev() {
+      if let Some(trm) = resolve(&Name::from(segments[..k].join("."))) {
+        let trm = segments[k..].iter().try_fold(trm, |trm, field| {
+          project(&defs, pos, trm, &Name::from(*field))
+        });
+        return match trm {
+          Ok(trm) => Ok((upto, trm)),
+          Err(kind) => Err(Err::Error(ParseError::new(upto, kind))),
+        };
+      }
     }
-    else if let Some(def) = defs.get(&nam) {
-      Ok((upto, Term::Ref(pos, nam.clone(), def.def_cid, def.ast_cid)))
+    // A typo is most likely of a local variable, the definition being
+    // defined, or another definition in scope
+    let names = ctx.iter().chain(rec.iter()).cloned().chain(defs.names());
+    let sugs = suggest(&nam, names).into_iter().map(Name::from).collect();
+    Err(Err::Error(ParseError::new(
+      upto,
+      ParseErrorKind::UndefinedReference(nam, ctx.as_ref().clone(), sugs),
+    )))
+  }
+}
+
+/// Projects the field `field` out of `trm`, as the application of the
+/// projection of the only record with a field of that name. The projection
+/// of a field several records have is left as a hole, to be chosen from the
+/// type of `trm` when checking.
+pub fn project(
+  defs: &Defs,
+  pos: Pos,
+  trm: Term,
+  field: &Name,
+) -> Result<Term, ParseErrorKind> {
+  let prj = match find_field(defs, field).as_slice() {
+    [] => return Err(ParseErrorKind::UnknownField(field.clone())),
+    [typ] => {
+      let nam = Name::from(format!("{}.{}", typ, field));
+      let def = defs.get(&nam).unwrap();
+      let prj = Term::Ref(pos, nam, def.def_cid, def.ast_cid);
+      insert_implicits(pos, &def.typ_, prj)
     }
-    else {
-      Err(Err::Error(ParseError::new(
+    _ => Term::Hol(pos, Name::from(""), 0),
+  };
+  Ok(Term::Prj(pos, field.clone(), Box::new((prj, trm))))
+}
+
+/// Parses the projections `.x.y` following the term `trm`, parsed from `from`
+/// to `i`
+pub fn parse_projections<'a>(
+  input: Cid,
+  defs: &Defs,
+  from: Span<'a>,
+  trm: Term,
+  i: Span<'a>,
+) -> IResult<Span<'a>, Term, ParseError<Span<'a>>> {
+  let (upto, fields) = opt(preceded(tag("."), parse_name))(i)?;
+  match fields {
+    None => Ok((i, trm)),
+    Some(fields) => {
+      let pos = Pos::from_upto(input, from, upto);
+      let mut trm = trm;
+      for field in fields.split('.') {
+        trm = project(defs, pos, trm, &Name::from(field))
+          .map_err(|kind| Err::Error(ParseError::new(upto, kind)))?;
+      }
+      Ok((upto, trm))
+    }
+  }
+}
+
+/// Parses a record literal `{ x = a, y = b }`, which builds the only record
+/// with exactly these fields
+pub fn parse_record(
+  input: Cid,
+  defs: Defs,
+  rec: Option<Name>,
+  ctx: Ctx,
+  quasi: VecDeque<Term>,
+) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
+  move |from: Span| {
+    let (mut i, _) = terminated(tag("{"), parse_space)(from)?;
+    let mut vals: Vec<(Name, Term)> = Vec::new();
+    let upto = loop {
+      if let Ok((upto, _)) = tag::<&str, Span, ParseError<Span>>("}")(i) {
+        break upto;
+      }
+      let (i2, nam) = parse_name(i)?;
+      let (i2, _) = parse_space(i2)?;
+      let (i2, _) = terminated(tag("="), parse_space)(i2)?;
+      let (i2, val) = context(
+        "record field value",
+        parse_expression(
+          input,
+          defs.clone(),
+          rec.clone(),
+          ctx.clone(),
+          quasi.clone(),
+        ),
+      )(i2)?;
+      if vals.iter().any(|(n, _)| *n == nam) {
+        return Err(Err::Error(ParseError::new(
+          i2,
+          ParseErrorKind::DuplicateField(nam),
+        )));
+      }
+      vals.push((nam, val));
+      let (i2, _) = parse_space(i2)?;
+      let (i2, _) = opt(terminated(tag(","), parse_space))(i2)?;
+      i = i2;
+    };
+    let pos = Pos::from_upto(input, from, upto);
+    let nams: Vec<Name> = vals.iter().map(|(n, _)| n.clone()).collect();
+    match find_records(&defs, &nams).as_slice() {
+      [] => Err(Err::Error(ParseError::new(
         upto,
-        ParseErrorKind::UndefinedReference(nam.clone(), ctx.as_ref().clone()),
-      )))
+        ParseErrorKind::UnknownRecord(nams),
+      ))),
+      [(typ, fields)] => {
+        let nam = Name::from(format!("{}.New", typ));
+        let def = defs.get(&nam).unwrap();
+        let ctor = Term::Ref(pos, nam, def.def_cid, def.ast_cid);
+        let ctor = insert_implicits(pos, &def.typ_, ctor);
+        let trm = fields.iter().fold(ctor, |acc, field| {
+          let (_, val) = vals.iter().find(|(n, _)| n == field).unwrap();
+          Term::App(pos, Box::new((acc, val.clone())))
+        });
+        Ok((upto, Term::Rcd(pos, fields.clone(), Box::new(trm))))
+      }
+      typs => Err(Err::Error(ParseError::new(
+        upto,
+        ParseErrorKind::AmbiguousRecord(
+          typs.iter().map(|(typ, _)| typ.clone()).collect(),
+        ),
+      ))),
     }
   }
 }
 
+/// Applies a reference to an anonymous hole for each leading implicit
+/// parameter of its type. These holes share the position of the reference,
+/// which is how `fill_implicit` tells them apart from holes written by hand
+pub fn insert_implicits(pos: Pos, typ: &Term, trm: Term) -> Term {
+  match typ {
+    Term::All(_, true, _, _, dom_img) => {
+      let hol = Term::Hol(pos, Name::from(""), 0);
+      insert_implicits(pos, &dom_img.1, Term::App(pos, Box::new((trm, hol))))
+    }
+    _ => trm,
+  }
+}
+
+/// Replaces the first hole inserted by `insert_implicits` in the spine of
+/// `fun` by an explicitly given implicit argument, handing the argument back
+/// if there is none left
+pub fn fill_implicit(fun: &mut Term, arg: Term) -> Result<(), Term> {
+  match fun {
+    Term::App(pos, fun_arg) => {
+      let arg = match fill_implicit(&mut fun_arg.0, arg) {
+        Ok(()) => return Ok(()),
+        Err(arg) => arg,
+      };
+      match &fun_arg.1 {
+        Term::Hol(hol_pos, nam, _) if nam.is_empty() && hol_pos == pos => {
+          fun_arg.1 = arg;
+          Ok(())
+        }
+        _ => Err(arg),
+      }
+    }
+    _ => Err(arg),
+  }
+}
+
 pub fn parse_lam(
   input: Cid,
   defs: Defs,
@@ -259,13 +466,19 @@ pub fn parse_lam(
   move |from: Span| {
     let (i, _) = alt((tag("λ"), tag("lambda")))(from)?;
     let (i, _) = parse_space(i)?;
-    let (i, ns) = separated_list1(multispace1, parse_name)(i)?;
+    let (i, bs) = parse_lam_binders(
+      input,
+      defs.clone(),
+      rec.clone(),
+      ctx.clone(),
+      quasi.clone(),
+    )(i)?;
     let (i, _) = parse_space(i)?;
     let (i, _) = tag("=>")(i)?;
     let (i, _) = parse_space(i)?;
     let mut ctx2 = ctx.as_ref().clone();
-    for n in ns.clone().into_iter() {
-      ctx2.push_front(n);
+    for (n, _) in bs.iter() {
+      ctx2.push_front(n.clone());
     }
     let (upto, bod) = parse_expression(
       input,
@@ -275,14 +488,61 @@ pub fn parse_lam(
       quasi.to_owned(),
     )(i)?;
     let pos = Pos::from_upto(input, from, upto);
-    let trm = ns
-      .iter()
-      .rev()
-      .fold(bod, |acc, n| Term::Lam(pos, n.clone(), Box::new(acc)));
+    let trm = bs.into_iter().rev().fold(bod, |acc, (n, dom)| {
+      let dom = dom.map(|(u, t)| (u, Box::new(t)));
+      Term::Lam(pos, n, dom, Box::new(acc))
+    });
     Ok((upto, trm))
   }
 }
 
+/// Parses the binders of a lambda, each being either a bare name or an
+/// annotated group such as `(0 x y: A)`
+pub fn parse_lam_binders(
+  input: Cid,
+  defs: Defs,
+  rec: Option<Name>,
+  ctx: Ctx,
+  quasi: VecDeque<Term>,
+) -> impl Fn(Span) -> IResult<Span, LamBinders, ParseError<Span>> {
+  move |mut i: Span| {
+    let mut ctx = ctx.as_ref().clone();
+    let mut res = Vec::new();
+    loop {
+      let i2 = if res.is_empty() { i } else { parse_space(i)?.0 };
+      let binder = alt((
+        map(
+          parse_binder_full(
+            input,
+            defs.clone(),
+            rec.clone(),
+            Rc::new(ctx.clone()),
+            quasi.clone(),
+          ),
+          |bs| bs.into_iter().map(|(u, n, t)| (n, Some((u, t)))).collect(),
+        ),
+        map(parse_name, |n| vec![(n, None)]),
+      ))(i2);
+      match binder {
+        Err(Err::Error(e)) => {
+          if res.is_empty() {
+            return Err(Err::Error(e));
+          }
+          return Ok((i, res));
+        }
+        Err(e) => return Err(e),
+        Ok((i3, bs)) => {
+          for (n, dom) in bs {
+            ctx.push_front(n.clone());
+            res.push((n, dom));
+          }
+          i = i3;
+        }
+      }
+    }
+  }
+}
+
 pub fn parse_uses(i: Span) -> IResult<Span, Uses, ParseError<Span>> {
   alt((
     value(Uses::None, terminated(tag("0"), multispace1)),
@@ -298,9 +558,33 @@ pub fn parse_binder_full(
   rec: Option<Name>,
   ctx: Ctx,
   quasi: VecDeque<Term>,
+) -> impl Fn(Span) -> IResult<Span, Vec<(Uses, Name, Term)>, ParseError<Span>> {
+  parse_binder_group(input, defs, rec, ctx, quasi, "(", ")")
+}
+
+/// Parses an implicit binder group such as `{A B: Type}`, whose arguments are
+/// inserted as holes and solved by the typechecker
+pub fn parse_binder_implicit(
+  input: Cid,
+  defs: Defs,
+  rec: Option<Name>,
+  ctx: Ctx,
+  quasi: VecDeque<Term>,
+) -> impl Fn(Span) -> IResult<Span, Vec<(Uses, Name, Term)>, ParseError<Span>> {
+  parse_binder_group(input, defs, rec, ctx, quasi, "{", "}")
+}
+
+pub fn parse_binder_group(
+  input: Cid,
+  defs: Defs,
+  rec: Option<Name>,
+  ctx: Ctx,
+  quasi: VecDeque<Term>,
+  open: &'static str,
+  close: &'static str,
 ) -> impl Fn(Span) -> IResult<Span, Vec<(Uses, Name, Term)>, ParseError<Span>> {
   move |i: Span| {
-    let (i, _) = tag("(")(i)?;
+    let (i, _) = tag(open)(i)?;
     let (i, _) = parse_space(i)?;
     let (i, u) = parse_uses(i)?;
     let (i, ns) = many1(terminated(parse_name, parse_space))(i)?;
@@ -313,7 +597,7 @@ pub fn parse_binder_full(
       ctx.clone(),
       quasi.to_owned(),
     )(i)?;
-    let (i, _) = tag(")")(i)?;
+    let (i, _) = tag(close)(i)?;
     let mut res = Vec::new();
     for (i, n) in ns.iter().enumerate() {
       res.push((u, n.to_owned(), typ.clone().shift(i as u64, 0)))
@@ -350,34 +634,46 @@ pub fn parse_binder(
   ctx: Ctx,
   quasi: VecDeque<Term>,
   nam_opt: bool,
-) -> impl Fn(Span) -> IResult<Span, Vec<(Uses, Name, Term)>, ParseError<Span>> {
+) -> impl Fn(Span) -> IResult<Span, Binders, ParseError<Span>> {
   move |i: Span| {
+    let explicit = |bs: Vec<(Uses, Name, Term)>| {
+      bs.into_iter().map(|(u, n, t)| (false, u, n, t)).collect()
+    };
+    let implicit = |bs: Vec<(Uses, Name, Term)>| {
+      bs.into_iter().map(|(u, n, t)| (true, u, n, t)).collect()
+    };
+    let full = parse_binder_full(
+      input,
+      defs.clone(),
+      rec.clone(),
+      ctx.clone(),
+      quasi.clone(),
+    );
+    let imp = parse_binder_implicit(
+      input,
+      defs.clone(),
+      rec.clone(),
+      ctx.clone(),
+      quasi.clone(),
+    );
     if nam_opt {
       alt((
-        parse_binder_full(
-          input,
-          defs.clone(),
-          rec.clone(),
-          ctx.clone(),
-          quasi.clone(),
-        ),
-        parse_binder_short(
-          input,
-          defs.to_owned(),
-          rec.clone(),
-          ctx.clone(),
-          quasi.to_owned(),
+        map(full, explicit),
+        map(imp, implicit),
+        map(
+          parse_binder_short(
+            input,
+            defs.to_owned(),
+            rec.clone(),
+            ctx.clone(),
+            quasi.to_owned(),
+          ),
+          explicit,
         ),
       ))(i)
     }
     else {
-      parse_binder_full(
-        input,
-        defs.to_owned(),
-        rec.clone(),
-        ctx.clone(),
-        quasi.to_owned(),
-      )(i)
+      alt((map(full, explicit), map(imp, implicit)))(i)
     }
   }
 }
@@ -389,8 +685,7 @@ pub fn parse_binders(
   ctx: Ctx,
   quasi: VecDeque<Term>,
   nam_opt: bool,
-) -> impl FnMut(Span) -> IResult<Span, Vec<(Uses, Name, Term)>, ParseError<Span>>
-{
+) -> impl FnMut(Span) -> IResult<Span, Binders, ParseError<Span>> {
   move |mut i: Span| {
     let mut ctx = ctx.as_ref().clone();
     let mut res = Vec::new();
@@ -406,9 +701,9 @@ pub fn parse_binders(
     {
       Err(e) => return Err(e),
       Ok((i1, bs)) => {
-        for (u, n, t) in bs {
+        for (b, u, n, t) in bs {
           ctx.push_front(n.to_owned());
-          res.push((u, n, t));
+          res.push((b, u, n, t));
         }
         i = i1;
       }
@@ -430,9 +725,9 @@ pub fn parse_binders(
         Err(Err::Error(_)) => return Ok((i, res)),
         Err(e) => return Err(e),
         Ok((i2, bs)) => {
-          for (u, n, t) in bs {
+          for (b, u, n, t) in bs {
             ctx.push_front(n.to_owned());
-            res.push((u, n, t));
+            res.push((b, u, n, t));
           }
           i = i2;
         }
@@ -463,7 +758,7 @@ pub fn parse_all(
     let (i, _) = tag("->")(i)?;
     let (i, _) = parse_space(i)?;
     let mut ctx2 = ctx.as_ref().clone();
-    for (_, n, _) in bs.iter() {
+    for (_, _, n, _) in bs.iter() {
       ctx2.push_front(n.clone());
     }
     let (upto, bod) = parse_expression(
@@ -474,10 +769,9 @@ pub fn parse_all(
       quasi.to_owned(),
     )(i)?;
     let pos = Pos::from_upto(input, from, upto);
-    let trm = bs
-      .into_iter()
-      .rev()
-      .fold(bod, |acc, (u, n, t)| Term::All(pos, u, n, Box::new((t, acc))));
+    let trm = bs.into_iter().rev().fold(bod, |acc, (b, u, n, t)| {
+      Term::All(pos, b, u, n, Box::new((t, acc)))
+    });
     Ok((upto, trm))
   }
 }
@@ -486,9 +780,27 @@ pub fn parse_type(
   input: Cid,
 ) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
   move |from: Span| {
-    let (upto, _) = tag("Type")(from)?;
+    let (i, _) = tag("Type")(from)?;
+    let (upto, lvl) = opt(digit1)(i)?;
+    let lvl = match lvl {
+      Some(lvl) => lvl.fragment().parse().map_err(|e| {
+        Err::Error(ParseError::new(i, ParseErrorKind::ParseIntErr(e)))
+      })?,
+      None => 0,
+    };
     let pos = Pos::from_upto(input, from, upto);
-    Ok((upto, Term::Typ(pos)))
+    Ok((upto, Term::Typ(pos, lvl)))
+  }
+}
+
+pub fn parse_hole(
+  input: Cid,
+) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
+  move |from: Span| {
+    let (i, _) = tag("?")(from)?;
+    let (upto, nam) = opt(parse_name)(i)?;
+    let pos = Pos::from_upto(input, from, upto);
+    Ok((upto, Term::Hol(pos, nam.unwrap_or_else(|| Name::from("")), 0)))
   }
 }
 
@@ -539,6 +851,105 @@ pub fn parse_case(
   }
 }
 
+pub fn parse_pattern_atom(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Pattern, ParseError<Span>> {
+  move |from: Span| {
+    if let Ok((i, _)) = terminated(tag("("), parse_space)(from) {
+      let (i, pat) = parse_pattern(input, defs.clone())(i)?;
+      let (upto, _) = preceded(parse_space, tag(")"))(i)?;
+      return Ok((upto, pat));
+    }
+    if let Ok((upto, Term::Lit(pos, lit))) = parse_lit(input)(from) {
+      return Ok((upto, Pattern::Lit(pos, lit)));
+    }
+    let (upto, nam) = parse_name(from)?;
+    let pos = Pos::from_upto(input, from, upto);
+    match find_constructor(&defs, &nam) {
+      Some((typ, idx)) => Ok((upto, Pattern::Ctor(pos, typ, idx, nam, vec![]))),
+      None => Ok((upto, Pattern::Var(pos, nam))),
+    }
+  }
+}
+
+/// Parses a pattern, either a variable, a literal or a constructor of a
+/// datatype applied to patterns for its fields
+pub fn parse_pattern(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Pattern, ParseError<Span>> {
+  move |from: Span| {
+    let (mut upto, pat) = parse_pattern_atom(input, defs.clone())(from)?;
+    match pat {
+      Pattern::Ctor(_, typ, idx, nam, _) => {
+        let mut args = vec![];
+        while let Ok((i, arg)) =
+          preceded(parse_space, parse_pattern_atom(input, defs.clone()))(upto)
+        {
+          args.push(arg);
+          upto = i;
+        }
+        let pos = Pos::from_upto(input, from, upto);
+        Ok((upto, Pattern::Ctor(pos, typ, idx, nam, args)))
+      }
+      pat => Ok((upto, pat)),
+    }
+  }
+}
+
+pub fn parse_match(
+  input: Cid,
+  defs: Defs,
+  rec: Option<Name>,
+  ctx: Ctx,
+  quasi: VecDeque<Term>,
+) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
+  move |from: Span| {
+    let (i, _) = tag("match")(from)?;
+    let (i, _) = parse_space(i)?;
+    let (i, scrutinee) =
+      parse_term(input, defs.clone(), rec.clone(), ctx.clone(), quasi.clone())(
+        i,
+      )?;
+    let (i, _) = parse_space(i)?;
+    let (mut i, _) = terminated(tag("{"), parse_space)(i)?;
+    let mut arms = vec![];
+    loop {
+      if let Ok((upto, _)) = tag::<&str, Span, ParseError<Span>>("}")(i) {
+        let pos = Pos::from_upto(input, from, upto);
+        return match compile_match(&defs, pos, scrutinee.clone(), arms) {
+          Ok(term) => Ok((upto, term)),
+          Err(err) => Err(Err::Error(ParseError::new(
+            upto,
+            ParseErrorKind::InvalidMatch(err),
+          ))),
+        };
+      }
+      let arm_from = i;
+      let (i2, pat) = parse_pattern(input, defs.clone())(i)?;
+      let (i2, _) = preceded(parse_space, tag("=>"))(i2)?;
+      let (i2, _) = parse_space(i2)?;
+      let mut ctx2 = ctx.as_ref().clone();
+      for nam in pat.vars() {
+        ctx2.push_front(nam);
+      }
+      let (upto, bod) = parse_expression(
+        input,
+        defs.clone(),
+        rec.clone(),
+        Rc::new(ctx2),
+        quasi.clone(),
+      )(i2)?;
+      let pos = Pos::from_upto(input, arm_from, upto);
+      arms.push(Arm { pos, pat, bod });
+      let (i2, _) = parse_space(upto)?;
+      let (i2, _) = opt(terminated(tag(","), parse_space))(i2)?;
+      i = i2;
+    }
+  }
+}
+
 pub fn parse_data(
   input: Cid,
   defs: Defs,
@@ -592,7 +1003,7 @@ pub fn parse_bound_expression(
     let (i, _) = tag(":")(i)?;
     let (i, _) = parse_space(i)?;
     let mut type_ctx = ctx.as_ref().clone();
-    for (_, n, _) in bs.iter() {
+    for (_, _, n, _) in bs.iter() {
       type_ctx.push_front(n.clone());
     }
     let (i, typ) = parse_expression(
@@ -602,11 +1013,24 @@ pub fn parse_bound_expression(
       Rc::new(type_ctx),
       quasi.clone(),
     )(i)?;
+    // A definition whose type has implicit parameters is added to the ones its
+    // value is parsed with, for its recursive references to find them
+    let mut term_defs = defs.clone();
+    if !letrec && rec.as_ref() == Some(&nam) && defs.get(&nam).is_none() {
+      let typ = bs.iter().rev().fold(typ.clone(), |acc, (b, u, n, t)| {
+        Term::All(Pos::None, *b, *u, n.clone(), Box::new((t.clone(), acc)))
+      });
+      if let Term::All(_, true, ..) = typ {
+        let hol = Term::Hol(Pos::None, Name::from(""), 0);
+        let (def, _) = Def::make(Pos::None, false, false, typ, hol);
+        term_defs.insert(nam.clone(), def);
+      }
+    }
     let mut term_ctx = ctx.as_ref().clone();
     if letrec {
       term_ctx.push_front(nam.clone());
     };
-    for (_, n, _) in bs.iter() {
+    for (_, _, n, _) in bs.iter() {
       term_ctx.push_front(n.clone());
     }
     let (i, _) = parse_space(i)?;
@@ -614,20 +1038,18 @@ pub fn parse_bound_expression(
     let (i, _) = parse_space(i)?;
     let (upto, trm) = parse_expression(
       input,
-      defs.clone(),
+      term_defs,
       rec.clone(),
       Rc::new(term_ctx),
       quasi.clone(),
     )(i)?;
     let pos = Pos::from_upto(input, from, upto);
-    let trm = bs
-      .iter()
-      .rev()
-      .fold(trm, |acc, (_, n, _)| Term::Lam(pos, n.clone(), Box::new(acc)));
-    let typ = bs
-      .into_iter()
-      .rev()
-      .fold(typ, |acc, (u, n, t)| Term::All(pos, u, n, Box::new((t, acc))));
+    let trm = bs.iter().rev().fold(trm, |acc, (_, _, n, _)| {
+      Term::Lam(pos, n.clone(), None, Box::new(acc))
+    });
+    let typ = bs.into_iter().rev().fold(typ, |acc, (b, u, n, t)| {
+      Term::All(pos, b, u, n, Box::new((t, acc)))
+    });
     Ok((upto, (typ, trm)))
   }
 }
@@ -680,6 +1102,8 @@ pub fn parse_builtin_symbol_end()
       peek(value((), eof)),
       peek(value((), tag("("))),
       peek(value((), tag(")"))),
+      peek(value((), tag("{"))),
+      peek(value((), tag("}"))),
       peek(value((), tag(";"))),
       peek(value((), tag(":"))),
       peek(value((), tag(","))),
@@ -719,6 +1143,22 @@ pub fn parse_lty(
     Ok((upto, Term::LTy(pos, lty)))
   }
 }
+pub fn parse_eql(
+  input: Cid,
+) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
+  move |from: Span| {
+    let (upto, op) = alt((
+      value(EqlOp::Eq, terminated(tag("#Eq"), parse_builtin_symbol_end())),
+      value(EqlOp::Refl, terminated(tag("refl"), parse_builtin_symbol_end())),
+      value(
+        EqlOp::Rewrite,
+        terminated(tag("rewrite"), parse_builtin_symbol_end()),
+      ),
+    ))(from)?;
+    let pos = Pos::from_upto(input, from, upto);
+    Ok((upto, insert_implicits(pos, &op.type_of(), Term::Eql(pos, op))))
+  }
+}
 // pub fn parse_exception()
 //-> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
 //  move |from: Span| {
@@ -767,6 +1207,27 @@ pub fn parse_expression(
       parse_apps(input, defs.clone(), rec.clone(), ctx.clone(), quasi.clone())(
         from,
       )?;
+    let (i, has_eq) = opt(terminated(tag("=="), parse_space))(i)?;
+    let (i, trm) = if has_eq.is_some() {
+      let (i, rhs) = context(
+        "right hand side of an equality",
+        parse_apps(
+          input,
+          defs.clone(),
+          rec.clone(),
+          ctx.clone(),
+          quasi.clone(),
+        ),
+      )(i)?;
+      let pos = Pos::from_upto(input, from, i);
+      let eq = Term::Eql(pos, EqlOp::Eq);
+      let eq = insert_implicits(pos, &EqlOp::Eq.type_of(), eq);
+      let lhs = Term::App(pos, Box::new((eq, trm)));
+      (i, Term::App(pos, Box::new((lhs, rhs))))
+    }
+    else {
+      (i, trm)
+    };
     let (i, has_ann) = opt(tag("::"))(i)?;
     if has_ann.is_some() {
       let (i, typ) = context(
@@ -791,12 +1252,20 @@ pub fn parse_expression(
 pub fn parse_app_end(i: Span) -> IResult<Span, (), ParseError<Span>> {
   let (i, _) = alt((
     peek(tag("def")),
+    peek(terminated(tag("type"), multispace1)),
+    peek(terminated(tag("record"), multispace1)),
+    peek(terminated(tag("partial"), multispace1)),
+    peek(terminated(tag("opaque"), multispace1)),
+    peek(terminated(tag("axiom"), multispace1)),
+    peek(terminated(tag("mutual"), multispace1)),
     peek(tag("open")),
     peek(tag("::")),
     peek(tag("=")),
     peek(tag("->")),
     peek(tag(";")),
+    peek(tag(",")),
     peek(tag(")")),
+    peek(tag("}")),
     peek(eof),
   ))(i)?;
   Ok((i, ()))
@@ -811,7 +1280,7 @@ pub fn parse_apps(
 ) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
   move |from: Span| {
     let (i2, _) = parse_space(from)?;
-    let (i2, fun) =
+    let (i2, mut fun) =
       parse_term(input, defs.clone(), rec.clone(), ctx.clone(), quasi.clone())(
         i2,
       )?;
@@ -828,6 +1297,28 @@ pub fn parse_apps(
           return Ok((i2, trm));
         }
         _ => {
+          let implicit_arg: IResult<Span, Term, ParseError<Span>> = delimited(
+            preceded(tag("{"), parse_space),
+            parse_expression(
+              input,
+              defs.clone(),
+              rec.clone(),
+              ctx.clone(),
+              quasi.clone(),
+            ),
+            preceded(parse_space, tag("}")),
+          )(i2);
+          if let Ok((i3, arg)) = implicit_arg {
+            // Implicit arguments can only be given before the explicit ones
+            if !args.is_empty() || fill_implicit(&mut fun, arg).is_err() {
+              return Err(Err::Error(ParseError::new(
+                i2,
+                ParseErrorKind::UnexpectedImplicitArg,
+              )));
+            }
+            i = i3;
+            continue;
+          }
           let (i2, arg) = parse_term(
             input,
             defs.clone(),
@@ -850,8 +1341,8 @@ pub fn parse_term(
   ctx: Ctx,
   quasi: VecDeque<Term>,
 ) -> impl Fn(Span) -> IResult<Span, Term, ParseError<Span>> {
-  move |i: Span| {
-    context(
+  move |from: Span| {
+    let (i, trm) = context(
       "term",
       alt((
         delimited(
@@ -892,17 +1383,34 @@ pub fn parse_term(
           ctx.clone(),
           quasi.clone(),
         ),
+        parse_match(
+          input,
+          defs.clone(),
+          rec.clone(),
+          ctx.clone(),
+          quasi.clone(),
+        ),
         parse_all(input, defs.clone(), rec.clone(), ctx.clone(), quasi.clone()),
         parse_lam(input, defs.clone(), rec.clone(), ctx.clone(), quasi.clone()),
         parse_let(input, defs.clone(), rec.clone(), ctx.clone(), quasi.clone()),
         parse_type(input),
         parse_lty(input),
+        parse_eql(input),
         parse_opr(input),
         parse_lit(input),
         parse_antiquote(ctx.clone(), quasi.clone()),
+        parse_hole(input),
+        parse_record(
+          input,
+          defs.clone(),
+          rec.clone(),
+          ctx.clone(),
+          quasi.clone(),
+        ),
         parse_var(input, defs.to_owned(), rec.clone(), ctx.clone()),
       )),
-    )(i)
+    )(from)?;
+    parse_projections(input, &defs, from, trm, i)
   }
 }
 
@@ -951,6 +1459,37 @@ pub mod tests {
   use super::*;
   use crate::term::tests::test_defs;
 
+  #[test]
+  fn suggest_references() {
+    let err = |src: &str| {
+      let res = crate::parse::package::parse_defs(input_cid(src), Defs::new())(
+        Span::new(src),
+      );
+      match res {
+        Err(Err::Error(err)) => err.errors[0].to_string(),
+        _ => panic!("{} parsed", src),
+      }
+    };
+    assert_eq!(
+      err("def List.map: #Nat = 1 def f: #Nat = List.mapp"),
+      "Undefined reference List.mapp. Did you mean List.map?"
+    );
+    assert_eq!(
+      err("def f (count: #Nat): #Nat = cuont"),
+      "Undefined reference cuont. Did you mean count?"
+    );
+    assert_eq!(
+      err("def f: #Nat = #Text.lenchars \"a\""),
+      "Unknown primitive Text operation #Text.lenchars. Did you mean \
+       #Text.len_chars?"
+    );
+    assert_eq!(
+      err("def f: #Nat = #Nat.sux 1"),
+      "Unknown primitive Nat operation #Nat.sux. Did you mean one of \
+       #Nat.sub, #Nat.suc?"
+    );
+  }
+
   #[test]
   fn test_parse_apps() {
     fn test(i: &str) -> IResult<Span, Term, ParseError<Span>> {
@@ -994,6 +1533,11 @@ pub mod tests {
     assert!(res.is_ok());
     let res = test("∀ (_ :Type) -> Type");
     assert!(res.is_ok());
+    let res = test("#Nat.add 1 1 == 2 :: Type");
+    assert!(res.is_ok());
+    assert_eq!(format!("{}", res.unwrap().1), "((#Nat.add 1 1) == 2) :: Type");
+    let res = test("rewrite (λ x => x == 1) refl refl");
+    assert!(res.is_ok());
   }
 
   #[test]
@@ -1002,7 +1546,7 @@ pub mod tests {
     fn test(
       nam_opt: bool,
       i: &str,
-    ) -> IResult<Span, Vec<(Uses, Name, Term)>, ParseError<Span>> {
+    ) -> IResult<Span, Binders, ParseError<Span>> {
       parse_binders(
         input_cid(i),
         Defs::new(),
@@ -1030,21 +1574,60 @@ pub mod tests {
     assert!(
       res.unwrap().1
         == vec![
-          (Uses::Many, Name::from(""), Typ(Pos::None)),
-          (Uses::Many, Name::from(""), Typ(Pos::None)),
+          (false, Uses::Many, Name::from(""), Typ(Pos::None, 0)),
+          (false, Uses::Many, Name::from(""), Typ(Pos::None, 0)),
         ]
     );
     let res = test(true, "(A: Type) (a b c: A)");
     assert!(res.is_ok());
+    let a = |i| Var(Pos::None, Name::from("A"), i);
     assert!(
       res.unwrap().1
         == vec![
-          (Uses::Many, Name::from("A"), Typ(Pos::None)),
-          (Uses::Many, Name::from("a"), Var(Pos::None, Name::from("A"), 0)),
-          (Uses::Many, Name::from("b"), Var(Pos::None, Name::from("A"), 1)),
-          (Uses::Many, Name::from("c"), Var(Pos::None, Name::from("A"), 2)),
+          (false, Uses::Many, Name::from("A"), Typ(Pos::None, 0)),
+          (false, Uses::Many, Name::from("a"), a(0)),
+          (false, Uses::Many, Name::from("b"), a(1)),
+          (false, Uses::Many, Name::from("c"), a(2)),
         ]
     );
+    let res = test(false, "{0 A: Type} (a: A)");
+    assert!(res.is_ok());
+    assert!(
+      res.unwrap().1
+        == vec![
+          (true, Uses::None, Name::from("A"), Typ(Pos::None, 0)),
+          (false, Uses::Many, Name::from("a"), a(0)),
+        ]
+    );
+  }
+
+  #[test]
+  fn test_parse_lam() {
+    use Term::*;
+    fn test(i: &str) -> IResult<Span, Term, ParseError<Span>> {
+      parse_lam(
+        input_cid(i),
+        Defs::new(),
+        None,
+        Rc::new(VecDeque::new()),
+        VecDeque::new(),
+      )(Span::new(i))
+    }
+    let res = test("λ (0 A: Type) x (y: A) => y");
+    assert!(res.is_ok());
+    let typ = Box::new(Typ(Pos::None, 0));
+    let a = Box::new(Var(Pos::None, Name::from("A"), 1));
+    let y = Box::new(Var(Pos::None, Name::from("y"), 0));
+    let lam = Lam(Pos::None, Name::from("y"), Some((Uses::Many, a)), y);
+    let lam = Lam(Pos::None, Name::from("x"), None, Box::new(lam));
+    let lam =
+      Lam(Pos::None, Name::from("A"), Some((Uses::None, typ)), Box::new(lam));
+    assert_eq!(res.unwrap().1, lam);
+    let res = test("λ (& a b: Type)=> a");
+    assert!(res.is_ok());
+    let (_, trm) = res.unwrap();
+    let (_, trm2) = parse(&format!("{}", trm), Defs::new()).unwrap();
+    assert_eq!(trm, trm2);
   }
 
   #[quickcheck]
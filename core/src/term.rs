@@ -1,6 +1,7 @@
 pub use crate::{
   anon::Anon,
   embed_error::EmbedError,
+  eql::EqlOp,
   literal::{
     LitType,
     Literal,
@@ -19,30 +20,50 @@ use std::fmt;
 #[derive(Clone, Debug)]
 pub enum Term {
   Var(Pos, Name, u64),
-  Lam(Pos, Name, Box<Term>),
+  Lam(Pos, Name, Option<(Uses, Box<Term>)>, Box<Term>),
   App(Pos, Box<(Term, Term)>),
-  All(Pos, Uses, Name, Box<(Term, Term)>),
+  All(Pos, bool, Uses, Name, Box<(Term, Term)>),
   Slf(Pos, Name, Box<Term>),
   Dat(Pos, Box<Term>),
   Cse(Pos, Box<Term>),
   Ref(Pos, Name, Cid, Cid),
   Let(Pos, bool, Uses, Name, Box<(Term, Term, Term)>),
-  Typ(Pos),
+  Typ(Pos, u64),
   Ann(Pos, Box<(Term, Term)>),
   Lit(Pos, Literal),
   LTy(Pos, LitType),
   Opr(Pos, Op),
+  // A constant of the identity type
+  Eql(Pos, EqlOp),
   Rec(Pos),
+  // A reference to the member of the enclosing `mutual` block at this index
+  Mut(Pos, Name, u64),
+  Hol(Pos, Name, u64),
+  // The body of an axiom, a constant with no definition, named here and
+  // identified by the anonymous content id of its type, so that renaming it
+  // doesn't change it. Axioms of the same type are then the same axiom, like
+  // definitions of the same term are the same definition.
+  Axm(Pos, Name, Cid),
+  // A record literal `{ x = a, y = b }`, the application of the record's
+  // constructor to the values of its fields, which are named here
+  Rcd(Pos, Vec<Name>, Box<Term>),
+  // A projection `r.x`, the application of the projection of the record, the
+  // first term, to `r`, the second. The projection of a field several records
+  // have is an anonymous hole, solved when checking by the projection of the
+  // record the type of `r` is an instance of.
+  Prj(Pos, Name, Box<(Term, Term)>),
 }
 
 impl PartialEq for Term {
   fn eq(&self, other: &Self) -> bool {
     match (self, other) {
       (Self::Var(_, na, ia), Self::Var(_, nb, ib)) => na == nb && ia == ib,
-      (Self::Lam(_, na, ba), Self::Lam(_, nb, bb)) => na == nb && ba == bb,
+      (Self::Lam(_, na, da, ba), Self::Lam(_, nb, db, bb)) => {
+        na == nb && da == db && ba == bb
+      }
       (Self::App(_, ta), Self::App(_, tb)) => ta.0 == tb.0 && ta.1 == tb.1,
-      (Self::All(_, ua, na, ta), Self::All(_, ub, nb, tb)) => {
-        ua == ub && na == nb && ta.0 == tb.0 && ta.1 == tb.1
+      (Self::All(_, ia, ua, na, ta), Self::All(_, ib, ub, nb, tb)) => {
+        ia == ib && ua == ub && na == nb && ta.0 == tb.0 && ta.1 == tb.1
       }
       (Self::Slf(_, na, ba), Self::Slf(_, nb, bb)) => na == nb && ba == bb,
       (Self::Dat(_, ba), Self::Dat(_, bb)) => ba == bb,
@@ -58,12 +79,18 @@ impl PartialEq for Term {
           && ta.1 == tb.1
           && ta.2 == tb.2
       }
-      (Self::Typ(_), Self::Typ(_)) => true,
+      (Self::Typ(_, la), Self::Typ(_, lb)) => la == lb,
       (Self::Rec(_), Self::Rec(_)) => true,
+      (Self::Mut(_, _, a), Self::Mut(_, _, b)) => a == b,
       (Self::Ann(_, ta), Self::Ann(_, tb)) => ta.0 == tb.0 && ta.1 == tb.1,
       (Self::Lit(_, a), Self::Lit(_, b)) => a == b,
       (Self::LTy(_, a), Self::LTy(_, b)) => a == b,
       (Self::Opr(_, a), Self::Opr(_, b)) => a == b,
+      (Self::Eql(_, a), Self::Eql(_, b)) => a == b,
+      (Self::Hol(_, a, _), Self::Hol(_, b, _)) => a == b,
+      (Self::Axm(_, na, a), Self::Axm(_, nb, b)) => na == nb && a == b,
+      (Self::Rcd(_, na, ta), Self::Rcd(_, nb, tb)) => na == nb && ta == tb,
+      (Self::Prj(_, na, ta), Self::Prj(_, nb, tb)) => na == nb && ta == tb,
       _ => false,
     }
   }
@@ -82,11 +109,17 @@ impl Term {
       Term::Dat(pos, _) => *pos,
       Term::Cse(pos, _) => *pos,
       Term::Let(pos, ..) => *pos,
-      Term::Typ(pos) => *pos,
+      Term::Typ(pos, _) => *pos,
       Term::LTy(pos, _) => *pos,
       Term::Lit(pos, _) => *pos,
       Term::Opr(pos, _) => *pos,
+      Term::Eql(pos, _) => *pos,
       Term::Rec(pos) => *pos,
+      Term::Mut(pos, ..) => *pos,
+      Term::Hol(pos, ..) => *pos,
+      Term::Axm(pos, ..) => *pos,
+      Term::Rcd(pos, ..) => *pos,
+      Term::Prj(pos, ..) => *pos,
     }
   }
 
@@ -94,14 +127,25 @@ impl Term {
     match self {
       Self::Var(pos, nam, idx) if idx < dep => Self::Var(pos, nam, idx),
       Self::Var(pos, nam, idx) => Self::Var(pos, nam, idx + inc),
-      Self::Lam(pos, nam, bod) => {
-        Self::Lam(pos, nam, Box::new((*bod).shift(inc, dep + 1)))
-      }
+      Self::Lam(pos, nam, dom, bod) => Self::Lam(
+        pos,
+        nam,
+        dom.map(|(uses, dom)| (uses, Box::new((*dom).shift(inc, dep)))),
+        Box::new((*bod).shift(inc, dep + 1)),
+      ),
       Self::Slf(pos, nam, bod) => {
         Self::Slf(pos, nam, Box::new((*bod).shift(inc, dep + 1)))
       }
       Self::Cse(pos, bod) => Self::Cse(pos, Box::new((*bod).shift(inc, dep))),
       Self::Dat(pos, bod) => Self::Dat(pos, Box::new((*bod).shift(inc, dep))),
+      Self::Rcd(pos, nams, bod) => {
+        Self::Rcd(pos, nams, Box::new((*bod).shift(inc, dep)))
+      }
+      Self::Prj(pos, nam, prj_arg) => {
+        let (prj, arg) = *prj_arg;
+        let prj_arg = (prj.shift(inc, dep), arg.shift(inc, dep));
+        Self::Prj(pos, nam, Box::new(prj_arg))
+      }
       Self::App(pos, fun_arg) => {
         let (fun, arg) = *fun_arg;
         Self::App(pos, Box::new((fun.shift(inc, dep), arg.shift(inc, dep))))
@@ -110,10 +154,11 @@ impl Term {
         let (typ, exp) = *typ_exp;
         Self::Ann(pos, Box::new((typ.shift(inc, dep), exp.shift(inc, dep))))
       }
-      Self::All(pos, uses, nam, dom_img) => {
+      Self::All(pos, imp, uses, nam, dom_img) => {
         let (dom, img) = *dom_img;
         Self::All(
           pos,
+          imp,
           uses,
           nam,
           Box::new((dom.shift(inc, dep), img.shift(inc, dep + 1))),
@@ -137,6 +182,51 @@ impl Term {
     }
   }
 
+  /// Replaces the subterms `f` gives a replacement for, outermost first
+  pub fn replace(&self, f: &impl Fn(&Term) -> Option<Term>) -> Term {
+    if let Some(term) = f(self) {
+      return term;
+    }
+    match self {
+      Self::Lam(pos, nam, dom, bod) => Self::Lam(
+        *pos,
+        nam.clone(),
+        dom.as_ref().map(|(uses, dom)| (*uses, Box::new(dom.replace(f)))),
+        Box::new(bod.replace(f)),
+      ),
+      Self::App(pos, fun_arg) => {
+        let fun_arg = (fun_arg.0.replace(f), fun_arg.1.replace(f));
+        Self::App(*pos, Box::new(fun_arg))
+      }
+      Self::All(pos, imp, uses, nam, dom_img) => {
+        let dom_img = (dom_img.0.replace(f), dom_img.1.replace(f));
+        Self::All(*pos, *imp, *uses, nam.clone(), Box::new(dom_img))
+      }
+      Self::Slf(pos, nam, bod) => {
+        Self::Slf(*pos, nam.clone(), Box::new(bod.replace(f)))
+      }
+      Self::Dat(pos, bod) => Self::Dat(*pos, Box::new(bod.replace(f))),
+      Self::Cse(pos, bod) => Self::Cse(*pos, Box::new(bod.replace(f))),
+      Self::Rcd(pos, nams, bod) => {
+        Self::Rcd(*pos, nams.clone(), Box::new(bod.replace(f)))
+      }
+      Self::Prj(pos, nam, prj_arg) => {
+        let prj_arg = (prj_arg.0.replace(f), prj_arg.1.replace(f));
+        Self::Prj(*pos, nam.clone(), Box::new(prj_arg))
+      }
+      Self::Let(pos, rec, uses, nam, typ_exp_bod) => {
+        let (typ, exp, bod) = &**typ_exp_bod;
+        let typ_exp_bod = (typ.replace(f), exp.replace(f), bod.replace(f));
+        Self::Let(*pos, *rec, *uses, nam.clone(), Box::new(typ_exp_bod))
+      }
+      Self::Ann(pos, typ_exp) => {
+        let typ_exp = (typ_exp.0.replace(f), typ_exp.1.replace(f));
+        Self::Ann(*pos, Box::new(typ_exp))
+      }
+      _ => self.clone(),
+    }
+  }
+
   pub fn embed(&self) -> (Anon, Meta) {
     match self {
       Self::Var(pos, name, idx) => {
@@ -148,14 +238,36 @@ impl Term {
       Self::Lit(pos, lit) => (Anon::Lit(lit.clone()), Meta::Lit(*pos)),
       Self::LTy(pos, lty) => (Anon::LTy(*lty), Meta::LTy(*pos)),
       Self::Opr(pos, opr) => (Anon::Opr(*opr), Meta::Opr(*pos)),
+      Self::Eql(pos, op) => (Anon::Eql(*op), Meta::Eql(*pos)),
       Self::Rec(pos) => (Anon::Rec, Meta::Rec(*pos)),
-      Self::Typ(pos) => (Anon::Typ, Meta::Typ(*pos)),
-      Self::Lam(pos, name, body) => {
+      Self::Mut(pos, name, idx) => {
+        (Anon::Mut(*idx), Meta::Mut(*pos, name.clone()))
+      }
+      Self::Hol(pos, name, _) => (Anon::Hol, Meta::Hol(*pos, name.clone())),
+      Self::Axm(pos, name, typ) => {
+        (Anon::Axm(*typ), Meta::Axm(*pos, name.clone()))
+      }
+      Self::Typ(pos, lvl) => (Anon::Typ(*lvl), Meta::Typ(*pos)),
+      Self::Lam(pos, name, dom, body) => {
         let (anon, meta) = (*body).embed();
-        (
-          Anon::Lam(Box::new(anon)),
-          Meta::Lam(*pos, name.clone(), Box::new(meta)),
-        )
+        match dom {
+          None => (
+            Anon::Lam(None, Box::new(anon)),
+            Meta::Lam(*pos, name.clone(), None, Box::new(meta)),
+          ),
+          Some((uses, dom)) => {
+            let (dom_anon, dom_meta) = (*dom).embed();
+            (
+              Anon::Lam(Some((*uses, Box::new(dom_anon))), Box::new(anon)),
+              Meta::Lam(
+                *pos,
+                name.clone(),
+                Some(Box::new(dom_meta)),
+                Box::new(meta),
+              ),
+            )
+          }
+        }
       }
       Self::Slf(pos, name, body) => {
         let (anon, meta) = (*body).embed();
@@ -188,12 +300,30 @@ impl Term {
         let (anon, meta) = (*body).embed();
         (Anon::Cse(Box::new(anon)), Meta::Cse(*pos, Box::new(meta)))
       }
-      Self::All(pos, uses, name, terms) => {
+      // Records are only sugar, which leaves no trace in the anonymous tree
+      Self::Rcd(pos, names, body) => {
+        let (anon, meta) = (*body).embed();
+        (anon, Meta::Rcd(*pos, names.clone(), Box::new(meta)))
+      }
+      // The meta of a projection wraps the meta of the application it is
+      Self::Prj(pos, name, terms) => {
+        let (prj_anon, prj_meta) = terms.0.embed();
+        let (arg_anon, arg_meta) = terms.1.embed();
+        (
+          Anon::App(Box::new((prj_anon, arg_anon))),
+          Meta::Prj(
+            *pos,
+            name.clone(),
+            Box::new(Meta::App(*pos, Box::new((prj_meta, arg_meta)))),
+          ),
+        )
+      }
+      Self::All(pos, imp, uses, name, terms) => {
         let (typ_anon, typ_meta) = terms.0.embed();
         let (bod_anon, bod_meta) = terms.1.embed();
         (
           Anon::All(*uses, Box::new((typ_anon, bod_anon))),
-          Meta::All(*pos, name.clone(), Box::new((typ_meta, bod_meta))),
+          Meta::All(*pos, *imp, name.clone(), Box::new((typ_meta, bod_meta))),
         )
       }
       Self::Let(pos, rec, uses, name, terms) => {
@@ -214,6 +344,18 @@ impl Term {
 
   pub fn unembed(anon: &Anon, meta: &Meta) -> Result<Self, EmbedError> {
     match (anon, meta) {
+      (anon, Meta::Rcd(pos, nams, meta)) => {
+        let bod = Term::unembed(anon, meta)?;
+        Ok(Self::Rcd(*pos, nams.clone(), Box::new(bod)))
+      }
+      (Anon::App(anons), Meta::Prj(pos, nam, app)) => match &**app {
+        Meta::App(_, metas) => {
+          let prj = Term::unembed(&anons.0, &metas.0)?;
+          let arg = Term::unembed(&anons.1, &metas.1)?;
+          Ok(Self::Prj(*pos, nam.clone(), Box::new((prj, arg))))
+        }
+        _ => Err(EmbedError::Term(anon.clone(), meta.clone())),
+      },
       (Anon::Var(idx), Meta::Var(pos, nam)) => {
         Ok(Self::Var(*pos, nam.clone(), *idx))
       }
@@ -223,11 +365,29 @@ impl Term {
       (Anon::Lit(lit), Meta::Lit(pos)) => Ok(Self::Lit(*pos, lit.clone())),
       (Anon::LTy(lty), Meta::LTy(pos)) => Ok(Self::LTy(*pos, *lty)),
       (Anon::Opr(opr), Meta::Opr(pos)) => Ok(Self::Opr(*pos, *opr)),
-      (Anon::Typ, Meta::Typ(pos)) => Ok(Self::Typ(*pos)),
+      (Anon::Eql(op), Meta::Eql(pos)) => Ok(Self::Eql(*pos, *op)),
+      (Anon::Typ(lvl), Meta::Typ(pos)) => Ok(Self::Typ(*pos, *lvl)),
       (Anon::Rec, Meta::Rec(pos)) => Ok(Self::Rec(*pos)),
-      (Anon::Lam(anon_bod), Meta::Lam(pos, nam, meta_bod)) => {
+      (Anon::Mut(idx), Meta::Mut(pos, nam)) => {
+        Ok(Self::Mut(*pos, nam.clone(), *idx))
+      }
+      (Anon::Hol, Meta::Hol(pos, nam)) => Ok(Self::Hol(*pos, nam.clone(), 0)),
+      (Anon::Axm(typ), Meta::Axm(pos, nam)) => {
+        Ok(Self::Axm(*pos, nam.clone(), *typ))
+      }
+      (
+        Anon::Lam(anon_dom, anon_bod),
+        Meta::Lam(pos, nam, meta_dom, meta_bod),
+      ) => {
+        let dom = match (anon_dom, meta_dom) {
+          (None, None) => None,
+          (Some((uses, anon_dom)), Some(meta_dom)) => {
+            Some((*uses, Box::new(Term::unembed(anon_dom, meta_dom)?)))
+          }
+          _ => return Err(EmbedError::Term(anon.clone(), meta.clone())),
+        };
         let bod = Term::unembed(anon_bod, meta_bod)?;
-        Ok(Self::Lam(*pos, nam.clone(), Box::new(bod)))
+        Ok(Self::Lam(*pos, nam.clone(), dom, Box::new(bod)))
       }
       (Anon::Slf(anon_bod), Meta::Slf(pos, nam, meta_bod)) => {
         let bod = Term::unembed(anon_bod, meta_bod)?;
@@ -255,12 +415,12 @@ impl Term {
         let exp = Term::unembed(exp_anon, exp_meta)?;
         Ok(Self::Ann(*pos, Box::new((typ, exp))))
       }
-      (Anon::All(uses, anon), Meta::All(pos, name, meta)) => {
+      (Anon::All(uses, anon), Meta::All(pos, imp, name, meta)) => {
         let (dom_anon, img_anon) = anon.as_ref();
         let (dom_meta, img_meta) = meta.as_ref();
         let dom = Term::unembed(dom_anon, dom_meta)?;
         let img = Term::unembed(img_anon, img_meta)?;
-        Ok(Self::All(*pos, *uses, name.clone(), Box::new((dom, img))))
+        Ok(Self::All(*pos, *imp, *uses, name.clone(), Box::new((dom, img))))
       }
       (Anon::Let(rec, uses, anon), Meta::Let(pos, name, meta)) => {
         let (typ_anon, exp_anon, bod_anon) = anon.as_ref();
@@ -296,42 +456,91 @@ impl Term {
     }
 
     fn is_atom(term: &Term) -> bool {
-      matches!(term, Var(..) | Ref(..) | Lit(..) | LTy(..) | Opr(..) | Typ(..))
+      matches!(
+        term,
+        Var(..)
+          | Ref(..)
+          | Mut(..)
+          | Lit(..)
+          | LTy(..)
+          | Opr(..)
+          | Eql(..)
+          | Typ(..)
+          | Hol(..)
+          | Axm(..)
+          | Rcd(..)
+          | Prj(..)
+      )
     }
 
-    fn lams(rec: Option<&String>, nam: &str, bod: &Term) -> String {
+    fn binder(
+      rec: Option<&String>,
+      nam: &str,
+      dom: &Option<(Uses, Box<Term>)>,
+    ) -> String {
+      match dom {
+        None => name(nam).to_string(),
+        Some((use_, dom)) => {
+          format!("({}{}: {})", uses(use_), name(nam), dom.pretty(rec))
+        }
+      }
+    }
+
+    fn lams(
+      rec: Option<&String>,
+      nam: &str,
+      dom: &Option<(Uses, Box<Term>)>,
+      bod: &Term,
+    ) -> String {
       match bod {
-        Lam(_, nam2, bod2) => {
-          format!("{} {}", name(nam), lams(rec, nam2, bod2))
+        Lam(_, nam2, dom2, bod2) => {
+          format!("{} {}", binder(rec, nam, dom), lams(rec, nam2, dom2, bod2))
         }
-        _ => format!("{} => {}", nam, bod.pretty(rec)),
+        _ => format!("{} => {}", binder(rec, nam, dom), bod.pretty(rec)),
       }
     }
 
     fn alls(
       rec: Option<&String>,
+      imp: bool,
       use_: &Uses,
       nam: &str,
       typ: &Term,
       bod: &Term,
     ) -> String {
+      let (open, close) = if imp { ("{", "}") } else { ("(", ")") };
+      let binder = format!(
+        " {}{}{}: {}{}",
+        open,
+        uses(use_),
+        name(nam),
+        typ.pretty(rec),
+        close
+      );
       match bod {
-        All(_, bod_use, bod_nam, bod) => {
-          format!(
-            " ({}{}: {}){}",
-            uses(use_),
-            name(nam),
-            typ.pretty(rec),
-            alls(rec, bod_use, bod_nam, &bod.0, &bod.1)
-          )
+        All(_, bod_imp, bod_use, bod_nam, bod) => {
+          let rest = alls(rec, *bod_imp, bod_use, bod_nam, &bod.0, &bod.1);
+          format!("{}{}", binder, rest)
+        }
+        _ => format!("{} -> {}", binder, bod.pretty(rec)),
+      }
+    }
+
+    // Whether a term is `#Eq A`, so that applying it to two sides is printed
+    // as `a == b`
+    fn is_eq(term: &Term) -> bool {
+      matches!(term, App(_, eq_typ) if matches!(eq_typ.0, Eql(_, EqlOp::Eq)))
+    }
+
+    // The arguments of an application spine, the last one first
+    fn args(term: &Term) -> Vec<&Term> {
+      match term {
+        App(_, fun_arg) => {
+          let mut spine = vec![&fun_arg.1];
+          spine.extend(args(&fun_arg.0));
+          spine
         }
-        _ => format!(
-          " ({}{}: {}) -> {}",
-          uses(use_),
-          name(nam),
-          typ.pretty(rec),
-          bod.pretty(rec)
-        ),
+        _ => vec![],
       }
     }
 
@@ -364,13 +573,19 @@ impl Term {
     match self {
       Var(_, nam, ..) => nam.to_string(),
       Ref(_, nam, ..) => nam.to_string(),
+      Mut(_, nam, _) => nam.to_string(),
       Rec(_) => match rec {
         Some(rec) => rec.to_owned(),
         _ => "#^".to_string(),
       },
 
-      Lam(_, nam, term) => format!("λ {}", lams(rec, nam, term)),
-      App(_, terms) => apps(rec, &terms.0, &terms.1),
+      Lam(_, nam, dom, term) => format!("λ {}", lams(rec, nam, dom, term)),
+      App(_, terms) => match &terms.0 {
+        App(_, eq_lhs) if is_eq(&eq_lhs.0) => {
+          format!("{} == {}", parens(rec, &eq_lhs.1), parens(rec, &terms.1))
+        }
+        _ => apps(rec, &terms.0, &terms.1),
+      },
       Let(_, letrec, u, n, terms) => {
         format!(
           "let{} {}{}: {} = {}; {}",
@@ -383,18 +598,32 @@ impl Term {
         )
       }
       Slf(_, nam, bod) => format!("@{} {}", name(nam), bod.pretty(rec)),
-      All(_, us_, nam, terms) => {
-        format!("∀{}", alls(rec, us_, nam, &terms.0, &terms.1))
+      All(_, imp, us_, nam, terms) => {
+        format!("∀{}", alls(rec, *imp, us_, nam, &terms.0, &terms.1))
       }
       Ann(_, terms) => {
         format!("{} :: {}", parens(rec, &terms.1), parens(rec, &terms.0))
       }
       Dat(_, bod) => format!("data {}", bod.pretty(rec)),
       Cse(_, bod) => format!("case {}", bod.pretty(rec)),
-      Typ(_) => "Type".to_string(),
+      Typ(_, 0) => "Type".to_string(),
+      Typ(_, lvl) => format!("Type{}", lvl),
       Lit(_, lit) => format!("{}", lit),
       LTy(_, lty) => format!("{}", lty),
       Opr(_, opr) => format!("{}", opr),
+      Eql(_, op) => format!("{}", op),
+      Hol(_, nam, _) => format!("?{}", nam),
+      Axm(_, nam, _) => format!("axiom {}", nam),
+      Rcd(_, nams, bod) => {
+        let vals = args(bod);
+        let fields: Vec<String> = nams
+          .iter()
+          .zip(vals.iter().take(nams.len()).rev())
+          .map(|(nam, val)| format!("{} = {}", nam, val.pretty(rec)))
+          .collect();
+        format!("{{ {} }}", fields.join(", "))
+      }
+      Prj(_, nam, prj_arg) => format!("{}.{}", parens(rec, &prj_arg.1), nam),
     }
   }
 }
@@ -445,7 +674,7 @@ pub mod tests {
   fn arbitrary_var(ctx: VecDeque<Name>) -> Box<dyn Fn(&mut Gen) -> Term> {
     Box::new(move |_g: &mut Gen| {
       if ctx.len() == 0 {
-        return Term::Typ(Pos::None);
+        return Term::Typ(Pos::None, 0);
       }
       let mut rng = rand::thread_rng();
       let gen = rng.gen_range(0..ctx.len());
@@ -459,16 +688,22 @@ pub mod tests {
     let mut defs = Defs::new();
     let (id, _) = Def::make(
       Pos::None,
+      false,
+      false,
       yatima!("∀ (A: Type) (x: A) -> A"),
       yatima!("λ A x => x"),
     );
     let (fst, _) = Def::make(
       Pos::None,
+      false,
+      false,
       yatima!("∀ (A: Type) (x y: A) -> A"),
       yatima!("λ A x y => x"),
     );
     let (snd, _) = Def::make(
       Pos::None,
+      false,
+      false,
       yatima!("∀ (A: Type) (x y: A) -> A"),
       yatima!("λ A x y => y"),
     );
@@ -494,7 +729,7 @@ pub mod tests {
       let len = refs.len();
       println!("len {}", len);
       if len == 0 {
-        return Term::Typ(Pos::None);
+        return Term::Typ(Pos::None, 0);
       }
       let gen = rng.gen_range(0..(len - 1));
       println!("gen {:?}", gen);
@@ -507,6 +742,7 @@ pub mod tests {
   }
 
   fn arbitrary_lam(
+    depth: u64,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
@@ -515,11 +751,17 @@ pub mod tests {
       let n = arbitrary_name(g);
       let mut ctx2 = ctx.clone();
       ctx2.push_front(n.clone());
-      Lam(Pos::None, n, Box::new(arbitrary_term(g, rec, defs.clone(), ctx2)))
+      Lam(
+        Pos::None,
+        n,
+        None,
+        Box::new(arbitrary_term_at(g, depth, rec, defs.clone(), ctx2)),
+      )
     })
   }
 
   fn arbitrary_app(
+    depth: u64,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
@@ -528,14 +770,15 @@ pub mod tests {
       Term::App(
         Pos::None,
         Box::new((
-          arbitrary_term(g, rec, defs.clone(), ctx.clone()),
-          arbitrary_term(g, rec, defs.clone(), ctx.clone()),
+          arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone()),
+          arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone()),
         )),
       )
     })
   }
 
   fn arbitrary_ann(
+    depth: u64,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
@@ -544,14 +787,15 @@ pub mod tests {
       Term::Ann(
         Pos::None,
         Box::new((
-          arbitrary_term(g, rec, defs.clone(), ctx.clone()),
-          arbitrary_term(g, rec, defs.clone(), ctx.clone()),
+          arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone()),
+          arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone()),
         )),
       )
     })
   }
 
   fn arbitrary_slf(
+    depth: u64,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
@@ -560,11 +804,13 @@ pub mod tests {
       let n = arbitrary_name(g);
       let mut ctx2 = ctx.clone();
       ctx2.push_front(n.clone());
-      Slf(Pos::None, n, Box::new(arbitrary_term(g, rec, defs.clone(), ctx2)))
+      let bod = arbitrary_term_at(g, depth, rec, defs.clone(), ctx2);
+      Slf(Pos::None, n, Box::new(bod))
     })
   }
 
   fn arbitrary_dat(
+    depth: u64,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
@@ -572,12 +818,13 @@ pub mod tests {
     Box::new(move |g: &mut Gen| {
       Term::Dat(
         Pos::None,
-        Box::new(arbitrary_term(g, rec, defs.clone(), ctx.clone())),
+        Box::new(arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone())),
       )
     })
   }
 
   fn arbitrary_cse(
+    depth: u64,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
@@ -585,88 +832,110 @@ pub mod tests {
     Box::new(move |g: &mut Gen| {
       Term::Cse(
         Pos::None,
-        Box::new(arbitrary_term(g, rec, defs.clone(), ctx.clone())),
+        Box::new(arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone())),
       )
     })
   }
 
   fn arbitrary_all(
+    depth: u64,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
   ) -> Box<dyn Fn(&mut Gen) -> Term> {
     Box::new(move |g: &mut Gen| {
       let n = arbitrary_name(g);
+      let i: bool = Arbitrary::arbitrary(g);
       let u: Uses = Arbitrary::arbitrary(g);
       let mut ctx2 = ctx.clone();
       ctx2.push_front(n.clone());
       All(
         Pos::None,
+        i,
         u,
         n,
         Box::new((
-          arbitrary_term(g, rec, defs.clone(), ctx.clone()),
-          arbitrary_term(g, rec, defs.clone(), ctx2),
+          arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone()),
+          arbitrary_term_at(g, depth, rec, defs.clone(), ctx2),
         )),
       )
     })
   }
 
   fn arbitrary_let(
+    depth: u64,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
   ) -> Box<dyn Fn(&mut Gen) -> Term> {
     Box::new(move |g: &mut Gen| {
-      // let rec: bool = Arbitrary::arbitrary(g);
-      let letrec: bool = false;
+      let letrec: bool = Arbitrary::arbitrary(g);
       let n = arbitrary_name(g);
       let u: Uses = Arbitrary::arbitrary(g);
-      let typ = arbitrary_term(g, rec, defs.clone(), ctx.clone());
+      let typ = arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone());
       if letrec {
         let mut ctx2 = ctx.clone();
         ctx2.push_front(n.clone());
-        let exp = arbitrary_term(g, rec, defs.clone(), ctx2.clone());
-        let bod = arbitrary_term(g, rec, defs.clone(), ctx2);
+        let exp = arbitrary_term_at(g, depth, rec, defs.clone(), ctx2.clone());
+        let bod = arbitrary_term_at(g, depth, rec, defs.clone(), ctx2);
         Let(Pos::None, letrec, u, n, Box::new((typ, exp, bod)))
       }
       else {
         let mut ctx2 = ctx.clone();
         ctx2.push_front(n.clone());
-        let exp = arbitrary_term(g, rec, defs.clone(), ctx.clone());
-        let bod = arbitrary_term(g, rec, defs.clone(), ctx2);
+        let exp = arbitrary_term_at(g, depth, rec, defs.clone(), ctx.clone());
+        let bod = arbitrary_term_at(g, depth, rec, defs.clone(), ctx2);
         Let(Pos::None, letrec, u, n, Box::new((typ, exp, bod)))
       }
     })
   }
 
+  // How deep the generated terms nest, so that printing, parsing and embedding
+  // them can't overflow the stack
+  const MAX_DEPTH: u64 = 12;
+
   pub fn arbitrary_term(
     g: &mut Gen,
     rec: bool,
     defs: Defs,
     ctx: VecDeque<Name>,
+  ) -> Term {
+    arbitrary_term_at(g, MAX_DEPTH, rec, defs, ctx)
+  }
+
+  // Generates a term whose subterms nest at most `depth` times
+  fn arbitrary_term_at(
+    g: &mut Gen,
+    depth: u64,
+    rec: bool,
+    defs: Defs,
+    ctx: VecDeque<Name>,
   ) -> Term {
     let len = ctx.len();
+    let dep = depth.saturating_sub(1);
+    // At the maximum depth only the terms without subterms are generated
+    let nest = |weight| if depth > 0 { weight } else { 0 };
     if len == 0 {
-      arbitrary_lam(rec, defs, ctx)(g)
+      arbitrary_lam(dep, rec, defs, ctx)(g)
     }
     else {
       frequency(g, vec![
         (100, arbitrary_var(ctx.clone())),
         (100, arbitrary_ref(defs.clone(), ctx.clone())),
-        (100, Box::new(|_| Term::Typ(Pos::None))),
+        (100, Box::new(|g| Term::Typ(Pos::None, u64::arbitrary(g) % 3))),
         (if rec { 100 } else { 0 }, Box::new(|_| Term::Rec(Pos::None))),
         (100, Box::new(|g| Term::Lit(Pos::None, Arbitrary::arbitrary(g)))),
         (100, Box::new(|g| Term::LTy(Pos::None, Arbitrary::arbitrary(g)))),
         (100, Box::new(|g| Term::Opr(Pos::None, Arbitrary::arbitrary(g)))),
-        (90, arbitrary_lam(rec, defs.clone(), ctx.clone())),
-        (90, arbitrary_dat(rec, defs.clone(), ctx.clone())),
-        (90, arbitrary_cse(rec, defs.clone(), ctx.clone())),
-        (90, arbitrary_slf(rec, defs.clone(), ctx.clone())),
-        (80, arbitrary_all(rec, defs.clone(), ctx.clone())),
-        (80, arbitrary_app(rec, defs.clone(), ctx.clone())),
-        (80, arbitrary_ann(rec, defs.clone(), ctx.clone())),
-        (30, arbitrary_let(rec, defs.clone(), ctx.clone())),
+        (10, Box::new(|g| Term::Hol(Pos::None, arbitrary_name(g), 0))),
+        (nest(90), arbitrary_lam(dep, rec, defs.clone(), ctx.clone())),
+        (nest(90), arbitrary_dat(dep, rec, defs.clone(), ctx.clone())),
+        (nest(90), arbitrary_cse(dep, rec, defs.clone(), ctx.clone())),
+        (nest(90), arbitrary_slf(dep, rec, defs.clone(), ctx.clone())),
+        (nest(80), arbitrary_all(dep, rec, defs.clone(), ctx.clone())),
+        (nest(80), arbitrary_app(dep, rec, defs.clone(), ctx.clone())),
+        (nest(80), arbitrary_ann(dep, rec, defs.clone(), ctx.clone())),
+        (nest(30), arbitrary_let(dep, rec, defs.clone(), ctx.clone())),
       ])
     }
   }
@@ -1,6 +1,7 @@
 use crate::{
   dag::*,
   dll::*,
+  eval::stats,
 };
 
 use core::ptr::NonNull;
@@ -98,6 +99,7 @@ pub fn clean_up(cc: &ParentPtr) {
 
 // The core up-copy function.
 pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
+  stats::record(|stats| stats.upcopies += 1);
   unsafe {
     match cc {
       ParentPtr::LamBod(link) => {
@@ -207,13 +209,13 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
         }
       }
       ParentPtr::AllDom(link) => {
-        let All { copy, uses, img, parents, .. } = link.as_ref();
+        let All { copy, imp, uses, img, parents, .. } = link.as_ref();
         match copy {
           Some(cache) => {
             (*cache.as_ptr()).dom = new_child;
           }
           None => {
-            let new_all = alloc_all(*uses, new_child, *img, None);
+            let new_all = alloc_all(*imp, *uses, new_child, *img, None);
             (*link.as_ptr()).copy = Some(new_all);
             for parent in DLL::iter_option(*parents) {
               upcopy(DAGPtr::All(new_all), *parent)
@@ -222,7 +224,7 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
         }
       }
       ParentPtr::AllImg(link) => {
-        let All { copy, uses, dom, parents, .. } = link.as_ref();
+        let All { copy, imp, uses, dom, parents, .. } = link.as_ref();
         let new_child = match new_child {
           DAGPtr::Lam(link) => link,
           _ => panic!("Cannot install a non-lambda node as image"),
@@ -232,7 +234,7 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
             (*cache.as_ptr()).img = new_child;
           }
           None => {
-            let new_all = alloc_all(*uses, *dom, new_child, None);
+            let new_all = alloc_all(*imp, *uses, *dom, new_child, None);
             (*link.as_ptr()).copy = Some(new_all);
             for parent in DLL::iter_option(*parents) {
               upcopy(DAGPtr::All(new_all), *parent)
@@ -241,7 +243,7 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
         }
       }
       ParentPtr::LetExp(link) => {
-        let Let { copy, uses, var, typ, bod, parents, .. } = link.as_ref();
+        let Let { copy, uses, rec, var, typ, bod, parents, .. } = link.as_ref();
         match copy {
           Some(cache) => {
             (*cache.as_ptr()).exp = new_child;
@@ -252,9 +254,10 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
               nam.clone(),
               *dep,
               None,
+              *rec,
               *uses,
-              new_child,
               *typ,
+              new_child,
               *bod,
               None,
             );
@@ -270,7 +273,7 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
         }
       }
       ParentPtr::LetTyp(link) => {
-        let Let { copy, uses, var, exp, bod, parents, .. } = link.as_ref();
+        let Let { copy, uses, rec, var, exp, bod, parents, .. } = link.as_ref();
         match copy {
           Some(cache) => {
             (*cache.as_ptr()).typ = new_child;
@@ -281,9 +284,10 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
               nam.clone(),
               *dep,
               None,
+              *rec,
               *uses,
-              *exp,
               new_child,
+              *exp,
               *bod,
               None,
             );
@@ -299,7 +303,7 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
         }
       }
       ParentPtr::LetBod(link) => {
-        let Let { copy, uses, var, typ, exp, parents, .. } = link.as_ref();
+        let Let { copy, uses, rec, var, typ, exp, parents, .. } = link.as_ref();
         match copy {
           Some(cache) => {
             (*cache.as_ptr()).bod = new_child;
@@ -310,9 +314,10 @@ pub fn upcopy(new_child: DAGPtr, cc: ParentPtr) {
               nam.clone(),
               *dep,
               None,
+              *rec,
               *uses,
-              *exp,
               *typ,
+              *exp,
               new_child,
               None,
             );
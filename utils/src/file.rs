@@ -1,69 +1,314 @@
 use sp_ipld::Ipld;
 use std::{
+  collections::HashSet,
   path::PathBuf,
   rc::Rc,
 };
 use yatima_core::{
-  check::error::CheckError,
+  check::{
+    check_def_all,
+    quantity::{
+      infer_uses,
+      is_quantity_error,
+      BinderUses,
+    },
+    CheckOpts,
+  },
   defs::Defs,
+  lint::{
+    lint_package,
+    Lint,
+  },
+  name::Name,
   position::Pos,
 };
 
-use crate::store::Store;
+use crate::{
+  file::{
+    error::Diagnostic,
+    quantity::Edit,
+  },
+  store::Store,
+};
 
+pub mod cache;
 pub mod error;
 pub mod parse;
+pub mod quantity;
 
-pub fn check_all(path: PathBuf, store: Rc<dyn Store>) -> std::io::Result<Defs> {
+/// Parses and checks a package, printing the outcome for each definition as
+/// it goes. Rather than stopping at the first error, every parse and check
+/// error in the package, and every hole left in it, is collected and returned,
+/// to be reported together with `print_diagnostics`. Unless `cache` is false,
+/// the definitions recorded in the store to have checked before are not
+/// checked again, and the outcomes of the others are recorded.
+pub fn check_all(
+  path: PathBuf,
+  store: Rc<dyn Store>,
+  opts: CheckOpts,
+  cache: bool,
+) -> std::io::Result<(Defs, Vec<Diagnostic>)> {
   let root = std::env::current_dir()?;
   let env = parse::PackageEnv::new(root, path, store.clone());
-  let (_, p, ds) = parse::parse_file(env);
+  let (_, p, ds) = parse::parse_file(env.clone());
+  let mut diagnostics = env.diagnostics();
   let cid = store.put(p.to_ipld());
   // let _ipld_cid =
   //  ipfs::dag_put(p.to_ipld()).await.expect("Failed to put to ipfs.");
   println!("Checking package {} at {}", p.name, cid);
+  let check = |name: &str| {
+    if cache {
+      cache::check_def_cached(&store, &ds, name, opts)
+    }
+    else {
+      (check_def_all(&ds, name, opts), false)
+    }
+  };
   for i in &p.imports {
     println!("Checking import  {} at {}", i.name, i.cid);
     for n in &i.with {
-      match yatima_core::check::check_def(
-        &ds,
-        &yatima_core::package::import_alias(n.to_owned(), &i),
-      ) {
-        Ok(ty) => println!("✓ {}: {}", n, ty.pretty(Some(&n.to_string()))),
-        Err(e @ CheckError::UndefinedReference(Pos::None, _)) => {
-          println!("✕ {}: {}", n, e);
+      let name = yatima_core::package::import_alias(n.to_owned(), &i);
+      match check(&name) {
+        (Ok((_, holes)), _) if !holes.is_empty() => {
+          print_failed(&ds, &name, holes.len(), "hole");
+          diagnostics.extend(holes.into_iter().map(|hole| {
+            Diagnostic::Hole { name: name.clone(), hole }
+          }));
         }
-        Err(err) => {
-          let def = ds.get(n).unwrap();
-          println!("✕ {}: {}", n, def.typ_.pretty(Some(&n.to_string())));
-          if let Pos::Some(pos) = err.pos() {
-            if let Some(Ipld::String(input)) = store.get(pos.input) {
-              println!("{}", pos.range(input))
-            }
-          }
-          print!("Error: {}", err);
+        (Ok((ty, _)), cached) => {
+          let cached = if cached { " (cached)" } else { "" };
+          println!("✓ {}{}: {}", n, cached, ty.pretty(Some(&n.to_string())));
+        }
+        (Err(errs), _) => {
+          print_failed(&ds, &name, errs.len(), "error");
+          diagnostics.extend(errs.into_iter().map(|error| {
+            Diagnostic::Check { name: name.clone(), error }
+          }));
         }
       }
     }
   }
   println!("Checking definitions:");
   for (n, _) in &p.index.0 {
-    match yatima_core::check::check_def(&ds, n) {
-      Ok(ty) => println!("✓ {}: {}", n, ty.pretty(Some(&n.to_string()))),
-      Err(e @ CheckError::UndefinedReference(Pos::None, _)) => {
-        println!("✕ {}: {}", n, e);
+    match check(n) {
+      // A definition is only accepted once its holes are filled in
+      (Ok((_, holes)), _) if !holes.is_empty() => {
+        print_failed(&ds, n, holes.len(), "hole");
+        diagnostics.extend(
+          holes
+            .into_iter()
+            .map(|hole| Diagnostic::Hole { name: n.clone(), hole }),
+        );
       }
-      Err(err) => {
+      (Ok((ty, _)), cached) => {
         let def = ds.get(n).unwrap();
-        println!("✕ {}: {}", n, def.typ_.pretty(Some(&n.to_string())));
-        if let Pos::Some(pos) = err.pos() {
+        // Partial definitions are accepted without checking termination, and
+        // opaque ones are never unfolded while checking the others
+        let marks: String = [
+          (def.partial, " (partial)"),
+          (def.opaque, " (opaque)"),
+          (def.is_axiom(), " (axiom)"),
+          (cached, " (cached)"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, mark)| *mark)
+        .collect();
+        println!("✓ {}{}: {}", n, marks, ty.pretty(Some(&n.to_string())));
+        let axioms = ds.axioms(def);
+        if !axioms.is_empty() {
+          let axioms: Vec<String> =
+            axioms.iter().map(|name| name.to_string()).collect();
+          println!("  depends on axioms: {}", axioms.join(", "));
+        }
+      }
+      (Err(errs), _) => {
+        print_failed(&ds, n, errs.len(), "error");
+        if errs.iter().any(is_quantity_error) {
+          if let Some(binders) = infer_uses(&ds, n, opts) {
+            print_uses(&binders);
+          }
+        }
+        diagnostics.extend(
+          errs
+            .into_iter()
+            .map(|error| Diagnostic::Check { name: n.clone(), error }),
+        );
+      }
+    }
+  }
+  Ok((ds, diagnostics))
+}
+
+// Prints the line of a definition that failed to check, or left holes,
+// whose `count` errors or holes are reported later on with all the others
+fn print_failed(ds: &Defs, n: &Name, count: usize, kind: &str) {
+  match ds.get(n) {
+    Some(def) => {
+      let plural = if count == 1 { "" } else { "s" };
+      println!(
+        "✕ {}: {} ({} {}{})",
+        n,
+        def.typ_.pretty(Some(&n.to_string())),
+        count,
+        kind,
+        plural
+      );
+    }
+    None => println!("✕ {}: undefined", n),
+  }
+}
+
+// Prints the quantities inferred for the binders of a definition that differ
+// from the ones written
+fn print_uses(binders: &[BinderUses]) {
+  for b in binders.iter().filter(|b| b.written != b.inferred) {
+    println!(
+      "  `{}` can have quantity {} instead of {}",
+      b.name, b.inferred, b.written
+    );
+  }
+}
+
+/// Infers the tightest quantities of the parameters and `let` binders of the
+/// definitions of a package, and rewrites the ones that differ in its source
+/// file. Only definitions that check, or fail to only because of quantities,
+/// are changed, and not the ones whose new types some other definition of the
+/// package doesn't check against.
+pub fn suggest_uses(
+  path: PathBuf,
+  store: Rc<dyn Store>,
+  opts: CheckOpts,
+) -> std::io::Result<()> {
+  let root = std::env::current_dir()?;
+  let src = std::fs::read_to_string(&path)?;
+  let env = parse::PackageEnv::new(root.clone(), path.clone(), store.clone());
+  let (_, p, ds) = parse::parse_text(src.clone(), env.clone());
+  print_diagnostics(&env.diagnostics(), store.clone());
+  let mut suggested = Vec::new();
+  let mut expected = HashSet::new();
+  for (n, _) in &p.index.0 {
+    if check_def_all(&ds, n, opts).is_ok() {
+      expected.insert(n.clone());
+    }
+    let binders = match infer_uses(&ds, n, opts) {
+      Some(binders) => binders,
+      None => continue,
+    };
+    expected.insert(n.clone());
+    let def = ds.get(n).unwrap();
+    let def_edits = quantity::uses_edits(&src, def, &binders);
+    if !def_edits.is_empty() {
+      suggested.push((n.clone(), binders, def_edits));
+    }
+  }
+  // Each definition is tightened on its own, so the package is checked again
+  // with all the edits applied, dropping those of the definitions that the
+  // ones which no longer check depend on one at a time
+  let new_src = loop {
+    let edits: Vec<Edit> =
+      suggested.iter().flat_map(|(_, _, edits)| edits.clone()).collect();
+    let new_src = quantity::apply_edits(&src, &edits);
+    if edits.is_empty() {
+      break new_src;
+    }
+    let env = parse::PackageEnv::new(root.clone(), path.clone(), store.clone());
+    let (_, _, new_ds) = parse::parse_text(new_src.clone(), env);
+    let broken: Vec<&Name> = expected
+      .iter()
+      .filter(|n| check_def_all(&new_ds, n, opts).is_err())
+      .collect();
+    if broken.is_empty() {
+      break new_src;
+    }
+    let deps: HashSet<Name> = broken
+      .iter()
+      .filter_map(|n| new_ds.get(n))
+      .flat_map(|def| new_ds.dependencies(def))
+      .map(|(n, _)| n)
+      .collect();
+    let drop = suggested
+      .iter()
+      .position(|(n, ..)| deps.contains(n))
+      .or_else(|| suggested.iter().position(|(n, ..)| broken.contains(&n)));
+    match drop {
+      Some(i) => {
+        suggested.remove(i);
+      }
+      None => suggested.clear(),
+    }
+  };
+  if suggested.is_empty() {
+    println!("No quantities to change in {}", path.to_string_lossy());
+    return Ok(());
+  }
+  for (n, binders, _) in &suggested {
+    println!("{}:", n);
+    print_uses(binders);
+  }
+  std::fs::write(&path, new_src)?;
+  println!("Rewrote {}", path.to_string_lossy());
+  Ok(())
+}
+
+/// Parses a package and lints its definitions, printing each lint with its
+/// source range. Parse errors are printed first, and the definitions that
+/// parsed are linted regardless.
+pub fn lint(
+  path: PathBuf,
+  store: Rc<dyn Store>,
+  opts: CheckOpts,
+) -> std::io::Result<Vec<(Name, Lint)>> {
+  let root = std::env::current_dir()?;
+  let env = parse::PackageEnv::new(root, path, store.clone());
+  let (_, p, ds) = parse::parse_file(env.clone());
+  print_diagnostics(&env.diagnostics(), store.clone());
+  println!("Linting package {}", p.name);
+  let lints = lint_package(&ds, &p.index, opts);
+  for (name, lint) in &lints {
+    if let Pos::Some(pos) = lint.pos() {
+      if let Some(Ipld::String(input)) = store.get(pos.input) {
+        println!("{}", pos.range(input))
+      }
+    }
+    println!("{}: {}", name, lint);
+  }
+  let warnings = if lints.len() == 1 { "warning" } else { "warnings" };
+  println!("{} {} found", lints.len(), warnings);
+  Ok(lints)
+}
+
+/// Prints every diagnostic of a package together, followed by their count.
+/// Check errors are shown with their labelled source ranges, and holes with
+/// theirs, the text of which is looked up in the store, so that errors in
+/// imported packages point into their own files.
+pub fn print_diagnostics(diagnostics: &[Diagnostic], store: Rc<dyn Store>) {
+  if diagnostics.is_empty() {
+    return;
+  }
+  println!("Errors:");
+  for diagnostic in diagnostics {
+    match diagnostic {
+      Diagnostic::Check { error, .. } => {
+        for (pos, label) in error.labels() {
+          if let Pos::Some(pos) = pos {
+            if let Some(Ipld::String(input)) = store.get(pos.input) {
+              println!("{} {}", pos.range(input), label)
+            }
+          }
+        }
+      }
+      Diagnostic::Hole { hole, .. } => {
+        if let Pos::Some(pos) = hole.pos {
           if let Some(Ipld::String(input)) = store.get(pos.input) {
             println!("{}", pos.range(input))
           }
         }
-        print!("Error: {}", err);
       }
+      Diagnostic::Read { .. } | Diagnostic::Parse { .. } => (),
     }
+    print!("{}", diagnostic);
   }
-  Ok(ds)
+  let errors = if diagnostics.len() == 1 { "error" } else { "errors" };
+  println!("{} {} found", diagnostics.len(), errors);
 }
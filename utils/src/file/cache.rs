@@ -0,0 +1,258 @@
+use cid::Cid;
+use sp_ipld::{
+  dag_cbor::cid,
+  Ipld,
+};
+use std::rc::Rc;
+use yatima_core::{
+  anon::Anon,
+  check::{
+    check_def_all,
+    error::CheckError,
+    hole::Hole,
+    CheckOpts,
+  },
+  defs::{
+    Def,
+    Defs,
+  },
+  term::Term,
+};
+
+use crate::store::Store;
+
+// Records written by a checker that might disagree with this one are never
+// looked up, since this is part of their keys
+const VERSION: &str = "yatima.check.1";
+
+/// The outcome of checking a definition, as recorded in the store. Since the
+/// content id of a definition covers the content ids of the definitions it
+/// refers to, and theirs in turn, the outcome holds wherever the definition
+/// is found with the same dependencies.
+#[derive(PartialEq, Clone, Debug)]
+pub struct CheckRecord {
+  pub def_cid: Cid,
+  /// Whether the definition checked without leaving holes
+  pub ok: bool,
+  /// The content id of the anonymous type of the definition
+  pub typ: Cid,
+  /// The content ids of the definitions it depends on, transitively
+  pub deps: Vec<Cid>,
+}
+
+impl CheckRecord {
+  pub fn to_ipld(&self) -> Ipld {
+    Ipld::List(vec![
+      Ipld::Link(self.def_cid),
+      Ipld::Bool(self.ok),
+      Ipld::Link(self.typ),
+      Ipld::List(self.deps.iter().map(|dep| Ipld::Link(*dep)).collect()),
+    ])
+  }
+
+  pub fn from_ipld(ipld: &Ipld) -> Option<Self> {
+    match ipld {
+      Ipld::List(xs) => match xs.as_slice() {
+        [Ipld::Link(def), Ipld::Bool(ok), Ipld::Link(typ), Ipld::List(ds)] => {
+          let mut deps = Vec::new();
+          for dep in ds {
+            match dep {
+              Ipld::Link(dep) => deps.push(*dep),
+              _ => return None,
+            }
+          }
+          Some(CheckRecord { def_cid: *def, ok: *ok, typ: *typ, deps })
+        }
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+}
+
+// The key the record of a definition checked with `opts` is linked from
+fn key(def_cid: Cid, opts: CheckOpts) -> Cid {
+  cid(&Ipld::List(vec![
+    Ipld::String(VERSION.to_owned()),
+    Ipld::Link(def_cid),
+    Ipld::Bool(opts.type_in_type),
+  ]))
+}
+
+/// The record of checking a definition with `opts`, if there is one and the
+/// dependencies it was checked against are all in `defs`
+pub fn lookup(
+  store: &Rc<dyn Store>,
+  defs: &Defs,
+  def: &Def,
+  opts: CheckOpts,
+) -> Option<CheckRecord> {
+  let link = store.get_link(key(def.def_cid, opts))?;
+  let record = CheckRecord::from_ipld(&store.get(link)?)?;
+  let (entry, ..) = def.embed();
+  let valid = record.def_cid == def.def_cid
+    && record.typ == entry.type_anon
+    && record.deps.iter().all(|dep| defs.defs.contains_key(dep));
+  if valid { Some(record) } else { None }
+}
+
+/// Records the outcome of checking a definition with `opts` in the store,
+/// along with its anonymous type
+pub fn record(
+  store: &Rc<dyn Store>,
+  defs: &Defs,
+  def: &Def,
+  opts: CheckOpts,
+  ok: bool,
+) -> CheckRecord {
+  let (entry, type_anon, _) = def.embed();
+  store.put(type_anon.to_ipld());
+  let mut deps: Vec<Cid> =
+    defs.dependencies(def).into_iter().map(|(_, dep)| dep).collect();
+  deps.sort_by_key(|dep| dep.to_bytes());
+  let record =
+    CheckRecord { def_cid: def.def_cid, ok, typ: entry.type_anon, deps };
+  let link = store.put(record.to_ipld());
+  store.put_link(key(def.def_cid, opts), link);
+  record
+}
+
+/// The outcome of `check_def_all`
+pub type Checked = Result<(Term, Vec<Hole>), Vec<CheckError>>;
+
+// The type a record was made with, named and positioned like the type of the
+// definition it records, if it's in the store
+fn recorded_type(
+  store: &Rc<dyn Store>,
+  def: &Def,
+  record: &CheckRecord,
+) -> Option<Term> {
+  let anon = Anon::from_ipld(&store.get(record.typ)?).ok()?;
+  let (entry, ..) = def.embed();
+  Term::unembed(&anon, &entry.type_meta).ok()
+}
+
+/// Checks a definition like `check_def_all`, unless it is recorded to have
+/// checked already, in which case its recorded type is returned without
+/// checking it again, along with whether it was. Definitions recorded to fail,
+/// or to leave holes, are checked again to report their errors and holes,
+/// which refer to the source they were parsed from that records don't keep,
+/// but aren't recorded again.
+pub fn check_def_cached(
+  store: &Rc<dyn Store>,
+  defs: &Defs,
+  name: &str,
+  opts: CheckOpts,
+) -> (Checked, bool) {
+  let def = match defs.get(&name.into()) {
+    Some(def) => def,
+    None => return (check_def_all(defs, name, opts), false),
+  };
+  let found = lookup(store, defs, def, opts);
+  if let Some(found) = &found {
+    if found.ok {
+      if let Some(typ) = recorded_type(store, def, found) {
+        return (Ok((typ, vec![])), true);
+      }
+    }
+  }
+  let res = check_def_all(defs, name, opts);
+  let ok = matches!(&res, Ok((_, holes)) if holes.is_empty());
+  if found.map(|found| found.ok) != Some(ok) {
+    record(store, defs, def, opts, ok);
+  }
+  (res, false)
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use std::{
+    cell::RefCell,
+    collections::HashMap,
+  };
+  use yatima_core::parse::{
+    package::parse_defs,
+    span::Span,
+    term::input_cid,
+  };
+
+  #[derive(Debug, Default)]
+  struct MemStore {
+    content: RefCell<HashMap<Cid, Ipld>>,
+    links: RefCell<HashMap<Cid, Cid>>,
+  }
+
+  impl Store for MemStore {
+    fn put(&self, expr: Ipld) -> Cid {
+      let link = cid(&expr);
+      self.content.borrow_mut().insert(link, expr);
+      link
+    }
+
+    fn get(&self, link: Cid) -> Option<Ipld> {
+      self.content.borrow().get(&link).cloned()
+    }
+
+    fn put_link(&self, key: Cid, link: Cid) {
+      self.links.borrow_mut().insert(key, link);
+    }
+
+    fn get_link(&self, key: Cid) -> Option<Cid> {
+      self.links.borrow().get(&key).copied()
+    }
+  }
+
+  fn defs(src: &str) -> Defs {
+    let (_, (defs, _)) =
+      parse_defs(input_cid(src), Defs::new())(Span::new(src)).unwrap();
+    defs
+  }
+
+  #[test]
+  fn record_lookup() {
+    let store: Rc<dyn Store> = Rc::new(MemStore::default());
+    let opts = CheckOpts::default();
+    let defs = defs(
+      "def id {0 A: Type} (x: A): A = x
+       def one: #Nat = id 1
+       def bad: #Nat = \"one\"",
+    );
+    let one = defs.get(&"one".into()).unwrap();
+    assert_eq!(lookup(&store, &defs, one, opts), None);
+    let recorded = record(&store, &defs, one, opts, true);
+    let ipld = recorded.to_ipld();
+    assert_eq!(CheckRecord::from_ipld(&ipld), Some(recorded.clone()));
+    assert_eq!(lookup(&store, &defs, one, opts), Some(recorded.clone()));
+    assert_eq!(recorded.deps, vec![defs.get(&"id".into()).unwrap().def_cid]);
+    // Records of other options, or missing dependencies, aren't found
+    let type_in_type = CheckOpts { type_in_type: true };
+    assert_eq!(lookup(&store, &defs, one, type_in_type), None);
+    let mut without_id = defs.clone();
+    without_id.defs.remove(&recorded.deps[0]);
+    assert_eq!(lookup(&store, &without_id, one, opts), None);
+  }
+
+  #[test]
+  fn check_cached() {
+    let store: Rc<dyn Store> = Rc::new(MemStore::default());
+    let opts = CheckOpts::default();
+    let defs = defs(
+      "def id {0 A: Type} (x: A): A = x
+       def one: #Nat = id 1
+       def bad: #Nat = \"one\"",
+    );
+    let (res, cached) = check_def_cached(&store, &defs, "one", opts);
+    assert!(res.is_ok() && !cached);
+    let (res, cached) = check_def_cached(&store, &defs, "one", opts);
+    assert!(cached);
+    assert_eq!(format!("{}", res.unwrap().0), "#Nat");
+    // Failures are recorded, but checked again for their errors
+    let (res, cached) = check_def_cached(&store, &defs, "bad", opts);
+    assert!(res.is_err() && !cached);
+    let bad = defs.get(&"bad".into()).unwrap();
+    assert!(!lookup(&store, &defs, bad, opts).unwrap().ok);
+    let (res, cached) = check_def_cached(&store, &defs, "bad", opts);
+    assert!(res.is_err() && !cached);
+  }
+}
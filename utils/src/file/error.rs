@@ -1,6 +1,10 @@
 use cid::Cid;
 use yatima_core::{
   self,
+  check::{
+    error::CheckError,
+    hole::Hole,
+  },
   ipld_error::IpldError,
   name::Name,
   parse::{
@@ -34,6 +38,7 @@ pub enum FileErrorKind {
   ImportCollision(String, Cid, String),
   MisnamedImport(String, Cid, String),
   ImportCycle(PathBuf),
+  DefCidMismatch(Name, Cid, Cid),
   IpldError(IpldError),
   EmbedError(Box<yatima_core::embed_error::EmbedError>),
   Nom(ErrorKind),
@@ -94,6 +99,13 @@ impl<'a> fmt::Display for FileErrorKind {
           path
         )
       }
+      Self::DefCidMismatch(name, expected, stored) => {
+        write!(
+          f,
+          "Definition {} was stored as {} instead of its content id {}",
+          name, stored, expected
+        )
+      }
       Self::ImportCollision(imp_name, _cid, def_name) => {
         writeln!(
           f,
@@ -228,6 +240,63 @@ where
   }
 }
 
+/// A problem found in a package. All of the diagnostics of a package are
+/// collected before any of them is reported.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Diagnostic {
+  /// A package file that couldn't be read
+  Read { path: PathBuf, message: String },
+  /// A declaration that failed to parse, which parsing resumed after, or a
+  /// package header or import that did, which parsing stopped at
+  Parse { path: PathBuf, line: u32, column: usize, message: String },
+  /// An error in a definition that failed to typecheck
+  Check { name: Name, error: CheckError },
+  /// A hole left in a definition that typechecked otherwise
+  Hole { name: Name, hole: Hole },
+}
+
+impl Diagnostic {
+  pub fn from_parse_error(
+    path: PathBuf,
+    err: &parse::error::ParseError<Span>,
+  ) -> Self {
+    Self::Parse {
+      path,
+      line: err.input.location_line(),
+      column: err.input.get_column(),
+      message: err.to_string(),
+    }
+  }
+
+  pub fn from_file_error(path: PathBuf, err: &FileError<Span>) -> Self {
+    Self::Parse {
+      path,
+      line: err.input.location_line(),
+      column: err.input.get_column(),
+      message: err.to_string(),
+    }
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Read { path, message } => {
+        writeln!(f, "Cannot read {}: {}", path.to_string_lossy(), message)
+      }
+      Self::Parse { path, message, .. } => {
+        write!(f, "Parse error in {}:\n{}", path.to_string_lossy(), message)
+      }
+      Self::Check { name, error } => {
+        write!(f, "Error in {}: {}", name, error)
+      }
+      Self::Hole { name, hole } => {
+        write!(f, "Unfilled hole in {}: {}", name, hole)
+      }
+    }
+  }
+}
+
 pub fn convert<I: AsBytes>(
   x: Err<parse::error::ParseError<I>>,
 ) -> Err<FileError<I>> {
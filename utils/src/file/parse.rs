@@ -2,6 +2,7 @@ use crate::{
   file::{
     error,
     error::{
+      Diagnostic,
       FileError,
       FileErrorKind,
     },
@@ -26,7 +27,7 @@ use yatima_core::{
   parse::{
     package::{
       parse_alias,
-      parse_defs,
+      parse_defs_recover,
       parse_link,
       parse_with,
     },
@@ -48,6 +49,7 @@ use nom::{
   sequence::terminated,
   Err,
   IResult,
+  Slice,
 };
 
 use std::{
@@ -69,6 +71,8 @@ pub struct PackageEnv {
   store: Rc<dyn Store>,
   open: Rc<RefCell<HashSet<PathBuf>>>,
   done: Rc<RefCell<HashMap<PathBuf, Cid>>>,
+  // The diagnostics of every file parsed, imports included
+  diagnostics: Rc<RefCell<Vec<Diagnostic>>>,
   // sources: Rc<RefCell<HashMap<Cid, PathBuf>>>,
 }
 
@@ -81,6 +85,7 @@ impl PackageEnv {
       store: store.clone(),
       open: Rc::new(RefCell::new(HashSet::new())),
       done: Rc::new(RefCell::new(HashMap::new())),
+      diagnostics: Rc::new(RefCell::new(Vec::new())),
     }
   }
 
@@ -105,38 +110,78 @@ impl PackageEnv {
     cid.cloned()
   }
 
+  pub fn push_diagnostic(&self, diagnostic: Diagnostic) {
+    let mut diagnostics = self.diagnostics.borrow_mut();
+    diagnostics.push(diagnostic)
+  }
+
+  pub fn diagnostics(&self) -> Vec<Diagnostic> {
+    let diagnostics = self.diagnostics.borrow();
+    diagnostics.clone()
+  }
+
   // pub fn insert_source(&self, cid: Cid, path: PathBuf) {
   //  let mut sources = self.sources.borrow_mut();
   //  sources.insert(cid, path);
   //}
 }
 
+/// Reads and parses a package file. A file that can't be read is reported as
+/// a diagnostic, and parsed as an empty package.
 pub fn parse_file(env: PackageEnv) -> (Cid, Package, Defs) {
   let path = env.path.clone();
-  let txt = fs::read_to_string(&path).expect("file not found");
-  parse_text(txt, env)
+  match fs::read_to_string(&path) {
+    Ok(txt) => parse_text(txt, env),
+    Err(e) => {
+      env.push_diagnostic(Diagnostic::Read { path, message: e.to_string() });
+      empty_package(&env)
+    }
+  }
 }
 
+/// Parses the text of a package file. An error in the header or imports of
+/// the package is reported as a diagnostic, like the errors in its
+/// declarations, and the package is then parsed as an empty one.
 pub fn parse_text(txt: String, env: PackageEnv) -> (Cid, Package, Defs) {
   let path = env.path.clone();
   let input_cid = env.store.put(Ipld::String(txt.clone()));
   // env.insert_source(input_cid, path.clone());
-  match parse_package(input_cid, env)(Span::new(&txt)) {
+  match parse_package(input_cid, env.clone())(Span::new(&txt)) {
     Ok((_, p)) => p,
-    Err(e) => match e {
-      Err::Incomplete(_) => panic!("Incomplete"),
-      Err::Failure(e) => {
-        println!("Parse Failure in {}:\n{}", path.to_string_lossy(), e);
-        panic!()
-      }
-      Err::Error(e) => {
-        println!("Parse Error in {}:\n{}", path.to_string_lossy(), e);
-        panic!()
-      }
-    },
+    Err(e) => {
+      let diagnostic = match e {
+        Err::Incomplete(_) => {
+          let end = Span::new(&txt).slice(txt.len()..);
+          Diagnostic::Parse {
+            path,
+            line: end.location_line(),
+            column: end.get_column(),
+            message: "Unexpected end of input".to_owned(),
+          }
+        }
+        Err::Error(e) | Err::Failure(e) => {
+          Diagnostic::from_file_error(path, &e)
+        }
+      };
+      env.push_diagnostic(diagnostic);
+      empty_package(&env)
+    }
   }
 }
 
+// A package named after its file, without imports or definitions, standing
+// for one that couldn't be parsed
+fn empty_package(env: &PackageEnv) -> (Cid, Package, Defs) {
+  let name = env.path.file_stem().unwrap_or_default().to_string_lossy();
+  let package = Package {
+    pos: Pos::None,
+    name: Name::from(name.into_owned()),
+    imports: Vec::new(),
+    index: Index(Vec::new()),
+  };
+  (env.store.put(package.to_ipld()), package, Defs::new())
+}
+
 pub fn entry_to_def(
   d: Entry,
   store: Rc<dyn Store>,
@@ -168,6 +213,7 @@ pub fn index_to_defs(
     let def = entry_to_def(entry, env.store.clone())?;
     defs.insert(n.clone(), def);
   }
+  defs.link_mutual();
   Ok(defs)
 }
 
@@ -228,6 +274,7 @@ pub fn parse_import(
           path: import_path.clone(),
           open: env.open.clone(),
           done: env.done.clone(),
+          diagnostics: env.diagnostics.clone(),
           store: env.store.clone(),
         };
         let (from, _, defs) = parse_file(env.clone());
@@ -301,8 +348,13 @@ pub fn parse_package(
     }
     let (i, (imports, defs)) = parse_imports(env.clone())(i)?;
     let (i, _) = parse_space(i).map_err(error::convert)?;
-    let (upto, (defs, index)) =
-      parse_defs(input, defs)(i).map_err(error::convert)?;
+    // A declaration that fails to parse is reported and skipped, so that the
+    // errors of the rest can be reported along with it
+    let (upto, (defs, index, errs)) =
+      parse_defs_recover(input, defs)(i).map_err(error::convert)?;
+    for err in &errs {
+      env.push_diagnostic(Diagnostic::from_parse_error(env.path.clone(), err));
+    }
     for (n, _) in index.0.iter() {
       let d = defs.get(n).unwrap();
       let (entry, typ, trm) = d.clone().embed();
@@ -310,7 +362,10 @@ pub fn parse_package(
       env.store.put(trm.to_ipld());
       let entry_cid = env.store.put(entry.to_ipld());
       if entry_cid != d.def_cid {
-        panic!("def cid error, expected {}, got {}", d.def_cid, entry_cid)
+        return Err(Err::Failure(FileError::new(
+          i,
+          FileErrorKind::DefCidMismatch(n.clone(), d.def_cid, entry_cid),
+        )));
       }
     }
     let pos = Pos::from_upto(input, from, upto);
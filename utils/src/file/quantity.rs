@@ -0,0 +1,255 @@
+use yatima_core::{
+  check::quantity::BinderUses,
+  defs::Def,
+  position::Pos,
+  uses::Uses,
+};
+
+/// An edit of a source text, replacing the bytes from `from` up to `upto`
+#[derive(PartialEq, Clone, Debug)]
+pub struct Edit {
+  pub from: usize,
+  pub upto: usize,
+  pub text: String,
+}
+
+// The annotation of a quantity in a binder, which `ω` is written without
+fn uses_prefix(uses: Uses) -> &'static str {
+  match uses {
+    Uses::None => "0 ",
+    Uses::Affi => "& ",
+    Uses::Once => "1 ",
+    Uses::Many => "",
+  }
+}
+
+fn skip_space(txt: &str, i: usize) -> usize {
+  txt[i..].find(|c: char| !c.is_whitespace()).map_or(txt.len(), |j| i + j)
+}
+
+// The length of a written quantity `0`, `&` or `1` at the start of `txt`
+fn uses_token(txt: &str) -> usize {
+  let mut chars = txt.chars();
+  match (chars.next(), chars.next()) {
+    (Some('0' | '&' | '1'), Some(c)) if c.is_whitespace() => 1,
+    _ => 0,
+  }
+}
+
+// The offset just past the bracket closing the one at `open`
+fn matching_close(txt: &str, open: usize) -> Option<usize> {
+  let mut depth = 0;
+  for (i, c) in txt[open..].char_indices() {
+    match c {
+      '(' | '{' => depth += 1,
+      ')' | '}' => {
+        depth -= 1;
+        if depth == 0 {
+          return Some(open + i + 1);
+        }
+      }
+      _ => (),
+    }
+  }
+  None
+}
+
+// The binder groups such as `(1 x y: A)` between the name of a definition
+// and the `:` before its type, as the offsets of their brackets
+fn param_groups(txt: &str) -> Option<Vec<(usize, usize)>> {
+  let mut i = skip_space(txt, 0);
+  for modifier in ["partial", "opaque"].iter() {
+    if txt[i..].starts_with(modifier) {
+      i = skip_space(txt, i + modifier.len());
+    }
+  }
+  if !txt[i..].starts_with("def") {
+    return None;
+  }
+  i = skip_space(txt, i + 3);
+  i += txt[i..]
+    .find(|c: char| c.is_whitespace() || "({:".contains(c))
+    .unwrap_or(txt.len() - i);
+  let mut groups = Vec::new();
+  loop {
+    i = skip_space(txt, i);
+    if !(txt[i..].starts_with('(') || txt[i..].starts_with('{')) {
+      return Some(groups);
+    }
+    let close = matching_close(txt, i)?;
+    groups.push((i, close));
+    i = close;
+  }
+}
+
+/// The edits giving the binders of a definition the quantities inferred for
+/// them, rewriting the source the definition was parsed from. A parameter
+/// group whose binders are inferred different quantities, like `(x y: A)`
+/// with `x` linear and `y` erased, is split into `(1 x: A) (0 y: A)`. Returns
+/// no edits for definitions that aren't written with `def`, such as the ones
+/// generated by a `type` declaration.
+pub fn uses_edits(src: &str, def: &Def, binders: &[BinderUses]) -> Vec<Edit> {
+  let pos = match def.pos {
+    Pos::Some(pos) => pos,
+    Pos::None => return vec![],
+  };
+  let (from, upto) = (pos.from_offset as usize, pos.upto_offset as usize);
+  let txt = match src.get(from..upto) {
+    Some(txt) => txt,
+    None => return vec![],
+  };
+  let groups = match param_groups(txt) {
+    Some(groups) => groups,
+    None => return vec![],
+  };
+  let mut edits = Vec::new();
+  let mut params = binders.iter().filter(|b| b.pos.is_none());
+  for (open, close) in groups {
+    let inner = &txt[open + 1..close - 1];
+    let start = skip_space(inner, 0);
+    let token = uses_token(&inner[start..]);
+    let colon = match inner.find(':') {
+      Some(colon) => colon,
+      None => return vec![],
+    };
+    let names: Vec<&str> =
+      inner[start + token..colon].split_whitespace().collect();
+    let typ = inner[colon + 1..].trim();
+    let group: Vec<&BinderUses> = params.by_ref().take(names.len()).collect();
+    if group.len() != names.len() {
+      return vec![];
+    }
+    if group.iter().all(|b| b.written == b.inferred) {
+      continue;
+    }
+    // Consecutive binders with the same quantity stay in one group
+    let mut runs: Vec<(Uses, Vec<&str>)> = Vec::new();
+    for (name, binder) in names.iter().zip(group) {
+      match runs.last_mut() {
+        Some((uses, names)) if *uses == binder.inferred => names.push(name),
+        _ => runs.push((binder.inferred, vec![name])),
+      }
+    }
+    let open_bracket = &txt[open..open + 1];
+    let close_bracket = &txt[close - 1..close];
+    let text: Vec<String> = runs
+      .into_iter()
+      .map(|(uses, names)| {
+        let names = names.join(" ");
+        let prefix = uses_prefix(uses);
+        format!("{}{}{}: {}{}", open_bracket, prefix, names, typ, close_bracket)
+      })
+      .collect();
+    let (from, upto) = (from + open, from + close);
+    edits.push(Edit { from, upto, text: text.join(" ") });
+  }
+  for binder in binders {
+    if let Some(Pos::Some(pos)) = binder.pos {
+      if binder.written == binder.inferred {
+        continue;
+      }
+      let at = pos.from_offset as usize;
+      let txt = &src[at..];
+      let keyword = if txt.starts_with("letrec") { 6 } else { 3 };
+      let start = skip_space(txt, keyword);
+      let token = uses_token(&txt[start..]);
+      let end = skip_space(txt, start + token);
+      edits.push(Edit {
+        from: at + start,
+        upto: at + end,
+        text: uses_prefix(binder.inferred).to_owned(),
+      });
+    }
+  }
+  edits
+}
+
+/// Applies edits that don't overlap to a source text
+pub fn apply_edits(src: &str, edits: &[Edit]) -> String {
+  let mut edits: Vec<&Edit> = edits.iter().collect();
+  edits.sort_by_key(|edit| edit.from);
+  let mut res = String::new();
+  let mut last = 0;
+  for edit in edits {
+    res.push_str(&src[last..edit.from]);
+    res.push_str(&edit.text);
+    last = edit.upto;
+  }
+  res.push_str(&src[last..]);
+  res
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use yatima_core::{
+    check::{
+      quantity::infer_uses,
+      CheckOpts,
+    },
+    defs::Defs,
+    parse::{
+      package::parse_defs,
+      span::Span,
+      term::input_cid,
+    },
+  };
+
+  // The source of a definition rewritten with the quantities inferred for it
+  fn rewrite(src: &str, name: &str) -> String {
+    let (_, (defs, _)) =
+      parse_defs(input_cid(src), Defs::new())(Span::new(src)).unwrap();
+    let def = defs.get(&name.into()).unwrap();
+    let binders = infer_uses(&defs, name, CheckOpts::default()).unwrap();
+    apply_edits(src, &uses_edits(src, def, &binders))
+  }
+
+  #[test]
+  fn find_param_groups() {
+    let txt = "partial def f (a: A) {b c: B}\n  (d: (C -> C)): D";
+    let groups = param_groups(txt).unwrap();
+    let groups: Vec<&str> =
+      groups.into_iter().map(|(open, close)| &txt[open..close]).collect();
+    assert_eq!(groups, vec!["(a: A)", "{b c: B}", "(d: (C -> C))"]);
+    assert_eq!(param_groups("def g: A"), Some(vec![]));
+    assert_eq!(param_groups("type Bool { True, False }"), None);
+  }
+
+  #[test]
+  fn edit_quantities() {
+    // A group is split where its binders are inferred different quantities
+    assert_eq!(
+      rewrite("def f (x y: #Nat): #Nat = x", "f"),
+      "def f (1 x: #Nat) (0 y: #Nat): #Nat = x"
+    );
+    assert_eq!(
+      rewrite("def f (x y z: #Nat): #Nat = #Nat.add y z", "f"),
+      "def f (0 x: #Nat) (y z: #Nat): #Nat = #Nat.add y z"
+    );
+    assert_eq!(
+      rewrite(
+        "def g {A: Type}
+           (1 x: A)
+           (n: #Nat): A =
+           let m: #Nat = n;
+           x",
+        "g"
+      ),
+      "def g {0 A: Type}
+           (1 x: A)
+           (0 n: #Nat): A =
+           let 0 m: #Nat = n;
+           x"
+    );
+    // Definitions that already have their tightest quantities are unchanged
+    let src = "def id (0 A: Type) (1 x: A): A = x";
+    assert_eq!(rewrite(src, "id"), src);
+  }
+
+  #[test]
+  fn apply_unordered_edits() {
+    let edit = |from, upto, text: &str| Edit { from, upto, text: text.into() };
+    let edits = [edit(6, 9, "ω"), edit(0, 1, "A"), edit(3, 3, "+")];
+    assert_eq!(apply_edits("abcdefghij", &edits), "Abc+defωj");
+  }
+}
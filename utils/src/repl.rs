@@ -1,5 +1,6 @@
 pub mod command;
 pub mod error;
+pub mod step;
 
 use crate::{
   file,
@@ -16,18 +17,68 @@ use std::{
 use yatima_core::{
   check::{
     check_def,
+    elaborate_defs,
+    elaborate_term,
     infer_term,
+    CheckOpts,
   },
   dag::DAG,
-  defs::Defs,
+  defs::{
+    Def,
+    Defs,
+  },
+  eval::{
+    stats,
+    Fuel,
+  },
+  name::Name,
   parse::{
     span::Span,
     term::input_cid,
   },
+  position::Pos,
+  term::Term,
 };
 
-use command::Command;
+use command::{
+  Command,
+  Setting,
+};
 use error::ReplError;
+use step::Stepper;
+
+/// The settings evaluations in the REPL are run with
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReplSettings {
+  /// The most reduction steps an evaluation may take, if there's a limit
+  pub max_steps: Option<u64>,
+}
+
+// The term and the definitions it refers to with the holes solved when
+// checking them, such as the projections of fields several records have,
+// filled in. They are left as written if they don't check.
+fn elaborate(defs: &Defs, term: &Term) -> (Defs, Term) {
+  let term =
+    elaborate_term(defs, term.clone()).unwrap_or_else(|_| term.clone());
+  let typ = Term::Hol(Pos::None, Name::from(""), 0);
+  let (def, _) = Def::make(Pos::None, false, false, typ, term.clone());
+  let roots: Vec<Name> =
+    defs.dependencies(&def).into_iter().map(|(name, _)| name).collect();
+  let defs = elaborate_defs(defs, &roots, CheckOpts::default())
+    .unwrap_or_else(|_| defs.clone());
+  (defs, term)
+}
+
+// Evaluates a term to its normal form, or as far as `max_steps` steps get
+fn evaluate(defs: &Defs, term: &Term, max_steps: Option<u64>) -> String {
+  let (defs, term) = elaborate(defs, term);
+  let mut dag = DAG::from_term(&term);
+  let mut fuel = max_steps.map_or_else(Fuel::unlimited, Fuel::new);
+  match dag.norm_fueled(&defs, &mut fuel) {
+    Ok(()) => format!("{}", dag),
+    Err(err) => format!("Error: {}, stopped at:\n{}", err, dag),
+  }
+}
 
 pub trait Repl {
   fn readline(&mut self, prompt: &str) -> Result<String, ReplError>;
@@ -37,6 +88,9 @@ pub trait Repl {
   fn save_history(&mut self);
   fn get_defs(&self) -> Arc<Mutex<Defs>>;
   fn get_store(&self) -> Rc<dyn Store>;
+  fn get_settings(&self) -> Arc<Mutex<ReplSettings>>;
+  /// The evaluation being stepped through with `:step`, if any
+  fn get_stepper(&self) -> Arc<Mutex<Option<Stepper>>>;
   fn handle_line(
     &mut self,
     readline: Result<String, ReplError>,
@@ -47,6 +101,25 @@ pub trait Repl {
     match readline {
       Ok(line) => {
         self.add_history_entry(line.as_str());
+        let mutex_stepper = self.get_stepper();
+        let mut stepper = mutex_stepper.lock().unwrap();
+        // While stepping, lines are stepping commands
+        if let Some(session) = stepper.as_mut() {
+          match step::parse_step_command(Span::new(&line)) {
+            Ok((_, command)) => {
+              let max_steps = self.get_settings().lock().unwrap().max_steps;
+              self.println(session.run(command, max_steps));
+              if session.is_done() {
+                *stepper = None;
+              }
+            }
+            Err(_) => self.println(format!(
+              "Unknown stepping command, `help` lists them: {}",
+              line
+            )),
+          }
+          return Ok(());
+        }
         let res = command::parse_command(
           input_cid(line.as_str()),
           defs.clone(),
@@ -61,19 +134,42 @@ pub trait Repl {
                   path.push(n);
                 }
                 path.set_extension("ya");
-                if let Ok(ds) = file::check_all(path, store) {
+                let opts = CheckOpts::default();
+                if let Ok((ds, diagnostics)) =
+                  file::check_all(path, store.clone(), opts, true)
+                {
+                  file::print_diagnostics(&diagnostics, store);
                   *defs = ds;
                 }
               }
               Command::Eval(term) => {
-                let mut dag = DAG::from_term(&term);
-                dag.norm(&defs);
-                self.println(format!("{}", dag));
+                let max_steps = self.get_settings().lock().unwrap().max_steps;
+                self.println(evaluate(&defs, &term, max_steps));
+              }
+              Command::Stats(term) => {
+                let max_steps = self.get_settings().lock().unwrap().max_steps;
+                let (res, stats) =
+                  stats::collect(|| evaluate(&defs, &term, max_steps));
+                self.println(res);
+                self.println(format!("{}", stats));
+              }
+              Command::Step(term) => {
+                let mut session = Stepper::new(&defs, &term);
+                self.println(session.show());
+                if !session.is_done() {
+                  self.println("Stepping, `help` lists the commands".to_string());
+                  *stepper = Some(session);
+                }
               }
               Command::Type(term) => {
                 let res = infer_term(&defs, *term);
                 match res {
-                  Ok(term) => self.println(format!("{}", term)),
+                  Ok((term, holes)) => {
+                    self.println(format!("{}", term));
+                    for hole in holes {
+                      self.println(format!("{}", hole));
+                    }
+                  }
                   Err(e) => self.println(format!("Error: {}", e)),
                 }
               }
@@ -83,13 +179,16 @@ pub trait Repl {
                 tmp_defs.insert(n.clone(), def);
                 let res = check_def(&tmp_defs, &n);
                 match res {
-                  Ok(res) => {
+                  Ok((res, holes)) => {
                     *defs = tmp_defs;
                     self.println(format!(
                       "{} : {}",
                       n,
                       res.pretty(Some(&n.to_string()))
-                    ))
+                    ));
+                    for hole in holes {
+                      self.println(format!("{}", hole));
+                    }
                   }
                   Err(e) => self.println(format!("Error: {}", e)),
                 }
@@ -99,6 +198,9 @@ pub trait Repl {
                   self.println(format!("{}", d.pretty(n.to_string())))
                 }
               }
+              Command::Set(Setting::MaxSteps(max_steps)) => {
+                self.get_settings().lock().unwrap().max_steps = max_steps;
+              }
               Command::Quit => {
                 self.println(format!("Goodbye."));
                 return Err(());
@@ -141,7 +243,8 @@ pub trait Repl {
 pub fn run_repl(rl: &mut dyn Repl) {
   rl.load_history();
   loop {
-    let readline = rl.readline("⅄ ");
+    let stepping = rl.get_stepper().lock().unwrap().is_some();
+    let readline = rl.readline(if stepping { "step ⅄ " } else { "⅄ " });
     match rl.handle_line(readline) {
       Ok(()) => continue,
       Err(()) => break,
@@ -32,12 +32,19 @@ use nom::{
   self,
   branch::alt,
   bytes::complete::tag,
+  character::complete::digit1,
+  combinator::{
+    map_opt,
+    value,
+  },
   IResult,
 };
 
 pub enum Command {
   Eval(Box<Term>),
   Type(Box<Term>),
+  Stats(Box<Term>),
+  Step(Box<Term>),
   Browse,
   // Help,
   Define(Box<(Name, Def, Entry)>),
@@ -45,6 +52,14 @@ pub enum Command {
   Load(Name),
   // Import,
   Quit,
+  Set(Setting),
+}
+
+/// A change to the settings of the REPL
+pub enum Setting {
+  /// `:set max-steps N` limits evaluations to `N` reduction steps, and
+  /// `:set max-steps none` lifts the limit
+  MaxSteps(Option<u64>),
 }
 
 pub fn parse_eval(
@@ -82,6 +97,42 @@ pub fn parse_type(
   }
 }
 
+pub fn parse_stats(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Command, FileError<Span>> {
+  move |i: Span| {
+    let (i, _) = tag(":stats")(i)?;
+    let (i, trm) = parse_expression(
+      input,
+      defs.clone(),
+      None,
+      Rc::new(VecDeque::new()),
+      VecDeque::new(),
+    )(i)
+    .map_err(error::convert)?;
+    Ok((i, Command::Stats(Box::new(trm))))
+  }
+}
+
+pub fn parse_step(
+  input: Cid,
+  defs: Defs,
+) -> impl Fn(Span) -> IResult<Span, Command, FileError<Span>> {
+  move |i: Span| {
+    let (i, _) = tag(":step")(i)?;
+    let (i, trm) = parse_expression(
+      input,
+      defs.clone(),
+      None,
+      Rc::new(VecDeque::new()),
+      VecDeque::new(),
+    )(i)
+    .map_err(error::convert)?;
+    Ok((i, Command::Step(Box::new(trm))))
+  }
+}
+
 pub fn parse_define(
   input: Cid,
   defs: Defs,
@@ -119,6 +170,21 @@ pub fn parse_load() -> impl Fn(Span) -> IResult<Span, Command, FileError<Span>>
   }
 }
 
+pub fn parse_set() -> impl Fn(Span) -> IResult<Span, Command, FileError<Span>>
+{
+  move |i: Span| {
+    let (i, _) = tag(":set")(i)?;
+    let (i, _) = parse_space1(i).map_err(error::convert)?;
+    let (i, _) = tag("max-steps")(i)?;
+    let (i, _) = parse_space1(i).map_err(error::convert)?;
+    let (i, max_steps) = alt((
+      value(None, tag("none")),
+      map_opt(digit1, |d: Span| d.fragment().parse().ok().map(Some)),
+    ))(i)?;
+    Ok((i, Command::Set(Setting::MaxSteps(max_steps))))
+  }
+}
+
 pub fn parse_command(
   input: Cid,
   defs: Defs,
@@ -128,6 +194,9 @@ pub fn parse_command(
       parse_quit(),
       parse_browse(),
       parse_load(),
+      parse_set(),
+      parse_stats(input, defs.clone()),
+      parse_step(input, defs.clone()),
       parse_type(input, defs.clone()),
       parse_define(input, defs.clone()),
       parse_eval(input, defs.clone()),
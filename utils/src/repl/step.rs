@@ -0,0 +1,256 @@
+use yatima_core::{
+  dag::DAG,
+  defs::Defs,
+  eval::{
+    error::EvalError,
+    trace,
+    Fuel,
+  },
+  graph,
+  name::Name,
+  parse::{
+    span::Span,
+    term::{
+      parse_name,
+      parse_space,
+      parse_space1,
+    },
+  },
+  term::Term,
+};
+
+use crate::{
+  file::{
+    error,
+    error::FileError,
+  },
+  repl::elaborate,
+};
+
+use nom::{
+  branch::alt,
+  bytes::complete::tag,
+  combinator::{
+    eof,
+    value,
+  },
+  IResult,
+};
+
+/// What to do with an evaluation being stepped through
+#[derive(Clone)]
+pub enum StepCommand {
+  /// Take the next reduction step, on `step`, `s` or an empty line
+  Step,
+  /// Reduce to weak head normal form, on `whnf` or `w`
+  Whnf,
+  /// Reduce to normal form, on `norm` or `n`
+  Norm,
+  /// Expand the references to a definition, on `expand <name>` or
+  /// `e <name>`
+  Expand(Name),
+  /// Print the graph of the term in DOT, on `graph` or `g`
+  Graph,
+  /// List the commands, on `help` or `h`
+  Help,
+  /// Stop stepping, on `quit` or `q`
+  Quit,
+}
+
+const HELP: &str = "Stepping commands:
+  step, s, <Enter>   take the next reduction step, marked in ⟦ ⟧
+  whnf, w            reduce to weak head normal form
+  norm, n            reduce to normal form
+  expand, e <name>   expand the references to <name>
+  graph, g           print the graph of the term in DOT
+  help, h            print this list
+  quit, q            stop stepping";
+
+pub fn parse_step_command(
+  i: Span,
+) -> IResult<Span, StepCommand, FileError<Span>> {
+  let (i, _) = parse_space(i).map_err(error::convert)?;
+  let (i, command) = alt((
+    |i| {
+      let (i, _) = alt((tag("expand"), tag("e")))(i)?;
+      let (i, _) = parse_space1(i).map_err(error::convert)?;
+      let (i, name) = parse_name(i).map_err(error::convert)?;
+      Ok((i, StepCommand::Expand(name)))
+    },
+    value(StepCommand::Step, alt((tag("step"), tag("s")))),
+    value(StepCommand::Whnf, alt((tag("whnf"), tag("w")))),
+    value(StepCommand::Norm, alt((tag("norm"), tag("n")))),
+    value(StepCommand::Graph, alt((tag("graph"), tag("g")))),
+    value(StepCommand::Help, alt((tag("help"), tag("h")))),
+    value(StepCommand::Quit, alt((tag("quit"), tag("q")))),
+    value(StepCommand::Step, eof),
+  ))(i)?;
+  let (i, _) = parse_space(i).map_err(error::convert)?;
+  let (i, _) = eof(i)?;
+  Ok((i, command))
+}
+
+/// An evaluation started with `:step`, which reduces its term to normal form
+/// a step at a time
+pub struct Stepper {
+  defs: Defs,
+  dag: DAG,
+  steps: u64,
+  done: bool,
+}
+
+impl Stepper {
+  /// Starts stepping through the evaluation of a term, elaborated along with
+  /// the definitions it refers to like `:eval` does
+  pub fn new(defs: &Defs, term: &Term) -> Self {
+    let (defs, term) = elaborate(defs, term);
+    Stepper { defs, dag: DAG::from_term(&term), steps: 0, done: false }
+  }
+
+  /// Whether the evaluation reached its normal form or was quit
+  pub fn is_done(&self) -> bool { self.done }
+
+  /// Shows the term with the redex of the next step marked, or as its normal
+  /// form once there's no next step
+  pub fn show(&mut self) -> String {
+    let defs = &self.defs;
+    let next = trace::next_redex(&mut self.dag, |dag, fuel| {
+      dag.norm_fueled(defs, fuel)
+    });
+    match next {
+      Ok(Some(redex)) => {
+        format!("[{}] {}", self.steps, self.dag.to_term_marked(false, redex))
+      }
+      Ok(None) => {
+        self.done = true;
+        format!("[{}] {}\nNormal form reached.", self.steps, self.dag)
+      }
+      Err(err) => {
+        self.done = true;
+        format!("[{}] {}\nError: {}, stopped here.", self.steps, self.dag, err)
+      }
+    }
+  }
+
+  // Runs an evaluation of the term, counting the steps it takes
+  fn eval(
+    &mut self,
+    fuel: &mut Fuel,
+    eval: impl FnOnce(&mut DAG, &Defs, &mut Fuel) -> Result<(), EvalError>,
+  ) -> Result<(), EvalError> {
+    let res = eval(&mut self.dag, &self.defs, fuel);
+    self.steps += fuel.steps;
+    res
+  }
+
+  /// Runs a command, returning what to print. `max_steps` bounds the
+  /// reductions to weak head and normal forms.
+  pub fn run(
+    &mut self,
+    command: StepCommand,
+    max_steps: Option<u64>,
+  ) -> String {
+    let mut fuel = max_steps.map_or_else(Fuel::unlimited, Fuel::new);
+    let res = match command {
+      // The step after the one taken runs out of fuel
+      StepCommand::Step => self
+        .eval(&mut Fuel::new(1), |dag, defs, fuel| dag.norm_fueled(defs, fuel))
+        .or_else(|err| match err {
+          EvalError::OutOfFuel(_) => Ok(()),
+          err => Err(err),
+        }),
+      StepCommand::Whnf => self.eval(&mut fuel, |dag, defs, fuel| {
+        dag.whnf_fueled(defs, true, fuel)
+      }),
+      StepCommand::Norm => {
+        self.eval(&mut fuel, |dag, defs, fuel| dag.norm_fueled(defs, fuel))
+      }
+      StepCommand::Expand(name) => {
+        match self.dag.expand_refs(&self.defs, &name) {
+          Ok(0) => return format!("No references to {} in the term", name),
+          Ok(_) => Ok(()),
+          Err(err) => return format!("Error: {}\n{}", err, self.show()),
+        }
+      }
+      StepCommand::Graph => {
+        return format!("{}", graph::to_dot(&graph::from_dag(&self.dag)));
+      }
+      StepCommand::Help => return HELP.to_string(),
+      StepCommand::Quit => {
+        self.done = true;
+        return format!("Stopped after {} steps at:\n{}", self.steps, self.dag);
+      }
+    };
+    match res {
+      Err(err @ EvalError::OutOfFuel(_)) => {
+        format!("Error: {}\n{}", err, self.show())
+      }
+      // Any other error is the one `show` finds the term stuck at
+      _ => self.show(),
+    }
+  }
+}
+
+impl Drop for Stepper {
+  fn drop(&mut self) { DAG::new(self.dag.head).free() }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use yatima_core::parse::{
+    package::parse_defs,
+    term::{
+      input_cid,
+      parse,
+    },
+  };
+
+  fn command(line: &str) -> Option<StepCommand> {
+    parse_step_command(Span::new(line)).ok().map(|(_, command)| command)
+  }
+
+  #[test]
+  fn parse_step_commands() {
+    assert!(matches!(command(""), Some(StepCommand::Step)));
+    assert!(matches!(command("  s "), Some(StepCommand::Step)));
+    assert!(matches!(command("whnf"), Some(StepCommand::Whnf)));
+    assert!(matches!(command("n"), Some(StepCommand::Norm)));
+    assert!(matches!(command("g"), Some(StepCommand::Graph)));
+    assert!(matches!(command("help"), Some(StepCommand::Help)));
+    assert!(matches!(command("q"), Some(StepCommand::Quit)));
+    match command("expand List.map") {
+      Some(StepCommand::Expand(name)) => {
+        assert_eq!(name.to_string(), "List.map")
+      }
+      _ => panic!("expected an expand command"),
+    }
+    assert!(command("e").is_none());
+    assert!(command("steps").is_none());
+    assert!(command("norm 3").is_none());
+  }
+
+  #[test]
+  fn step_session() {
+    let src = "def id {0 A: Type} (x: A): A = x
+      def two: #Nat = #Nat.add 1 1
+      def const (y: #Nat): #Nat = two";
+    let (_, (defs, _)) =
+      parse_defs(input_cid(src), Defs::new())(Span::new(src)).unwrap();
+    let term = |src: &str| parse(src, defs.clone()).unwrap().1;
+    // The implicit argument of `id` is filled in, as when evaluating
+    let mut session = Stepper::new(&defs, &term("id const"));
+    assert_eq!(session.show(), "[0] ⟦id⟧ (∀ (y: #Nat) -> #Nat) const");
+    assert_eq!(
+      session.run(StepCommand::Step, None),
+      "[1] ⟦(λ A x => x) (∀ (y: #Nat) -> #Nat)⟧ const"
+    );
+    assert_eq!(session.run(StepCommand::Whnf, None), "[4] λ y => ⟦two⟧");
+    assert!(!session.is_done());
+    assert_eq!(
+      session.run(StepCommand::Norm, None),
+      "[6] λ y => 2\nNormal form reached."
+    );
+    assert!(session.is_done());
+  }
+}
@@ -6,4 +6,12 @@ use sp_ipld::Ipld;
 pub trait Store: std::fmt::Debug {
   fn put(&self, expr: Ipld) -> Cid;
   fn get(&self, link: Cid) -> Option<Ipld>;
+  /// Associates a link with a key, replacing the link associated with it
+  /// before. Unlike the content of the store, which is addressed by its
+  /// content id, keys may be associated with different links over time.
+  /// Stores that don't keep links, and so never skip checking definitions
+  /// recorded to have checked, can leave this and `get_link` out.
+  fn put_link(&self, _key: Cid, _link: Cid) {}
+  /// The link last associated with a key
+  fn get_link(&self, _key: Cid) -> Option<Cid> { None }
 }
@@ -9,7 +9,9 @@ use yatima_core::defs::Defs;
 use yatima_utils::{
   repl::{
     error::ReplError,
+    step::Stepper,
     Repl,
+    ReplSettings,
   },
   store::Store,
 };
@@ -65,6 +67,8 @@ struct WebRepl {
   defs: Arc<Mutex<Defs>>,
   shell_state: Arc<Mutex<ShellState>>,
   store: Rc<WebStore>,
+  settings: Arc<Mutex<ReplSettings>>,
+  stepper: Arc<Mutex<Option<Stepper>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -114,7 +118,9 @@ impl WebRepl {
     addon.fit();
     terminal.focus();
     let store = Rc::new(WebStore::new());
-    WebRepl { terminal, defs, shell_state, store }
+    let settings = Arc::new(Mutex::new(ReplSettings::default()));
+    let stepper = Arc::new(Mutex::new(None));
+    WebRepl { terminal, defs, shell_state, store, settings, stepper }
   }
 
   pub fn handle_event(&mut self, e: OnKeyEvent) {
@@ -190,6 +196,10 @@ impl Repl for WebRepl {
 
   fn get_store(&self) -> Rc<dyn Store> { self.store.clone() }
 
+  fn get_settings(&self) -> Arc<Mutex<ReplSettings>> { self.settings.clone() }
+
+  fn get_stepper(&self) -> Arc<Mutex<Option<Stepper>>> { self.stepper.clone() }
+
   fn println(&self, s: String) { self.terminal.writeln(s.as_str()); }
 
   fn load_history(&mut self) {